@@ -0,0 +1,25 @@
+#include <metal_stdlib>
+#import "../../shader_types/shader_types.h"
+
+using namespace metal;
+
+struct OverlayVertexOut {
+  float4 position [[position]];
+  float4 color;
+};
+
+// Vertices of the overlay are already in world space, so only the view and
+// projection of the uniforms are used.
+vertex OverlayVertexOut vertex_overlay(constant OverlayVertex *vertices [[buffer(BufferIndexVertices)]],
+                                       constant Uniforms &uniforms [[buffer(BufferIndexUniforms)]],
+                                       uint vertexId [[vertex_id]]) {
+  OverlayVertex in = vertices[vertexId];
+  OverlayVertexOut out;
+  out.position = uniforms.projectionMatrix * uniforms.viewMatrix * in.position;
+  out.color = in.color;
+  return out;
+}
+
+fragment float4 fragment_overlay(OverlayVertexOut in [[stage_in]]) {
+  return in.color;
+}
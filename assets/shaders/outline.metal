@@ -0,0 +1,34 @@
+#include <metal_stdlib>
+#import "../../shader_types/shader_types.h"
+
+using namespace metal;
+
+struct OutlineVertexIn {
+  float4 position [[attribute(Position)]];
+  float3 normal [[attribute(Normal)]];
+};
+
+// Pushes each vertex out along its normal, as seen on screen, by the width of
+// the outline in pixels. With a width of zero it only places the vertex.
+vertex float4 vertex_outline(OutlineVertexIn vertexIn [[stage_in]],
+                             constant Uniforms &uniforms [[buffer(BufferIndexUniforms)]],
+                             constant Instance *instances [[buffer(BufferIndexInstances)]],
+                             constant OutlineUniforms &outline [[buffer(BufferIndexOutline)]],
+                             uint instanceId [[instance_id]]) {
+  Instance instance = instances[instanceId];
+  float4x4 modelMatrix = uniforms.modelMatrix * instance.modelMatrix;
+  float3x3 normalMatrix = uniforms.normalMatrix * instance.normalMatrix;
+
+  float4 position = uniforms.projectionMatrix * uniforms.viewMatrix * modelMatrix * vertexIn.position;
+  float3 worldNormal = normalMatrix * vertexIn.normal;
+  float2 clipNormal = (uniforms.projectionMatrix * uniforms.viewMatrix * float4(worldNormal, 0)).xy;
+  if (length(clipNormal) > 0) {
+    float2 offset = normalize(clipNormal) * outline.width * 2 / outline.viewportSize;
+    position.xy += offset * position.w;
+  }
+  return position;
+}
+
+fragment float4 fragment_outline(constant OutlineUniforms &outline [[buffer(BufferIndexOutline)]]) {
+  return outline.color;
+}
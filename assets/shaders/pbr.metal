@@ -45,13 +45,20 @@ typedef struct Lighting {
 
 float3 render (Lighting lighting);
 
-vertex VertexOut vertex_main(VertexIn vertexIn [[stage_in]], constant Uniforms &uniforms [[buffer(BufferIndexUniforms)]]) {
+vertex VertexOut vertex_main(VertexIn vertexIn [[stage_in]],
+                             constant Uniforms &uniforms [[buffer(BufferIndexUniforms)]],
+                             constant Instance *instances [[buffer(BufferIndexInstances)]],
+                             uint instanceId [[instance_id]]) {
+  Instance instance = instances[instanceId];
+  float4x4 modelMatrix = uniforms.modelMatrix * instance.modelMatrix;
+  float3x3 normalMatrix = uniforms.normalMatrix * instance.normalMatrix;
+
   VertexOut out {
-    .position = uniforms.projectionMatrix * uniforms.viewMatrix * uniforms.modelMatrix * vertexIn.position,
-    .worldPosition = (uniforms.modelMatrix * vertexIn.position).xyz,
-    .worldNormal = uniforms.normalMatrix * vertexIn.normal,
-    .worldTangent = uniforms.normalMatrix * vertexIn.tangent,
-    .worldBitangent = uniforms.normalMatrix * vertexIn.bitangent,
+    .position = uniforms.projectionMatrix * uniforms.viewMatrix * modelMatrix * vertexIn.position,
+    .worldPosition = (modelMatrix * vertexIn.position).xyz,
+    .worldNormal = normalMatrix * vertexIn.normal,
+    .worldTangent = normalMatrix * vertexIn.tangent,
+    .worldBitangent = normalMatrix * vertexIn.bitangent,
     .uv = vertexIn.uv,
   };
   return out;
@@ -89,8 +96,9 @@ fragment float4 fragment_main(VertexOut in [[stage_in]],
   // extract color
   float3 baseColor;
   if (hasColorTexture) {
-    baseColor = pow(baseColorTexture.sample(textureSampler,
-                                        in.uv * fragmentUniforms.tiling).rgb, 2.2);
+    // sRGB texture, sampled as linear
+    baseColor = baseColorTexture.sample(textureSampler,
+                                        in.uv * fragmentUniforms.tiling).rgb;
   } else {
     baseColor = material.baseColor.rgb;
   }
@@ -125,7 +133,10 @@ fragment float4 fragment_main(VertexOut in [[stage_in]],
   // normal map
   float3 normal;
   if (hasNormalTexture) {
-    float3 normalValue = normalTexture.sample(textureSampler, in.uv * fragmentUniforms.tiling).xyz * 2.0 - 1.0;
+    float3 normalValue;
+    // z is rebuilt from x and y, as two channel (BC5) normal maps do not store it
+    normalValue.xy = normalTexture.sample(textureSampler, in.uv * fragmentUniforms.tiling).xy * 2.0 - 1.0;
+    normalValue.z = sqrt(saturate(1.0 - dot(normalValue.xy, normalValue.xy)));
     normal = in.worldNormal * normalValue.z
     + in.worldTangent * normalValue.x
     + in.worldBitangent * normalValue.y;
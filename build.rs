@@ -15,7 +15,7 @@ fn main() {
 // xcrun -sdk macosx metallib shaders.air -o shaders.metallib
 
 fn compile_shaders() {
-    let shader_files = ["pbr", "environment_map", "brdf"];
+    let shader_files = ["pbr", "environment_map", "brdf", "outline", "gizmo"];
     let shader_air_files: Vec<_> = shader_files
         .iter()
         .map(|each| format!("./assets/shaders/{each}.air"))
@@ -10,6 +10,11 @@ typedef struct {
   matrix_float3x3 normalMatrix;
 } Uniforms;
 
+typedef struct {
+  matrix_float4x4 modelMatrix;
+  matrix_float3x3 normalMatrix;
+} Instance;
+
 typedef enum {
   unused = 0,
   Sunlight = 1,
@@ -36,11 +41,24 @@ typedef struct {
   unsigned int tiling;
 } FragmentUniforms;
 
+typedef struct {
+  vector_float4 color;
+  vector_float2 viewportSize;
+  float width;
+} OutlineUniforms;
+
+typedef struct {
+  vector_float4 position;
+  vector_float4 color;
+} OverlayVertex;
+
 typedef enum {
   BufferIndexVertices = 0,
   BufferIndexLights = 1,
   BufferIndexUniforms = 2,
   BufferIndexFragmentUniforms = 3,
+  BufferIndexInstances = 4,
+  BufferIndexOutline = 5,
   BufferIndexSkybox = 13,
   BufferIndexMaterials = 14
 } BufferIndices;
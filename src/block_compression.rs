@@ -0,0 +1,552 @@
+//! CPU encoders and decoders for the 4x4 block formats textures are baked to.
+//! The decoders only understand the block layouts the encoders write: BC7 mode
+//! 6 and single partition ASTC blocks with 2-bit weights.
+//!
+//! These are not full encoders. One color line per block suits smooth
+//! content, at about 43 dB PSNR for BC7 and 33 dB for ASTC on the gradients
+//! in the tests, but blocks holding several unrelated colors fall to about
+//! 9 dB, where BC7 partitioned modes or multi-partition ASTC would do far
+//! better.
+
+const BLOCK_SIZE: usize = 4;
+const BLOCK_PIXELS: usize = BLOCK_SIZE * BLOCK_SIZE;
+pub const BYTES_PER_BLOCK: usize = 16;
+
+// https://learn.microsoft.com/en-us/windows/win32/direct3d11/bc7-format-mode-reference
+const BC7_MODE_6: u128 = 1 << 6;
+const BC7_WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+// https://registry.khronos.org/DataFormat/specs/1.3/dataformat.1.3.html#ASTC
+/// 4x4 weight grid, single plane, weights quantized to four levels.
+const ASTC_BLOCK_MODE: u128 = 0x42;
+const ASTC_CEM_LDR_RGBA_DIRECT: u128 = 12;
+const ASTC_ENDPOINTS_OFFSET: u32 = 17;
+const ASTC_WEIGHTS: [u32; 4] = [0, 21, 43, 64];
+
+type Block = [[u8; 4]; BLOCK_PIXELS];
+
+/// Encodes RGBA pixels to BC7, all blocks in mode 6.
+pub fn encode_bc7(width: u32, height: u32, pixels: &[[u8; 4]]) -> Vec<u8> {
+    encode_blocks(width, height, pixels, |block| {
+        encode_bc7_block(block).to_le_bytes()
+    })
+}
+
+pub fn decode_bc7(width: u32, height: u32, data: &[u8]) -> Vec<[u8; 4]> {
+    decode_blocks(width, height, data, decode_bc7_block)
+}
+
+/// Encodes the red and green channels to BC5.
+pub fn encode_bc5(width: u32, height: u32, pixels: &[[u8; 4]]) -> Vec<u8> {
+    encode_blocks(width, height, pixels, |block| {
+        let mut bytes = [0; BYTES_PER_BLOCK];
+        for channel in 0..2 {
+            let values = block.map(|pixel| pixel[channel]);
+            bytes[channel * 8..channel * 8 + 8].copy_from_slice(&encode_bc4_block(&values));
+        }
+        bytes
+    })
+}
+
+pub fn decode_bc5(width: u32, height: u32, data: &[u8]) -> Vec<[u8; 4]> {
+    decode_blocks(width, height, data, |block| {
+        let red = decode_bc4_block(&block[..8]);
+        let green = decode_bc4_block(&block[8..]);
+        let mut pixels = [[0, 0, 0, 255]; BLOCK_PIXELS];
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            pixel[0] = red[i];
+            pixel[1] = green[i];
+        }
+        pixels
+    })
+}
+
+/// Encodes RGBA pixels to ASTC with a 4x4 footprint.
+pub fn encode_astc(width: u32, height: u32, pixels: &[[u8; 4]]) -> Vec<u8> {
+    encode_blocks(width, height, pixels, |block| {
+        encode_astc_block(block).to_le_bytes()
+    })
+}
+
+/// `srgb` selects the endpoint expansion of the `_sRGB` formats; the decoded
+/// values are still sRGB encoded.
+pub fn decode_astc(width: u32, height: u32, data: &[u8], srgb: bool) -> Vec<[u8; 4]> {
+    decode_blocks(width, height, data, |block| decode_astc_block(block, srgb))
+}
+
+fn blocks_across(size: u32) -> usize {
+    (size as usize).div_ceil(BLOCK_SIZE)
+}
+
+/// Splits the image in 4x4 blocks, repeating the last row and column where
+/// the size is not a multiple of four.
+fn encode_blocks<F>(width: u32, height: u32, pixels: &[[u8; 4]], encode: F) -> Vec<u8>
+where
+    F: Fn(&Block) -> [u8; BYTES_PER_BLOCK],
+{
+    let (width, height) = (width as usize, height as usize);
+    let block_count = blocks_across(width as u32) * blocks_across(height as u32);
+    let mut data = Vec::with_capacity(block_count * BYTES_PER_BLOCK);
+
+    for block_y in 0..blocks_across(height as u32) {
+        for block_x in 0..blocks_across(width as u32) {
+            let mut block = [[0; 4]; BLOCK_PIXELS];
+            for (i, pixel) in block.iter_mut().enumerate() {
+                let x = (block_x * BLOCK_SIZE + i % BLOCK_SIZE).min(width - 1);
+                let y = (block_y * BLOCK_SIZE + i / BLOCK_SIZE).min(height - 1);
+                *pixel = pixels[y * width + x];
+            }
+            data.extend(encode(&block));
+        }
+    }
+    data
+}
+
+fn decode_blocks<F>(width: u32, height: u32, data: &[u8], decode: F) -> Vec<[u8; 4]>
+where
+    F: Fn(&[u8]) -> Block,
+{
+    let (width, height) = (width as usize, height as usize);
+    let blocks_x = blocks_across(width as u32);
+    let mut pixels = vec![[0; 4]; width * height];
+
+    for (index, bytes) in data.chunks_exact(BYTES_PER_BLOCK).enumerate() {
+        let block = decode(bytes);
+        let (block_x, block_y) = (index % blocks_x, index / blocks_x);
+        for (i, pixel) in block.iter().enumerate() {
+            let x = block_x * BLOCK_SIZE + i % BLOCK_SIZE;
+            let y = block_y * BLOCK_SIZE + i / BLOCK_SIZE;
+            if x < width && y < height {
+                pixels[y * width + x] = *pixel;
+            }
+        }
+    }
+    pixels
+}
+
+fn read_block(bytes: &[u8]) -> u128 {
+    let mut block = [0; BYTES_PER_BLOCK];
+    block.copy_from_slice(&bytes[..BYTES_PER_BLOCK]);
+    u128::from_le_bytes(block)
+}
+
+fn bits(block: u128, offset: u32, count: u32) -> u32 {
+    ((block >> offset) & ((1 << count) - 1)) as u32
+}
+
+/// End points of the line through the block's colors along their principal
+/// axis, found by power iteration on the covariance matrix.
+fn principal_endpoints(block: &Block) -> ([f32; 4], [f32; 4]) {
+    let mut mean = [0.0; 4];
+    for pixel in block {
+        for c in 0..4 {
+            mean[c] += pixel[c] as f32 / BLOCK_PIXELS as f32;
+        }
+    }
+
+    let mut covariance = [[0.0; 4]; 4];
+    for pixel in block {
+        let d: [f32; 4] = std::array::from_fn(|c| pixel[c] as f32 - mean[c]);
+        for (row, &d_row) in covariance.iter_mut().zip(d.iter()) {
+            for (value, &d_column) in row.iter_mut().zip(d.iter()) {
+                *value += d_row * d_column;
+            }
+        }
+    }
+
+    let mut axis = [1.0, 1.0, 1.0, 1.0];
+    for _ in 0..8 {
+        let next: [f32; 4] =
+            std::array::from_fn(|r| (0..4).map(|c| covariance[r][c] * axis[c]).sum());
+        let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if length < f32::EPSILON {
+            break;
+        }
+        axis = next.map(|v| v / length);
+    }
+
+    let (mut low, mut high) = (f32::MAX, f32::MIN);
+    for pixel in block {
+        let t: f32 = (0..4).map(|c| (pixel[c] as f32 - mean[c]) * axis[c]).sum();
+        low = low.min(t);
+        high = high.max(t);
+    }
+
+    let point = |t: f32| std::array::from_fn(|c| (mean[c] + axis[c] * t).clamp(0.0, 255.0));
+    (point(low), point(high))
+}
+
+fn distance(a: &[u8; 4], b: &[u8; 4]) -> u32 {
+    (0..4)
+        .map(|c| {
+            let d = a[c] as i32 - b[c] as i32;
+            (d * d) as u32
+        })
+        .sum()
+}
+
+/// Index of the palette entry closest to each pixel, and the total error.
+fn fit_indices(block: &Block, palette: &[[u8; 4]]) -> ([u8; BLOCK_PIXELS], u32) {
+    let mut indices = [0; BLOCK_PIXELS];
+    let mut error = 0;
+    for (index, pixel) in indices.iter_mut().zip(block.iter()) {
+        let (best, best_error) = palette
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (i, distance(pixel, entry)))
+            .min_by_key(|&(_, error)| error)
+            .unwrap();
+        *index = best as u8;
+        error += best_error;
+    }
+    (indices, error)
+}
+
+fn interpolate(e0: &[u8; 4], e1: &[u8; 4], weight: u32) -> [u8; 4] {
+    std::array::from_fn(|c| {
+        ((e0[c] as u32 * (64 - weight) + e1[c] as u32 * weight + 32) >> 6) as u8
+    })
+}
+
+fn bc7_palette(e0: &[u8; 4], e1: &[u8; 4]) -> Vec<[u8; 4]> {
+    BC7_WEIGHTS
+        .iter()
+        .map(|&weight| interpolate(e0, e1, weight))
+        .collect()
+}
+
+/// Mode 6 has 7-bit end points sharing one extra low bit per end point.
+fn encode_bc7_block(block: &Block) -> u128 {
+    let (low, high) = principal_endpoints(block);
+    let quantize = |value: f32, p_bit: u8| {
+        let q = ((value - p_bit as f32) / 2.0).round().clamp(0.0, 127.0) as u8;
+        q << 1 | p_bit
+    };
+
+    let mut best_error = u32::MAX;
+    let (mut e0, mut e1, mut indices) = ([0; 4], [0; 4], [0; BLOCK_PIXELS]);
+    for p0 in 0..2 {
+        for p1 in 0..2 {
+            let low = low.map(|v| quantize(v, p0));
+            let high = high.map(|v| quantize(v, p1));
+            let (fitted, error) = fit_indices(block, &bc7_palette(&low, &high));
+            if error < best_error {
+                best_error = error;
+                (e0, e1, indices) = (low, high, fitted);
+            }
+        }
+    }
+
+    // the first index is stored without its high bit
+    if indices[0] >= 8 {
+        std::mem::swap(&mut e0, &mut e1);
+        indices = indices.map(|index| 15 - index);
+    }
+
+    let mut block = BC7_MODE_6;
+    let mut offset = 7;
+    for c in 0..4 {
+        for endpoint in [e0, e1] {
+            block |= ((endpoint[c] >> 1) as u128) << offset;
+            offset += 7;
+        }
+    }
+    block |= ((e0[0] & 1) as u128) << offset;
+    block |= ((e1[0] & 1) as u128) << (offset + 1);
+    offset += 2;
+    for (i, &index) in indices.iter().enumerate() {
+        let count = if i == 0 { 3 } else { 4 };
+        block |= (index as u128) << offset;
+        offset += count;
+    }
+    block
+}
+
+fn decode_bc7_block(bytes: &[u8]) -> Block {
+    let block = read_block(bytes);
+    if block & 0x7f != BC7_MODE_6 {
+        return [[0; 4]; BLOCK_PIXELS];
+    }
+
+    let mut e0 = [0; 4];
+    let mut e1 = [0; 4];
+    let mut offset = 7;
+    for c in 0..4 {
+        e0[c] = (bits(block, offset, 7) << 1) as u8;
+        e1[c] = (bits(block, offset + 7, 7) << 1) as u8;
+        offset += 14;
+    }
+    let (p0, p1) = (
+        bits(block, offset, 1) as u8,
+        bits(block, offset + 1, 1) as u8,
+    );
+    offset += 2;
+    let e0 = e0.map(|v| v | p0);
+    let e1 = e1.map(|v| v | p1);
+
+    let palette = bc7_palette(&e0, &e1);
+    let mut pixels = [[0; 4]; BLOCK_PIXELS];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let count = if i == 0 { 3 } else { 4 };
+        *pixel = palette[bits(block, offset, count) as usize];
+        offset += count;
+    }
+    pixels
+}
+
+fn bc4_palette(e0: u8, e1: u8) -> [u8; 8] {
+    let (e0, e1) = (e0 as u32, e1 as u32);
+    let mut palette = [e0 as u8, e1 as u8, 0, 0, 0, 0, 0, 255];
+    if e0 > e1 {
+        for (i, value) in palette.iter_mut().enumerate().skip(2) {
+            let i = i as u32;
+            *value = (((8 - i) * e0 + (i - 1) * e1 + 3) / 7) as u8;
+        }
+    } else {
+        for (i, value) in palette.iter_mut().enumerate().skip(2).take(4) {
+            let i = i as u32;
+            *value = (((6 - i) * e0 + (i - 1) * e1 + 2) / 5) as u8;
+        }
+    }
+    palette
+}
+
+fn encode_bc4_block(values: &[u8; BLOCK_PIXELS]) -> [u8; 8] {
+    let high = *values.iter().max().unwrap();
+    let low = *values.iter().min().unwrap();
+    let palette = bc4_palette(high, low);
+
+    let mut bytes = [high, low, 0, 0, 0, 0, 0, 0];
+    let mut indices = 0u64;
+    for (i, &value) in values.iter().enumerate() {
+        let index = (0..8)
+            .min_by_key(|&index| (palette[index] as i32 - value as i32).abs())
+            .unwrap();
+        indices |= (index as u64) << (i * 3);
+    }
+    bytes[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+    bytes
+}
+
+fn decode_bc4_block(bytes: &[u8]) -> [u8; BLOCK_PIXELS] {
+    let palette = bc4_palette(bytes[0], bytes[1]);
+    let mut index_bytes = [0; 8];
+    index_bytes[..6].copy_from_slice(&bytes[2..8]);
+    let indices = u64::from_le_bytes(index_bytes);
+    std::array::from_fn(|i| palette[((indices >> (i * 3)) & 7) as usize])
+}
+
+fn astc_endpoint_sum(endpoint: &[u8; 4]) -> u32 {
+    endpoint[..3].iter().map(|&v| v as u32).sum()
+}
+
+fn astc_interpolate(e0: &[u8; 4], e1: &[u8; 4], weight: u32, srgb: bool) -> [u8; 4] {
+    let expand = |value: u8| {
+        if srgb {
+            (value as u32) << 8 | 0x80
+        } else {
+            value as u32 * 257
+        }
+    };
+
+    std::array::from_fn(|c| {
+        let value = (expand(e0[c]) * (64 - weight) + expand(e1[c]) * weight + 32) >> 6;
+        if srgb {
+            (value >> 8) as u8
+        } else {
+            ((value * 255 + 32767) / 65535) as u8
+        }
+    })
+}
+
+fn encode_astc_block(block: &Block) -> u128 {
+    let (low, high) = principal_endpoints(block);
+    let mut e0 = low.map(|v| v.round() as u8);
+    let mut e1 = high.map(|v| v.round() as u8);
+
+    // a decreasing sum would make the decoder apply blue contraction
+    if astc_endpoint_sum(&e1) < astc_endpoint_sum(&e0) {
+        std::mem::swap(&mut e0, &mut e1);
+    }
+
+    let palette: Vec<[u8; 4]> = ASTC_WEIGHTS
+        .iter()
+        .map(|&weight| astc_interpolate(&e0, &e1, weight, false))
+        .collect();
+    let (weights, _) = fit_indices(block, &palette);
+
+    let mut block = ASTC_BLOCK_MODE | ASTC_CEM_LDR_RGBA_DIRECT << 13;
+    for c in 0..4 {
+        block |= (e0[c] as u128) << (ASTC_ENDPOINTS_OFFSET + c as u32 * 16);
+        block |= (e1[c] as u128) << (ASTC_ENDPOINTS_OFFSET + c as u32 * 16 + 8);
+    }
+    // weights are stored bit reversed from the top of the block
+    for (i, &weight) in weights.iter().enumerate() {
+        for bit in 0..2 {
+            if weight >> bit & 1 == 1 {
+                block |= 1 << (127 - (i * 2 + bit));
+            }
+        }
+    }
+    block
+}
+
+fn decode_astc_block(bytes: &[u8], srgb: bool) -> Block {
+    let block = read_block(bytes);
+    if block & 0x1fff != ASTC_BLOCK_MODE || (block >> 13) & 0xf != ASTC_CEM_LDR_RGBA_DIRECT {
+        return [[0; 4]; BLOCK_PIXELS];
+    }
+
+    let mut e0 = [0; 4];
+    let mut e1 = [0; 4];
+    for c in 0..4 {
+        e0[c] = bits(block, ASTC_ENDPOINTS_OFFSET + c as u32 * 16, 8) as u8;
+        e1[c] = bits(block, ASTC_ENDPOINTS_OFFSET + c as u32 * 16 + 8, 8) as u8;
+    }
+
+    std::array::from_fn(|i| {
+        let high = bits(block, 127 - (i as u32 * 2 + 1), 1);
+        let low = bits(block, 127 - i as u32 * 2, 1);
+        astc_interpolate(&e0, &e1, ASTC_WEIGHTS[(high << 1 | low) as usize], srgb)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smooth, correlated channels with a little noise, like most textures.
+    fn gradient(width: u32, height: u32) -> Vec<[u8; 4]> {
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let t = ((x * 8 + y * 7) % 256) as u8;
+                let noise = (i.wrapping_mul(2654435761) >> 29) as u8;
+                [t.saturating_add(noise), t / 2 + 40, 255 - t, 200 - t / 4]
+            })
+            .collect()
+    }
+
+    /// Hard blocks: four unrelated colors in every block.
+    fn checker(width: u32, height: u32) -> Vec<[u8; 4]> {
+        const COLORS: [[u8; 4]; 4] = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 255, 0],
+        ];
+        (0..width * height)
+            .map(|i| COLORS[((i % width) % 2 + (i / width) % 2 * 2) as usize])
+            .collect()
+    }
+
+    fn psnr(expected: &[[u8; 4]], actual: &[[u8; 4]], channels: usize) -> f64 {
+        let mut squared_error = 0.0;
+        for (a, b) in expected.iter().zip(actual) {
+            for channel in 0..channels {
+                squared_error += (a[channel] as f64 - b[channel] as f64).powi(2);
+            }
+        }
+        let mse = squared_error / (expected.len() * channels) as f64;
+        if mse == 0.0 {
+            f64::INFINITY
+        } else {
+            10.0 * (255.0 * 255.0 / mse).log10()
+        }
+    }
+
+    fn max_error(expected: &[[u8; 4]], actual: &[[u8; 4]]) -> i32 {
+        expected
+            .iter()
+            .zip(actual)
+            .flat_map(|(a, b)| {
+                (0..4).map(move |channel| (a[channel] as i32 - b[channel] as i32).abs())
+            })
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn bc7_round_trips_within_bounds() {
+        for (width, height) in [(64, 64), (13, 7), (2, 2), (1, 1)] {
+            let pixels = gradient(width, height);
+            let data = encode_bc7(width, height, &pixels);
+            assert_eq!(
+                data.len(),
+                blocks_across(width) * blocks_across(height) * BYTES_PER_BLOCK
+            );
+
+            let decoded = decode_bc7(width, height, &data);
+            assert_eq!(decoded.len(), pixels.len());
+            let psnr = psnr(&pixels, &decoded, 4);
+            assert!(psnr > 40.0, "{}x{}: {:.1} dB", width, height, psnr);
+        }
+
+        // A single line through color space cannot fit four corners.
+        let pixels = checker(16, 16);
+        let psnr = psnr(
+            &pixels,
+            &decode_bc7(16, 16, &encode_bc7(16, 16, &pixels)),
+            4,
+        );
+        assert!(psnr > 8.0, "{:.1} dB", psnr);
+    }
+
+    #[test]
+    fn bc7_keeps_flat_blocks() {
+        // 7-bit end points and a shared low bit are off by at most one.
+        let pixels = vec![[10, 200, 30, 255]; 16];
+        let decoded = decode_bc7(4, 4, &encode_bc7(4, 4, &pixels));
+        assert!(max_error(&pixels, &decoded) <= 1, "{:?}", decoded[0]);
+        assert_eq!(read_block(&encode_bc7(4, 4, &pixels)) & 0x7f, BC7_MODE_6);
+    }
+
+    #[test]
+    fn bc5_round_trips_within_bounds() {
+        let pixels = gradient(16, 12);
+        let decoded = decode_bc5(16, 12, &encode_bc5(16, 12, &pixels));
+        let psnr = psnr(&pixels, &decoded, 2);
+        assert!(psnr > 38.0, "{:.1} dB", psnr);
+        assert!(decoded.iter().all(|pixel| pixel[2] == 0 && pixel[3] == 255));
+
+        let flat = vec![[0, 255, 0, 0]; 16];
+        let decoded = decode_bc5(4, 4, &encode_bc5(4, 4, &flat));
+        assert!(decoded.iter().all(|pixel| pixel[..2] == [0, 255]));
+    }
+
+    #[test]
+    fn astc_round_trips_within_bounds() {
+        for (width, height) in [(64, 64), (13, 7), (1, 1)] {
+            let pixels = gradient(width, height);
+            let data = encode_astc(width, height, &pixels);
+            assert_eq!(
+                data.len(),
+                blocks_across(width) * blocks_across(height) * BYTES_PER_BLOCK
+            );
+
+            let decoded = decode_astc(width, height, &data, false);
+            let psnr = psnr(&pixels, &decoded, 4);
+            assert!(psnr > 30.0, "{}x{}: {:.1} dB", width, height, psnr);
+        }
+
+        let pixels = checker(16, 16);
+        let psnr = psnr(
+            &pixels,
+            &decode_astc(16, 16, &encode_astc(16, 16, &pixels), false),
+            4,
+        );
+        assert!(psnr > 8.0, "{:.1} dB", psnr);
+    }
+
+    #[test]
+    fn astc_keeps_flat_blocks() {
+        let pixels = vec![[250, 20, 100, 128]; 16];
+        let data = encode_astc(4, 4, &pixels);
+        assert_eq!(decode_astc(4, 4, &data, false), pixels);
+        assert_eq!(decode_astc(4, 4, &data, true), pixels);
+
+        let block = read_block(&data);
+        assert_eq!(block & 0x7ff, ASTC_BLOCK_MODE);
+        assert_eq!(bits(block, 13, 4) as u128, ASTC_CEM_LDR_RGBA_DIRECT);
+    }
+}
@@ -0,0 +1,275 @@
+use glam::{const_vec3, Mat4, Vec3};
+
+/// Axis-aligned bounding box. An empty box has `min > max`, so it grows to
+/// exactly the first point or box it is joined with.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl Bounds {
+    pub const EMPTY: Bounds = Bounds {
+        min: const_vec3!([f32::INFINITY; 3]),
+        max: const_vec3!([f32::NEG_INFINITY; 3]),
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points
+            .into_iter()
+            .fold(Self::EMPTY, |bounds, point| bounds.with_point(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn with_point(self, point: Vec3) -> Self {
+        Self {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn union(self, other: Bounds) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// The box around this one after `matrix` is applied to it.
+    pub fn transform(&self, matrix: Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        Self::from_points(
+            self.corners()
+                .iter()
+                .map(|&corner| matrix.transform_point3(corner)),
+        )
+    }
+}
+
+/// A sphere around a set of points. Empty spheres have a negative radius.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Default for BoundingSphere {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl BoundingSphere {
+    pub const EMPTY: BoundingSphere = BoundingSphere {
+        center: const_vec3!([0.0; 3]),
+        radius: -1.0,
+    };
+
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// Centered on the box around `points`, which is tighter than the sphere
+    /// around the box for all but box-shaped meshes.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3> + Clone) -> Self {
+        let bounds = Bounds::from_points(points.clone());
+        if bounds.is_empty() {
+            return Self::EMPTY;
+        }
+        let center = bounds.center();
+        let radius = points
+            .into_iter()
+            .map(|point| point.distance_squared(center))
+            .fold(0.0, f32::max)
+            .sqrt();
+        Self { center, radius }
+    }
+
+    pub fn from_bounds(bounds: &Bounds) -> Self {
+        if bounds.is_empty() {
+            return Self::EMPTY;
+        }
+        Self {
+            center: bounds.center(),
+            radius: bounds.size().length() * 0.5,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.radius < 0.0
+    }
+
+    /// The smallest sphere around both spheres.
+    pub fn union(self, other: BoundingSphere) -> Self {
+        if self.is_empty() {
+            return other;
+        }
+        if other.is_empty() {
+            return self;
+        }
+
+        let offset = other.center - self.center;
+        let distance = offset.length();
+        if distance + other.radius <= self.radius {
+            return self;
+        }
+        if distance + self.radius <= other.radius {
+            return other;
+        }
+
+        let radius = (distance + self.radius + other.radius) * 0.5;
+        let center = self.center + offset * ((radius - self.radius) / distance);
+        Self { center, radius }
+    }
+
+    /// The sphere around this one after `matrix` is applied to it. Non-uniform
+    /// scales grow the radius by the largest axis scale.
+    pub fn transform(&self, matrix: Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        let scale = matrix
+            .x_axis
+            .truncate()
+            .length()
+            .max(matrix.y_axis.truncate().length())
+            .max(matrix.z_axis.truncate().length());
+        Self {
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    #[test]
+    fn boxes_grow_around_points() {
+        assert!(Bounds::EMPTY.is_empty());
+        assert!(Bounds::from_points(Vec::<Vec3>::new()).is_empty());
+
+        let bounds = Bounds::from_points([Vec3::new(1.0, 2.0, 3.0), Vec3::new(-1.0, 0.0, 5.0)]);
+        assert_eq!(bounds.min, Vec3::new(-1.0, 0.0, 3.0));
+        assert_eq!(bounds.max, Vec3::new(1.0, 2.0, 5.0));
+        assert_eq!(bounds.center(), Vec3::new(0.0, 1.0, 4.0));
+        assert_eq!(bounds.size(), Vec3::new(2.0, 2.0, 2.0));
+        assert_eq!(Bounds::EMPTY.union(bounds), bounds);
+        assert_eq!(bounds.union(Bounds::EMPTY), bounds);
+    }
+
+    #[test]
+    fn transformed_boxes_contain_transformed_corners() {
+        let bounds = Bounds::new(Vec3::new(-1.0, 0.0, 3.0), Vec3::new(1.0, 2.0, 5.0));
+        let moved = bounds.transform(Mat4::from_translation(Vec3::X));
+        assert_eq!(moved.max, Vec3::new(2.0, 2.0, 5.0));
+        assert!(Bounds::EMPTY
+            .transform(Mat4::from_scale(Vec3::splat(2.0)))
+            .is_empty());
+
+        // a child node below a rotated and scaled parent
+        let parent = Mat4::from_scale_rotation_translation(
+            Vec3::splat(2.0),
+            Quat::from_rotation_y(0.6),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let child = Mat4::from_rotation_z(1.2) * Mat4::from_translation(Vec3::new(3.0, 0.0, 0.0));
+        let world = parent * child;
+        let transformed = bounds.transform(world);
+        for corner in bounds.corners() {
+            let corner = world.transform_point3(corner);
+            assert!(corner.cmpge(transformed.min - 1e-5).all());
+            assert!(corner.cmple(transformed.max + 1e-5).all());
+        }
+
+        // boxes transformed one node at a time are looser, never tighter
+        let stepwise = bounds.transform(child).transform(parent);
+        assert!(stepwise.min.cmple(transformed.min + 1e-5).all());
+        assert!(stepwise.max.cmpge(transformed.max - 1e-5).all());
+    }
+
+    #[test]
+    fn spheres_are_centered_on_the_box() {
+        let sphere = BoundingSphere::from_points([
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.5, 0.0),
+        ]);
+        assert_eq!(sphere.center, Vec3::new(0.0, 0.25, 0.0));
+        assert!((sphere.radius - 1.0625f32.sqrt()).abs() < 1e-6);
+        assert!(BoundingSphere::from_points(Vec::<Vec3>::new()).is_empty());
+
+        let bounds = Bounds::new(Vec3::ZERO, Vec3::new(2.0, 2.0, 1.0));
+        let sphere = BoundingSphere::from_bounds(&bounds);
+        assert_eq!(sphere.center, Vec3::new(1.0, 1.0, 0.5));
+        assert_eq!(sphere.radius, 1.5);
+        assert!(BoundingSphere::from_bounds(&Bounds::EMPTY).is_empty());
+    }
+
+    #[test]
+    fn sphere_unions() {
+        let a = BoundingSphere::new(Vec3::ZERO, 1.0);
+        let b = BoundingSphere::new(Vec3::new(4.0, 0.0, 0.0), 1.0);
+        let union = a.union(b);
+        assert_eq!(union.center, Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(union.radius, 3.0);
+        assert_eq!(a.union(BoundingSphere::new(Vec3::X * 0.2, 0.5)), a);
+        assert_eq!(BoundingSphere::new(Vec3::X * 0.2, 0.5).union(a), a);
+        assert_eq!(BoundingSphere::EMPTY.union(b), b);
+        assert_eq!(b.union(BoundingSphere::EMPTY), b);
+    }
+
+    #[test]
+    fn transformed_spheres_grow_by_the_largest_scale() {
+        let sphere = BoundingSphere::new(Vec3::ZERO, 1.0);
+        let transformed = sphere.transform(Mat4::from_scale_rotation_translation(
+            Vec3::new(1.0, 3.0, 2.0),
+            Quat::from_rotation_y(1.0),
+            Vec3::Y,
+        ));
+        assert_eq!(transformed.center, Vec3::Y);
+        assert!((transformed.radius - 3.0).abs() < 1e-5);
+        assert!(BoundingSphere::EMPTY
+            .transform(Mat4::from_translation(Vec3::X))
+            .is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+use crate::bounds::Bounds;
+use crate::frustum::Frustum;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+/// Items a leaf holds at most, unless they cannot be told apart.
+const MAX_LEAF_ITEMS: usize = 4;
+
+/// A box around part of the items. The items under a node are contiguous in
+/// `Bvh::items`, so a node inside a query takes all of them at once.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct BvhNode {
+    bounds: Bounds,
+    start: usize,
+    end: usize,
+    /// The second child of an inner node. The first one follows the node.
+    second_child: Option<usize>,
+}
+
+/// A bounding volume hierarchy: a binary tree of boxes over items that each
+/// have bounds, split at the median along the longest axis.
+#[derive(Debug, Clone)]
+pub struct Bvh<T> {
+    nodes: Vec<BvhNode>,
+    items: Vec<(Bounds, T)>,
+}
+
+impl<T> Default for Bvh<T> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            items: vec![],
+        }
+    }
+}
+
+impl<T> Bvh<T> {
+    /// Builds the tree over `items`. Items with empty bounds are left out.
+    pub fn new(items: impl IntoIterator<Item = (Bounds, T)>) -> Self {
+        let mut bvh = Self {
+            nodes: vec![],
+            items: items
+                .into_iter()
+                .filter(|(bounds, _)| !bounds.is_empty())
+                .collect(),
+        };
+        if !bvh.items.is_empty() {
+            bvh.build(0..bvh.items.len());
+        }
+        bvh
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The box around every item.
+    pub fn bounds(&self) -> Bounds {
+        self.nodes.first().map_or(Bounds::EMPTY, |root| root.bounds)
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &(Bounds, T)> {
+        self.items.iter()
+    }
+
+    /// Calls `visit` with every item whose bounds may be inside `frustum`.
+    /// Items under a node entirely inside it are taken without testing.
+    pub fn query_frustum(&self, frustum: &Frustum, mut visit: impl FnMut(&T)) {
+        self.query(
+            |bounds| {
+                if frustum.contains_bounds(bounds) {
+                    Overlap::Inside
+                } else if frustum.intersects_bounds(bounds) {
+                    Overlap::Partial
+                } else {
+                    Overlap::Outside
+                }
+            },
+            |_, item| visit(item),
+        );
+    }
+
+    /// Walks the tree, skipping the nodes `overlap` finds outside, and calls
+    /// `visit` with the items of the leaves it reaches and of the nodes it
+    /// finds inside.
+    pub(crate) fn query(
+        &self,
+        mut overlap: impl FnMut(&Bounds) -> Overlap,
+        mut visit: impl FnMut(&Bounds, &T),
+    ) {
+        let mut stack = match self.nodes.is_empty() {
+            true => vec![],
+            false => vec![0],
+        };
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let take_all = match overlap(&node.bounds) {
+                Overlap::Outside => continue,
+                Overlap::Inside => true,
+                Overlap::Partial => false,
+            };
+            match node.second_child {
+                Some(second_child) if !take_all => {
+                    stack.push(second_child);
+                    stack.push(index + 1);
+                }
+                _ => {
+                    for (bounds, item) in self.items[node.start..node.end].iter() {
+                        if take_all || overlap(bounds) != Overlap::Outside {
+                            visit(bounds, item);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Gives every item the bounds `bounds` returns for it and fits the boxes
+    /// of the tree around them again, keeping its shape. Cheaper than
+    /// building the tree anew, but queries slow down as items move far from
+    /// where they were when it was built.
+    pub fn refit(&mut self, mut bounds: impl FnMut(&T) -> Bounds) {
+        for (item_bounds, item) in self.items.iter_mut() {
+            *item_bounds = bounds(item);
+        }
+        // children come after their parent
+        for index in (0..self.nodes.len()).rev() {
+            let node = self.nodes[index];
+            self.nodes[index].bounds = match node.second_child {
+                Some(second_child) => self.nodes[index + 1]
+                    .bounds
+                    .union(self.nodes[second_child].bounds),
+                None => self.items[node.start..node.end]
+                    .iter()
+                    .fold(Bounds::EMPTY, |bounds, (item, _)| bounds.union(*item)),
+            };
+        }
+    }
+
+    /// Adds the node over `range` and the nodes below it.
+    fn build(&mut self, range: Range<usize>) {
+        let items = &mut self.items[range.clone()];
+        let bounds = items
+            .iter()
+            .fold(Bounds::EMPTY, |bounds, (item, _)| bounds.union(*item));
+        let centers = items.iter().fold(Bounds::EMPTY, |centers, (item, _)| {
+            centers.with_point(item.center())
+        });
+
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            start: range.start,
+            end: range.end,
+            second_child: None,
+        });
+
+        let extent = centers.size();
+        if items.len() <= MAX_LEAF_ITEMS || extent.max_element() <= 0.0 {
+            return;
+        }
+
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let middle = items.len() / 2;
+        items.select_nth_unstable_by(middle, |(a, _), (b, _)| {
+            a.center()[axis]
+                .partial_cmp(&b.center()[axis])
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let middle = range.start + middle;
+        self.build(range.start..middle);
+        self.nodes[index].second_child = Some(self.nodes.len());
+        self.build(middle..range.end);
+    }
+}
+
+/// Where a box is relative to a query volume.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Overlap {
+    Outside,
+    Partial,
+    Inside,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{Mat4, Vec3};
+
+    fn view_projection() -> Mat4 {
+        let projection = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let view = Mat4::look_at_lh(Vec3::new(0.0, 0.0, -5.0), Vec3::ZERO, Vec3::Y);
+        projection * view
+    }
+
+    /// A grid of unit boxes from -30 to 30 on each axis, with a simple
+    /// deterministic jitter.
+    fn grid() -> Vec<(Bounds, usize)> {
+        let mut items = vec![];
+        let mut seed = 1u32;
+        for x in -6..=6 {
+            for y in -6..=6 {
+                for z in -6..=6 {
+                    seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                    let jitter = (seed >> 8) as f32 / (1 << 24) as f32;
+                    let center = Vec3::new(x as f32, y as f32, z as f32) * 5.0 + jitter;
+                    let half = 0.5 + jitter;
+                    items.push((Bounds::new(center - half, center + half), items.len()));
+                }
+            }
+        }
+        items
+    }
+
+    #[test]
+    fn frustum_query_matches_brute_force() {
+        let items = grid();
+        let bvh = Bvh::new(items.clone());
+        assert_eq!(bvh.len(), items.len());
+        let frustum = Frustum::from_matrix(view_projection());
+
+        let mut found = vec![];
+        bvh.query_frustum(&frustum, |&index| found.push(index));
+        found.sort_unstable();
+        let expected: Vec<usize> = items
+            .iter()
+            .filter(|(bounds, _)| frustum.intersects_bounds(bounds))
+            .map(|&(_, index)| index)
+            .collect();
+        assert!(!expected.is_empty() && expected.len() < items.len());
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn root_bounds_cover_every_item() {
+        let items = grid();
+        let bvh = Bvh::new(items.clone());
+        let all = items
+            .iter()
+            .fold(Bounds::EMPTY, |all, (bounds, _)| all.union(*bounds));
+        assert_eq!(bvh.bounds(), all);
+    }
+
+    #[test]
+    fn empty_and_degenerate_trees() {
+        let frustum = Frustum::from_matrix(view_projection());
+        let empty: Bvh<usize> = Bvh::new(vec![(Bounds::EMPTY, 0)]);
+        assert!(empty.is_empty());
+        assert!(empty.bounds().is_empty());
+        empty.query_frustum(&frustum, |_| panic!("nothing to visit"));
+
+        // every item at the same place ends up in one leaf
+        let unit = Bounds::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+        let stacked = Bvh::new((0..100).map(|index| (unit, index)));
+        let mut count = 0;
+        stacked.query_frustum(&frustum, |_| count += 1);
+        assert_eq!(count, 100);
+    }
+
+    #[test]
+    fn refit_matches_a_new_tree() {
+        let items = grid();
+        let mut bvh = Bvh::new(items.clone());
+        let offset = |index: usize| Vec3::new(index as f32 % 7.0, -(index as f32 % 3.0), 2.0);
+        let moved: Vec<(Bounds, usize)> = items
+            .iter()
+            .map(|&(bounds, index)| {
+                (
+                    Bounds::new(bounds.min + offset(index), bounds.max + offset(index)),
+                    index,
+                )
+            })
+            .collect();
+        bvh.refit(|&index| moved[index].0);
+        assert_eq!(bvh.bounds(), Bvh::new(moved.clone()).bounds());
+
+        let frustum = Frustum::from_matrix(view_projection());
+        let mut found = vec![];
+        bvh.query_frustum(&frustum, |&index| found.push(index));
+        found.sort_unstable();
+        let expected: Vec<usize> = moved
+            .iter()
+            .filter(|(bounds, _)| frustum.intersects_bounds(bounds))
+            .map(|&(_, index)| index)
+            .collect();
+        assert_eq!(found, expected);
+    }
+}
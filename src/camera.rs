@@ -1,5 +1,8 @@
-use crate::node::InnerNode;
-use glam::{Mat3, Mat4, Vec3};
+use crate::bounds::BoundingSphere;
+use crate::model::Model;
+use crate::node::{InnerNode, SceneGraph};
+use crate::scene_file::CameraDescription;
+use glam::{Mat4, Vec3};
 
 pub trait CameraFunction {
     fn zoom(&mut self, delta: f32);
@@ -49,6 +52,13 @@ impl Camera {
         self.fov_degrees.to_radians()
     }
 
+    /// The smaller of the vertical and horizontal field of view.
+    pub fn min_fov_radians(&self) -> f32 {
+        let vertical = self.fov_radians();
+        let horizontal = 2.0 * ((vertical / 2.0).tan() * self.aspect_ratio).atan();
+        vertical.min(horizontal)
+    }
+
     pub fn projection_matrix(&self) -> Mat4 {
         Mat4::perspective_lh(
             self.fov_radians(),
@@ -59,12 +69,7 @@ impl Camera {
     }
 
     pub fn view_matrix(&self) -> Mat4 {
-        let translate_matrix = Mat4::from_translation(self.inner_node.position);
-        let rotate_matrix = Mat4::from_rotation_x(self.inner_node.rotation.x)
-            * Mat4::from_rotation_y(self.inner_node.rotation.y)
-            * Mat4::from_rotation_z(self.inner_node.rotation.z);
-        let scale_matrix = Mat4::from_scale(self.inner_node.scale);
-        (translate_matrix * scale_matrix * rotate_matrix).inverse()
+        self.inner_node.model_matrix().inverse()
     }
 }
 
@@ -73,6 +78,8 @@ pub struct ArcballCamera {
     max_distance: f32,
     target: Vec3,
     distance: f32,
+    /// Pitch around X and yaw around Y, in radians.
+    rotation: Vec3,
     camera: Camera,
     view_matrix: Mat4,
 }
@@ -84,6 +91,7 @@ impl ArcballCamera {
             max_distance,
             target,
             distance,
+            rotation: Vec3::ZERO,
             camera: Camera::default(),
             view_matrix: Mat4::IDENTITY,
         };
@@ -92,6 +100,39 @@ impl ArcballCamera {
         camera
     }
 
+    /// A camera as stored in a scene file. The clipping planes are moved to
+    /// fit the range of distances, as `frame` does.
+    pub fn from_description(description: &CameraDescription) -> Self {
+        let mut camera = Self::new(
+            description.min_distance,
+            description.max_distance,
+            Vec3::from(description.target),
+            description.distance,
+        );
+        camera.camera.fov_degrees = description.fov;
+        camera.camera.z_near = camera.camera.z_near.min(description.min_distance * 0.1);
+        camera.camera.z_far = camera.camera.z_far.max(description.max_distance * 2.0);
+        camera.set_rotation(Vec3::new(
+            description.pitch.to_radians(),
+            description.yaw.to_radians(),
+            0.0,
+        ));
+        camera
+    }
+
+    /// The camera as stored in a scene file.
+    pub fn description(&self) -> CameraDescription {
+        CameraDescription {
+            target: self.target.to_array(),
+            distance: self.distance,
+            min_distance: self.min_distance,
+            max_distance: self.max_distance,
+            pitch: self.rotation.x.to_degrees(),
+            yaw: self.rotation.y.to_degrees(),
+            fov: self.camera.fov_degrees,
+        }
+    }
+
     pub fn set_target(&mut self, target: Vec3) {
         self.target = target;
         self.view_matrix = self.update_view_matrix();
@@ -103,7 +144,7 @@ impl ArcballCamera {
     }
 
     pub fn set_rotation(&mut self, rotation: Vec3) {
-        self.camera.inner_node.rotation = rotation;
+        self.rotation = rotation;
         self.view_matrix = self.update_view_matrix();
     }
 
@@ -116,7 +157,7 @@ impl ArcballCamera {
     }
 
     pub fn rotation(&self) -> &Vec3 {
-        &self.camera.inner_node.rotation
+        &self.rotation
     }
 
     pub fn position(&self) -> &Vec3 {
@@ -131,19 +172,74 @@ impl ArcballCamera {
         self.camera.projection_matrix()
     }
 
+    pub fn fov_radians(&self) -> f32 {
+        self.camera.fov_radians()
+    }
+
+    pub fn target(&self) -> Vec3 {
+        self.target
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// Moves the target to the center of `sphere` and backs off until the
+    /// whole sphere is in view, keeping the current rotation.
+    pub fn frame(&mut self, sphere: BoundingSphere) {
+        if sphere.is_empty() {
+            return;
+        }
+
+        // a little room around the edges
+        let radius = sphere.radius.max(f32::EPSILON) * 1.1;
+        let distance = radius / (self.camera.min_fov_radians() / 2.0).sin();
+
+        self.min_distance = self.min_distance.min(radius * 0.1);
+        self.max_distance = self.max_distance.max(distance * 4.0);
+        self.camera.z_near = self.camera.z_near.min(radius * 0.01);
+        self.camera.z_far = self.camera.z_far.max((distance + radius) * 2.0);
+
+        self.target = sphere.center;
+        self.distance = distance;
+        self.view_matrix = self.update_view_matrix();
+    }
+
+    /// Frames every model in world space.
+    pub fn frame_all(&mut self, models: &[Model], scene_graph: &SceneGraph) {
+        let sphere = models.iter().fold(BoundingSphere::EMPTY, |sphere, model| {
+            sphere.union(model.world_bounding_sphere(scene_graph))
+        });
+        self.frame(sphere);
+    }
+
+    /// Frames the models at the indices in `selection`.
+    pub fn frame_selection(
+        &mut self,
+        models: &[Model],
+        scene_graph: &SceneGraph,
+        selection: &[usize],
+    ) {
+        let sphere = selection
+            .iter()
+            .filter_map(|&index| models.get(index))
+            .fold(BoundingSphere::EMPTY, |sphere, model| {
+                sphere.union(model.world_bounding_sphere(scene_graph))
+            });
+        self.frame(sphere);
+    }
+
+    /// Orbits `target` at `distance`, placing the camera node there.
     fn update_view_matrix(&mut self) -> Mat4 {
-        let translate_matrix = Mat4::from_translation(Vec3::new(
-            self.target.x,
-            self.target.y,
-            self.target.z - self.distance,
-        ));
         let rotate_matrix = Mat4::from_rotation_y(self.rotation().y)
             * Mat4::from_rotation_x(-self.rotation().x)
             * Mat4::from_rotation_z(0.0);
-        let matrix = (rotate_matrix * translate_matrix).inverse();
-        self.set_position(Mat3::from_mat4(rotate_matrix) * -matrix.col(3).truncate());
+        let camera_matrix = Mat4::from_translation(self.target)
+            * rotate_matrix
+            * Mat4::from_translation(Vec3::new(0.0, 0.0, -self.distance));
+        self.camera.inner_node.set_matrix(camera_matrix);
 
-        matrix
+        self.camera.view_matrix()
     }
 }
 
@@ -159,10 +255,9 @@ impl CameraFunction for ArcballCamera {
 
         rotation.y += delta.0.to_radians() * sensitivity;
         rotation.x += -delta.1.to_radians() * sensitivity;
-        rotation.x = f32::max(
-            -std::f32::consts::PI / 2.0,
-            f32::min(rotation.x, std::f32::consts::PI / 2.0),
-        );
+        rotation.x = rotation
+            .x
+            .clamp(-std::f32::consts::PI / 2.0, std::f32::consts::PI / 2.0);
         self.set_rotation(rotation);
     }
 }
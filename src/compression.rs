@@ -0,0 +1,854 @@
+use serde::Deserialize;
+use std::fmt;
+
+// https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Vendor/EXT_meshopt_compression
+const VERTEX_HEADER: u8 = 0xa0;
+const INDEX_HEADER: u8 = 0xe0;
+const SEQUENCE_HEADER: u8 = 0xd0;
+
+const VERTEX_BLOCK_SIZE_BYTES: usize = 8192;
+const VERTEX_BLOCK_MAX_SIZE: usize = 256;
+const BYTE_GROUP_SIZE: usize = 16;
+const TAIL_MAX_SIZE: usize = 32;
+
+const CODE_AUX_TABLE_SIZE: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MeshoptMode {
+    Attributes,
+    Triangles,
+    Indices,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MeshoptFilter {
+    #[default]
+    None,
+    Octahedral,
+    Quaternion,
+    Exponential,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    InvalidHeader(u8),
+    UnsupportedVersion(u8),
+    InvalidStride(usize),
+    UnexpectedEnd,
+    TrailingData,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidHeader(header) => write!(f, "invalid header byte 0x{:02x}", header),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported codec version {}", version)
+            }
+            DecodeError::InvalidStride(stride) => write!(f, "invalid byte stride {}", stride),
+            DecodeError::UnexpectedEnd => write!(f, "compressed data ended unexpectedly"),
+            DecodeError::TrailingData => write!(f, "compressed data has trailing bytes"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decodes an `EXT_meshopt_compression` buffer view into `count * stride`
+/// bytes of plain glTF data, applying the filter for attribute streams.
+pub fn decode_meshopt(
+    source: &[u8],
+    count: usize,
+    stride: usize,
+    mode: MeshoptMode,
+    filter: MeshoptFilter,
+) -> Result<Vec<u8>, DecodeError> {
+    match mode {
+        MeshoptMode::Attributes => {
+            let mut data = decode_vertex_buffer(source, count, stride)?;
+            apply_filter(&mut data, count, stride, filter)?;
+            Ok(data)
+        }
+        MeshoptMode::Triangles => decode_index_buffer(source, count, stride),
+        MeshoptMode::Indices => decode_index_sequence(source, count, stride),
+    }
+}
+
+pub fn decode_vertex_buffer(
+    source: &[u8],
+    vertex_count: usize,
+    vertex_size: usize,
+) -> Result<Vec<u8>, DecodeError> {
+    if vertex_size == 0 || vertex_size > 256 || !vertex_size.is_multiple_of(4) {
+        return Err(DecodeError::InvalidStride(vertex_size));
+    }
+    if source.len() < 1 + vertex_size {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let header = source[0];
+    if header & 0xf0 != VERTEX_HEADER {
+        return Err(DecodeError::InvalidHeader(header));
+    }
+    let version = header & 0x0f;
+    if version > 0 {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    // The tail of the stream holds the vertex every first block is delta-encoded against.
+    let mut last_vertex = [0u8; 256];
+    last_vertex[..vertex_size].copy_from_slice(&source[source.len() - vertex_size..]);
+
+    let block_size = vertex_block_size(vertex_size);
+    let mut output = vec![0u8; vertex_count * vertex_size];
+    let mut position = 1;
+    let mut vertex_offset = 0;
+
+    while vertex_offset < vertex_count {
+        let count = usize::min(block_size, vertex_count - vertex_offset);
+        let block = &mut output[vertex_offset * vertex_size..(vertex_offset + count) * vertex_size];
+        position = decode_vertex_block(
+            source,
+            position,
+            block,
+            count,
+            vertex_size,
+            &mut last_vertex,
+        )?;
+        vertex_offset += count;
+    }
+
+    let tail_size = usize::max(vertex_size, TAIL_MAX_SIZE);
+    if source.len() - position != tail_size {
+        return Err(DecodeError::TrailingData);
+    }
+
+    Ok(output)
+}
+
+fn vertex_block_size(vertex_size: usize) -> usize {
+    let size = (VERTEX_BLOCK_SIZE_BYTES / vertex_size) & !(BYTE_GROUP_SIZE - 1);
+    usize::min(size, VERTEX_BLOCK_MAX_SIZE)
+}
+
+fn decode_vertex_block(
+    source: &[u8],
+    mut position: usize,
+    output: &mut [u8],
+    vertex_count: usize,
+    vertex_size: usize,
+    last_vertex: &mut [u8; 256],
+) -> Result<usize, DecodeError> {
+    let mut buffer = [0u8; VERTEX_BLOCK_MAX_SIZE];
+    let aligned_count = (vertex_count + BYTE_GROUP_SIZE - 1) & !(BYTE_GROUP_SIZE - 1);
+
+    for k in 0..vertex_size {
+        position = decode_bytes(source, position, &mut buffer[..aligned_count])?;
+
+        let mut previous = last_vertex[k];
+        for i in 0..vertex_count {
+            let value = unzigzag8(buffer[i]).wrapping_add(previous);
+            output[i * vertex_size + k] = value;
+            previous = value;
+        }
+    }
+
+    last_vertex[..vertex_size]
+        .copy_from_slice(&output[(vertex_count - 1) * vertex_size..vertex_count * vertex_size]);
+
+    Ok(position)
+}
+
+fn decode_bytes(source: &[u8], position: usize, buffer: &mut [u8]) -> Result<usize, DecodeError> {
+    // Two bits per group select its encoding, rounded up to whole header bytes.
+    let group_count = buffer.len() / BYTE_GROUP_SIZE;
+    let header_size = group_count.div_ceil(4);
+    if source.len() < position + header_size {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let header = &source[position..position + header_size];
+    let mut position = position + header_size;
+
+    for group in 0..group_count {
+        let bits_log2 = (header[group / 4] >> ((group % 4) * 2)) & 3;
+        let output = &mut buffer[group * BYTE_GROUP_SIZE..(group + 1) * BYTE_GROUP_SIZE];
+        position = decode_bytes_group(source, position, output, bits_log2)?;
+    }
+
+    Ok(position)
+}
+
+fn decode_bytes_group(
+    source: &[u8],
+    position: usize,
+    output: &mut [u8],
+    bits_log2: u8,
+) -> Result<usize, DecodeError> {
+    match bits_log2 {
+        0 => {
+            output.fill(0);
+            Ok(position)
+        }
+        3 => {
+            let end = position + BYTE_GROUP_SIZE;
+            let bytes = source
+                .get(position..end)
+                .ok_or(DecodeError::UnexpectedEnd)?;
+            output.copy_from_slice(bytes);
+            Ok(end)
+        }
+        _ => {
+            // Packed 2-bit or 4-bit values; the all-ones value escapes to a full byte
+            // stored after the packed section.
+            let bits = 1usize << bits_log2;
+            let packed_size = BYTE_GROUP_SIZE * bits / 8;
+            let packed = source
+                .get(position..position + packed_size)
+                .ok_or(DecodeError::UnexpectedEnd)?;
+            let escape = ((1u16 << bits) - 1) as u8;
+            let mut variable = position + packed_size;
+
+            for (i, value) in output.iter_mut().enumerate() {
+                let bit_offset = i * bits;
+                let byte = packed[bit_offset / 8];
+                let encoded = (byte >> (8 - bits - bit_offset % 8)) & escape;
+
+                *value = if encoded == escape {
+                    let byte = *source.get(variable).ok_or(DecodeError::UnexpectedEnd)?;
+                    variable += 1;
+                    byte
+                } else {
+                    encoded
+                };
+            }
+
+            Ok(variable)
+        }
+    }
+}
+
+fn unzigzag8(value: u8) -> u8 {
+    (0u8.wrapping_sub(value & 1)) ^ (value >> 1)
+}
+
+pub fn decode_index_buffer(
+    source: &[u8],
+    index_count: usize,
+    index_size: usize,
+) -> Result<Vec<u8>, DecodeError> {
+    if index_size != 2 && index_size != 4 {
+        return Err(DecodeError::InvalidStride(index_size));
+    }
+    if !index_count.is_multiple_of(3) || source.len() < 1 + index_count / 3 + CODE_AUX_TABLE_SIZE {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let header = source[0];
+    if header & 0xf0 != INDEX_HEADER {
+        return Err(DecodeError::InvalidHeader(header));
+    }
+    let version = header & 0x0f;
+    if version > 1 {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    let mut edge_fifo = [[u32::MAX; 2]; 16];
+    let mut vertex_fifo = [u32::MAX; 16];
+    let mut edge_fifo_offset = 0usize;
+    let mut vertex_fifo_offset = 0usize;
+
+    let mut next = 0u32;
+    let mut last = 0u32;
+    let fec_max = if version >= 1 { 13 } else { 15 };
+
+    // Layout: header, one code byte per triangle, variable-length data and a
+    // 16 byte code-aux table at the very end.
+    let data_end = source.len() - CODE_AUX_TABLE_SIZE;
+    let code_aux_table = &source[data_end..];
+    let codes = &source[1..1 + index_count / 3];
+    let mut data = 1 + index_count / 3;
+
+    let mut indices = Vec::with_capacity(index_count);
+
+    for &code_tri in codes {
+        if data > data_end {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        if code_tri < 0xf0 {
+            let fe = (code_tri >> 4) as usize;
+            let [a, b] = edge_fifo[edge_fifo_offset.wrapping_sub(1 + fe) & 15];
+            let fec = (code_tri & 15) as usize;
+
+            let c = if fec < fec_max {
+                let c = if fec == 0 {
+                    next
+                } else {
+                    vertex_fifo[vertex_fifo_offset.wrapping_sub(1 + fec) & 15]
+                };
+                if fec == 0 {
+                    next += 1;
+                }
+                push_vertex_fifo(&mut vertex_fifo, c, &mut vertex_fifo_offset, fec == 0);
+                c
+            } else {
+                // 13 and 14 encode a free index of last - 1 and last + 1.
+                last = match fec {
+                    13 => last.wrapping_sub(1),
+                    14 => last.wrapping_add(1),
+                    _ => decode_index(source, &mut data, data_end, last)?,
+                };
+                push_vertex_fifo(&mut vertex_fifo, last, &mut vertex_fifo_offset, true);
+                last
+            };
+
+            indices.extend_from_slice(&[a, b, c]);
+            push_edge_fifo(&mut edge_fifo, c, b, &mut edge_fifo_offset);
+            push_edge_fifo(&mut edge_fifo, a, c, &mut edge_fifo_offset);
+        } else if code_tri < 0xfe {
+            let code_aux = code_aux_table[(code_tri & 15) as usize];
+            let feb = (code_aux >> 4) as usize;
+            let fec = (code_aux & 15) as usize;
+
+            let a = next;
+            next += 1;
+
+            let b = if feb == 0 {
+                next
+            } else {
+                vertex_fifo[vertex_fifo_offset.wrapping_sub(feb) & 15]
+            };
+            if feb == 0 {
+                next += 1;
+            }
+
+            let c = if fec == 0 {
+                next
+            } else {
+                vertex_fifo[vertex_fifo_offset.wrapping_sub(fec) & 15]
+            };
+            if fec == 0 {
+                next += 1;
+            }
+
+            indices.extend_from_slice(&[a, b, c]);
+            push_vertex_fifo(&mut vertex_fifo, a, &mut vertex_fifo_offset, true);
+            push_vertex_fifo(&mut vertex_fifo, b, &mut vertex_fifo_offset, feb == 0);
+            push_vertex_fifo(&mut vertex_fifo, c, &mut vertex_fifo_offset, fec == 0);
+            push_edge_fifo(&mut edge_fifo, b, a, &mut edge_fifo_offset);
+            push_edge_fifo(&mut edge_fifo, c, b, &mut edge_fifo_offset);
+            push_edge_fifo(&mut edge_fifo, a, c, &mut edge_fifo_offset);
+        } else {
+            let code_aux = *source.get(data).ok_or(DecodeError::UnexpectedEnd)?;
+            data += 1;
+
+            let fea = if code_tri == 0xfe { 0 } else { 15 };
+            let feb = (code_aux >> 4) as usize;
+            let fec = (code_aux & 15) as usize;
+
+            if code_aux == 0 {
+                next = 0;
+            }
+
+            let mut take_next = || {
+                let value = next;
+                next += 1;
+                value
+            };
+
+            let mut a = if fea == 0 { take_next() } else { 0 };
+            let mut b = if feb == 0 {
+                take_next()
+            } else {
+                vertex_fifo[vertex_fifo_offset.wrapping_sub(feb) & 15]
+            };
+            let mut c = if fec == 0 {
+                take_next()
+            } else {
+                vertex_fifo[vertex_fifo_offset.wrapping_sub(fec) & 15]
+            };
+
+            if fea == 15 {
+                last = decode_index(source, &mut data, data_end, last)?;
+                a = last;
+            }
+            if feb == 15 {
+                last = decode_index(source, &mut data, data_end, last)?;
+                b = last;
+            }
+            if fec == 15 {
+                last = decode_index(source, &mut data, data_end, last)?;
+                c = last;
+            }
+
+            indices.extend_from_slice(&[a, b, c]);
+            push_vertex_fifo(&mut vertex_fifo, a, &mut vertex_fifo_offset, true);
+            push_vertex_fifo(
+                &mut vertex_fifo,
+                b,
+                &mut vertex_fifo_offset,
+                feb == 0 || feb == 15,
+            );
+            push_vertex_fifo(
+                &mut vertex_fifo,
+                c,
+                &mut vertex_fifo_offset,
+                fec == 0 || fec == 15,
+            );
+            push_edge_fifo(&mut edge_fifo, b, a, &mut edge_fifo_offset);
+            push_edge_fifo(&mut edge_fifo, c, b, &mut edge_fifo_offset);
+            push_edge_fifo(&mut edge_fifo, a, c, &mut edge_fifo_offset);
+        }
+    }
+
+    if data != data_end {
+        return Err(DecodeError::TrailingData);
+    }
+
+    Ok(write_indices(&indices, index_size))
+}
+
+pub fn decode_index_sequence(
+    source: &[u8],
+    index_count: usize,
+    index_size: usize,
+) -> Result<Vec<u8>, DecodeError> {
+    if index_size != 2 && index_size != 4 {
+        return Err(DecodeError::InvalidStride(index_size));
+    }
+    if source.len() < 1 + index_count + 4 {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let header = source[0];
+    if header & 0xf0 != SEQUENCE_HEADER {
+        return Err(DecodeError::InvalidHeader(header));
+    }
+    let version = header & 0x0f;
+    if version > 1 {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    // Every index is a delta against one of two baselines, chosen by the low bit.
+    let data_end = source.len() - 4;
+    let mut data = 1;
+    let mut last = [0u32; 2];
+    let mut indices = Vec::with_capacity(index_count);
+
+    for _ in 0..index_count {
+        if data >= data_end {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let value = decode_vbyte(source, &mut data, data_end)?;
+        let baseline = (value & 1) as usize;
+        let delta = unzigzag32(value >> 1);
+
+        last[baseline] = last[baseline].wrapping_add(delta);
+        indices.push(last[baseline]);
+    }
+
+    if data != data_end {
+        return Err(DecodeError::TrailingData);
+    }
+
+    Ok(write_indices(&indices, index_size))
+}
+
+fn push_edge_fifo(fifo: &mut [[u32; 2]; 16], a: u32, b: u32, offset: &mut usize) {
+    fifo[*offset] = [a, b];
+    *offset = (*offset + 1) & 15;
+}
+
+fn push_vertex_fifo(fifo: &mut [u32; 16], value: u32, offset: &mut usize, advance: bool) {
+    fifo[*offset] = value;
+    *offset = (*offset + advance as usize) & 15;
+}
+
+fn decode_vbyte(source: &[u8], data: &mut usize, end: usize) -> Result<u32, DecodeError> {
+    let mut read = || -> Result<u8, DecodeError> {
+        if *data >= end {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let byte = source[*data];
+        *data += 1;
+        Ok(byte)
+    };
+
+    let lead = read()?;
+    if lead < 128 {
+        return Ok(lead as u32);
+    }
+
+    let mut result = (lead & 127) as u32;
+    let mut shift = 7;
+    for _ in 0..4 {
+        let group = read()?;
+        result |= ((group & 127) as u32) << shift;
+        shift += 7;
+        if group < 128 {
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+fn decode_index(
+    source: &[u8],
+    data: &mut usize,
+    end: usize,
+    last: u32,
+) -> Result<u32, DecodeError> {
+    let value = decode_vbyte(source, data, end)?;
+    Ok(last.wrapping_add(unzigzag32(value)))
+}
+
+fn unzigzag32(value: u32) -> u32 {
+    (value >> 1) ^ 0u32.wrapping_sub(value & 1)
+}
+
+fn write_indices(indices: &[u32], index_size: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(indices.len() * index_size);
+    for index in indices {
+        if index_size == 2 {
+            output.extend_from_slice(&(*index as u16).to_le_bytes());
+        } else {
+            output.extend_from_slice(&index.to_le_bytes());
+        }
+    }
+    output
+}
+
+fn apply_filter(
+    data: &mut [u8],
+    count: usize,
+    stride: usize,
+    filter: MeshoptFilter,
+) -> Result<(), DecodeError> {
+    match filter {
+        MeshoptFilter::None => Ok(()),
+        MeshoptFilter::Octahedral if stride == 4 => {
+            for vertex in data.chunks_exact_mut(4).take(count) {
+                let [x, y, z] = decode_octahedral(
+                    vertex[0] as i8 as f32,
+                    vertex[1] as i8 as f32,
+                    vertex[2] as i8 as f32,
+                    i8::MAX as f32,
+                );
+                vertex[0] = x as i8 as u8;
+                vertex[1] = y as i8 as u8;
+                vertex[2] = z as i8 as u8;
+            }
+            Ok(())
+        }
+        MeshoptFilter::Octahedral if stride == 8 => {
+            for vertex in data.chunks_exact_mut(8).take(count) {
+                let [x, y, z] = decode_octahedral(
+                    read_i16(vertex, 0) as f32,
+                    read_i16(vertex, 1) as f32,
+                    read_i16(vertex, 2) as f32,
+                    i16::MAX as f32,
+                );
+                write_i16(vertex, 0, x as i16);
+                write_i16(vertex, 1, y as i16);
+                write_i16(vertex, 2, z as i16);
+            }
+            Ok(())
+        }
+        MeshoptFilter::Quaternion if stride == 8 => {
+            let scale = 1.0 / 2.0_f32.sqrt();
+            for vertex in data.chunks_exact_mut(8).take(count) {
+                let packed = read_i16(vertex, 3);
+                let component_scale = scale / (packed | 3) as f32;
+
+                let x = read_i16(vertex, 0) as f32 * component_scale;
+                let y = read_i16(vertex, 1) as f32 * component_scale;
+                let z = read_i16(vertex, 2) as f32 * component_scale;
+                let w = f32::max(1.0 - x * x - y * y - z * z, 0.0).sqrt();
+
+                // The two low bits name the component that was dropped by the encoder.
+                let dropped = (packed & 3) as usize;
+                write_i16(vertex, (dropped + 1) & 3, round_snorm(x, 32767.0) as i16);
+                write_i16(vertex, (dropped + 2) & 3, round_snorm(y, 32767.0) as i16);
+                write_i16(vertex, (dropped + 3) & 3, round_snorm(z, 32767.0) as i16);
+                write_i16(vertex, dropped, round_snorm(w, 32767.0) as i16);
+            }
+            Ok(())
+        }
+        MeshoptFilter::Exponential if stride.is_multiple_of(4) => {
+            for value in data.chunks_exact_mut(4).take(count * stride / 4) {
+                let bits = u32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+                let mantissa = ((bits << 8) as i32) >> 8;
+                let exponent = (bits as i32) >> 24;
+                let decoded = mantissa as f32 * 2.0_f32.powi(exponent);
+                value.copy_from_slice(&decoded.to_le_bytes());
+            }
+            Ok(())
+        }
+        _ => Err(DecodeError::InvalidStride(stride)),
+    }
+}
+
+fn decode_octahedral(x: f32, y: f32, z: f32, max: f32) -> [i32; 3] {
+    // z stores 1.0 at the same precision as x and y; reconstruct it and unfold z < 0.
+    let z = z - x.abs() - y.abs();
+    let t = f32::min(z, 0.0);
+    let x = x + if x >= 0.0 { t } else { -t };
+    let y = y + if y >= 0.0 { t } else { -t };
+
+    let scale = max / (x * x + y * y + z * z).sqrt();
+    [
+        round_snorm(x, scale),
+        round_snorm(y, scale),
+        round_snorm(z, scale),
+    ]
+}
+
+fn round_snorm(value: f32, scale: f32) -> i32 {
+    (value * scale + if value >= 0.0 { 0.5 } else { -0.5 }) as i32
+}
+
+fn read_i16(vertex: &[u8], component: usize) -> i16 {
+    i16::from_le_bytes([vertex[component * 2], vertex[component * 2 + 1]])
+}
+
+fn write_i16(vertex: &mut [u8], component: usize, value: i16) {
+    vertex[component * 2..component * 2 + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift, so the data is noisy but the same on every run.
+    fn random(seed: &mut u32) -> u32 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 17;
+        *seed ^= *seed << 5;
+        *seed
+    }
+
+    fn encode_vertex_buffer(bytes: &[u8], count: usize, stride: usize) -> Vec<u8> {
+        unsafe {
+            let bound = meshopt::ffi::meshopt_encodeVertexBufferBound(count, stride);
+            let mut encoded = vec![0u8; bound];
+            let size = meshopt::ffi::meshopt_encodeVertexBuffer(
+                encoded.as_mut_ptr(),
+                encoded.len(),
+                bytes.as_ptr() as *const _,
+                count,
+                stride,
+            );
+            encoded.truncate(size);
+            encoded
+        }
+    }
+
+    fn read_u32s(bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect()
+    }
+
+    /// Each triangle starting at its smallest index, as the encoder may rotate
+    /// triangles.
+    fn canonical_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        indices
+            .chunks_exact(3)
+            .map(|t| {
+                *[[t[0], t[1], t[2]], [t[1], t[2], t[0]], [t[2], t[0], t[1]]]
+                    .iter()
+                    .min()
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vertex_buffer_round_trips() {
+        let mut seed = 12345;
+        for stride in [4, 8, 12, 16, 32, 64] {
+            // Counts around the 16 vertex groups and across several blocks.
+            for count in [1, 3, 15, 16, 17, 100, 1000, 5000] {
+                let bytes: Vec<u8> = (0..count * stride / 4)
+                    .map(|i| match i % 3 {
+                        0 => random(&mut seed),
+                        _ => (i as u32 / 7) & 0xff,
+                    })
+                    .flat_map(u32::to_le_bytes)
+                    .collect();
+
+                let encoded = encode_vertex_buffer(&bytes, count, stride);
+                let decoded = decode_vertex_buffer(&encoded, count, stride).unwrap();
+                assert_eq!(decoded, bytes, "stride {} count {}", stride, count);
+            }
+        }
+    }
+
+    #[test]
+    fn index_buffer_round_trips() {
+        let mut seed = 999;
+        for triangle_count in [1, 2, 50, 1000] {
+            let vertex_count = triangle_count * 2 + 3;
+            let mut indices = vec![];
+            for triangle in 0..triangle_count {
+                let a = triangle as u32 % vertex_count as u32;
+                indices.extend_from_slice(&[
+                    a,
+                    (a + 1) % vertex_count as u32,
+                    random(&mut seed) % vertex_count as u32,
+                ]);
+            }
+            let indices = meshopt::optimize_vertex_cache(&indices, vertex_count);
+            let encoded = meshopt::encode_index_buffer(&indices, vertex_count).unwrap();
+
+            let decoded = decode_index_buffer(&encoded, indices.len(), 4).unwrap();
+            assert_eq!(
+                canonical_triangles(&read_u32s(&decoded)),
+                canonical_triangles(&indices)
+            );
+
+            let decoded = decode_index_buffer(&encoded, indices.len(), 2).unwrap();
+            let narrowed: Vec<u32> = decoded
+                .chunks_exact(2)
+                .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]) as u32)
+                .collect();
+            assert_eq!(
+                canonical_triangles(&narrowed),
+                canonical_triangles(&indices)
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_index_sequence() {
+        // 5 against baseline 0: zigzag 10, tagged 20. 7 against baseline 1:
+        // zigzag 14, tagged 29. Then the 4 byte tail.
+        let encoded = [0xd1, 20, 29, 0, 0, 0, 0];
+        let decoded = decode_index_sequence(&encoded, 2, 4).unwrap();
+        assert_eq!(read_u32s(&decoded), vec![5, 7]);
+    }
+
+    #[test]
+    fn decode_meshopt_dispatches_on_mode() {
+        let bytes: Vec<u8> = (0..64u32).flat_map(u32::to_le_bytes).collect();
+        let encoded = encode_vertex_buffer(&bytes, 16, 16);
+        let decoded = decode_meshopt(
+            &encoded,
+            16,
+            16,
+            MeshoptMode::Attributes,
+            MeshoptFilter::None,
+        )
+        .unwrap();
+        assert_eq!(decoded, bytes);
+
+        let encoded = [0xd1, 20, 29, 0, 0, 0, 0];
+        let decoded =
+            decode_meshopt(&encoded, 2, 2, MeshoptMode::Indices, MeshoptFilter::None).unwrap();
+        assert_eq!(decoded, vec![5, 0, 7, 0]);
+    }
+
+    #[test]
+    fn rejects_malformed_streams() {
+        let bytes = vec![1u8; 64];
+        let encoded = encode_vertex_buffer(&bytes, 16, 4);
+
+        let mut wrong_header = encoded.clone();
+        wrong_header[0] = 0x10;
+        assert_eq!(
+            decode_vertex_buffer(&wrong_header, 16, 4),
+            Err(DecodeError::InvalidHeader(0x10))
+        );
+
+        let mut future_version = encoded.clone();
+        future_version[0] = VERTEX_HEADER | 1;
+        assert_eq!(
+            decode_vertex_buffer(&future_version, 16, 4),
+            Err(DecodeError::UnsupportedVersion(1))
+        );
+
+        assert_eq!(
+            decode_vertex_buffer(&encoded, 16, 6),
+            Err(DecodeError::InvalidStride(6))
+        );
+        assert_eq!(
+            decode_vertex_buffer(&encoded[..4], 16, 4),
+            Err(DecodeError::UnexpectedEnd)
+        );
+
+        let mut trailing = encoded.clone();
+        trailing.insert(1, 0);
+        assert!(decode_vertex_buffer(&trailing, 16, 4).is_err());
+
+        assert_eq!(
+            decode_index_buffer(&[INDEX_HEADER; 32], 3, 3),
+            Err(DecodeError::InvalidStride(3))
+        );
+        assert_eq!(
+            decode_index_sequence(&[0xd1, 20], 2, 4),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn applies_exponential_filter() {
+        // Mantissa 3 and exponent -1.
+        let mut data = ((-1i32 as u32) << 24 | 3).to_le_bytes().to_vec();
+        apply_filter(&mut data, 1, 4, MeshoptFilter::Exponential).unwrap();
+        assert_eq!(
+            f32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+            1.5
+        );
+    }
+
+    #[test]
+    fn applies_octahedral_filter() {
+        let mut data = vec![0, 0, 127, 0];
+        apply_filter(&mut data, 1, 4, MeshoptFilter::Octahedral).unwrap();
+        assert_eq!(data, vec![0, 0, 127, 0]);
+
+        // Folded into the lower hemisphere: x = 0.5, y = 0, z = -0.5.
+        let mut data = vec![0; 8];
+        write_i16(&mut data, 0, 16384);
+        write_i16(&mut data, 2, -32767 + 16384 + 16384 + 1);
+        apply_filter(&mut data, 1, 8, MeshoptFilter::Octahedral).unwrap();
+        let length = (0..3)
+            .map(|component| (read_i16(&data, component) as f32 / 32767.0).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        assert!((length - 1.0).abs() < 1e-3);
+        assert!(read_i16(&data, 2) < 0);
+    }
+
+    #[test]
+    fn applies_quaternion_filter() {
+        // The identity, with w dropped and stored in the last component.
+        let mut data = vec![0; 8];
+        write_i16(&mut data, 3, (32767 & !3) | 3);
+        apply_filter(&mut data, 1, 8, MeshoptFilter::Quaternion).unwrap();
+        assert_eq!(read_i16(&data, 3), 32767);
+        assert_eq!(
+            [read_i16(&data, 0), read_i16(&data, 1), read_i16(&data, 2)],
+            [0; 3]
+        );
+    }
+
+    #[test]
+    fn rejects_filter_strides() {
+        let mut data = vec![0; 12];
+        assert_eq!(
+            apply_filter(&mut data, 1, 12, MeshoptFilter::Octahedral),
+            Err(DecodeError::InvalidStride(12))
+        );
+        assert_eq!(
+            apply_filter(&mut data, 3, 4, MeshoptFilter::Quaternion),
+            Err(DecodeError::InvalidStride(4))
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use crate::bvh::Bvh;
+use crate::frustum::Frustum;
+use std::collections::HashSet;
+
+/// A submesh of a scene: the index of its model, of its mesh in the model
+/// and of the submesh in the mesh.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubmeshId {
+    pub model: usize,
+    pub mesh: usize,
+    pub submesh: usize,
+}
+
+/// How many submeshes the last frame drew and left out.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub visible: usize,
+    pub culled: usize,
+}
+
+/// The submeshes a camera may see.
+#[derive(Debug, Clone, Default)]
+pub struct Visibility {
+    visible: HashSet<SubmeshId>,
+    stats: CullingStats,
+}
+
+impl Visibility {
+    /// Finds the submeshes in `bvh` whose bounds may be inside `frustum`.
+    pub fn cull(bvh: &Bvh<SubmeshId>, frustum: &Frustum) -> Self {
+        let mut visible = HashSet::new();
+        bvh.query_frustum(frustum, |&id| {
+            visible.insert(id);
+        });
+        let stats = CullingStats {
+            visible: visible.len(),
+            culled: bvh.len() - visible.len(),
+        };
+        Self { visible, stats }
+    }
+
+    pub fn is_visible(&self, id: SubmeshId) -> bool {
+        self.visible.contains(&id)
+    }
+
+    pub fn stats(&self) -> CullingStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounds::Bounds;
+    use glam::{Mat4, Vec3};
+
+    fn view_projection() -> Mat4 {
+        let projection = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let view = Mat4::look_at_lh(Vec3::new(0.0, 0.0, -5.0), Vec3::ZERO, Vec3::Y);
+        projection * view
+    }
+
+    #[test]
+    fn visibility_counts() {
+        let id = |model| SubmeshId {
+            model,
+            mesh: 0,
+            submesh: 0,
+        };
+        let at = |z: f32| Bounds::new(Vec3::new(-0.5, -0.5, z - 0.5), Vec3::new(0.5, 0.5, z + 0.5));
+        let bvh = Bvh::new(vec![
+            (at(0.0), id(0)),
+            (at(-20.0), id(1)),
+            (at(10.0), id(2)),
+        ]);
+        let visibility = Visibility::cull(&bvh, &Frustum::from_matrix(view_projection()));
+        assert!(visibility.is_visible(id(0)));
+        assert!(!visibility.is_visible(id(1)));
+        assert!(visibility.is_visible(id(2)));
+        assert_eq!(
+            visibility.stats(),
+            CullingStats {
+                visible: 2,
+                culled: 1
+            }
+        );
+    }
+}
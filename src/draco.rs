@@ -0,0 +1,271 @@
+use draco_oxide_core::attribute::ComponentDataType;
+use draco_oxide_decoder::Decoder;
+use gltf::accessor::DataType;
+use std::fmt;
+
+// https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_draco_mesh_compression
+#[derive(Debug)]
+pub enum DracoError {
+    Decode(draco_oxide_decoder::Err),
+    /// glTF has no accessor type for 64-bit or signed 32-bit components.
+    UnsupportedComponentType(ComponentDataType),
+    /// The attributes do not all cover the same points.
+    InconsistentAttributes,
+    /// A face or vertex refers to a point or value that does not exist.
+    InvalidIndex(usize),
+}
+
+impl fmt::Display for DracoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DracoError::Decode(error) => write!(f, "{}", error),
+            DracoError::UnsupportedComponentType(component_type) => {
+                write!(f, "unsupported component type {:?}", component_type)
+            }
+            DracoError::InconsistentAttributes => {
+                write!(f, "attributes have different vertex counts")
+            }
+            DracoError::InvalidIndex(index) => write!(f, "index {} is out of range", index),
+        }
+    }
+}
+
+impl std::error::Error for DracoError {}
+
+impl From<draco_oxide_decoder::Err> for DracoError {
+    fn from(error: draco_oxide_decoder::Err) -> Self {
+        DracoError::Decode(error)
+    }
+}
+
+/// One decoded attribute, a value per vertex, tightly packed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DracoAttribute {
+    /// The unique id the `KHR_draco_mesh_compression` extension refers to the
+    /// attribute by.
+    pub id: usize,
+    pub data_type: DataType,
+    pub components: usize,
+    pub data: Vec<u8>,
+}
+
+/// A Draco compressed triangle mesh decoded into plain glTF data. The decoder
+/// may reorder and merge vertices, so the counts can differ from the
+/// accessors of the original primitive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DracoMesh {
+    pub vertex_count: usize,
+    pub indices: Vec<u32>,
+    pub attributes: Vec<DracoAttribute>,
+}
+
+impl DracoMesh {
+    pub fn attribute(&self, id: usize) -> Option<&DracoAttribute> {
+        self.attributes.iter().find(|attribute| attribute.id == id)
+    }
+}
+
+/// Decodes a Draco bitstream, as stored in the buffer view of a
+/// `KHR_draco_mesh_compression` primitive.
+pub fn decode_draco(source: &[u8]) -> Result<DracoMesh, DracoError> {
+    let mesh = Decoder::new().decode_mesh(source)?;
+
+    let vertex_count = mesh
+        .attributes
+        .first()
+        .map_or(0, |attribute| attribute.len());
+    let mut attributes = Vec::with_capacity(mesh.attributes.len());
+    for attribute in &mesh.attributes {
+        if attribute.len() != vertex_count {
+            return Err(DracoError::InconsistentAttributes);
+        }
+        let component_type = attribute.get_component_type();
+        let data_type = data_type(component_type)
+            .ok_or(DracoError::UnsupportedComponentType(component_type))?;
+
+        // Values are stored once and shared by every vertex that has them.
+        let size = component_type.size() * attribute.get_num_components();
+        let values = attribute.get_data_as_bytes();
+        let mut data = Vec::with_capacity(vertex_count * size);
+        for vertex in 0..vertex_count {
+            let value = usize::from(attribute.get_unique_val_idx(vertex.into()));
+            let bytes = values
+                .get(value * size..(value + 1) * size)
+                .ok_or(DracoError::InvalidIndex(value))?;
+            data.extend_from_slice(bytes);
+        }
+
+        attributes.push(DracoAttribute {
+            id: attribute.get_id().as_usize(),
+            data_type,
+            components: attribute.get_num_components(),
+            data,
+        });
+    }
+
+    let mut indices = Vec::with_capacity(mesh.faces.len() * 3);
+    for index in mesh.faces.iter().flatten().map(|&index| usize::from(index)) {
+        if index >= vertex_count {
+            return Err(DracoError::InvalidIndex(index));
+        }
+        indices.push(index as u32);
+    }
+
+    Ok(DracoMesh {
+        vertex_count,
+        indices,
+        attributes,
+    })
+}
+
+fn data_type(component_type: ComponentDataType) -> Option<DataType> {
+    match component_type {
+        ComponentDataType::I8 => Some(DataType::I8),
+        ComponentDataType::U8 => Some(DataType::U8),
+        ComponentDataType::I16 => Some(DataType::I16),
+        ComponentDataType::U16 => Some(DataType::U16),
+        ComponentDataType::U32 => Some(DataType::U32),
+        ComponentDataType::F32 => Some(DataType::F32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use draco_oxide::encode::{self, Config};
+    use draco_oxide::{AttributeDomain, AttributeType, ConfigType, MeshBuilder, NdVector};
+
+    /// Positions, normals, texture coordinates and indices, as glTF would
+    /// store them uncompressed.
+    type Mesh = (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u32>);
+
+    /// A bumpy `size` by `size` grid of quads.
+    fn grid(size: usize) -> Mesh {
+        let mut positions = vec![];
+        let mut normals = vec![];
+        let mut uvs = vec![];
+        for y in 0..=size {
+            for x in 0..=size {
+                let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+                let height = (u * 6.0).sin() * (v * 4.0).cos() * 0.2;
+                positions.push([u, v, height]);
+                let normal = [-(u * 6.0).cos() * 1.2, (v * 4.0).sin() * 0.8, 1.0];
+                let length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+                normals.push(normal.map(|c| c / length));
+                uvs.push([u, 1.0 - v]);
+            }
+        }
+
+        let row = size as u32 + 1;
+        let mut indices = vec![];
+        for y in 0..size as u32 {
+            for x in 0..size as u32 {
+                let corner = y * row + x;
+                indices.extend([corner, corner + 1, corner + row + 1]);
+                indices.extend([corner, corner + row + 1, corner + row]);
+            }
+        }
+        (positions, normals, uvs, indices)
+    }
+
+    fn encode(
+        positions: &[[f32; 3]],
+        normals: &[[f32; 3]],
+        uvs: &[[f32; 2]],
+        indices: &[u32],
+    ) -> Vec<u8> {
+        let mut builder = MeshBuilder::new();
+        builder.set_connectivity_attribute(
+            indices
+                .chunks_exact(3)
+                .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+                .collect(),
+        );
+        let position = builder.add_attribute(
+            positions.iter().map(|&p| NdVector::from(p)).collect(),
+            AttributeType::Position,
+            AttributeDomain::Position,
+            vec![],
+        );
+        builder.add_attribute(
+            normals.iter().map(|&n| NdVector::from(n)).collect(),
+            AttributeType::Normal,
+            AttributeDomain::Position,
+            vec![position],
+        );
+        builder.add_attribute(
+            uvs.iter().map(|&uv| NdVector::from(uv)).collect(),
+            AttributeType::TextureCoordinate,
+            AttributeDomain::Position,
+            vec![position],
+        );
+
+        let mut encoded = Vec::new();
+        encode::encode_mesh(builder.build().unwrap(), &mut encoded, Config::default()).unwrap();
+        encoded
+    }
+
+    fn floats(attribute: &DracoAttribute) -> Vec<f32> {
+        assert_eq!(attribute.data_type, DataType::F32);
+        attribute
+            .data
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect()
+    }
+
+    fn close(a: &[f32], b: &[f32], tolerance: f32) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() <= tolerance)
+    }
+
+    #[test]
+    fn round_trips_against_the_uncompressed_mesh() {
+        let (positions, normals, uvs, indices) = grid(6);
+        let mesh = decode_draco(&encode(&positions, &normals, &uvs, &indices)).unwrap();
+
+        assert_eq!(mesh.indices.len(), indices.len());
+        let [decoded_positions, decoded_normals, decoded_uvs] =
+            [0, 1, 2].map(|id| floats(mesh.attribute(id).unwrap()));
+        assert_eq!(mesh.attribute(0).unwrap().components, 3);
+        assert_eq!(mesh.attribute(2).unwrap().components, 2);
+        assert_eq!(decoded_positions.len(), mesh.vertex_count * 3);
+
+        // Vertices and triangles come back in another order, and quantized.
+        let mut unmatched: Vec<&[u32]> = indices.chunks_exact(3).collect();
+        for triangle in mesh.indices.chunks_exact(3) {
+            let corner = |i: usize| triangle[i] as usize;
+            let found = unmatched.iter().position(|original| {
+                (0..3).any(|rotation| {
+                    (0..3).all(|i| {
+                        let (vertex, original) = (corner(i), original[(i + rotation) % 3] as usize);
+                        close(
+                            &decoded_positions[vertex * 3..vertex * 3 + 3],
+                            &positions[original],
+                            1e-3,
+                        ) && close(
+                            &decoded_normals[vertex * 3..vertex * 3 + 3],
+                            &normals[original],
+                            2e-2,
+                        ) && close(
+                            &decoded_uvs[vertex * 2..vertex * 2 + 2],
+                            &uvs[original],
+                            1e-3,
+                        )
+                    })
+                })
+            });
+            unmatched.swap_remove(found.expect("decoded triangle is not in the mesh"));
+        }
+        assert!(unmatched.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_streams() {
+        let (positions, normals, uvs, indices) = grid(2);
+        let encoded = encode(&positions, &normals, &uvs, &indices);
+        assert!(decode_draco(&encoded[..encoded.len() / 2]).is_err());
+        assert!(decode_draco(b"not a draco stream").is_err());
+        assert!(decode_draco(&[]).is_err());
+    }
+}
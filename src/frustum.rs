@@ -0,0 +1,147 @@
+use crate::bounds::{BoundingSphere, Bounds};
+use glam::{Mat4, Vec3, Vec4};
+
+/// The points `p` with `normal.dot(p) + distance == 0`. Points on the side
+/// `normal` points to are in front of it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    /// Normalizes a plane given as the coefficients `(a, b, c, d)`.
+    pub fn from_coefficients(coefficients: Vec4) -> Self {
+        let length = coefficients.truncate().length();
+        Self {
+            normal: coefficients.truncate() / length,
+            distance: coefficients.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// The six planes around what a camera sees, facing inwards.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view-projection matrix with Metal's clip
+    /// space, where depth goes from 0 to 1. Planes extracted from a
+    /// model-view-projection matrix are in model space.
+    pub fn from_matrix(view_projection: Mat4) -> Self {
+        let row = |index: usize| view_projection.row(index);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [
+                Plane::from_coefficients(w + x),
+                Plane::from_coefficients(w - x),
+                Plane::from_coefficients(w + y),
+                Plane::from_coefficients(w - y),
+                Plane::from_coefficients(z),
+                Plane::from_coefficients(w - z),
+            ],
+        }
+    }
+
+    /// Whether any part of `sphere` may be inside. Spheres near a corner
+    /// outside of it can still pass.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        !sphere.is_empty()
+            && self
+                .planes
+                .iter()
+                .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    /// Whether any part of `bounds` may be inside. Like spheres, boxes near
+    /// a corner outside of it can still pass.
+    pub fn intersects_bounds(&self, bounds: &Bounds) -> bool {
+        // the corner furthest along each normal is the last to leave
+        !bounds.is_empty()
+            && self.planes.iter().all(|plane| {
+                let corner = Vec3::select(plane.normal.cmpge(Vec3::ZERO), bounds.max, bounds.min);
+                plane.signed_distance(corner) >= 0.0
+            })
+    }
+
+    /// Whether all of `bounds` is inside.
+    pub fn contains_bounds(&self, bounds: &Bounds) -> bool {
+        !bounds.is_empty()
+            && self.planes.iter().all(|plane| {
+                let corner = Vec3::select(plane.normal.cmpge(Vec3::ZERO), bounds.min, bounds.max);
+                plane.signed_distance(corner) >= 0.0
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view_projection() -> Mat4 {
+        // camera at z = -5 looking down +Z, as perspective_lh expects
+        let projection = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let view = Mat4::look_at_lh(Vec3::new(0.0, 0.0, -5.0), Vec3::ZERO, Vec3::Y);
+        projection * view
+    }
+
+    #[test]
+    fn planes_face_inwards_and_are_normalized() {
+        let frustum = Frustum::from_matrix(view_projection());
+        for plane in frustum.planes.iter() {
+            assert!((plane.normal.length() - 1.0).abs() < 1e-5);
+            assert!(plane.signed_distance(Vec3::ZERO) > 0.0);
+        }
+        let [_, _, _, _, near, far] = frustum.planes;
+        assert!((near.signed_distance(Vec3::new(0.0, 0.0, -4.9))).abs() < 1e-4);
+        assert!((far.signed_distance(Vec3::new(0.0, 0.0, 95.0))).abs() < 1e-2);
+        // 90 degrees: the left plane passes through (-d, 0, d) in view space
+        let left = frustum.planes[0];
+        assert!(left.signed_distance(Vec3::new(-4.0, 0.0, -1.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn sphere_tests() {
+        let frustum = Frustum::from_matrix(view_projection());
+        let sphere = |x: f32, y: f32, z: f32, r: f32| BoundingSphere::new(Vec3::new(x, y, z), r);
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, 0.0, 1.0)));
+        // behind the camera
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, -10.0, 1.0)));
+        // beyond the far plane, and touching it
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, 200.0, 1.0)));
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, 95.5, 1.0)));
+        // off to the side, and straddling the side plane
+        assert!(!frustum.intersects_sphere(&sphere(20.0, 0.0, 0.0, 1.0)));
+        assert!(frustum.intersects_sphere(&sphere(5.5, 0.0, 0.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&BoundingSphere::EMPTY));
+    }
+
+    #[test]
+    fn box_tests() {
+        let frustum = Frustum::from_matrix(view_projection());
+        let cube = |x: f32, y: f32, z: f32, half: f32| {
+            Bounds::new(Vec3::new(x, y, z) - half, Vec3::new(x, y, z) + half)
+        };
+        assert!(frustum.intersects_bounds(&cube(0.0, 0.0, 0.0, 1.0)));
+        assert!(frustum.contains_bounds(&cube(0.0, 0.0, 0.0, 1.0)));
+        // behind the camera and beyond the far plane
+        assert!(!frustum.intersects_bounds(&cube(0.0, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects_bounds(&cube(0.0, 0.0, 200.0, 1.0)));
+        // straddling the side plane: seen, but not all of it
+        assert!(frustum.intersects_bounds(&cube(5.5, 0.0, 0.0, 1.0)));
+        assert!(!frustum.contains_bounds(&cube(5.5, 0.0, 0.0, 1.0)));
+        assert!(!frustum.intersects_bounds(&cube(20.0, 0.0, 0.0, 1.0)));
+        // around the whole frustum
+        assert!(frustum.intersects_bounds(&cube(0.0, 0.0, 0.0, 500.0)));
+        assert!(!frustum.contains_bounds(&cube(0.0, 0.0, 0.0, 500.0)));
+        assert!(!frustum.intersects_bounds(&Bounds::EMPTY));
+        assert!(!frustum.contains_bounds(&Bounds::EMPTY));
+    }
+}
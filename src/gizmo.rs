@@ -0,0 +1,1015 @@
+use crate::node::{InnerNode, NodeId};
+use crate::picking::Ray;
+use glam::{Mat4, Quat, Vec3, Vec4};
+
+// Sizes of the handles, as fractions of the length of the axes.
+const AXIS_LENGTH: f32 = 1.0;
+/// How far from a handle a ray may pass and still grab it.
+const HANDLE_RADIUS: f32 = 0.08;
+const PLANE_START: f32 = 0.2;
+const PLANE_END: f32 = 0.45;
+const RING_RADIUS: f32 = 1.0;
+const TIP_LENGTH: f32 = 0.2;
+const TIP_RADIUS: f32 = 0.06;
+/// Half the side of the box at the tip of each scale axis.
+const BOX_SIZE: f32 = 0.06;
+/// Half the side of the box at the center that scales uniformly.
+const UNIFORM_SIZE: f32 = 0.12;
+
+const RING_SEGMENTS: usize = 64;
+const TIP_SEGMENTS: usize = 12;
+/// A drag never scales a node down further than this.
+const MIN_SCALE_FACTOR: f32 = 0.01;
+
+const HIGHLIGHT_COLOR: [f32; 4] = [1.0, 0.85, 0.1, 1.0];
+const UNIFORM_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const PLANE_ALPHA: f32 = 0.4;
+
+/// What dragging a gizmo does to its node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+/// The axes a gizmo moves and rotates along. Scaling is always along the
+/// axes of the node, since other axes would need a shear.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GizmoSpace {
+    World,
+    Local,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    pub fn index(self) -> usize {
+        match self {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        }
+    }
+
+    /// The two other axes, in cyclic order.
+    pub fn others(self) -> (Axis, Axis) {
+        match self {
+            Axis::X => (Axis::Y, Axis::Z),
+            Axis::Y => (Axis::Z, Axis::X),
+            Axis::Z => (Axis::X, Axis::Y),
+        }
+    }
+
+    fn color(self) -> Vec4 {
+        match self {
+            Axis::X => Vec4::new(0.9, 0.2, 0.2, 1.0),
+            Axis::Y => Vec4::new(0.3, 0.8, 0.3, 1.0),
+            Axis::Z => Vec4::new(0.2, 0.4, 0.9, 1.0),
+        }
+    }
+}
+
+/// A part of a gizmo that can be dragged.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GizmoHandle {
+    /// Along one axis, or around it when rotating.
+    Axis(Axis),
+    /// Across the plane the axis is normal to.
+    Plane(Axis),
+    /// Every axis at once, when scaling.
+    Uniform,
+}
+
+/// The steps drags move in while snapping.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Snapping {
+    /// In world units.
+    pub translation: f32,
+    /// In degrees.
+    pub rotation: f32,
+    /// As a fraction of the scale when the drag started.
+    pub scale: f32,
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Self {
+            translation: 0.25,
+            rotation: 15.0,
+            scale: 0.1,
+        }
+    }
+}
+
+/// How the scene is seen: the matrix from world to clip space and the size
+/// of the viewport, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GizmoView {
+    pub view_projection: Mat4,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl GizmoView {
+    /// The ray through the pixel at `(x, y)`, counted from the top left.
+    pub fn ray(&self, x: f32, y: f32) -> Ray {
+        Ray::from_screen(x, y, self.width, self.height, self.view_projection)
+    }
+
+    /// How many world units a pixel covers at `point`.
+    pub fn pixel_size(&self, point: Vec3) -> f32 {
+        let clip = self.view_projection * point.extend(1.0);
+        let moved = clip + Vec4::new(0.0, 2.0 * clip.w / self.height, 0.0, 0.0);
+        let moved = self.view_projection.inverse() * moved;
+        (moved.truncate() / moved.w - point).length()
+    }
+}
+
+/// Where a gizmo is drawn: the origin of its node, the directions of its
+/// axes in world space, all of unit length, and how long they are.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GizmoFrame {
+    pub origin: Vec3,
+    pub axes: [Vec3; 3],
+    pub size: f32,
+}
+
+impl GizmoFrame {
+    pub fn axis(&self, axis: Axis) -> Vec3 {
+        self.axes[axis.index()]
+    }
+}
+
+/// A corner of a line or triangle of a gizmo, in world space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GizmoVertex {
+    pub position: Vec3,
+    pub color: Vec4,
+}
+
+/// What a gizmo looks like: a list of lines and one of triangles, two and
+/// three vertices each.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GizmoGeometry {
+    pub lines: Vec<GizmoVertex>,
+    pub triangles: Vec<GizmoVertex>,
+}
+
+impl GizmoGeometry {
+    fn line(&mut self, a: Vec3, b: Vec3, color: Vec4) {
+        for position in [a, b] {
+            self.lines.push(GizmoVertex { position, color });
+        }
+    }
+
+    fn triangle(&mut self, a: Vec3, b: Vec3, c: Vec3, color: Vec4) {
+        for position in [a, b, c] {
+            self.triangles.push(GizmoVertex { position, color });
+        }
+    }
+
+    fn quad(&mut self, corners: [Vec3; 4], color: Vec4) {
+        self.triangle(corners[0], corners[1], corners[2], color);
+        self.triangle(corners[0], corners[2], corners[3], color);
+    }
+
+    /// A box around `center` whose sides are `2 * half` along `axes`.
+    fn cube(&mut self, center: Vec3, axes: [Vec3; 3], half: f32, color: Vec4) {
+        let corner =
+            |x: f32, y: f32, z: f32| center + (axes[0] * x + axes[1] * y + axes[2] * z) * half;
+        for sign in [-1.0, 1.0] {
+            self.quad(
+                [
+                    corner(sign, -1.0, -1.0),
+                    corner(sign, 1.0, -1.0),
+                    corner(sign, 1.0, 1.0),
+                    corner(sign, -1.0, 1.0),
+                ],
+                color,
+            );
+            self.quad(
+                [
+                    corner(-1.0, sign, -1.0),
+                    corner(1.0, sign, -1.0),
+                    corner(1.0, sign, 1.0),
+                    corner(-1.0, sign, 1.0),
+                ],
+                color,
+            );
+            self.quad(
+                [
+                    corner(-1.0, -1.0, sign),
+                    corner(1.0, -1.0, sign),
+                    corner(1.0, 1.0, sign),
+                    corner(-1.0, 1.0, sign),
+                ],
+                color,
+            );
+        }
+    }
+
+    /// The tip of a translation axis: a cone from a disc around `base`,
+    /// spanned by `u` and `v`, to a point further along `direction`.
+    fn cone(&mut self, base: Vec3, direction: Vec3, u: Vec3, v: Vec3, size: f32, color: Vec4) {
+        let tip = base + direction * TIP_LENGTH * size;
+        let rim = |segment: usize| {
+            let angle = segment as f32 / TIP_SEGMENTS as f32 * std::f32::consts::TAU;
+            base + (u * angle.cos() + v * angle.sin()) * TIP_RADIUS * size
+        };
+        for segment in 0..TIP_SEGMENTS {
+            let (a, b) = (rim(segment), rim(segment + 1));
+            self.triangle(tip, a, b, color);
+            self.triangle(base, b, a, color);
+        }
+    }
+}
+
+/// What a drag keeps the point under the cursor on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Constraint {
+    /// The line through the origin of the gizmo along a direction.
+    Line(Vec3),
+    /// The plane through the origin of the gizmo with a normal.
+    Plane(Vec3),
+}
+
+impl Constraint {
+    /// The point of the constraint under `ray`.
+    fn point(&self, origin: Vec3, ray: &Ray) -> Option<Vec3> {
+        match *self {
+            Constraint::Line(direction) => closest_to_line(ray, origin, direction)
+                .map(|closest| origin + direction * closest.along_line),
+            Constraint::Plane(normal) => intersect_plane(ray, origin, normal).map(|t| ray.at(t)),
+        }
+    }
+}
+
+/// A drag in progress. The transform is recomputed from where the drag
+/// started on every move, so it does not drift.
+#[derive(Debug, Clone, PartialEq)]
+struct GizmoDrag {
+    node: NodeId,
+    handle: GizmoHandle,
+    frame: GizmoFrame,
+    constraint: Constraint,
+    /// Where the drag started on the constraint.
+    anchor: Vec3,
+    /// Up on screen when the drag started, which a uniform scale grows
+    /// towards.
+    up: Vec3,
+    /// The transform of the node when the drag started, relative to its
+    /// parent.
+    start: InnerNode,
+    parent_matrix: Mat4,
+}
+
+/// Handles drawn around a node that move, rotate or scale it when dragged.
+/// Hit tests and drags are computed on the CPU from rays through the
+/// cursor; `geometry` gives what to draw.
+#[derive(Debug, Clone)]
+pub struct Gizmo {
+    mode: GizmoMode,
+    space: GizmoSpace,
+    snapping: Snapping,
+    snap: bool,
+    /// The length of the axes, in pixels.
+    size: f32,
+    hovered: Option<GizmoHandle>,
+    drag: Option<GizmoDrag>,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gizmo {
+    pub fn new() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            space: GizmoSpace::World,
+            snapping: Snapping::default(),
+            snap: false,
+            size: 100.0,
+            hovered: None,
+            drag: None,
+        }
+    }
+
+    pub fn mode(&self) -> GizmoMode {
+        self.mode
+    }
+
+    /// Changes what the gizmo does, ending any drag.
+    pub fn set_mode(&mut self, mode: GizmoMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.hovered = None;
+            self.drag = None;
+        }
+    }
+
+    pub fn space(&self) -> GizmoSpace {
+        self.space
+    }
+
+    pub fn set_space(&mut self, space: GizmoSpace) {
+        self.space = space;
+    }
+
+    pub fn snapping(&self) -> Snapping {
+        self.snapping
+    }
+
+    pub fn set_snapping(&mut self, snapping: Snapping) {
+        self.snapping = snapping;
+    }
+
+    pub fn is_snapping(&self) -> bool {
+        self.snap
+    }
+
+    /// Whether drags move in the steps of `snapping`.
+    pub fn set_snap(&mut self, snap: bool) {
+        self.snap = snap;
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Sets the length of the axes on screen, in pixels.
+    pub fn set_size(&mut self, size: f32) {
+        self.size = size.max(1.0);
+    }
+
+    pub fn hovered(&self) -> Option<GizmoHandle> {
+        self.hovered
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// The node being dragged.
+    pub fn dragged_node(&self) -> Option<NodeId> {
+        self.drag.as_ref().map(|drag| drag.node)
+    }
+
+    /// The handle being dragged, or else the one under the cursor.
+    pub fn active_handle(&self) -> Option<GizmoHandle> {
+        self.drag.as_ref().map(|drag| drag.handle).or(self.hovered)
+    }
+
+    /// Where the gizmo of a node whose world transform is `world_matrix`
+    /// is, keeping the same size on screen wherever the node is.
+    pub fn frame(&self, world_matrix: Mat4, view: &GizmoView) -> GizmoFrame {
+        let (_, rotation, origin) = world_matrix.to_scale_rotation_translation();
+        let axes = if self.mode == GizmoMode::Scale || self.space == GizmoSpace::Local {
+            [rotation * Vec3::X, rotation * Vec3::Y, rotation * Vec3::Z]
+        } else {
+            [Vec3::X, Vec3::Y, Vec3::Z]
+        };
+        GizmoFrame {
+            origin,
+            axes,
+            size: self.size * view.pixel_size(origin),
+        }
+    }
+
+    /// The handle closest along `ray` that it passes near.
+    pub fn hit_test(&self, frame: &GizmoFrame, ray: &Ray) -> Option<GizmoHandle> {
+        let tolerance = HANDLE_RADIUS * frame.size;
+        let mut closest: Option<(f32, GizmoHandle)> = None;
+        let mut consider = |t: Option<f32>, handle: GizmoHandle| match (t, closest) {
+            (Some(t), Some((closest_t, _))) if t >= closest_t => {}
+            (Some(t), _) => closest = Some((t, handle)),
+            (None, _) => {}
+        };
+
+        for axis in Axis::ALL {
+            let direction = frame.axis(axis);
+            match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let start = match self.mode {
+                        GizmoMode::Scale => UNIFORM_SIZE,
+                        _ => 0.0,
+                    };
+                    let t = closest_to_line(ray, frame.origin, direction).and_then(|closest| {
+                        let along = closest.along_line / frame.size;
+                        if closest.along_ray >= 0.0
+                            && (start..=AXIS_LENGTH + TIP_LENGTH).contains(&along)
+                            && closest.distance <= tolerance
+                        {
+                            Some(closest.along_ray)
+                        } else {
+                            None
+                        }
+                    });
+                    consider(t, GizmoHandle::Axis(axis));
+
+                    let (u, v) = axis.others();
+                    let t = intersect_plane(ray, frame.origin, direction).filter(|&t| {
+                        let offset = (ray.at(t) - frame.origin) / frame.size;
+                        let range = PLANE_START..=PLANE_END;
+                        range.contains(&offset.dot(frame.axis(u)))
+                            && range.contains(&offset.dot(frame.axis(v)))
+                    });
+                    consider(t, GizmoHandle::Plane(axis));
+                }
+                GizmoMode::Rotate => {
+                    let t = intersect_plane(ray, frame.origin, direction).filter(|&t| {
+                        let radius = (ray.at(t) - frame.origin).length();
+                        (radius - RING_RADIUS * frame.size).abs() <= tolerance
+                    });
+                    consider(t, GizmoHandle::Axis(axis));
+                }
+            }
+        }
+
+        if self.mode == GizmoMode::Scale {
+            let to_origin = frame.origin - ray.origin;
+            let t = to_origin.dot(ray.direction) / ray.direction.length_squared();
+            let distance = (ray.at(t) - frame.origin).length();
+            if t >= 0.0 && distance <= UNIFORM_SIZE * frame.size {
+                consider(Some(t), GizmoHandle::Uniform);
+            }
+        }
+
+        closest.map(|(_, handle)| handle)
+    }
+
+    /// Highlights the handle under `ray`, unless a drag is in progress.
+    pub fn hover(&mut self, frame: &GizmoFrame, ray: &Ray) -> Option<GizmoHandle> {
+        if self.drag.is_none() {
+            self.hovered = self.hit_test(frame, ray);
+        }
+        self.hovered
+    }
+
+    /// Starts dragging the handle under `ray`, if there is one. `start` is
+    /// the transform of `node` relative to its parent, and `parent_matrix`
+    /// the world transform of the parent.
+    pub fn begin_drag(
+        &mut self,
+        frame: &GizmoFrame,
+        ray: &Ray,
+        node: NodeId,
+        start: &InnerNode,
+        parent_matrix: Mat4,
+    ) -> bool {
+        let handle = match self.hit_test(frame, ray) {
+            Some(handle) => handle,
+            None => return false,
+        };
+        let toward_camera = -ray.direction.normalize();
+        let constraint = match (self.mode, handle) {
+            (GizmoMode::Rotate, GizmoHandle::Axis(axis)) | (_, GizmoHandle::Plane(axis)) => {
+                Constraint::Plane(frame.axis(axis))
+            }
+            (_, GizmoHandle::Axis(axis)) => Constraint::Line(frame.axis(axis)),
+            (_, GizmoHandle::Uniform) => Constraint::Plane(toward_camera),
+        };
+        let anchor = match constraint.point(frame.origin, ray) {
+            Some(anchor) => anchor,
+            None => return false,
+        };
+        let right = match (-toward_camera).cross(Vec3::Y).try_normalize() {
+            Some(right) => right,
+            None => Vec3::X,
+        };
+        self.hovered = Some(handle);
+        self.drag = Some(GizmoDrag {
+            node,
+            handle,
+            frame: *frame,
+            constraint,
+            anchor,
+            up: right.cross(-toward_camera),
+            start: start.clone(),
+            parent_matrix,
+        });
+        true
+    }
+
+    /// The transform, relative to its parent, the dragged node has with the
+    /// cursor under `ray`. `None` when nothing is dragged or the cursor is
+    /// off the constraint, as when looking along a plane.
+    pub fn drag(&self, ray: &Ray) -> Option<InnerNode> {
+        let drag = self.drag.as_ref()?;
+        let frame = &drag.frame;
+        let point = drag.constraint.point(frame.origin, ray)?;
+        let delta = point - drag.anchor;
+        let mut transform = drag.start.clone();
+
+        match (self.mode, drag.handle) {
+            (GizmoMode::Translate, handle) => {
+                let axes = match handle {
+                    GizmoHandle::Axis(axis) => vec![axis],
+                    GizmoHandle::Plane(axis) => {
+                        let (u, v) = axis.others();
+                        vec![u, v]
+                    }
+                    GizmoHandle::Uniform => vec![],
+                };
+                let translation = axes.into_iter().fold(Vec3::ZERO, |translation, axis| {
+                    let direction = frame.axis(axis);
+                    let distance = self.snapped(delta.dot(direction), self.snapping.translation);
+                    translation + direction * distance
+                });
+                transform.position += drag.parent_matrix.inverse().transform_vector3(translation);
+            }
+            (GizmoMode::Rotate, handle) => {
+                let axis = match handle {
+                    GizmoHandle::Axis(axis) | GizmoHandle::Plane(axis) => frame.axis(axis),
+                    GizmoHandle::Uniform => return None,
+                };
+                let from = (drag.anchor - frame.origin).try_normalize()?;
+                let to = (point - frame.origin).try_normalize()?;
+                let angle = axis.dot(from.cross(to)).atan2(from.dot(to));
+                let angle = self.snapped(angle.to_degrees(), self.snapping.rotation);
+                let rotation = Quat::from_axis_angle(axis, angle.to_radians());
+                // the same rotation about the same axis, seen from the parent
+                let (_, parent_rotation, _) = drag.parent_matrix.to_scale_rotation_translation();
+                let rotation = parent_rotation.inverse() * rotation * parent_rotation;
+                transform.rotation = (rotation * drag.start.rotation).normalize();
+            }
+            (GizmoMode::Scale, handle) => {
+                let (axes, factor) = match handle {
+                    GizmoHandle::Axis(axis) => {
+                        let direction = frame.axis(axis);
+                        let factor = (point - frame.origin).dot(direction)
+                            / (drag.anchor - frame.origin).dot(direction);
+                        (vec![axis], factor)
+                    }
+                    GizmoHandle::Plane(axis) => {
+                        let (u, v) = axis.others();
+                        let diagonal = frame.axis(u) + frame.axis(v);
+                        let factor = (point - frame.origin).dot(diagonal)
+                            / (drag.anchor - frame.origin).dot(diagonal);
+                        (vec![u, v], factor)
+                    }
+                    GizmoHandle::Uniform => {
+                        let factor = 1.0 + delta.dot(drag.up) / frame.size;
+                        (Axis::ALL.to_vec(), factor)
+                    }
+                };
+                if !factor.is_finite() {
+                    return None;
+                }
+                let factor = self
+                    .snapped(factor, self.snapping.scale)
+                    .max(MIN_SCALE_FACTOR);
+                for axis in axes {
+                    transform.scale[axis.index()] *= factor;
+                }
+            }
+        }
+        Some(transform)
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    /// The lines and triangles of the handles of the current mode around
+    /// `frame`, with the active handle highlighted.
+    pub fn geometry(&self, frame: &GizmoFrame) -> GizmoGeometry {
+        let mut geometry = GizmoGeometry::default();
+        let active = self.active_handle();
+        let color = |handle: GizmoHandle, color: Vec4| {
+            if active == Some(handle) {
+                Vec4::from(HIGHLIGHT_COLOR)
+            } else {
+                color
+            }
+        };
+        let (origin, size) = (frame.origin, frame.size);
+
+        for axis in Axis::ALL {
+            let direction = frame.axis(axis);
+            let (u, v) = axis.others();
+            let (u, v) = (frame.axis(u), frame.axis(v));
+            let axis_color = color(GizmoHandle::Axis(axis), axis.color());
+            match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let end = origin + direction * AXIS_LENGTH * size;
+                    geometry.line(origin, end, axis_color);
+                    if self.mode == GizmoMode::Translate {
+                        geometry.cone(end, direction, u, v, size, axis_color);
+                    } else {
+                        geometry.cube(end, frame.axes, BOX_SIZE * size, axis_color);
+                    }
+
+                    let plane_color = color(
+                        GizmoHandle::Plane(axis),
+                        axis.color().truncate().extend(PLANE_ALPHA),
+                    );
+                    let corner = |a: f32, b: f32| origin + (u * a + v * b) * size;
+                    geometry.quad(
+                        [
+                            corner(PLANE_START, PLANE_START),
+                            corner(PLANE_END, PLANE_START),
+                            corner(PLANE_END, PLANE_END),
+                            corner(PLANE_START, PLANE_END),
+                        ],
+                        plane_color,
+                    );
+                }
+                GizmoMode::Rotate => {
+                    let point = |segment: usize| {
+                        let angle = segment as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                        origin + (u * angle.cos() + v * angle.sin()) * RING_RADIUS * size
+                    };
+                    for segment in 0..RING_SEGMENTS {
+                        geometry.line(point(segment), point(segment + 1), axis_color);
+                    }
+                }
+            }
+        }
+
+        if self.mode == GizmoMode::Scale {
+            geometry.cube(
+                origin,
+                frame.axes,
+                UNIFORM_SIZE * size,
+                color(GizmoHandle::Uniform, Vec4::from(UNIFORM_COLOR)),
+            );
+        }
+        geometry
+    }
+
+    fn snapped(&self, value: f32, step: f32) -> f32 {
+        if self.snap && step > 0.0 {
+            (value / step).round() * step
+        } else {
+            value
+        }
+    }
+}
+
+/// Where a ray passes closest to a line.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ClosestPoints {
+    along_ray: f32,
+    along_line: f32,
+    distance: f32,
+}
+
+/// The closest points of `ray` and the line through `origin` along the
+/// unit `direction`, or `None` if they are parallel.
+fn closest_to_line(ray: &Ray, origin: Vec3, direction: Vec3) -> Option<ClosestPoints> {
+    let to_ray = ray.origin - origin;
+    let a = ray.direction.length_squared();
+    let b = ray.direction.dot(direction);
+    let d = ray.direction.dot(to_ray);
+    let e = direction.dot(to_ray);
+    let denominator = a - b * b;
+    if denominator <= 1e-6 * a {
+        return None;
+    }
+    let along_ray = (b * e - d) / denominator;
+    let along_line = (a * e - b * d) / denominator;
+    let distance = (ray.at(along_ray) - (origin + direction * along_line)).length();
+    Some(ClosestPoints {
+        along_ray,
+        along_line,
+        distance,
+    })
+}
+
+/// Where `ray` crosses the plane through `point` with `normal`, ahead of
+/// its origin.
+fn intersect_plane(ray: &Ray, point: Vec3, normal: Vec3) -> Option<f32> {
+    let denominator = normal.dot(ray.direction);
+    if denominator.abs() <= 1e-6 * ray.direction.length() {
+        return None;
+    }
+    let t = normal.dot(point - ray.origin) / denominator;
+    if t >= 0.0 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{NodeContent, SceneGraph};
+
+    const SIZE: f32 = 1000.0;
+
+    fn view() -> GizmoView {
+        let projection = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let camera = Mat4::look_at_lh(Vec3::new(0.0, 0.0, -10.0), Vec3::ZERO, Vec3::Y);
+        GizmoView {
+            view_projection: projection * camera,
+            width: SIZE,
+            height: SIZE,
+        }
+    }
+
+    /// The pixel `point` is drawn at.
+    fn screen(point: Vec3) -> (f32, f32) {
+        let clip = view().view_projection * point.extend(1.0);
+        let ndc = clip.truncate() / clip.w;
+        ((ndc.x + 1.0) * 0.5 * SIZE, (1.0 - ndc.y) * 0.5 * SIZE)
+    }
+
+    fn ray_at(point: Vec3) -> Ray {
+        let (x, y) = screen(point);
+        view().ray(x, y)
+    }
+
+    fn node() -> NodeId {
+        let mut graph = SceneGraph::new();
+        graph.add(InnerNode::named("node"), NodeContent::Empty, None)
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-3
+    }
+
+    #[test]
+    fn frames_keep_their_size_on_screen() {
+        let gizmo = Gizmo::new();
+        let frame = gizmo.frame(Mat4::IDENTITY, &view());
+        // 100 pixels of a 1000 pixel, 90 degree view, 10 units away
+        assert!((frame.size - 2.0).abs() < 1e-3, "{}", frame.size);
+        let far = gizmo.frame(Mat4::from_translation(Vec3::new(0.0, 0.0, 10.0)), &view());
+        assert!((far.size - 4.0).abs() < 1e-3, "{}", far.size);
+        assert_eq!(far.origin, Vec3::new(0.0, 0.0, 10.0));
+    }
+
+    #[test]
+    fn local_frames_follow_the_node() {
+        let mut gizmo = Gizmo::new();
+        let matrix = Mat4::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        assert!(close(gizmo.frame(matrix, &view()).axes[0], Vec3::X));
+        gizmo.set_space(GizmoSpace::Local);
+        let frame = gizmo.frame(matrix, &view());
+        assert!(close(frame.axes[0], Vec3::new(0.0, 0.0, -1.0)));
+        assert_eq!(
+            gizmo.hit_test(&frame, &ray_at(Vec3::new(0.0, 1.0, 0.0))),
+            Some(GizmoHandle::Axis(Axis::Y))
+        );
+
+        // scaling is always along the axes of the node
+        gizmo.set_space(GizmoSpace::World);
+        gizmo.set_mode(GizmoMode::Scale);
+        assert!(close(
+            gizmo.frame(matrix, &view()).axes[0],
+            Vec3::new(0.0, 0.0, -1.0)
+        ));
+    }
+
+    #[test]
+    fn translate_hit_tests() {
+        let gizmo = Gizmo::new();
+        let frame = gizmo.frame(Mat4::IDENTITY, &view());
+        let hit = |point: Vec3| gizmo.hit_test(&frame, &ray_at(point));
+        assert_eq!(
+            hit(Vec3::new(1.0, 0.0, 0.0)),
+            Some(GizmoHandle::Axis(Axis::X))
+        );
+        assert_eq!(
+            hit(Vec3::new(0.0, 1.5, 0.0)),
+            Some(GizmoHandle::Axis(Axis::Y))
+        );
+        assert_eq!(
+            hit(Vec3::new(1.0, 0.1, 0.0)),
+            Some(GizmoHandle::Axis(Axis::X))
+        );
+        assert_eq!(
+            hit(Vec3::new(0.6, 0.6, 0.0)),
+            Some(GizmoHandle::Plane(Axis::Z))
+        );
+        assert_eq!(hit(Vec3::new(1.0, 0.5, 0.0)), None);
+        assert_eq!(hit(Vec3::new(-1.0, 0.0, 0.0)), None);
+        assert_eq!(hit(Vec3::new(3.0, 0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn rotate_and_scale_hit_tests() {
+        let mut gizmo = Gizmo::new();
+        gizmo.set_mode(GizmoMode::Rotate);
+        let frame = gizmo.frame(Mat4::IDENTITY, &view());
+        let angle = 0.7_f32;
+        let on_ring = Vec3::new(angle.cos(), angle.sin(), 0.0) * 2.0;
+        assert_eq!(
+            gizmo.hit_test(&frame, &ray_at(on_ring)),
+            Some(GizmoHandle::Axis(Axis::Z))
+        );
+        assert_eq!(gizmo.hit_test(&frame, &ray_at(on_ring * 0.5)), None);
+
+        gizmo.set_mode(GizmoMode::Scale);
+        let frame = gizmo.frame(Mat4::IDENTITY, &view());
+        assert_eq!(
+            gizmo.hit_test(&frame, &ray_at(Vec3::ZERO)),
+            Some(GizmoHandle::Uniform)
+        );
+        assert_eq!(
+            gizmo.hit_test(&frame, &ray_at(Vec3::new(2.0, 0.0, 0.0))),
+            Some(GizmoHandle::Axis(Axis::X))
+        );
+    }
+
+    #[test]
+    fn translating_along_an_axis() {
+        let mut gizmo = Gizmo::new();
+        let frame = gizmo.frame(Mat4::IDENTITY, &view());
+        let start = InnerNode::named("node");
+        assert!(!gizmo.begin_drag(
+            &frame,
+            &ray_at(Vec3::new(1.0, 0.5, 0.0)),
+            node(),
+            &start,
+            Mat4::IDENTITY
+        ));
+        assert!(gizmo.begin_drag(
+            &frame,
+            &ray_at(Vec3::new(1.0, 0.0, 0.0)),
+            node(),
+            &start,
+            Mat4::IDENTITY
+        ));
+        assert_eq!(gizmo.active_handle(), Some(GizmoHandle::Axis(Axis::X)));
+
+        let moved = gizmo.drag(&ray_at(Vec3::new(1.6, 0.0, 0.0))).unwrap();
+        assert!(close(moved.position(), Vec3::new(0.6, 0.0, 0.0)));
+        assert_eq!(moved.rotation(), start.rotation());
+        // off the axis it still only moves along it
+        let moved = gizmo.drag(&ray_at(Vec3::new(1.6, 0.5, 0.0))).unwrap();
+        assert_eq!(moved.position().y, 0.0);
+        assert_eq!(moved.position().z, 0.0);
+
+        gizmo.set_snap(true);
+        let snapped = gizmo.drag(&ray_at(Vec3::new(1.6, 0.0, 0.0))).unwrap();
+        assert!(close(snapped.position(), Vec3::new(0.5, 0.0, 0.0)));
+
+        gizmo.end_drag();
+        assert!(!gizmo.is_dragging());
+        assert_eq!(gizmo.drag(&ray_at(Vec3::ZERO)), None);
+    }
+
+    #[test]
+    fn translating_across_a_plane_under_a_scaled_parent() {
+        let mut gizmo = Gizmo::new();
+        let parent = Mat4::from_scale(Vec3::splat(2.0));
+        let start = InnerNode::named("node");
+        let frame = gizmo.frame(parent * start.model_matrix(), &view());
+        assert!(gizmo.begin_drag(
+            &frame,
+            &ray_at(Vec3::new(0.6, 0.6, 0.0)),
+            node(),
+            &start,
+            parent
+        ));
+        let moved = gizmo.drag(&ray_at(Vec3::new(1.6, -0.4, 0.0))).unwrap();
+        // a world move of (1, -1, 0) is half as far for the child
+        assert!(close(moved.position(), Vec3::new(0.5, -0.5, 0.0)));
+    }
+
+    #[test]
+    fn rotating_around_an_axis() {
+        let mut gizmo = Gizmo::new();
+        gizmo.set_mode(GizmoMode::Rotate);
+        let start = InnerNode::new(
+            "node".to_string(),
+            Vec3::ZERO,
+            Quat::from_rotation_x(0.3),
+            Vec3::ONE,
+        );
+        let frame = gizmo.frame(start.model_matrix(), &view());
+        // on the ring around Z, clear of the one around Y
+        assert!(gizmo.begin_drag(
+            &frame,
+            &ray_at(Vec3::new(0.0, -2.0, 0.0)),
+            node(),
+            &start,
+            Mat4::IDENTITY
+        ));
+        let rotated = gizmo.drag(&ray_at(Vec3::new(1.0, 0.0, 0.0))).unwrap();
+        let expected = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2) * start.rotation();
+        assert!(rotated.rotation().abs_diff_eq(expected, 1e-4));
+        assert_eq!(rotated.position(), Vec3::ZERO);
+
+        gizmo.set_snap(true);
+        let angle = (50.0_f32 - 90.0).to_radians();
+        let snapped = gizmo
+            .drag(&ray_at(Vec3::new(angle.cos(), angle.sin(), 0.0)))
+            .unwrap();
+        let expected = Quat::from_rotation_z(45.0_f32.to_radians()) * start.rotation();
+        assert!(snapped.rotation().abs_diff_eq(expected, 1e-4));
+    }
+
+    #[test]
+    fn rotating_under_a_rotated_parent() {
+        let mut gizmo = Gizmo::new();
+        gizmo.set_mode(GizmoMode::Rotate);
+        let parent = Mat4::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let start = InnerNode::named("node");
+        let frame = gizmo.frame(parent, &view());
+        // on the ring around Z only
+        let angle = 0.3_f32;
+        assert!(gizmo.begin_drag(
+            &frame,
+            &ray_at(Vec3::new(angle.cos(), angle.sin(), 0.0) * 2.0),
+            node(),
+            &start,
+            parent
+        ));
+        let angle = angle + std::f32::consts::FRAC_PI_2;
+        let rotated = gizmo
+            .drag(&ray_at(Vec3::new(angle.cos(), angle.sin(), 0.0)))
+            .unwrap();
+        // a quarter turn around world Z, in world space
+        let world = parent * rotated.model_matrix();
+        assert!(close(world.transform_vector3(Vec3::Z), Vec3::Y));
+    }
+
+    #[test]
+    fn scaling() {
+        let mut gizmo = Gizmo::new();
+        gizmo.set_mode(GizmoMode::Scale);
+        let start = InnerNode::named("node");
+        let frame = gizmo.frame(Mat4::IDENTITY, &view());
+        assert!(gizmo.begin_drag(
+            &frame,
+            &ray_at(Vec3::new(1.0, 0.0, 0.0)),
+            node(),
+            &start,
+            Mat4::IDENTITY
+        ));
+        let scaled = gizmo.drag(&ray_at(Vec3::new(2.5, 0.0, 0.0))).unwrap();
+        assert!(close(scaled.scale(), Vec3::new(2.5, 1.0, 1.0)));
+        // never through zero
+        let flat = gizmo.drag(&ray_at(Vec3::new(-1.0, 0.0, 0.0))).unwrap();
+        assert!(close(flat.scale(), Vec3::new(0.01, 1.0, 1.0)));
+        gizmo.end_drag();
+
+        assert!(gizmo.begin_drag(&frame, &ray_at(Vec3::ZERO), node(), &start, Mat4::IDENTITY));
+        // half the length of the axes upwards grows by half
+        let scaled = gizmo.drag(&ray_at(Vec3::new(0.0, 1.0, 0.0))).unwrap();
+        assert!(close(scaled.scale(), Vec3::splat(1.5)));
+        gizmo.set_snap(true);
+        gizmo.set_snapping(Snapping {
+            scale: 1.0,
+            ..Snapping::default()
+        });
+        let snapped = gizmo.drag(&ray_at(Vec3::new(0.0, 1.2, 0.0))).unwrap();
+        assert!(close(snapped.scale(), Vec3::splat(2.0)));
+    }
+
+    #[test]
+    fn changing_mode_ends_the_drag() {
+        let mut gizmo = Gizmo::new();
+        let frame = gizmo.frame(Mat4::IDENTITY, &view());
+        let node = node();
+        assert!(gizmo.begin_drag(
+            &frame,
+            &ray_at(Vec3::new(1.0, 0.0, 0.0)),
+            node,
+            &InnerNode::named("node"),
+            Mat4::IDENTITY
+        ));
+        assert_eq!(gizmo.dragged_node(), Some(node));
+        gizmo.set_mode(GizmoMode::Rotate);
+        assert!(!gizmo.is_dragging());
+        assert_eq!(gizmo.active_handle(), None);
+    }
+
+    #[test]
+    fn geometry_highlights_the_hovered_handle() {
+        let mut gizmo = Gizmo::new();
+        let frame = gizmo.frame(Mat4::IDENTITY, &view());
+        let geometry = gizmo.geometry(&frame);
+        assert_eq!(geometry.lines.len(), 6);
+        assert_eq!(geometry.triangles.len(), 3 * (12 * 2 * 3 + 6));
+        assert!(geometry
+            .lines
+            .iter()
+            .all(|vertex| vertex.color != Vec4::new(1.0, 0.85, 0.1, 1.0)));
+
+        assert_eq!(
+            gizmo.hover(&frame, &ray_at(Vec3::new(0.0, 1.0, 0.0))),
+            Some(GizmoHandle::Axis(Axis::Y))
+        );
+        let geometry = gizmo.geometry(&frame);
+        assert_eq!(geometry.lines[2].color, Vec4::new(1.0, 0.85, 0.1, 1.0));
+        assert_eq!(geometry.lines[0].color, Vec4::new(0.9, 0.2, 0.2, 1.0));
+
+        gizmo.set_mode(GizmoMode::Rotate);
+        assert_eq!(gizmo.geometry(&frame).lines.len(), 3 * 64 * 2);
+    }
+}
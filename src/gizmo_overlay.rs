@@ -0,0 +1,104 @@
+use crate::gizmo::{GizmoGeometry, GizmoVertex};
+use crate::renderer::COLOR_PIXEL_FORMAT;
+use crate::shader_bindings::{
+    BufferIndices_BufferIndexUniforms as BufferIndexUniforms,
+    BufferIndices_BufferIndexVertices as BufferIndexVertices, OverlayVertex, Uniforms,
+};
+use crate::simd;
+use metal::*;
+use std::mem;
+
+/// Draws the lines and triangles of a gizmo on top of everything else, in
+/// a pass of its own with no depth test, so that no geometry hides it.
+pub struct GizmoOverlay {
+    pipeline_state: RenderPipelineState,
+}
+
+impl GizmoOverlay {
+    pub fn new(library: &Library, device: &Device) -> Self {
+        Self {
+            pipeline_state: Self::build_pipeline_state(library, device),
+        }
+    }
+
+    /// Encodes `geometry` into `target`. `uniforms` hold the view and
+    /// projection of the main pass.
+    pub fn render(
+        &self,
+        device: &Device,
+        command_buffer: &CommandBufferRef,
+        target: &TextureRef,
+        geometry: &GizmoGeometry,
+        uniforms: &[Uniforms],
+    ) {
+        let render_pass_descriptor = RenderPassDescriptor::new();
+        let color_attachment = render_pass_descriptor
+            .color_attachments()
+            .object_at(0)
+            .unwrap();
+        color_attachment.set_texture(Some(target));
+        color_attachment.set_load_action(MTLLoadAction::Load);
+        color_attachment.set_store_action(MTLStoreAction::Store);
+
+        let render_encoder = command_buffer.new_render_command_encoder(render_pass_descriptor);
+        render_encoder.push_debug_group("gizmo");
+        render_encoder.set_render_pipeline_state(&self.pipeline_state);
+        render_encoder.set_vertex_bytes(
+            BufferIndexUniforms as u64,
+            mem::size_of::<Uniforms>() as u64,
+            uniforms.as_ptr() as *const _,
+        );
+
+        // triangles first, so the lines stay visible through the planes
+        let primitives = [
+            (MTLPrimitiveType::Triangle, &geometry.triangles),
+            (MTLPrimitiveType::Line, &geometry.lines),
+        ];
+        for (primitive_type, vertices) in primitives {
+            if vertices.is_empty() {
+                continue;
+            }
+            let vertices: Vec<OverlayVertex> = vertices.iter().map(overlay_vertex).collect();
+            // too many for set_vertex_bytes
+            let buffer = device.new_buffer_with_data(
+                vertices.as_ptr() as *const _,
+                mem::size_of::<OverlayVertex>() as u64 * vertices.len() as u64,
+                MTLResourceOptions::CPUCacheModeDefaultCache
+                    | MTLResourceOptions::StorageModeShared,
+            );
+            render_encoder.set_vertex_buffer(BufferIndexVertices as u64, Some(&buffer), 0);
+            render_encoder.draw_primitives(primitive_type, 0, vertices.len() as u64);
+        }
+
+        render_encoder.pop_debug_group();
+        render_encoder.end_encoding();
+    }
+
+    fn build_pipeline_state(library: &Library, device: &Device) -> RenderPipelineState {
+        let vertex_function = library.get_function("vertex_overlay", None).unwrap();
+        let fragment_function = library.get_function("fragment_overlay", None).unwrap();
+
+        let pipeline_state_descriptor = RenderPipelineDescriptor::new();
+        pipeline_state_descriptor.set_vertex_function(Some(&vertex_function));
+        pipeline_state_descriptor.set_fragment_function(Some(&fragment_function));
+        let color_attachment = pipeline_state_descriptor
+            .color_attachments()
+            .object_at(0)
+            .unwrap();
+        color_attachment.set_pixel_format(COLOR_PIXEL_FORMAT);
+        color_attachment.set_blending_enabled(true);
+        color_attachment.set_source_rgb_blend_factor(MTLBlendFactor::SourceAlpha);
+        color_attachment.set_destination_rgb_blend_factor(MTLBlendFactor::OneMinusSourceAlpha);
+
+        device
+            .new_render_pipeline_state(&pipeline_state_descriptor)
+            .unwrap()
+    }
+}
+
+fn overlay_vertex(vertex: &GizmoVertex) -> OverlayVertex {
+    OverlayVertex {
+        position: simd::vector4(vertex.position.extend(1.0)),
+        color: simd::vector4(vertex.color),
+    }
+}
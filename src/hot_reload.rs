@@ -0,0 +1,354 @@
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// Editors save in bursts of writes and renames; they are merged into one
+/// event per file over this long.
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(250);
+
+/// A change to a file on disk, as reported by the watcher.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileEvent {
+    Changed(PathBuf),
+    Removed(PathBuf),
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+/// An asset that has to be loaded again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Reload {
+    /// The glTF file or one of its buffers changed. Holds the model name.
+    Model(String),
+    Texture(PathBuf),
+    Environment,
+}
+
+/// Decides which assets a batch of file events affects. Knows nothing about
+/// the watcher, so it can be fed synthetic events.
+#[derive(Default)]
+pub struct ReloadPlanner {
+    model_files: HashMap<PathBuf, String>,
+    texture_files: HashSet<PathBuf>,
+    environment_directories: Vec<PathBuf>,
+}
+
+impl ReloadPlanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the files watched for the model called `name`.
+    pub fn watch_model(&mut self, name: &str, files: impl IntoIterator<Item = PathBuf>) {
+        self.model_files.retain(|_, model| model != name);
+        for file in files {
+            self.model_files.insert(normalize(&file), name.to_string());
+        }
+    }
+
+    pub fn watch_texture(&mut self, path: &Path) {
+        self.texture_files.insert(normalize(path));
+    }
+
+    /// Any file in `directory` or below it belongs to the environment.
+    pub fn watch_environment(&mut self, directory: &Path) {
+        let directory = normalize(directory);
+        if !self.environment_directories.contains(&directory) {
+            self.environment_directories.push(directory);
+        }
+    }
+
+    /// The directories to watch, and whether to watch them recursively.
+    /// Directories rather than files are watched, since saving often replaces
+    /// a file instead of writing to it.
+    pub fn directories(&self) -> Vec<(PathBuf, bool)> {
+        let mut directories: Vec<(PathBuf, bool)> = self
+            .environment_directories
+            .iter()
+            .map(|directory| (directory.clone(), true))
+            .collect();
+
+        let files = self.model_files.keys().chain(self.texture_files.iter());
+        for directory in files.filter_map(|file| file.parent()) {
+            let covered = directories.iter().any(|(watched, recursive)| {
+                watched == directory || *recursive && directory.starts_with(watched)
+            });
+            if !covered {
+                directories.push((directory.to_path_buf(), false));
+            }
+        }
+        directories
+    }
+
+    /// The assets affected by `events`, each once, in the order they were
+    /// first touched. Removed files are skipped; they are reloaded once
+    /// something is written in their place.
+    pub fn plan(&self, events: impl IntoIterator<Item = FileEvent>) -> Vec<Reload> {
+        let mut reloads = vec![];
+        for event in events {
+            let path = match event {
+                FileEvent::Changed(path) => path,
+                FileEvent::Renamed { to, .. } => to,
+                FileEvent::Removed(_) => continue,
+            };
+            let path = normalize(&path);
+
+            let reload = if let Some(name) = self.model_files.get(&path) {
+                Reload::Model(name.clone())
+            } else if self.texture_files.contains(&path) {
+                Reload::Texture(path)
+            } else if self
+                .environment_directories
+                .iter()
+                .any(|directory| path.starts_with(directory))
+            {
+                Reload::Environment
+            } else {
+                continue;
+            };
+
+            if !reloads.contains(&reload) {
+                reloads.push(reload);
+            }
+        }
+        reloads
+    }
+}
+
+/// Texture keys hold canonical paths; events and registered files are
+/// compared in the same form. Paths that do not exist are kept as they are.
+fn normalize(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Watches the directories of a `ReloadPlanner` and turns what changed into
+/// reloads.
+pub struct HotReload {
+    planner: ReloadPlanner,
+    watcher: Option<RecommendedWatcher>,
+    receiver: Receiver<DebouncedEvent>,
+    watched: HashSet<PathBuf>,
+}
+
+impl HotReload {
+    /// Hot reload is disabled, with a message, if the platform watcher cannot
+    /// be started.
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = match notify::watcher(sender, DEBOUNCE_DELAY) {
+            Ok(watcher) => Some(watcher),
+            Err(error) => {
+                log::warn!("hot reload disabled: {}", error);
+                None
+            }
+        };
+
+        Self {
+            planner: ReloadPlanner::new(),
+            watcher,
+            receiver,
+            watched: HashSet::new(),
+        }
+    }
+
+    pub fn planner_mut(&mut self) -> &mut ReloadPlanner {
+        &mut self.planner
+    }
+
+    /// Starts watching directories the planner needs that are not watched yet.
+    pub fn update_watches(&mut self) {
+        let watcher = match &mut self.watcher {
+            Some(watcher) => watcher,
+            None => return,
+        };
+
+        for (directory, recursive) in self.planner.directories() {
+            if self.watched.contains(&directory) {
+                continue;
+            }
+            let mode = if recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            match watcher.watch(&directory, mode) {
+                Ok(()) => {
+                    self.watched.insert(directory);
+                }
+                Err(error) => log::warn!("unable to watch {}: {}", directory.display(), error),
+            }
+        }
+    }
+
+    /// Reloads for the file changes seen since the last call, without blocking.
+    pub fn poll(&mut self) -> Vec<Reload> {
+        let events = self.receiver.try_iter().filter_map(|event| match event {
+            DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
+                Some(FileEvent::Changed(path))
+            }
+            DebouncedEvent::Remove(path) => Some(FileEvent::Removed(path)),
+            DebouncedEvent::Rename(from, to) => Some(FileEvent::Renamed { from, to }),
+            DebouncedEvent::Error(error, path) => {
+                log::warn!("file watcher error {:?}: {}", path, error);
+                None
+            }
+            _ => None,
+        });
+        self.planner.plan(events.collect::<Vec<_>>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Instant;
+
+    /// An empty directory for one test, with the given files created in it.
+    fn test_directory(name: &str, files: &[&str]) -> PathBuf {
+        let directory = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&directory);
+        for file in files {
+            let path = directory.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"data").unwrap();
+        }
+        fs::canonicalize(&directory).unwrap()
+    }
+
+    fn planner(directory: &Path) -> ReloadPlanner {
+        let mut planner = ReloadPlanner::new();
+        planner.watch_model(
+            "model.gltf",
+            vec![directory.join("model.gltf"), directory.join("model.bin")],
+        );
+        planner.watch_texture(&directory.join("albedo.png"));
+        planner.watch_environment(&directory.join("environment"));
+        planner
+    }
+
+    const FILES: [&str; 5] = [
+        "model.gltf",
+        "model.bin",
+        "albedo.png",
+        "notes.txt",
+        "environment/specular/0.png",
+    ];
+
+    #[test]
+    fn plans_each_asset_once() {
+        let directory = test_directory("hot-reload-plan", &FILES);
+        let planner = planner(&directory);
+
+        let events = vec![
+            FileEvent::Changed(directory.join("albedo.png")),
+            FileEvent::Changed(directory.join("model.bin")),
+            FileEvent::Changed(directory.join("albedo.png")),
+            FileEvent::Removed(directory.join("model.gltf")),
+            FileEvent::Changed(directory.join("notes.txt")),
+            FileEvent::Renamed {
+                from: directory.join("model.gltf~"),
+                to: directory.join("model.gltf"),
+            },
+            FileEvent::Changed(directory.join("environment/specular/0.png")),
+            FileEvent::Changed(PathBuf::from("/nowhere/albedo.png")),
+        ];
+        assert_eq!(
+            planner.plan(events),
+            [
+                Reload::Texture(directory.join("albedo.png")),
+                Reload::Model("model.gltf".to_string()),
+                Reload::Environment,
+            ]
+        );
+        assert!(planner
+            .plan(vec![FileEvent::Removed(directory.join("albedo.png"))])
+            .is_empty());
+    }
+
+    #[test]
+    fn watching_a_model_again_replaces_its_files() {
+        let directory = test_directory("hot-reload-replace", &FILES);
+        let mut planner = planner(&directory);
+        planner.watch_model("model.gltf", vec![directory.join("model.gltf")]);
+
+        let events = vec![FileEvent::Changed(directory.join("model.bin"))];
+        assert!(planner.plan(events).is_empty());
+    }
+
+    #[test]
+    fn directories_are_watched_once() {
+        let directory = test_directory("hot-reload-directories", &FILES);
+        let mut planner = planner(&directory);
+        planner.watch_environment(&directory.join("environment"));
+        planner.watch_texture(&directory.join("environment/specular/0.png"));
+
+        let directories = planner.directories();
+        assert_eq!(
+            directories,
+            [
+                (directory.join("environment"), true),
+                (directory.clone(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn polls_watcher_events() {
+        let directory = test_directory("hot-reload-poll", &FILES);
+        let (sender, receiver) = mpsc::channel();
+        let mut hot_reload = HotReload {
+            planner: planner(&directory),
+            watcher: None,
+            receiver,
+            watched: HashSet::new(),
+        };
+        assert!(hot_reload.poll().is_empty());
+
+        for event in [
+            DebouncedEvent::NoticeWrite(directory.join("albedo.png")),
+            DebouncedEvent::Remove(directory.join("model.gltf")),
+            DebouncedEvent::Rename(directory.join("model.tmp"), directory.join("model.gltf")),
+            DebouncedEvent::Write(directory.join("albedo.png")),
+            DebouncedEvent::Error(notify::Error::Generic("lost".to_string()), None),
+        ] {
+            sender.send(event).unwrap();
+        }
+        assert_eq!(
+            hot_reload.poll(),
+            [
+                Reload::Model("model.gltf".to_string()),
+                Reload::Texture(directory.join("albedo.png")),
+            ]
+        );
+        assert!(hot_reload.poll().is_empty());
+    }
+
+    #[test]
+    fn bursts_of_writes_are_debounced() {
+        let directory = test_directory("hot-reload-debounce", &FILES);
+        let mut hot_reload = HotReload::new();
+        hot_reload
+            .planner_mut()
+            .watch_texture(&directory.join("albedo.png"));
+        hot_reload.update_watches();
+
+        for _ in 0..3 {
+            fs::write(directory.join("albedo.png"), b"changed").unwrap();
+        }
+        assert!(hot_reload.poll().is_empty());
+
+        let start = Instant::now();
+        let mut reloads = vec![];
+        while reloads.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(DEBOUNCE_DELAY / 5);
+            reloads = hot_reload.poll();
+        }
+        assert!(start.elapsed() >= DEBOUNCE_DELAY / 2);
+        assert_eq!(reloads, [Reload::Texture(directory.join("albedo.png"))]);
+
+        std::thread::sleep(DEBOUNCE_DELAY * 2);
+        assert!(hot_reload.poll().is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+use crate::bounds::Bounds;
+use crate::lod::LodSettings;
+use crate::mesh_optimizer::MeshOptimization;
+use crate::meshlet::MeshletSettings;
+use glam::{Mat3, Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// The axis a model file treats as up. The viewer, like glTF, is Y-up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum UpAxis {
+    X,
+    Y,
+    /// Most CAD packages, Blender and 3ds Max exports.
+    Z,
+}
+
+/// The length one unit in a model file stands for. The viewer, like glTF,
+/// works in meters.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Unit {
+    Meters,
+    Centimeters,
+    Millimeters,
+    Inches,
+    Feet,
+    /// Any other unit, as its length in meters.
+    Custom(f32),
+}
+
+impl Unit {
+    pub fn meters(self) -> f32 {
+        match self {
+            Unit::Meters => 1.0,
+            Unit::Centimeters => 0.01,
+            Unit::Millimeters => 0.001,
+            Unit::Inches => 0.0254,
+            Unit::Feet => 0.3048,
+            Unit::Custom(meters) => meters,
+        }
+    }
+}
+
+/// How the geometry of a model is converted while it is imported, so that it
+/// shows up Y-up, in meters and optionally centered without per-model fixes,
+/// and how its meshes are prepared for drawing.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ImportOptions {
+    pub up_axis: UpAxis,
+    pub unit: Unit,
+    /// Moves the center of the bounding box to the origin.
+    pub recenter: bool,
+    /// Optimizes each primitive before it is cached. Off by default, so
+    /// vertices and indices stay in file order.
+    pub optimization: Option<MeshOptimization>,
+    /// Builds simplified levels of detail for each primitive. Works best on
+    /// welded meshes.
+    pub lod: Option<LodSettings>,
+    /// Splits each primitive into meshlets that are culled one by one.
+    pub meshlets: Option<MeshletSettings>,
+}
+
+impl Default for ImportOptions {
+    /// glTF files need no conversion.
+    fn default() -> Self {
+        Self {
+            up_axis: UpAxis::Y,
+            unit: Unit::Meters,
+            recenter: false,
+            optimization: None,
+            lod: None,
+            meshlets: None,
+        }
+    }
+}
+
+impl ImportOptions {
+    pub fn new(up_axis: UpAxis, unit: Unit, recenter: bool) -> Self {
+        Self {
+            up_axis,
+            unit,
+            recenter,
+            optimization: None,
+            lod: None,
+            meshlets: None,
+        }
+    }
+
+    pub fn with_optimization(mut self, optimization: MeshOptimization) -> Self {
+        self.optimization = Some(optimization);
+        self
+    }
+
+    pub fn with_meshlets(mut self, meshlets: MeshletSettings) -> Self {
+        self.meshlets = Some(meshlets);
+        self
+    }
+
+    pub fn with_lods(mut self, lod: LodSettings) -> Self {
+        self.lod = Some(lod);
+        self
+    }
+
+    /// Whether the geometry is used as it is in the file. Optimization, levels
+    /// of detail and meshlets do not move vertices, so they are not taken
+    /// into account.
+    pub fn is_identity(&self) -> bool {
+        self.up_axis == UpAxis::Y && self.unit.meters() == 1.0 && !self.recenter
+    }
+
+    /// Rotates the up axis onto +Y, keeping the handedness of the file.
+    pub fn axis_rotation(&self) -> Mat3 {
+        match self.up_axis {
+            UpAxis::X => Mat3::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            UpAxis::Y => Mat3::IDENTITY,
+            UpAxis::Z => Mat3::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+        }
+    }
+
+    /// The conversion for a model whose geometry spans `bounds` in file
+    /// space: the axis rotation and unit scale, then the recentering.
+    pub fn matrix(&self, bounds: &Bounds) -> Mat4 {
+        let linear = Mat4::from_mat3(self.axis_rotation().mul_scalar(self.unit.meters()));
+        if !self.recenter || bounds.is_empty() {
+            return linear;
+        }
+
+        let center = bounds.transform(linear).center();
+        Mat4::from_translation(-center) * linear
+    }
+
+    /// Converts a direction such as a normal or a tangent. The scale is
+    /// uniform, so only the rotation matters.
+    pub fn transform_direction(&self, direction: Vec3) -> Vec3 {
+        self.axis_rotation() * direction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn parses_defaults() {
+        let options: ImportOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options, ImportOptions::default());
+        assert!(options.is_identity());
+        assert_eq!(options.matrix(&Bounds::EMPTY), Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn parses_every_field() {
+        let options: ImportOptions = serde_json::from_str(
+            r#"{
+                "upAxis": "Z",
+                "unit": "millimeters",
+                "recenter": true,
+                "optimization": { "weld": false },
+                "lod": { "levels": 2 },
+                "meshlets": { "maxVertices": 32 }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(options.up_axis, UpAxis::Z);
+        assert_eq!(options.unit, Unit::Millimeters);
+        assert!(options.recenter);
+        let optimization = options.optimization.unwrap();
+        assert!(!optimization.weld);
+        assert!(optimization.vertex_cache);
+        let lod = options.lod.unwrap();
+        assert_eq!(lod.levels, 2);
+        assert_eq!(lod.reduction, LodSettings::default().reduction);
+        let meshlets = options.meshlets.unwrap();
+        assert_eq!(meshlets.max_vertices, 32);
+        assert_eq!(
+            meshlets.max_triangles,
+            MeshletSettings::default().max_triangles
+        );
+    }
+
+    #[test]
+    fn parses_custom_units() {
+        let options: ImportOptions =
+            serde_json::from_str(r#"{ "unit": { "custom": 2.5 } }"#).unwrap();
+        assert_eq!(options.unit, Unit::Custom(2.5));
+        assert_eq!(options.unit.meters(), 2.5);
+        assert!(!options.is_identity());
+    }
+
+    #[test]
+    fn rejects_unknown_values() {
+        assert!(serde_json::from_str::<ImportOptions>(r#"{ "upAxis": "W" }"#).is_err());
+        assert!(serde_json::from_str::<ImportOptions>(r#"{ "unit": "furlongs" }"#).is_err());
+        assert!(serde_json::from_str::<ImportOptions>(r#"{ "recenter": "yes" }"#).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let options = ImportOptions::new(UpAxis::X, Unit::Custom(0.5), true)
+            .with_optimization(MeshOptimization::default())
+            .with_lods(LodSettings::default())
+            .with_meshlets(MeshletSettings::default());
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ImportOptions>(&json).unwrap(),
+            options
+        );
+    }
+
+    #[test]
+    fn converts_z_up_millimeters() {
+        let options = ImportOptions::new(UpAxis::Z, Unit::Millimeters, false);
+        let matrix = options.matrix(&Bounds::EMPTY);
+        assert_close(
+            matrix.transform_point3(Vec3::new(0.0, 0.0, 1000.0)),
+            Vec3::Y,
+        );
+        assert_close(
+            matrix.transform_point3(Vec3::new(0.0, 1000.0, 0.0)),
+            -Vec3::Z,
+        );
+        assert_close(
+            matrix.transform_point3(Vec3::new(500.0, 0.0, 0.0)),
+            Vec3::X * 0.5,
+        );
+        assert_close(options.transform_direction(Vec3::Z), Vec3::Y);
+        assert!(matrix.determinant() > 0.0);
+    }
+
+    #[test]
+    fn converts_x_up_inches() {
+        let options = ImportOptions::new(UpAxis::X, Unit::Inches, false);
+        let matrix = options.matrix(&Bounds::EMPTY);
+        assert_close(matrix.transform_point3(Vec3::X), Vec3::Y * 0.0254);
+        assert_close(matrix.transform_point3(Vec3::Y), -Vec3::X * 0.0254);
+    }
+
+    #[test]
+    fn recenters_on_the_bounds() {
+        let bounds = Bounds::new(
+            Vec3::new(100.0, 200.0, 0.0),
+            Vec3::new(300.0, 400.0, 1000.0),
+        );
+        let options = ImportOptions::new(UpAxis::Z, Unit::Millimeters, true);
+        let converted = bounds.transform(options.matrix(&bounds));
+        assert_close(converted.center(), Vec3::ZERO);
+        assert_close(converted.size(), Vec3::new(0.2, 1.0, 0.2));
+
+        let options = ImportOptions::new(UpAxis::Y, Unit::Meters, true);
+        assert!(!options.is_identity());
+        assert_close(
+            options.matrix(&bounds).transform_point3(bounds.center()),
+            Vec3::ZERO,
+        );
+        assert_eq!(options.matrix(&Bounds::EMPTY), Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn conjugated_node_transforms_match_converted_vertices() {
+        let options = ImportOptions::new(UpAxis::Z, Unit::Centimeters, true);
+        let bounds = Bounds::new(Vec3::splat(-3.0), Vec3::new(5.0, 1.0, 9.0));
+        let conversion = options.matrix(&bounds);
+        let node = Mat4::from_scale_rotation_translation(
+            Vec3::splat(2.0),
+            Quat::from_rotation_y(0.7),
+            Vec3::new(1.0, 2.0, 3.0),
+        );
+        let converted_node = conversion * node * conversion.inverse();
+        let point = Vec3::new(0.3, -4.0, 2.0);
+        assert_close(
+            converted_node.transform_point3(conversion.transform_point3(point)),
+            conversion.transform_point3(node.transform_point3(point)),
+        );
+    }
+}
@@ -0,0 +1,874 @@
+use crate::compression::{self, DecodeError, MeshoptFilter, MeshoptMode};
+use crate::draco::{self, DracoError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const EXT_MESHOPT_COMPRESSION: &str = "EXT_meshopt_compression";
+const KHR_DRACO_MESH_COMPRESSION: &str = "KHR_draco_mesh_compression";
+
+/// A glTF document with its buffers loaded and any compressed buffer views
+/// and primitives already decoded, so primitives can be read with the regular
+/// `gltf` readers.
+pub struct GltfImport {
+    /// The file the document was read from.
+    pub path: PathBuf,
+    pub document: gltf::Document,
+    pub buffers: Vec<gltf::buffer::Data>,
+    /// The raw JSON root, for extensions `gltf` does not expose.
+    pub json: Value,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Gltf(gltf::Error),
+    Json(serde_json::Error),
+    Base64(base64::DecodeError),
+    Decode {
+        buffer_view: usize,
+        error: DecodeError,
+    },
+    Draco {
+        mesh: usize,
+        primitive: usize,
+        error: DracoError,
+    },
+    /// A Draco primitive names an attribute its bitstream does not have.
+    MissingDracoAttribute {
+        mesh: usize,
+        primitive: usize,
+        id: usize,
+    },
+    MissingBuffer(usize),
+    /// The image is not stored in a buffer view, or the view is out of range.
+    MissingImage(usize),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(error) => write!(f, "{}", error),
+            ImportError::Gltf(error) => write!(f, "{}", error),
+            ImportError::Json(error) => write!(f, "{}", error),
+            ImportError::Base64(error) => write!(f, "{}", error),
+            ImportError::Decode { buffer_view, error } => {
+                write!(f, "failed to decode buffer view {}: {}", buffer_view, error)
+            }
+            ImportError::Draco {
+                mesh,
+                primitive,
+                error,
+            } => write!(
+                f,
+                "failed to decode primitive {} of mesh {}: {}",
+                primitive, mesh, error
+            ),
+            ImportError::MissingDracoAttribute {
+                mesh,
+                primitive,
+                id,
+            } => write!(
+                f,
+                "primitive {} of mesh {} has no Draco attribute {}",
+                primitive, mesh, id
+            ),
+            ImportError::MissingBuffer(index) => write!(f, "buffer {} has no data", index),
+            ImportError::MissingImage(index) => {
+                write!(f, "image {} has no buffer view data", index)
+            }
+            ImportError::Image(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(error: std::io::Error) -> Self {
+        ImportError::Io(error)
+    }
+}
+
+impl From<gltf::Error> for ImportError {
+    fn from(error: gltf::Error) -> Self {
+        ImportError::Gltf(error)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(error: serde_json::Error) -> Self {
+        ImportError::Json(error)
+    }
+}
+
+impl From<image::ImageError> for ImportError {
+    fn from(error: image::ImageError) -> Self {
+        ImportError::Image(error)
+    }
+}
+
+/// Lets texture decoding, which reports `ImageError`s, read images embedded
+/// in a glTF file.
+impl From<ImportError> for image::ImageError {
+    fn from(error: ImportError) -> Self {
+        match error {
+            ImportError::Image(error) => error,
+            ImportError::Io(error) => image::ImageError::IoError(error),
+            error => image::ImageError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                error.to_string(),
+            )),
+        }
+    }
+}
+
+impl From<base64::DecodeError> for ImportError {
+    fn from(error: base64::DecodeError) -> Self {
+        ImportError::Base64(error)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MeshoptBufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: usize,
+    count: usize,
+    mode: MeshoptMode,
+    #[serde(default)]
+    filter: MeshoptFilter,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DracoPrimitive {
+    buffer_view: usize,
+    /// Draco attribute ids by glTF attribute name.
+    attributes: BTreeMap<String, usize>,
+}
+
+pub fn import(path: &Path) -> Result<GltfImport, ImportError> {
+    import_slice(path, &std::fs::read(path)?)
+}
+
+/// Imports the glTF or GLB `bytes` of the file at `path`, which relative
+/// URIs are resolved against.
+fn import_slice(path: &Path, bytes: &[u8]) -> Result<GltfImport, ImportError> {
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+
+    // Draco-only accessors have no buffer views until their primitives are
+    // decoded, so the document is validated afterwards.
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice_without_validation(bytes)?;
+    let mut json: Value = if bytes.starts_with(b"glTF") {
+        serde_json::from_slice(&gltf::Glb::from_slice(bytes)?.json)?
+    } else {
+        serde_json::from_slice(bytes)?
+    };
+
+    let mut buffers = load_buffers(&document, &json, base, blob)?;
+    decompress_buffer_views(&json, &mut buffers)?;
+    let document = if decompress_draco_primitives(&mut json, &mut buffers)? {
+        gltf::Document::from_json(serde_json::from_value(json.clone())?)?
+    } else {
+        gltf::Document::from_json(document.into_json())?
+    };
+
+    Ok(GltfImport {
+        path: path.to_path_buf(),
+        document,
+        buffers,
+        json,
+    })
+}
+
+impl GltfImport {
+    /// The encoded bytes of image `index`, which is stored in a buffer view,
+    /// as in `.glb` files.
+    pub fn image_bytes(&self, index: usize) -> Result<&[u8], ImportError> {
+        let view = match self
+            .document
+            .images()
+            .nth(index)
+            .map(|image| image.source())
+        {
+            Some(gltf::image::Source::View { view, .. }) => view,
+            _ => return Err(ImportError::MissingImage(index)),
+        };
+
+        let start = view.offset();
+        let end = start
+            .checked_add(view.length())
+            .ok_or(ImportError::MissingImage(index))?;
+        self.buffers
+            .get(view.buffer().index())
+            .and_then(|buffer| buffer.get(start..end))
+            .ok_or(ImportError::MissingImage(index))
+    }
+}
+
+/// The nodes of the default scene, or of the first scene, each after its
+/// parent and with the position of the parent in the list. Files without
+/// scenes show every node that is no other node's child. A node reached a
+/// second time, which valid files do not have, is skipped.
+pub fn scene_nodes(document: &gltf::Document) -> Vec<(gltf::Node<'_>, Option<usize>)> {
+    let roots: Vec<gltf::Node> = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().collect(),
+        None => {
+            let mut is_child = vec![false; document.nodes().len()];
+            for child in document.nodes().flat_map(|node| node.children()) {
+                is_child[child.index()] = true;
+            }
+            document
+                .nodes()
+                .filter(|node| !is_child[node.index()])
+                .collect()
+        }
+    };
+
+    let mut visited = vec![false; document.nodes().len()];
+    let mut nodes = vec![];
+    let mut pending: Vec<(gltf::Node, Option<usize>)> =
+        roots.into_iter().rev().map(|node| (node, None)).collect();
+    while let Some((node, parent)) = pending.pop() {
+        if std::mem::replace(&mut visited[node.index()], true) {
+            continue;
+        }
+        let position = nodes.len();
+        let children: Vec<gltf::Node> = node.children().collect();
+        pending.extend(
+            children
+                .into_iter()
+                .rev()
+                .map(|child| (child, Some(position))),
+        );
+        nodes.push((node, parent));
+    }
+    nodes
+}
+
+/// The encoded bytes of image `index` of the glTF file at `path`, read from
+/// its decoded buffers.
+pub fn read_image(path: &Path, index: usize) -> Result<Vec<u8>, ImportError> {
+    Ok(import(path)?.image_bytes(index)?.to_vec())
+}
+
+/// The glTF file at `path` and the buffer files stored next to it. Embedded
+/// buffers and the binary chunk of a `.glb` are part of the file itself.
+pub fn source_files(path: &Path) -> Result<Vec<PathBuf>, ImportError> {
+    let bytes = std::fs::read(path)?;
+    let mut files = vec![path.to_path_buf()];
+    if bytes.starts_with(b"glTF") {
+        return Ok(files);
+    }
+
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+    let json: Value = serde_json::from_slice(&bytes)?;
+    let uris = json["buffers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|buffer| buffer["uri"].as_str())
+        .filter(|uri| !uri.starts_with("data:"));
+    for uri in uris {
+        files.push(resolve_uri(base, uri));
+    }
+    Ok(files)
+}
+
+fn load_buffers(
+    document: &gltf::Document,
+    json: &Value,
+    base: &Path,
+    mut blob: Option<Vec<u8>>,
+) -> Result<Vec<gltf::buffer::Data>, ImportError> {
+    let mut buffers = Vec::new();
+
+    for buffer in document.buffers() {
+        let mut data = match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => read_uri(base, uri)?,
+            gltf::buffer::Source::Bin if buffer.index() == 0 && blob.is_some() => {
+                blob.take().unwrap()
+            }
+            gltf::buffer::Source::Bin => {
+                // Fallback buffers of compressed views have no data of their own;
+                // they are filled in when the views are decoded.
+                let extension = &json["buffers"][buffer.index()]["extensions"];
+                if extension[EXT_MESHOPT_COMPRESSION]["fallback"].as_bool() != Some(true) {
+                    return Err(ImportError::MissingBuffer(buffer.index()));
+                }
+                vec![0; buffer.length()]
+            }
+        };
+
+        if data.len() < buffer.length() {
+            return Err(ImportError::MissingBuffer(buffer.index()));
+        }
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        buffers.push(gltf::buffer::Data(data));
+    }
+
+    Ok(buffers)
+}
+
+fn read_uri(base: &Path, uri: &str) -> Result<Vec<u8>, ImportError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let encoded = data.split(";base64,").nth(1).unwrap_or(data);
+        return Ok(base64::decode(encoded)?);
+    }
+
+    Ok(std::fs::read(resolve_uri(base, uri))?)
+}
+
+/// The file a relative glTF `uri` refers to, next to the glTF file in `base`.
+pub fn resolve_uri(base: &Path, uri: &str) -> PathBuf {
+    base.join(uri.strip_prefix("file://").unwrap_or(uri))
+}
+
+fn decompress_buffer_views(
+    json: &Value,
+    buffers: &mut [gltf::buffer::Data],
+) -> Result<(), ImportError> {
+    let views = json["bufferViews"].as_array().into_iter().flatten();
+
+    for (index, view) in views.enumerate() {
+        let extension = &view["extensions"][EXT_MESHOPT_COMPRESSION];
+        if extension.is_null() {
+            continue;
+        }
+
+        let compressed = MeshoptBufferView::deserialize(extension)?;
+        let source = buffers
+            .get(compressed.buffer)
+            .zip(compressed.byte_offset.checked_add(compressed.byte_length))
+            .and_then(|(buffer, end)| buffer.get(compressed.byte_offset..end))
+            .ok_or(ImportError::MissingBuffer(compressed.buffer))?;
+
+        let decoded = compression::decode_meshopt(
+            source,
+            compressed.count,
+            compressed.byte_stride,
+            compressed.mode,
+            compressed.filter,
+        )
+        .map_err(|error| ImportError::Decode {
+            buffer_view: index,
+            error,
+        })?;
+
+        let target = view["buffer"].as_u64().unwrap_or(0) as usize;
+        let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let length = usize::min(
+            decoded.len(),
+            view["byteLength"].as_u64().unwrap_or(0) as usize,
+        );
+
+        buffers
+            .get_mut(target)
+            .zip(offset.checked_add(length))
+            .and_then(|(buffer, end)| buffer.0.get_mut(offset..end))
+            .ok_or(ImportError::MissingBuffer(target))?
+            .copy_from_slice(&decoded[..length]);
+    }
+
+    Ok(())
+}
+
+/// Decodes every `KHR_draco_mesh_compression` primitive into a buffer added
+/// after the others, and points the primitive at accessors over it.
+/// Returns whether `json` was changed, in which case the document has to be
+/// built from it again.
+fn decompress_draco_primitives(
+    json: &mut Value,
+    buffers: &mut Vec<gltf::buffer::Data>,
+) -> Result<bool, ImportError> {
+    let mut compressed = vec![];
+    for (mesh, mesh_json) in json["meshes"].as_array().into_iter().flatten().enumerate() {
+        let primitives = mesh_json["primitives"].as_array().into_iter().flatten();
+        for (primitive, primitive_json) in primitives.enumerate() {
+            let extension = &primitive_json["extensions"][KHR_DRACO_MESH_COMPRESSION];
+            if !extension.is_null() {
+                compressed.push((mesh, primitive, DracoPrimitive::deserialize(extension)?));
+            }
+        }
+    }
+    if compressed.is_empty() {
+        return Ok(false);
+    }
+
+    let buffer = buffers.len();
+    let mut data = vec![];
+
+    for (mesh, primitive, draco_primitive) in compressed {
+        let view = &json["bufferViews"][draco_primitive.buffer_view];
+        let source_buffer = view["buffer"].as_u64().unwrap_or(0) as usize;
+        let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let length = view["byteLength"].as_u64().unwrap_or(0) as usize;
+        let source = buffers
+            .get(source_buffer)
+            .zip(offset.checked_add(length))
+            .and_then(|(buffer, end)| buffer.get(offset..end))
+            .ok_or(ImportError::MissingBuffer(source_buffer))?;
+
+        let decoded = draco::decode_draco(source).map_err(|error| ImportError::Draco {
+            mesh,
+            primitive,
+            error,
+        })?;
+
+        let primitive_json = &json["meshes"][mesh]["primitives"][primitive];
+        let mut attributes = primitive_json["attributes"].clone();
+        let original_indices = primitive_json["indices"].as_u64();
+        for (name, &id) in &draco_primitive.attributes {
+            let attribute = decoded
+                .attribute(id)
+                .ok_or(ImportError::MissingDracoAttribute {
+                    mesh,
+                    primitive,
+                    id,
+                })?;
+            let original = attributes[name].as_u64();
+            let mut accessor =
+                original.map_or(json!({}), |index| json["accessors"][index as usize].clone());
+            if let Some(accessor) = accessor.as_object_mut() {
+                accessor.remove("byteOffset");
+                accessor.remove("sparse");
+            }
+            accessor["componentType"] = json!(attribute.data_type.as_gl_enum());
+            accessor["count"] = json!(decoded.vertex_count);
+            let index =
+                add_draco_accessor(json, &mut data, buffer, original, accessor, &attribute.data);
+            attributes[name] = json!(index);
+        }
+
+        let indices: Vec<u8> = decoded
+            .indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect();
+        let accessor = json!({
+            "componentType": gltf::accessor::DataType::U32.as_gl_enum(),
+            "count": decoded.indices.len(),
+            "type": "SCALAR",
+        });
+        let indices = add_draco_accessor(
+            json,
+            &mut data,
+            buffer,
+            original_indices,
+            accessor,
+            &indices,
+        );
+
+        let primitive_json = &mut json["meshes"][mesh]["primitives"][primitive];
+        primitive_json["attributes"] = attributes;
+        primitive_json["indices"] = json!(indices);
+    }
+
+    append(json, "buffers", vec![json!({ "byteLength": data.len() })]);
+    buffers.push(gltf::buffer::Data(data));
+    Ok(true)
+}
+
+/// Stores the decoded `bytes` of a Draco primitive in a buffer view of
+/// `buffer` and returns the index of `accessor` over it. The accessor replaces
+/// the `original` one if that is Draco-only, with no buffer view nothing else
+/// could read; otherwise it is added, as other primitives may share the
+/// original.
+fn add_draco_accessor(
+    json: &mut Value,
+    data: &mut Vec<u8>,
+    buffer: usize,
+    original: Option<u64>,
+    mut accessor: Value,
+    bytes: &[u8],
+) -> usize {
+    accessor["bufferView"] = json!(json["bufferViews"].as_array().map_or(0, Vec::len));
+    let view = json!({
+        "buffer": buffer,
+        "byteOffset": data.len(),
+        "byteLength": bytes.len(),
+    });
+    append(json, "bufferViews", vec![view]);
+    data.extend_from_slice(bytes);
+    while !data.len().is_multiple_of(4) {
+        data.push(0);
+    }
+
+    let original = original.map(|index| index as usize);
+    if let Some(index) = original {
+        if let Some(existing) = json["accessors"].get_mut(index) {
+            if existing["bufferView"].is_null() {
+                *existing = accessor;
+                return index;
+            }
+        }
+    }
+    let index = json["accessors"].as_array().map_or(0, Vec::len);
+    append(json, "accessors", vec![accessor]);
+    index
+}
+
+fn append(json: &mut Value, key: &str, values: Vec<Value>) {
+    match json[key].as_array_mut() {
+        Some(array) => array.extend(values),
+        None => json[key] = Value::Array(values),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(json: &str) -> gltf::Document {
+        gltf::Gltf::from_slice(json.as_bytes()).unwrap().document
+    }
+
+    /// A bumpy 4 by 4 grid of quads: positions, texture coordinates and
+    /// indices.
+    fn grid() -> (Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u32>) {
+        let size = 4;
+        let mut positions = vec![];
+        let mut uvs = vec![];
+        for y in 0..=size {
+            for x in 0..=size {
+                let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+                positions.push([u, v, (u * 6.0).sin() * (v * 4.0).cos() * 0.2]);
+                uvs.push([u, 1.0 - v]);
+            }
+        }
+
+        let row = size + 1;
+        let mut indices = vec![];
+        for y in 0..size {
+            for x in 0..size {
+                let corner = y * row + x;
+                indices.extend([corner, corner + 1, corner + row + 1]);
+                indices.extend([corner, corner + row + 1, corner + row]);
+            }
+        }
+        (positions, uvs, indices)
+    }
+
+    fn data_uri(bytes: &[u8]) -> String {
+        format!(
+            "data:application/octet-stream;base64,{}",
+            base64::encode(bytes)
+        )
+    }
+
+    /// The position, texture coordinate and index streams of the grid.
+    fn streams(positions: &[[f32; 3]], uvs: &[[f32; 2]], indices: &[u32]) -> [Vec<u8>; 3] {
+        [
+            positions
+                .iter()
+                .flatten()
+                .flat_map(|c| c.to_le_bytes())
+                .collect(),
+            uvs.iter().flatten().flat_map(|c| c.to_le_bytes()).collect(),
+            indices.iter().flat_map(|i| i.to_le_bytes()).collect(),
+        ]
+    }
+
+    /// Accessors 0 to 2 for the grid, over buffer views 0 to 2 if `views`.
+    fn accessors(vertex_count: usize, index_count: usize, views: bool) -> Value {
+        let mut accessors = json!([
+            {
+                "componentType": 5126,
+                "count": vertex_count,
+                "type": "VEC3",
+                "min": [0.0, 0.0, -1.0],
+                "max": [1.0, 1.0, 1.0],
+            },
+            { "componentType": 5126, "count": vertex_count, "type": "VEC2" },
+            { "componentType": 5125, "count": index_count, "type": "SCALAR" },
+        ]);
+        if views {
+            for view in 0..3 {
+                accessors[view]["bufferView"] = json!(view);
+            }
+        }
+        accessors
+    }
+
+    fn uncompressed(positions: &[[f32; 3]], uvs: &[[f32; 2]], indices: &[u32]) -> Value {
+        let mut data = vec![];
+        let mut views = vec![];
+        for stream in streams(positions, uvs, indices) {
+            views.push(json!({
+                "buffer": 0,
+                "byteOffset": data.len(),
+                "byteLength": stream.len(),
+            }));
+            data.extend(stream);
+        }
+
+        json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": data.len(), "uri": data_uri(&data) }],
+            "bufferViews": views,
+            "accessors": accessors(positions.len(), indices.len(), true),
+            "meshes": [{
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "TEXCOORD_0": 1 },
+                    "indices": 2,
+                }],
+            }],
+        })
+    }
+
+    fn import_json(json: &Value) -> Result<GltfImport, ImportError> {
+        import_slice(Path::new("test.gltf"), json.to_string().as_bytes())
+    }
+
+    /// The corners of every triangle of the first primitive, each a position
+    /// followed by a texture coordinate.
+    fn triangles(import: &GltfImport) -> Vec<[[f32; 5]; 3]> {
+        let primitive = import
+            .document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .next()
+            .unwrap();
+        let reader = primitive.reader(|buffer| Some(&import.buffers[buffer.index()]));
+        let positions: Vec<[f32; 3]> = reader.read_positions().unwrap().collect();
+        let uvs: Vec<[f32; 2]> = reader.read_tex_coords(0).unwrap().into_f32().collect();
+        let indices: Vec<u32> = reader.read_indices().unwrap().into_u32().collect();
+
+        let corner = |index: u32| {
+            let ([x, y, z], [u, v]) = (positions[index as usize], uvs[index as usize]);
+            [x, y, z, u, v]
+        };
+        indices
+            .chunks_exact(3)
+            .map(|t| [corner(t[0]), corner(t[1]), corner(t[2])])
+            .collect()
+    }
+
+    /// Whether both lists hold the same triangles, in any order and starting
+    /// at any corner, within `tolerance`.
+    fn same_triangles(a: &[[[f32; 5]; 3]], b: &[[[f32; 5]; 3]], tolerance: f32) -> bool {
+        let close =
+            |a: &[f32; 5], b: &[f32; 5]| a.iter().zip(b).all(|(a, b)| (a - b).abs() <= tolerance);
+        let mut unmatched: Vec<_> = b.iter().collect();
+        a.len() == b.len()
+            && a.iter().all(|triangle| {
+                let found = unmatched.iter().position(|other| {
+                    (0..3).any(|rotation| {
+                        (0..3).all(|i| close(&triangle[i], &other[(i + rotation) % 3]))
+                    })
+                });
+                found.map(|found| unmatched.swap_remove(found)).is_some()
+            })
+    }
+
+    fn names(document: &gltf::Document) -> Vec<(&str, Option<usize>)> {
+        scene_nodes(document)
+            .iter()
+            .map(|(node, parent)| (node.name().unwrap(), *parent))
+            .collect()
+    }
+
+    #[test]
+    fn scene_nodes_follow_their_parents() {
+        let document = document(
+            r#"{
+                "asset": { "version": "2.0" },
+                "scene": 1,
+                "scenes": [{ "nodes": [4] }, { "nodes": [0, 3] }],
+                "nodes": [
+                    { "name": "root", "children": [1, 2] },
+                    { "name": "child", "children": [5] },
+                    { "name": "sibling" },
+                    { "name": "second root" },
+                    { "name": "other scene" },
+                    { "name": "grandchild" }
+                ]
+            }"#,
+        );
+        assert_eq!(
+            names(&document),
+            [
+                ("root", None),
+                ("child", Some(0)),
+                ("grandchild", Some(1)),
+                ("sibling", Some(0)),
+                ("second root", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn scene_nodes_without_scenes_start_at_unparented_nodes() {
+        let document = document(
+            r#"{
+                "asset": { "version": "2.0" },
+                "nodes": [
+                    { "name": "child" },
+                    { "name": "root", "children": [0] },
+                    { "name": "lone" }
+                ]
+            }"#,
+        );
+        assert_eq!(
+            names(&document),
+            [("root", None), ("child", Some(0)), ("lone", None)]
+        );
+    }
+
+    #[test]
+    fn scene_nodes_are_listed_once() {
+        let document = document(
+            r#"{
+                "asset": { "version": "2.0" },
+                "scenes": [{ "nodes": [0, 1] }],
+                "nodes": [
+                    { "name": "first", "children": [2] },
+                    { "name": "second", "children": [2, 1] },
+                    { "name": "shared" }
+                ]
+            }"#,
+        );
+        assert_eq!(
+            names(&document),
+            [("first", None), ("shared", Some(0)), ("second", None)]
+        );
+    }
+
+    #[test]
+    fn imports_meshopt_compressed_buffer_views() {
+        let (positions, uvs, indices) = grid();
+        let streams = streams(&positions, &uvs, &indices);
+        let encoded = [
+            meshopt::encode_vertex_buffer(&positions).unwrap(),
+            meshopt::encode_vertex_buffer(&uvs).unwrap(),
+            meshopt::encode_index_buffer(&indices, positions.len()).unwrap(),
+        ];
+
+        // The decoded views fill in a fallback buffer with no data of its own.
+        let mut data = vec![];
+        let mut views = vec![];
+        let mut offset = 0;
+        for (i, (stream, encoded)) in streams.iter().zip(&encoded).enumerate() {
+            let (stride, count, mode) = match i {
+                0 => (12, positions.len(), "ATTRIBUTES"),
+                1 => (8, uvs.len(), "ATTRIBUTES"),
+                _ => (4, indices.len(), "TRIANGLES"),
+            };
+            views.push(json!({
+                "buffer": 1,
+                "byteOffset": offset,
+                "byteLength": stream.len(),
+                "extensions": {
+                    "EXT_meshopt_compression": {
+                        "buffer": 0,
+                        "byteOffset": data.len(),
+                        "byteLength": encoded.len(),
+                        "byteStride": stride,
+                        "count": count,
+                        "mode": mode,
+                    },
+                },
+            }));
+            offset += stream.len();
+            data.extend(encoded);
+        }
+
+        let mut compressed = uncompressed(&positions, &uvs, &indices);
+        compressed["extensionsUsed"] = json!([EXT_MESHOPT_COMPRESSION]);
+        compressed["extensionsRequired"] = json!([EXT_MESHOPT_COMPRESSION]);
+        compressed["buffers"] = json!([
+            { "byteLength": data.len(), "uri": data_uri(&data) },
+            {
+                "byteLength": offset,
+                "extensions": { "EXT_meshopt_compression": { "fallback": true } },
+            },
+        ]);
+        compressed["bufferViews"] = json!(views);
+
+        let expected = triangles(&import_json(&uncompressed(&positions, &uvs, &indices)).unwrap());
+        let imported = triangles(&import_json(&compressed).unwrap());
+        assert!(same_triangles(&imported, &expected, 0.0));
+    }
+
+    #[test]
+    fn imports_draco_compressed_primitives() {
+        use draco_oxide::encode::{self, Config};
+        use draco_oxide::{AttributeDomain, AttributeType, ConfigType, MeshBuilder, NdVector};
+
+        let (positions, uvs, indices) = grid();
+        let mut builder = MeshBuilder::new();
+        builder.set_connectivity_attribute(
+            indices
+                .chunks_exact(3)
+                .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+                .collect(),
+        );
+        let position = builder.add_attribute(
+            positions.iter().map(|&p| NdVector::from(p)).collect(),
+            AttributeType::Position,
+            AttributeDomain::Position,
+            vec![],
+        );
+        builder.add_attribute(
+            uvs.iter().map(|&uv| NdVector::from(uv)).collect(),
+            AttributeType::TextureCoordinate,
+            AttributeDomain::Position,
+            vec![position],
+        );
+        let mut data = Vec::new();
+        encode::encode_mesh(builder.build().unwrap(), &mut data, Config::default()).unwrap();
+
+        // Draco-only: the accessors have no buffer views of their own.
+        let mut compressed = json!({
+            "asset": { "version": "2.0" },
+            "extensionsUsed": [KHR_DRACO_MESH_COMPRESSION],
+            "extensionsRequired": [KHR_DRACO_MESH_COMPRESSION],
+            "buffers": [{ "byteLength": data.len(), "uri": data_uri(&data) }],
+            "bufferViews": [{ "buffer": 0, "byteLength": data.len() }],
+            "accessors": accessors(positions.len(), indices.len(), false),
+            "meshes": [{
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "TEXCOORD_0": 1 },
+                    "indices": 2,
+                    "extensions": {
+                        "KHR_draco_mesh_compression": {
+                            "bufferView": 0,
+                            "attributes": { "POSITION": 0, "TEXCOORD_0": 1 },
+                        },
+                    },
+                }],
+            }],
+        });
+
+        let expected = triangles(&import_json(&uncompressed(&positions, &uvs, &indices)).unwrap());
+        let import = import_json(&compressed).unwrap();
+        assert_eq!(import.document.buffers().count(), import.buffers.len());
+        // Positions and texture coordinates are quantized.
+        assert!(same_triangles(&triangles(&import), &expected, 1e-3));
+
+        compressed["meshes"][0]["primitives"][0]["extensions"][KHR_DRACO_MESH_COMPRESSION]
+            ["attributes"]["TEXCOORD_0"] = json!(5);
+        assert!(matches!(
+            import_json(&compressed),
+            Err(ImportError::MissingDracoAttribute {
+                mesh: 0,
+                primitive: 0,
+                id: 5,
+            })
+        ));
+    }
+}
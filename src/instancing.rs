@@ -0,0 +1,287 @@
+use crate::shader_bindings::{
+    BufferIndices_BufferIndexInstances as BufferIndexInstances, Instance,
+};
+use crate::simd;
+use glam::{Mat4, Quat, Vec3};
+use metal::*;
+use serde_json::Value;
+use std::mem;
+
+const EXT_MESH_GPU_INSTANCING: &str = "EXT_mesh_gpu_instancing";
+
+impl Instance {
+    pub fn new(transform: Mat4) -> Self {
+        let (model_matrix, normal_matrix) = simd::model_and_normal_matrix(transform);
+        Self {
+            modelMatrix: model_matrix,
+            normalMatrix: normal_matrix,
+        }
+    }
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self::new(Mat4::IDENTITY)
+    }
+}
+
+/// Per-instance transforms of a mesh. A mesh without instances is drawn once
+/// with an identity transform.
+#[derive(Default)]
+pub struct Instances {
+    transforms: Vec<Mat4>,
+    buffer: Option<Buffer>,
+}
+
+impl Instances {
+    pub fn transforms(&self) -> &[Mat4] {
+        &self.transforms
+    }
+
+    pub fn count(&self) -> u64 {
+        u64::max(self.transforms.len() as u64, 1)
+    }
+
+    /// Adds copies of the mesh to the ones already drawn. A mesh without
+    /// instances keeps its untransformed copy.
+    pub fn push(&mut self, device: &Device, transforms: &[Mat4]) {
+        if transforms.is_empty() {
+            return;
+        }
+        if self.transforms.is_empty() {
+            self.transforms.push(Mat4::IDENTITY);
+        }
+        self.transforms.extend_from_slice(transforms);
+        self.upload(device);
+    }
+
+    /// Replaces every copy of the mesh, the untransformed one included.
+    pub fn set(&mut self, device: &Device, transforms: &[Mat4]) {
+        self.transforms = transforms.to_vec();
+        self.upload(device);
+    }
+
+    pub fn bind(&self, render_encoder: &RenderCommandEncoderRef) {
+        match &self.buffer {
+            Some(buffer) => {
+                render_encoder.set_vertex_buffer(BufferIndexInstances as u64, Some(buffer), 0)
+            }
+            None => render_encoder.set_vertex_bytes(
+                BufferIndexInstances as u64,
+                mem::size_of::<Instance>() as u64,
+                [Instance::default()].as_ptr() as *const _,
+            ),
+        }
+    }
+
+    fn upload(&mut self, device: &Device) {
+        if self.transforms.is_empty() {
+            self.buffer = None;
+            return;
+        }
+
+        let instances: Vec<Instance> = self
+            .transforms
+            .iter()
+            .map(|transform| Instance::new(*transform))
+            .collect();
+
+        self.buffer = Some(device.new_buffer_with_data(
+            instances.as_ptr() as *const _,
+            mem::size_of::<Instance>() as u64 * instances.len() as u64,
+            MTLResourceOptions::CPUCacheModeDefaultCache | MTLResourceOptions::StorageModeManaged,
+        ));
+    }
+}
+
+/// Reads the per-instance TRS attributes of `EXT_mesh_gpu_instancing` on a node.
+pub fn read_gpu_instancing(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    json: &Value,
+    node: &gltf::Node,
+) -> Option<Vec<Mat4>> {
+    let attributes =
+        &json["nodes"][node.index()]["extensions"][EXT_MESH_GPU_INSTANCING]["attributes"];
+    if attributes.is_null() {
+        return None;
+    }
+
+    let accessor = |semantic: &str| {
+        attributes[semantic]
+            .as_u64()
+            .and_then(|index| document.accessors().nth(index as usize))
+    };
+    let get_buffer_data = |buffer: gltf::Buffer| Some(&*buffers[buffer.index()]);
+
+    let translations: Option<Vec<Vec3>> = accessor("TRANSLATION").map(|accessor| {
+        gltf::accessor::Iter::<[f32; 3]>::new(accessor, get_buffer_data)
+            .map(|iter| iter.map(Vec3::from).collect())
+            .unwrap_or_default()
+    });
+
+    let rotations: Option<Vec<Quat>> = accessor("ROTATION").map(|accessor| {
+        use gltf::accessor::{DataType, Iter};
+        use gltf::animation::util::Rotations;
+
+        let rotations = match accessor.data_type() {
+            DataType::I8 => Iter::new(accessor, get_buffer_data).map(Rotations::I8),
+            DataType::U8 => Iter::new(accessor, get_buffer_data).map(Rotations::U8),
+            DataType::I16 => Iter::new(accessor, get_buffer_data).map(Rotations::I16),
+            DataType::U16 => Iter::new(accessor, get_buffer_data).map(Rotations::U16),
+            DataType::F32 => Iter::new(accessor, get_buffer_data).map(Rotations::F32),
+            _ => None,
+        };
+        rotations
+            .map(|rotations| rotations.into_f32().map(Quat::from_array).collect())
+            .unwrap_or_default()
+    });
+
+    let scales: Option<Vec<Vec3>> = accessor("SCALE").map(|accessor| {
+        gltf::accessor::Iter::<[f32; 3]>::new(accessor, get_buffer_data)
+            .map(|iter| iter.map(Vec3::from).collect())
+            .unwrap_or_default()
+    });
+
+    let count = [
+        translations.as_ref().map(Vec::len),
+        rotations.as_ref().map(Vec::len),
+        scales.as_ref().map(Vec::len),
+    ]
+    .iter()
+    .flatten()
+    .copied()
+    .max()?;
+
+    let transforms = (0..count)
+        .map(|i| {
+            let translation = translations
+                .as_ref()
+                .and_then(|translations| translations.get(i).copied())
+                .unwrap_or(Vec3::ZERO);
+            let rotation = rotations
+                .as_ref()
+                .and_then(|rotations| rotations.get(i).copied())
+                .unwrap_or(Quat::IDENTITY);
+            let scale = scales
+                .as_ref()
+                .and_then(|scales| scales.get(i).copied())
+                .unwrap_or(Vec3::ONE);
+
+            Mat4::from_scale_rotation_translation(scale, rotation, translation)
+        })
+        .collect();
+
+    Some(transforms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A document whose first node has translations and rotations for three
+    /// instances, the second scales for two, and the third no instances.
+    fn instanced_nodes() -> (gltf::Document, Vec<gltf::buffer::Data>, Value) {
+        let translations = [[1.0f32, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 3.0]];
+        // Normalized shorts: no rotation, a quarter turn about z, a half turn
+        // about x.
+        let rotations = [[0i16, 0, 0, 32767], [0, 0, 23170, 23170], [32767, 0, 0, 0]];
+        let scales = [[2.0f32, 2.0, 2.0], [1.0, 0.5, 1.0]];
+
+        let mut data: Vec<u8> = translations
+            .iter()
+            .flatten()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        data.extend(rotations.iter().flatten().flat_map(|c| c.to_le_bytes()));
+        data.extend(scales.iter().flatten().flat_map(|c| c.to_le_bytes()));
+
+        let json: Value = serde_json::from_str(&format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "extensionsUsed": ["EXT_mesh_gpu_instancing"],
+                "buffers": [{{ "byteLength": {} }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 24 }},
+                    {{ "buffer": 0, "byteOffset": 60, "byteLength": 24 }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                    {{
+                        "bufferView": 1,
+                        "componentType": 5122,
+                        "normalized": true,
+                        "count": 3,
+                        "type": "VEC4"
+                    }},
+                    {{ "bufferView": 2, "componentType": 5126, "count": 2, "type": "VEC3" }}
+                ],
+                "nodes": [
+                    {{
+                        "extensions": {{
+                            "EXT_mesh_gpu_instancing": {{
+                                "attributes": {{ "TRANSLATION": 0, "ROTATION": 1 }}
+                            }}
+                        }}
+                    }},
+                    {{
+                        "extensions": {{
+                            "EXT_mesh_gpu_instancing": {{ "attributes": {{ "SCALE": 2 }} }}
+                        }}
+                    }},
+                    {{}}
+                ]
+            }}"#,
+            data.len()
+        ))
+        .unwrap();
+
+        let document = gltf::Document::from_json(serde_json::from_value(json.clone()).unwrap());
+        (document.unwrap(), vec![gltf::buffer::Data(data)], json)
+    }
+
+    fn read(node: usize) -> Option<Vec<Mat4>> {
+        let (document, buffers, json) = instanced_nodes();
+        let node = document.nodes().nth(node).unwrap();
+        read_gpu_instancing(&document, &buffers, &json, &node)
+    }
+
+    #[test]
+    fn reads_translations_and_rotations() {
+        let transforms = read(0).unwrap();
+        assert_eq!(transforms.len(), 3);
+
+        let expected = [
+            Mat4::from_translation(Vec3::X),
+            Mat4::from_rotation_translation(
+                Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+                Vec3::Y * 2.0,
+            ),
+            Mat4::from_rotation_translation(
+                Quat::from_rotation_x(std::f32::consts::PI),
+                Vec3::Z * 3.0,
+            ),
+        ];
+        for (transform, expected) in transforms.iter().zip(expected) {
+            assert!(transform.abs_diff_eq(expected, 1e-4), "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn missing_attributes_default_to_identity() {
+        // No translations or rotations: only the scales apply.
+        assert_eq!(
+            read(1).unwrap(),
+            [
+                Mat4::from_scale(Vec3::splat(2.0)),
+                Mat4::from_scale(Vec3::new(1.0, 0.5, 1.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn nodes_without_the_extension_have_no_instances() {
+        assert_eq!(read(2), None);
+    }
+}
@@ -0,0 +1,619 @@
+use crate::texture_bake::TextureCompression;
+use crate::texture_data::{ColorSpace, TextureData, TextureFormat};
+use basisu::{DecodeFlags, TargetFormat, Transcoder};
+use image::error::{DecodingError, ImageError, ImageFormatHint};
+use ktx2::{
+    BasicDataFormatDescriptor, ColorModel, Format, SupercompressionScheme, TransferFunction,
+};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+const KHR_TEXTURE_BASISU: &str = "KHR_texture_basisu";
+
+#[derive(Debug)]
+pub enum KtxError {
+    Io(std::io::Error),
+    Parse(ktx2::ParseError),
+    /// ETC1S or UASTC data the transcoder rejects.
+    Basis(basisu::Error),
+    Supercompression(SupercompressionScheme),
+    /// A Zstandard compressed level that fails to decompress.
+    Zstd(String),
+    /// A level whose faces are not the size of an image of the level.
+    LevelSize(usize),
+    UnsupportedFormat(Option<Format>),
+    /// Array and 3D textures.
+    UnsupportedLayout,
+}
+
+impl fmt::Display for KtxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KtxError::Io(error) => write!(f, "{}", error),
+            KtxError::Parse(error) => write!(f, "invalid KTX2 file: {}", error),
+            KtxError::Basis(error) => {
+                write!(f, "unable to transcode Basis Universal data: {:?}", error)
+            }
+            KtxError::Supercompression(scheme) => {
+                write!(f, "{:?} supercompression is not supported", scheme)
+            }
+            KtxError::Zstd(error) => write!(f, "invalid Zstandard data: {}", error),
+            KtxError::LevelSize(level) => write!(f, "level {} has a bad size", level),
+            KtxError::UnsupportedFormat(format) => write!(f, "unsupported format {:?}", format),
+            KtxError::UnsupportedLayout => write!(f, "array and 3D textures are not supported"),
+        }
+    }
+}
+
+impl std::error::Error for KtxError {}
+
+impl From<std::io::Error> for KtxError {
+    fn from(error: std::io::Error) -> Self {
+        KtxError::Io(error)
+    }
+}
+
+impl From<ktx2::ParseError> for KtxError {
+    fn from(error: ktx2::ParseError) -> Self {
+        KtxError::Parse(error)
+    }
+}
+
+impl From<basisu::Error> for KtxError {
+    fn from(error: basisu::Error) -> Self {
+        KtxError::Basis(error)
+    }
+}
+
+impl From<KtxError> for ImageError {
+    fn from(error: KtxError) -> Self {
+        match error {
+            KtxError::Io(error) => ImageError::IoError(error),
+            error => ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::Name("KTX2".to_string()),
+                error,
+            )),
+        }
+    }
+}
+
+/// How the levels of a KTX2 file are read.
+enum Payload {
+    /// Stored as `format`, with the red and blue channels swapped if `swizzle`.
+    Texture {
+        format: TextureFormat,
+        swizzle: bool,
+    },
+    /// ETC1S or UASTC data, transcoded to a format the GPU samples.
+    Basis,
+}
+
+/// The mip levels and cube faces of a KTX2 file, largest level first.
+pub struct KtxTexture {
+    pub face_count: u32,
+    /// Indexed by level, then by face.
+    pub levels: Vec<Vec<TextureData>>,
+}
+
+impl KtxTexture {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        compression: TextureCompression,
+    ) -> Result<Self, KtxError> {
+        Self::from_bytes(&std::fs::read(path)?, compression)
+    }
+
+    /// Reads the levels of a KTX2 file. Basis Universal data is transcoded to
+    /// the format textures are baked to with `compression`, and to `BGRA8`
+    /// when they are not compressed.
+    pub fn from_bytes(bytes: &[u8], compression: TextureCompression) -> Result<Self, KtxError> {
+        let reader = ktx2::Reader::new(bytes)?;
+        let header = reader.header();
+        let (format, swizzle) = match payload(&reader)? {
+            Payload::Texture { format, swizzle } => (format, swizzle),
+            Payload::Basis => return transcode(bytes, is_srgb(&reader), compression),
+        };
+        let face_count = header.face_count;
+
+        let mut levels = vec![];
+        for (level, data) in reader.levels().enumerate() {
+            let width = (header.pixel_width >> level).max(1);
+            let height = (header.pixel_height.max(1) >> level).max(1);
+            let data = match header.supercompression_scheme {
+                Some(SupercompressionScheme::Zstandard) => Cow::Owned(zstd_decompress(data)?),
+                _ => Cow::Borrowed(data),
+            };
+            let faces = face_count.max(1) as usize;
+            if data.is_empty() || !data.len().is_multiple_of(faces) {
+                return Err(KtxError::LevelSize(level));
+            }
+            let face_size = data.len() / faces;
+
+            let faces = data
+                .chunks_exact(face_size)
+                .map(|face| {
+                    let mut data = face.to_vec();
+                    if swizzle {
+                        swap_red_and_blue(&mut data);
+                    }
+                    let texture_data = TextureData {
+                        format,
+                        width,
+                        height,
+                        data,
+                    };
+                    if texture_data.bytes_per_image() != face_size as u64 {
+                        return Err(KtxError::LevelSize(level));
+                    }
+                    Ok(texture_data)
+                })
+                .collect::<Result<_, _>>()?;
+            levels.push(faces);
+        }
+
+        Ok(Self { face_count, levels })
+    }
+
+    /// Every level of one face.
+    pub fn mip_chain(&self, face: usize) -> Vec<TextureData> {
+        self.levels
+            .iter()
+            .map(|faces| faces[face].clone())
+            .collect()
+    }
+}
+
+pub fn is_ktx2(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ktx2"))
+}
+
+/// How the levels of `reader` are loaded, or why they cannot be.
+fn payload(reader: &ktx2::Reader<&[u8]>) -> Result<Payload, KtxError> {
+    let header = reader.header();
+    if header.layer_count > 1 || header.pixel_depth > 1 {
+        return Err(KtxError::UnsupportedLayout);
+    }
+    if is_basis(reader) {
+        return Ok(Payload::Basis);
+    }
+    match header.supercompression_scheme {
+        None | Some(SupercompressionScheme::Zstandard) => {}
+        Some(scheme) => return Err(KtxError::Supercompression(scheme)),
+    }
+    let (format, swizzle) = texture_format(header.format)?;
+    Ok(Payload::Texture { format, swizzle })
+}
+
+/// Transcodes every level and face of a Basis Universal file.
+fn transcode(
+    bytes: &[u8],
+    srgb: bool,
+    compression: TextureCompression,
+) -> Result<KtxTexture, KtxError> {
+    let (target, format) = match compression {
+        TextureCompression::None => (TargetFormat::Rgba32, TextureFormat::BGRA8Unorm),
+        TextureCompression::Bc => (TargetFormat::Bc7Rgba, TextureFormat::BC7RGBAUnorm),
+        TextureCompression::Astc => (TargetFormat::Astc4x4Rgba, TextureFormat::ASTC4x4Ldr),
+    };
+    let color_space = if srgb {
+        ColorSpace::Srgb
+    } else {
+        ColorSpace::Linear
+    };
+
+    let transcoder = Transcoder::new(bytes)?;
+    let face_count = transcoder.face_count().max(1);
+    let mut levels = vec![];
+    for level in 0..transcoder.level_count() {
+        let info = transcoder.image_level_info(level)?;
+        let faces = (0..face_count)
+            .map(|face| {
+                let mut data =
+                    transcoder.transcode_image(level, 0, face, target, DecodeFlags::NONE)?;
+                if target == TargetFormat::Rgba32 {
+                    swap_red_and_blue(&mut data);
+                }
+                let texture_data = TextureData {
+                    format,
+                    width: info.width,
+                    height: info.height,
+                    data,
+                };
+                Ok(texture_data.into_color_space(color_space))
+            })
+            .collect::<Result<_, KtxError>>()?;
+        levels.push(faces);
+    }
+
+    Ok(KtxTexture { face_count, levels })
+}
+
+fn swap_red_and_blue(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+/// Decompresses one Zstandard supercompressed level.
+fn zstd_decompress(mut data: &[u8]) -> Result<Vec<u8>, KtxError> {
+    let mut decoder = ruzstd::StreamingDecoder::new(&mut data).map_err(KtxError::Zstd)?;
+    let mut decompressed = vec![];
+    decoder
+        .read_to_end(&mut decompressed)
+        .map_err(|error| KtxError::Zstd(error.to_string()))?;
+    Ok(decompressed)
+}
+
+fn basic_descriptor<'a>(reader: &'a ktx2::Reader<&[u8]>) -> Option<BasicDataFormatDescriptor<'a>> {
+    reader
+        .data_format_descriptors()
+        .find_map(|descriptor| BasicDataFormatDescriptor::parse(descriptor.data).ok())
+}
+
+/// Whether the file carries Basis Universal data.
+fn is_basis(reader: &ktx2::Reader<&[u8]>) -> bool {
+    if reader.header().supercompression_scheme == Some(SupercompressionScheme::BasisLZ) {
+        return true;
+    }
+    let color_model = basic_descriptor(reader).and_then(|descriptor| descriptor.color_model);
+    matches!(
+        color_model,
+        Some(ColorModel::UASTC) | Some(ColorModel::ETC1S)
+    )
+}
+
+/// Whether the data format descriptor marks the texels as sRGB encoded.
+fn is_srgb(reader: &ktx2::Reader<&[u8]>) -> bool {
+    basic_descriptor(reader)
+        .is_some_and(|descriptor| descriptor.transfer_function == Some(TransferFunction::SRGB))
+}
+
+/// The texture format for a Vulkan format, and whether the red and blue
+/// channels have to be swapped to get there.
+fn texture_format(format: Option<Format>) -> Result<(TextureFormat, bool), KtxError> {
+    let texture_format = match format {
+        Some(Format::R8_UNORM) => TextureFormat::R8Unorm,
+        Some(Format::R8G8_UNORM) => TextureFormat::RG8Unorm,
+        Some(Format::B8G8R8A8_UNORM) => TextureFormat::BGRA8Unorm,
+        Some(Format::B8G8R8A8_SRGB) => TextureFormat::BGRA8UnormSrgb,
+        Some(Format::R8G8B8A8_UNORM) => return Ok((TextureFormat::BGRA8Unorm, true)),
+        Some(Format::R8G8B8A8_SRGB) => return Ok((TextureFormat::BGRA8UnormSrgb, true)),
+        Some(Format::R16G16B16A16_UNORM) => TextureFormat::RGBA16Unorm,
+        Some(Format::R16G16B16A16_SFLOAT) => TextureFormat::RGBA16Float,
+        Some(Format::R32G32B32A32_SFLOAT) => TextureFormat::RGBA32Float,
+        Some(Format::BC5_UNORM_BLOCK) => TextureFormat::BC5RGUnorm,
+        Some(Format::BC7_UNORM_BLOCK) => TextureFormat::BC7RGBAUnorm,
+        Some(Format::BC7_SRGB_BLOCK) => TextureFormat::BC7RGBAUnormSrgb,
+        Some(Format::ASTC_4x4_UNORM_BLOCK) => TextureFormat::ASTC4x4Ldr,
+        Some(Format::ASTC_4x4_SRGB_BLOCK) => TextureFormat::ASTC4x4Srgb,
+        format => return Err(KtxError::UnsupportedFormat(format)),
+    };
+    Ok((texture_format, false))
+}
+
+/// The URI of the KTX2 image a texture points to through `KHR_texture_basisu`.
+pub fn basisu_image_uri<'a>(json: &'a Value, texture: &gltf::Texture) -> Option<&'a str> {
+    let source =
+        json["textures"][texture.index()]["extensions"][KHR_TEXTURE_BASISU]["source"].as_u64()?;
+    json["images"][source as usize]["uri"].as_str()
+}
+
+/// Whether the KTX2 file at `path` can be loaded, transcoding Basis Universal
+/// data if need be.
+pub fn can_load(path: &Path) -> bool {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(_) => return false,
+    };
+    match ktx2::Reader::new(bytes.as_slice()).map(|reader| payload(&reader)) {
+        Ok(Ok(Payload::Texture { .. })) => true,
+        Ok(Ok(Payload::Basis)) => Transcoder::new(&bytes).is_ok(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A KTX2 file with one basic data format descriptor, `levels[level][face]`
+    /// holding the bytes stored for each face.
+    fn ktx2(
+        vk_format: u32,
+        size: (u32, u32),
+        scheme: u32,
+        color_model: u32,
+        levels: &[Vec<Vec<u8>>],
+    ) -> Vec<u8> {
+        let face_count = levels[0].len() as u32;
+        let level_count = levels.len() as u32;
+        let dfd_offset = 80 + 24 * level_count;
+        let dfd_block_size = 24 + 16;
+        let dfd_size = 4 + dfd_block_size;
+
+        let mut bytes = vec![
+            0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+        ];
+        for value in [
+            vk_format,
+            1,
+            size.0,
+            size.1,
+            0,
+            0,
+            face_count,
+            level_count,
+            scheme,
+            dfd_offset,
+            dfd_size,
+            0,
+            0,
+        ] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend([0; 16]);
+
+        let mut offset = (dfd_offset + dfd_size) as u64;
+        let blobs: Vec<Vec<u8>> = levels.iter().map(|faces| faces.concat()).collect();
+        for blob in &blobs {
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend((blob.len() as u64).to_le_bytes());
+            bytes.extend((blob.len() as u64).to_le_bytes());
+            offset += blob.len() as u64;
+        }
+
+        bytes.extend(dfd_size.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend((2 | (dfd_block_size << 16)).to_le_bytes());
+        bytes.extend((color_model | (1 << 8) | (2 << 16)).to_le_bytes());
+        bytes.extend([0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend([0; 16]);
+        for blob in blobs {
+            bytes.extend(blob);
+        }
+        bytes
+    }
+
+    /// A Zstandard frame holding `data` in a single raw block.
+    fn zstd_frame(data: &[u8]) -> Vec<u8> {
+        assert!(data.len() < 256);
+        let mut frame = 0xFD2FB528u32.to_le_bytes().to_vec();
+        frame.extend([0x20, data.len() as u8]);
+        frame.extend(&(1 | (data.len() as u32) << 3).to_le_bytes()[..3]);
+        frame.extend(data);
+        frame
+    }
+
+    const NONE: TextureCompression = TextureCompression::None;
+    const R8G8B8A8_SRGB: u32 = 43;
+    const BC7_SRGB_BLOCK: u32 = 146;
+
+    #[test]
+    fn header_and_level_index() {
+        let levels: Vec<Vec<Vec<u8>>> = (0..3)
+            .map(|level| {
+                let size = 4 >> level;
+                (0..6)
+                    .map(|face| [face * 10, 1, 2, 255].repeat(size * size))
+                    .collect()
+            })
+            .collect();
+        let texture =
+            KtxTexture::from_bytes(&ktx2(R8G8B8A8_SRGB, (4, 4), 0, 1, &levels), NONE).unwrap();
+
+        assert_eq!(texture.face_count, 6);
+        assert_eq!(texture.levels.len(), 3);
+        assert!(texture.levels.iter().all(|faces| faces.len() == 6));
+        assert_eq!(texture.levels[1][3].width, 2);
+        assert_eq!(texture.levels[2][5].height, 1);
+        assert_eq!(texture.levels[1][3].format, TextureFormat::BGRA8UnormSrgb);
+        assert_eq!(&texture.levels[0][3].data[..4], &[2, 1, 30, 255]);
+
+        let chain = texture.mip_chain(4);
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain[1].data.len(), 16);
+        assert_eq!(chain[2].data, [2, 1, 40, 255]);
+    }
+
+    #[test]
+    fn block_compressed_levels() {
+        let levels = vec![vec![vec![7; 64]], vec![vec![9; 16]]];
+        let texture =
+            KtxTexture::from_bytes(&ktx2(BC7_SRGB_BLOCK, (8, 8), 0, 1, &levels), NONE).unwrap();
+
+        assert_eq!(texture.face_count, 1);
+        assert_eq!(texture.levels[0][0].format, TextureFormat::BC7RGBAUnormSrgb);
+        assert_eq!(texture.levels[0][0].bytes_per_image(), 64);
+        assert_eq!(texture.levels[1][0].width, 4);
+        assert_eq!(texture.levels[1][0].data, [9; 16]);
+    }
+
+    #[test]
+    fn zstd_supercompressed_levels() {
+        let level0 = [10, 20, 30, 255].repeat(4);
+        let level1 = vec![40, 50, 60, 255];
+        let levels = vec![vec![zstd_frame(&level0)], vec![zstd_frame(&level1)]];
+        let texture =
+            KtxTexture::from_bytes(&ktx2(R8G8B8A8_SRGB, (2, 2), 2, 1, &levels), NONE).unwrap();
+
+        assert_eq!(texture.levels.len(), 2);
+        assert_eq!(texture.levels[0][0].data, [30, 20, 10, 255].repeat(4));
+        assert_eq!(texture.levels[1][0].data, [60, 50, 40, 255]);
+
+        let corrupt = ktx2(R8G8B8A8_SRGB, (1, 1), 2, 1, &[vec![vec![1, 2, 3, 4]]]);
+        assert!(matches!(
+            KtxTexture::from_bytes(&corrupt, NONE),
+            Err(KtxError::Zstd(_))
+        ));
+    }
+
+    #[test]
+    fn unsupported_files() {
+        let zlib = ktx2(R8G8B8A8_SRGB, (1, 1), 3, 1, &[vec![vec![0; 4]]]);
+        assert!(matches!(
+            KtxTexture::from_bytes(&zlib, NONE),
+            Err(KtxError::Supercompression(_))
+        ));
+        let etc2 = ktx2(147, (4, 4), 0, 1, &[vec![vec![0; 8]]]);
+        assert!(matches!(
+            KtxTexture::from_bytes(&etc2, NONE),
+            Err(KtxError::UnsupportedFormat(_))
+        ));
+        let uastc = ktx2(0, (4, 4), 0, 166, &[vec![vec![0; 15]]]);
+        assert!(matches!(
+            KtxTexture::from_bytes(&uastc, NONE),
+            Err(KtxError::Basis(_))
+        ));
+        assert!(matches!(
+            KtxTexture::from_bytes(&[0; 16], NONE),
+            Err(KtxError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn levels_must_hold_whole_images() {
+        let mut faces = vec![vec![]; 6];
+        faces[0] = vec![0; 8];
+        let uneven_cube = ktx2(R8G8B8A8_SRGB, (1, 1), 0, 1, &[faces]);
+        assert!(matches!(
+            KtxTexture::from_bytes(&uneven_cube, NONE),
+            Err(KtxError::LevelSize(0))
+        ));
+
+        let short = vec![
+            vec![[1, 2, 3, 255].repeat(16)],
+            vec![[4, 5, 6, 255].repeat(3)],
+        ];
+        assert!(matches!(
+            KtxTexture::from_bytes(&ktx2(R8G8B8A8_SRGB, (4, 4), 0, 1, &short), NONE),
+            Err(KtxError::LevelSize(1))
+        ));
+        let long_blocks = vec![vec![vec![7; 80]]];
+        assert!(matches!(
+            KtxTexture::from_bytes(&ktx2(BC7_SRGB_BLOCK, (8, 8), 0, 1, &long_blocks), NONE),
+            Err(KtxError::LevelSize(0))
+        ));
+    }
+
+    /// The mean difference of two images of the same size, between 0 and 1.
+    fn difference(a: &TextureData, b: &TextureData) -> f32 {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        let (a, b) = (a.to_rgba_f32(), b.to_rgba_f32());
+        let sum: f32 = a
+            .iter()
+            .zip(&b)
+            .flat_map(|(a, b)| a.iter().zip(b).map(|(a, b)| (a - b).abs()))
+            .sum();
+        sum / (a.len() * 4) as f32
+    }
+
+    /// A transcoded BC7 or ASTC level decoded to `BGRA8`. The decoders in
+    /// `block_compression` only read the blocks the baker writes, so this uses
+    /// a complete one.
+    fn decode_blocks(level: &TextureData) -> TextureData {
+        let (width, height) = (level.width as usize, level.height as usize);
+        let mut pixels = vec![0; width * height];
+        match level.format {
+            TextureFormat::BC7RGBAUnorm | TextureFormat::BC7RGBAUnormSrgb => {
+                texture2ddecoder::decode_bc7(&level.data, width, height, &mut pixels)
+            }
+            _ => texture2ddecoder::decode_astc(&level.data, width, height, 4, 4, &mut pixels),
+        }
+        .unwrap();
+        let format = if level.format.is_srgb() {
+            TextureFormat::BGRA8UnormSrgb
+        } else {
+            TextureFormat::BGRA8Unorm
+        };
+        TextureData {
+            format,
+            width: level.width,
+            height: level.height,
+            data: pixels
+                .iter()
+                .flat_map(|pixel| pixel.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    /// Transcodes `bytes` for every compression, checking the levels against
+    /// the uncompressed ones, and returns the uncompressed levels.
+    fn transcode_all(bytes: &[u8]) -> Vec<TextureData> {
+        let rgba = KtxTexture::from_bytes(bytes, NONE).unwrap().mip_chain(0);
+        for (compression, format) in [
+            (TextureCompression::Bc, TextureFormat::BC7RGBAUnormSrgb),
+            (TextureCompression::Astc, TextureFormat::ASTC4x4Srgb),
+        ] {
+            let levels = KtxTexture::from_bytes(bytes, compression)
+                .unwrap()
+                .mip_chain(0);
+            assert_eq!(levels.len(), rgba.len());
+            for (level, rgba) in levels.iter().zip(&rgba) {
+                assert_eq!(level.format, format);
+                assert_eq!((level.width, level.height), (rgba.width, rgba.height));
+                assert_eq!(level.bytes_per_image(), level.data.len() as u64);
+                let difference = difference(&decode_blocks(level), rgba);
+                assert!(difference < 0.01, "{:?}: {}", compression, difference);
+            }
+        }
+        rgba
+    }
+
+    #[test]
+    fn transcodes_uastc() {
+        // The UASTC blocks of rust-logo-uastc.basis from the test assets of
+        // the basis-universal crate, repackaged as KTX2, and the image they
+        // were encoded from.
+        let levels = transcode_all(include_bytes!("../resources/rust-logo-uastc.ktx2"));
+        let original = TextureData::from_memory(include_bytes!("../resources/rust-logo.png"))
+            .unwrap()
+            .into_color_space(ColorSpace::Srgb);
+
+        assert_eq!(levels.len(), 7);
+        assert_eq!(levels[0].format, TextureFormat::BGRA8UnormSrgb);
+        assert_eq!((levels[6].width, levels[6].height), (1, 1));
+        let difference = difference(&levels[0], &original);
+        assert!(difference < 0.01, "{}", difference);
+    }
+
+    #[test]
+    fn transcodes_etc1s() {
+        // A BasisLZ supercompressed file from the test data of the
+        // basis_transcoder crate.
+        let levels = transcode_all(include_bytes!("../resources/etc1s.ktx2"));
+
+        let sizes: Vec<(u32, u32)> = levels
+            .iter()
+            .map(|level| (level.width, level.height))
+            .collect();
+        assert_eq!(sizes, [(16, 28), (8, 14), (4, 7), (2, 3), (1, 1)]);
+        assert_eq!(levels[0].format, TextureFormat::BGRA8UnormSrgb);
+        assert_eq!(&levels[0].data[..4], &[123, 114, 106, 255]);
+    }
+
+    #[test]
+    fn loads_through_texture_data() {
+        let path = std::env::temp_dir().join("ktx-loads-through-texture-data.ktx2");
+        let levels = vec![vec![vec![7; 64]], vec![vec![9; 16]]];
+        std::fs::write(&path, ktx2(BC7_SRGB_BLOCK, (8, 8), 0, 1, &levels)).unwrap();
+        assert!(is_ktx2(&path));
+        assert!(can_load(&path));
+        assert_eq!(TextureData::open(&path).unwrap().width, 8);
+
+        std::fs::write(&path, include_bytes!("../resources/rust-logo-uastc.ktx2")).unwrap();
+        assert!(can_load(&path));
+        let texture_data = TextureData::open(&path).unwrap();
+        assert_eq!(texture_data.format, TextureFormat::BGRA8UnormSrgb);
+        assert_eq!(texture_data.width, 64);
+
+        std::fs::write(&path, ktx2(147, (4, 4), 0, 1, &[vec![vec![0; 8]]])).unwrap();
+        assert!(!can_load(&path));
+        let error = TextureData::open(&path).unwrap_err().to_string();
+        assert!(error.contains("unsupported format"), "{}", error);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,11 +1,62 @@
+mod block_compression;
+mod bounds;
+mod bvh;
 mod camera;
+mod compression;
+mod culling;
+mod draco;
+mod frustum;
+mod gizmo;
+mod gizmo_overlay;
+mod hot_reload;
+mod import_options;
+mod importer;
+mod instancing;
+mod ktx;
 mod lighting;
+mod loader;
+mod lod;
+mod mesh_cache;
+mod mesh_optimizer;
+mod meshlet;
+mod mipmaps;
 mod model;
 mod node;
+mod outline;
+mod picking;
 mod renderer;
 mod scene;
+mod scene_file;
 mod shader_bindings;
+mod simd;
 mod skybox;
 mod texturable;
+mod texture_bake;
+mod texture_cache;
+mod texture_data;
+mod variants;
 
+pub use bounds::{BoundingSphere, Bounds};
+pub use bvh::Bvh;
+pub use camera::{ArcballCamera, CameraFunction};
+pub use culling::{CullingStats, SubmeshId, Visibility};
+pub use frustum::{Frustum, Plane};
+pub use gizmo::{
+    Axis, Gizmo, GizmoFrame, GizmoGeometry, GizmoHandle, GizmoMode, GizmoSpace, GizmoVertex,
+    GizmoView, Snapping,
+};
+pub use import_options::{ImportOptions, Unit, UpAxis};
+pub use loader::LoadProgress;
+pub use lod::LodSettings;
+pub use mesh_optimizer::{MeshOptimization, OptimizationStats};
+pub use meshlet::MeshletSettings;
+pub use node::{InnerNode, Node, NodeContent, NodeId, SceneGraph, SceneGraphError};
+pub use picking::{Pick, Ray, TriangleHit, TriangleMesh};
 pub use renderer::Renderer;
+pub use scene::Scene;
+pub use scene_file::{
+    CameraDescription, EnvironmentDescription, LightDescription, LightKind, ModelDescription,
+    OutlineSettings, RenderSettings, SceneDescription, SceneFileError, TransformDescription,
+    SCENE_FILE_VERSION,
+};
+pub use texture_cache::CacheStats;
@@ -1,53 +1,111 @@
-use crate::shader_bindings::{Light, LightType_Ambientlight, LightType_Sunlight};
-use glam::Vec3A;
+use crate::node::{InnerNode, NodeContent, NodeId, SceneGraph};
+use crate::scene_file::{LightDescription, LightKind};
+use crate::shader_bindings::{
+    vector_float3, Light, LightType_Ambientlight, LightType_Pointlight, LightType_Spotlight,
+    LightType_Sunlight,
+};
+use crate::simd;
+use glam::{Vec3, Vec3A};
+use std::mem;
 
 pub struct Lighting {
     pub lights: Vec<Light>,
-    pub count: u32,
+    /// The node placing each light.
+    pub nodes: Vec<NodeId>,
 }
 
 impl Lighting {
-    pub fn new() -> Lighting {
-        let sunlight = {
-            let mut light = Self::build_default_light();
-            light.position = unsafe { std::mem::transmute(Vec3A::new(0.4, 1.0, -2.0)) };
-            light
-        };
-        let ambient_light = {
-            let mut light = Self::build_default_light();
-            light.color = unsafe { std::mem::transmute(Vec3A::new(1.0, 1.0, 1.0)) };
-            light.intensity = 0.1;
-            light.type_ = LightType_Ambientlight;
-            light
-        };
-        let fill_light = {
-            let mut light = Self::build_default_light();
-            light.position = unsafe { std::mem::transmute(Vec3A::new(0.0, -0.1, 0.4)) };
-            light.specularColor = unsafe { std::mem::transmute(Vec3A::new(0.0, 0.0, 0.0)) };
-            light.color = unsafe { std::mem::transmute(Vec3A::new(0.4, 0.4, 0.4)) };
-            light
-        };
-        let lights = vec![sunlight, ambient_light];
-        let count = lights.len() as u32;
+    /// The lights of a scene file. Adds a root node to `scene_graph` for each
+    /// light, at its position.
+    pub fn new(descriptions: &[LightDescription], scene_graph: &mut SceneGraph) -> Lighting {
+        let lights: Vec<Light> = descriptions.iter().map(Self::build_light).collect();
+        let nodes = lights
+            .iter()
+            .enumerate()
+            .map(|(index, light)| {
+                let mut inner_node = InnerNode::named(&format!("light {}", index));
+                inner_node.set_position(Vec3::from(array(light.position)));
+                scene_graph.add(inner_node, NodeContent::Light(index), None)
+            })
+            .collect();
+
+        Self { lights, nodes }
+    }
 
-        Self { lights, count }
+    /// The lights as stored in a scene file, where their nodes place them.
+    pub fn descriptions(&self, scene_graph: &SceneGraph) -> Vec<LightDescription> {
+        self.placed_lights(scene_graph)
+            .iter()
+            .map(Self::describe_light)
+            .collect()
+    }
+
+    /// The lights, each where its node is in world space. Lights whose node
+    /// was removed stay where they were placed.
+    pub fn placed_lights(&self, scene_graph: &SceneGraph) -> Vec<Light> {
+        let mut lights = self.lights.clone();
+        for (light, &node) in lights.iter_mut().zip(self.nodes.iter()) {
+            if !scene_graph.contains(node) {
+                continue;
+            }
+            let position = scene_graph.world_matrix(node).col(3).truncate();
+            light.position = vector(position.to_array());
+        }
+        lights
     }
 
-    fn build_default_light() -> Light {
+    fn build_light(description: &LightDescription) -> Light {
+        let type_ = match description.kind {
+            LightKind::Sun => LightType_Sunlight,
+            LightKind::Spot => LightType_Spotlight,
+            LightKind::Point => LightType_Pointlight,
+            LightKind::Ambient => LightType_Ambientlight,
+        };
         unsafe {
             Light {
-                position: std::mem::transmute(Vec3A::new(0.0, 0.0, 0.0)),
-                color: std::mem::transmute(Vec3A::new(1.0, 1.0, 1.0)),
-                specularColor: std::mem::transmute(Vec3A::new(1.0, 1.0, 1.0)),
-                intensity: 0.6,
-                attenuation: std::mem::transmute(Vec3A::new(1.0, 0.0, 0.0)),
-                type_: LightType_Sunlight,
-                coneAngle: 0.0,
-                coneDirection: std::mem::transmute(Vec3A::new(0.0, 0.0, 0.0)),
-                coneAttenuation: 0.0,
-                __bindgen_padding_0: std::mem::zeroed(),
-                __bindgen_padding_1: std::mem::zeroed(),
+                position: vector(description.position),
+                color: vector(description.color),
+                specularColor: vector(description.specular_color),
+                intensity: description.intensity,
+                attenuation: vector(description.attenuation),
+                type_,
+                coneAngle: description.cone_angle,
+                coneDirection: vector(description.cone_direction),
+                coneAttenuation: description.cone_attenuation,
+                __bindgen_padding_0: mem::zeroed(),
+                __bindgen_padding_1: mem::zeroed(),
             }
         }
     }
+
+    fn describe_light(light: &Light) -> LightDescription {
+        let kind = if light.type_ == LightType_Spotlight {
+            LightKind::Spot
+        } else if light.type_ == LightType_Pointlight {
+            LightKind::Point
+        } else if light.type_ == LightType_Ambientlight {
+            LightKind::Ambient
+        } else {
+            LightKind::Sun
+        };
+        LightDescription {
+            kind,
+            position: array(light.position),
+            color: array(light.color),
+            specular_color: array(light.specularColor),
+            intensity: light.intensity,
+            attenuation: array(light.attenuation),
+            cone_angle: light.coneAngle,
+            cone_direction: array(light.coneDirection),
+            cone_attenuation: light.coneAttenuation,
+        }
+    }
+}
+
+fn array(vector: vector_float3) -> [f32; 3] {
+    simd::vec3a(vector).to_array()
+}
+
+fn vector(array: [f32; 3]) -> vector_float3 {
+    simd::vector3(Vec3A::from(array))
 }
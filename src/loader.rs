@@ -0,0 +1,176 @@
+use crate::import_options::ImportOptions;
+use crate::model::ModelData;
+use crate::texture_bake::TextureCompression;
+use crate::texture_cache::TextureKey;
+use crate::texture_data::TextureData;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadProgress {
+    pub name: String,
+    pub files_loaded: usize,
+    pub files_total: usize,
+    pub bytes_loaded: u64,
+    pub bytes_total: u64,
+}
+
+impl LoadProgress {
+    pub fn percent(&self) -> f32 {
+        if self.bytes_total == 0 {
+            return 100.0;
+        }
+        self.bytes_loaded as f32 / self.bytes_total as f32 * 100.0
+    }
+}
+
+/// Counts the files and bytes read for one model, from any number of threads.
+pub struct ProgressTracker {
+    name: String,
+    files_total: usize,
+    bytes_total: u64,
+    files_loaded: AtomicUsize,
+    bytes_loaded: AtomicU64,
+}
+
+impl ProgressTracker {
+    pub fn new(name: &str, files_total: usize, bytes_total: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            files_total,
+            bytes_total,
+            files_loaded: AtomicUsize::new(0),
+            bytes_loaded: AtomicU64::new(0),
+        }
+    }
+
+    pub fn advance(&self, files: usize, bytes: u64) -> LoadProgress {
+        let files_loaded = self.files_loaded.fetch_add(files, Ordering::SeqCst) + files;
+        let bytes_loaded = self.bytes_loaded.fetch_add(bytes, Ordering::SeqCst) + bytes;
+
+        LoadProgress {
+            name: self.name.clone(),
+            files_loaded,
+            files_total: self.files_total,
+            bytes_loaded,
+            bytes_total: self.bytes_total,
+        }
+    }
+}
+
+pub enum LoadEvent {
+    Progress(LoadProgress),
+    /// `reload` is set for models loaded again because their files changed.
+    Loaded {
+        model_data: Box<ModelData>,
+        reload: bool,
+    },
+    Failed {
+        name: String,
+        error: String,
+        reload: bool,
+    },
+    TextureReloaded {
+        key: TextureKey,
+        levels: Vec<TextureData>,
+    },
+    TextureFailed {
+        key: TextureKey,
+        error: String,
+    },
+}
+
+/// Parses and decodes models on the rayon thread pool. Finished models come
+/// back through `poll`, so the GPU uploads happen on the render thread.
+pub struct AssetLoader {
+    sender: Sender<LoadEvent>,
+    receiver: Receiver<LoadEvent>,
+    pending: usize,
+    compression: TextureCompression,
+}
+
+impl AssetLoader {
+    /// Textures are baked with `compression`.
+    pub fn new(compression: TextureCompression) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            pending: 0,
+            compression,
+        }
+    }
+
+    pub fn load_model(&mut self, name: &str, tiling: u32, import_options: ImportOptions) {
+        self.pending += 1;
+        self.spawn_model(name, tiling, import_options, false);
+    }
+
+    /// Loads a model that is already shown again. Reloads do not count as
+    /// loading, so the placeholder is not drawn meanwhile.
+    pub fn reload_model(&mut self, name: &str, tiling: u32, import_options: ImportOptions) {
+        self.spawn_model(name, tiling, import_options, true);
+    }
+
+    /// Decodes the image of `key` again.
+    pub fn reload_texture(&self, key: TextureKey) {
+        let sender = self.sender.clone();
+        rayon::spawn(move || {
+            let event = match key.decode() {
+                Ok(levels) => LoadEvent::TextureReloaded { key, levels },
+                Err(error) => LoadEvent::TextureFailed {
+                    key,
+                    error: error.to_string(),
+                },
+            };
+            let _ = sender.send(event);
+        });
+    }
+
+    fn spawn_model(&self, name: &str, tiling: u32, import_options: ImportOptions, reload: bool) {
+        let name = name.to_string();
+        let sender = self.sender.clone();
+        let compression = self.compression;
+        rayon::spawn(move || {
+            let progress_sender = Mutex::new(sender.clone());
+            let progress = |progress: LoadProgress| {
+                let _ = progress_sender
+                    .lock()
+                    .unwrap()
+                    .send(LoadEvent::Progress(progress));
+            };
+
+            let event = match ModelData::load(&name, tiling, import_options, compression, &progress)
+            {
+                Ok(model_data) => LoadEvent::Loaded {
+                    model_data: Box::new(model_data),
+                    reload,
+                },
+                Err(error) => LoadEvent::Failed {
+                    name,
+                    error: error.to_string(),
+                    reload,
+                },
+            };
+            let _ = sender.send(event);
+        });
+    }
+
+    /// Events received since the last call, without blocking.
+    pub fn poll(&mut self) -> Vec<LoadEvent> {
+        let events: Vec<LoadEvent> = self.receiver.try_iter().collect();
+        for event in events.iter() {
+            if let LoadEvent::Loaded { reload: false, .. }
+            | LoadEvent::Failed { reload: false, .. } = event
+            {
+                self.pending -= 1;
+            }
+        }
+        events
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.pending > 0
+    }
+}
@@ -0,0 +1,282 @@
+use crate::bounds::BoundingSphere;
+use glam::{Mat4, Vec3};
+use meshopt::DecodePosition;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+/// How many simplified levels are built for each primitive.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LodSettings {
+    /// Simplified levels to build at most, besides the full mesh.
+    pub levels: usize,
+    /// Triangles each level aims to keep from the previous one.
+    pub reduction: f32,
+    /// The error the first level may have, relative to the size of the mesh.
+    /// Doubles with each level.
+    pub error: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            levels: 4,
+            reduction: 0.5,
+            error: 0.005,
+        }
+    }
+}
+
+impl Eq for LodSettings {}
+
+impl Hash for LodSettings {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.levels.hash(state);
+        self.reduction.to_bits().hash(state);
+        self.error.to_bits().hash(state);
+    }
+}
+
+/// One level of detail: a range of the index buffer shared by every level,
+/// and how far its surface may be from the full mesh.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lod {
+    pub index_offset: u32,
+    pub index_count: u32,
+    /// Relative to the radius of the bounding sphere of the primitive, so
+    /// it survives import conversions. Zero for the full mesh.
+    pub error: f32,
+}
+
+impl Lod {
+    /// The full mesh, drawn from the start of the index buffer.
+    pub fn full(index_count: u32) -> Self {
+        Self {
+            index_offset: 0,
+            index_count,
+            error: 0.0,
+        }
+    }
+}
+
+/// Simplifies the triangle list in `indices` with a quadric error metric and
+/// appends each level to it. Returns every level, the full mesh first. A
+/// level that could not drop enough triangles within its error is skipped.
+pub fn build_lods<T: DecodePosition>(
+    vertices: &[T],
+    indices: &mut Vec<u32>,
+    settings: &LodSettings,
+) -> Vec<Lod> {
+    let full = indices.clone();
+    let mut lods = vec![Lod::full(full.len() as u32)];
+
+    let mut error = settings.error;
+    for _ in 0..settings.levels {
+        let previous = lods.last().unwrap().index_count as usize;
+        let target_count = (previous as f32 / 3.0 * settings.reduction) as usize * 3;
+        if target_count < 3 {
+            break;
+        }
+
+        // simplified from the full mesh, so the errors do not add up
+        let simplified = meshopt::simplify_decoder(&full, vertices, target_count, error);
+        if simplified.len() >= 3 && simplified.len() as f32 <= previous as f32 * 0.9 {
+            let simplified = meshopt::optimize_vertex_cache(&simplified, vertices.len());
+            lods.push(Lod {
+                index_offset: indices.len() as u32,
+                index_count: simplified.len() as u32,
+                // relative to the largest side of the box, which is at most
+                // the diameter of the sphere
+                error: error * 2.0,
+            });
+            indices.extend(simplified);
+        }
+        error *= 2.0;
+    }
+    lods
+}
+
+/// What level selection needs to know about the camera.
+#[derive(Debug, Copy, Clone)]
+pub struct LodView {
+    pub eye: Vec3,
+    /// Pixels per unit of size at unit distance: the viewport height over
+    /// the height of the view at distance one.
+    pub pixels_per_unit: f32,
+    /// The error, in pixels, a level may show on screen.
+    pub threshold: f32,
+}
+
+impl LodView {
+    pub fn new(eye: Vec3, fov_radians: f32, viewport_height: f32, threshold: f32) -> Self {
+        Self {
+            eye,
+            pixels_per_unit: viewport_height / (2.0 * (fov_radians / 2.0).tan()),
+            threshold,
+        }
+    }
+
+    /// The radius of `sphere` on screen, in pixels. Infinite when the eye is
+    /// inside it.
+    pub fn projected_radius(&self, sphere: &BoundingSphere) -> f32 {
+        let distance = sphere.center.distance(self.eye);
+        if distance <= sphere.radius {
+            return f32::INFINITY;
+        }
+        sphere.radius / distance * self.pixels_per_unit
+    }
+
+    /// The coarsest of `lods` whose error stays under the threshold for a
+    /// primitive bounded by `sphere`, placed by each of `transforms`.
+    pub fn select(&self, lods: &[Lod], sphere: &BoundingSphere, transforms: &[Mat4]) -> usize {
+        if sphere.is_empty() {
+            return 0;
+        }
+        let radius = transforms
+            .iter()
+            .map(|&transform| self.projected_radius(&sphere.transform(transform)))
+            .fold(0.0, f32::max);
+
+        lods.iter()
+            .rposition(|lod| lod.error * radius <= self.threshold)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Default)]
+    struct Vertex([f32; 3]);
+
+    impl DecodePosition for Vertex {
+        fn decode_position(&self) -> [f32; 3] {
+            self.0
+        }
+    }
+
+    /// A finely tessellated, gently curved sheet, which simplifies well.
+    fn sheet(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = vec![];
+        for y in 0..=n {
+            for x in 0..=n {
+                let (u, v) = (x as f32 / n as f32, y as f32 / n as f32);
+                vertices.push(Vertex([u, v, 0.05 * (u * 3.0).sin()]));
+            }
+        }
+        let mut indices = vec![];
+        for y in 0..n {
+            for x in 0..n {
+                let i = y * (n + 1) + x;
+                indices.extend([i, i + 1, i + n + 1, i + 1, i + n + 2, i + n + 1]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    /// Three levels of a primitive with a sphere of radius one.
+    fn levels() -> Vec<Lod> {
+        vec![
+            Lod::full(600),
+            Lod {
+                index_offset: 600,
+                index_count: 300,
+                error: 0.01,
+            },
+            Lod {
+                index_offset: 900,
+                index_count: 150,
+                error: 0.02,
+            },
+        ]
+    }
+
+    fn sphere_at(distance: f32) -> BoundingSphere {
+        BoundingSphere::new(Vec3::new(0.0, 0.0, -distance), 1.0)
+    }
+
+    #[test]
+    fn builds_coarser_levels_after_the_full_mesh() {
+        let (vertices, mut indices) = sheet(64);
+        let full = indices.clone();
+        let lods = build_lods(&vertices, &mut indices, &LodSettings::default());
+
+        assert!(lods.len() >= 3, "{:?}", lods);
+        assert_eq!(lods[0], Lod::full(full.len() as u32));
+        assert_eq!(&indices[..full.len()], &full[..]);
+        for pair in lods.windows(2) {
+            assert!(pair[1].index_count < pair[0].index_count);
+            assert!(pair[1].error > pair[0].error);
+            assert_eq!(
+                pair[1].index_offset,
+                pair[0].index_offset + pair[0].index_count
+            );
+            assert_eq!(pair[1].index_count % 3, 0);
+        }
+        let last = lods.last().unwrap();
+        assert_eq!(
+            (last.index_offset + last.index_count) as usize,
+            indices.len()
+        );
+        assert!(indices
+            .iter()
+            .all(|&index| (index as usize) < vertices.len()));
+    }
+
+    #[test]
+    fn builds_only_the_full_mesh_without_levels() {
+        let (vertices, mut indices) = sheet(8);
+        let settings = LodSettings {
+            levels: 0,
+            ..LodSettings::default()
+        };
+        assert_eq!(
+            build_lods(&vertices, &mut indices, &settings),
+            [Lod::full(8 * 8 * 6)]
+        );
+        assert_eq!(indices.len(), 8 * 8 * 6);
+    }
+
+    #[test]
+    fn selects_by_projected_size() {
+        // a 90 degree view 1000 pixels high: 500 pixels per unit at distance one
+        let view = LodView::new(Vec3::ZERO, std::f32::consts::FRAC_PI_2, 1000.0, 1.0);
+        assert!((view.pixels_per_unit - 500.0).abs() < 1e-3);
+        assert!((view.projected_radius(&sphere_at(10.0)) - 50.0).abs() < 1e-3);
+
+        // 50 pixels across: level 1 is off by half a pixel, level 2 by one
+        let lods = levels();
+        assert_eq!(view.select(&lods, &sphere_at(10.0), &[Mat4::IDENTITY]), 2);
+        assert_eq!(view.select(&lods, &sphere_at(6.0), &[Mat4::IDENTITY]), 1);
+        assert_eq!(view.select(&lods, &sphere_at(2.0), &[Mat4::IDENTITY]), 0);
+        assert_eq!(view.select(&lods, &sphere_at(0.5), &[Mat4::IDENTITY]), 0);
+
+        let strict = LodView {
+            threshold: 0.0,
+            ..view
+        };
+        assert_eq!(
+            strict.select(&lods, &sphere_at(1000.0), &[Mat4::IDENTITY]),
+            0
+        );
+    }
+
+    #[test]
+    fn selects_for_the_largest_instance() {
+        let view = LodView::new(Vec3::ZERO, std::f32::consts::FRAC_PI_2, 1000.0, 1.0);
+        let lods = levels();
+
+        let center = Vec3::new(0.0, 0.0, -10.0);
+        let doubled = Mat4::from_translation(center)
+            * Mat4::from_scale(Vec3::splat(2.0))
+            * Mat4::from_translation(-center);
+        assert_eq!(view.select(&lods, &sphere_at(10.0), &[doubled]), 1);
+
+        let near = Mat4::from_translation(Vec3::new(0.0, 0.0, 8.0));
+        assert_eq!(
+            view.select(&lods, &sphere_at(10.0), &[Mat4::IDENTITY, near]),
+            0
+        );
+    }
+}
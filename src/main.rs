@@ -1,10 +1,11 @@
-use metal_gltf_viewer::Renderer;
+use metal_gltf_viewer::{CameraFunction, GizmoMode, GizmoSpace, Renderer};
 use objc::rc::autoreleasepool;
+use std::path::PathBuf;
 use winit::{
     dpi::LogicalSize,
     event::{
-        DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta,
-        VirtualKeyCode, WindowEvent,
+        DeviceEvent, ElementState, Event, KeyboardInput, ModifiersState, MouseButton,
+        MouseScrollDelta, VirtualKeyCode, WindowEvent,
     },
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
@@ -14,29 +15,54 @@ mod shader_bindings;
 
 const INITIAL_WINDOW_WIDTH: u32 = 1080;
 const INITIAL_WINDOW_HEIGHT: u32 = 720;
+const WINDOW_TITLE: &str = "Metal Rendering Engine";
+/// Where Cmd+S saves the scene when none was opened from the command line.
+const SAVED_SCENE: &str = "scene.json";
 
 struct State {
     left_mouse_pressed: bool,
+    /// In physical pixels from the top left of the window.
+    cursor_position: (f32, f32),
+    /// Whether a right click adds to the selection instead of replacing it.
+    shift_pressed: bool,
+    /// Cmd on macOS. Saving needs it, so a stray S does not overwrite the
+    /// scene file.
+    logo_pressed: bool,
+    loading: bool,
 }
 
 impl State {
     fn new() -> Self {
         Self {
             left_mouse_pressed: false,
+            cursor_position: (0.0, 0.0),
+            shift_pressed: false,
+            logo_pressed: false,
+            loading: false,
         }
     }
 }
 
 fn main() {
+    env_logger::init();
     let event_loop = EventLoop::new();
     let size = LogicalSize::new(INITIAL_WINDOW_WIDTH, INITIAL_WINDOW_HEIGHT);
     let window = WindowBuilder::new()
         .with_inner_size(size)
-        .with_title("Metal Rendering Engine".to_string())
+        .with_title(WINDOW_TITLE.to_string())
         .build(&event_loop)
         .unwrap();
 
     let mut renderer = Renderer::new(&window);
+    // the scene file given on the command line, or the sample helmet
+    let scene_path = std::env::args().nth(1).map(PathBuf::from);
+    let mut scene = match &scene_path {
+        Some(path) => renderer
+            .open_scene(path)
+            .unwrap_or_else(|error| panic!("Unable to open {}: {}", path.display(), error)),
+        None => renderer.default_scene(),
+    };
+    let save_path = scene_path.unwrap_or_else(|| PathBuf::from(SAVED_SCENE));
     let mut program_state = State::new();
 
     event_loop.run(move |event, _, control_flow| {
@@ -59,25 +85,130 @@ fn main() {
                             },
                         ..
                     } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F),
+                                ..
+                            },
+                        ..
+                    } => scene.frame_all(),
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::S),
+                                ..
+                            },
+                        ..
+                    } if program_state.logo_pressed => match scene.description().save(&save_path) {
+                        Ok(()) => log::info!("saved scene to {}", save_path.display()),
+                        Err(error) => {
+                            log::warn!("unable to save {}: {}", save_path.display(), error)
+                        }
+                    },
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => match key {
+                        VirtualKeyCode::W => scene.gizmo_mut().set_mode(GizmoMode::Translate),
+                        VirtualKeyCode::E => scene.gizmo_mut().set_mode(GizmoMode::Rotate),
+                        VirtualKeyCode::R => scene.gizmo_mut().set_mode(GizmoMode::Scale),
+                        VirtualKeyCode::Q => {
+                            let gizmo = scene.gizmo_mut();
+                            let space = match gizmo.space() {
+                                GizmoSpace::World => GizmoSpace::Local,
+                                GizmoSpace::Local => GizmoSpace::World,
+                            };
+                            gizmo.set_space(space);
+                            log::info!("gizmo space: {:?}", space);
+                        }
+                        _ => {}
+                    },
                     WindowEvent::Resized(size) => {
                         renderer.resize(size.width, size.height);
+                        scene
+                            .camera_mut()
+                            .set_aspect_ratio(size.width as f32 / size.height as f32);
                     }
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                         renderer.resize(new_inner_size.width, new_inner_size.height);
+                        scene.camera_mut().set_aspect_ratio(
+                            new_inner_size.width as f32 / new_inner_size.height as f32,
+                        );
+                    }
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button: MouseButton::Left,
+                        ..
+                    } => {
+                        // the camera only orbits when no handle is grabbed
+                        let (x, y) = program_state.cursor_position;
+                        let view = renderer.gizmo_view(&scene);
+                        program_state.left_mouse_pressed = !scene.begin_gizmo_drag(&view, x, y);
                     }
                     WindowEvent::MouseInput {
-                        state,
+                        state: ElementState::Released,
                         button: MouseButton::Left,
                         ..
                     } => {
-                        program_state.left_mouse_pressed = *state == ElementState::Pressed;
+                        program_state.left_mouse_pressed = false;
+                        scene.end_gizmo_drag();
+                    }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        program_state.cursor_position = (position.x as f32, position.y as f32);
+                        let (x, y) = program_state.cursor_position;
+                        let view = renderer.gizmo_view(&scene);
+                        if scene.gizmo().is_dragging() {
+                            scene.drag_gizmo(&view, x, y);
+                        } else {
+                            scene.hover_gizmo(&view, x, y);
+                        }
+                    }
+                    WindowEvent::ModifiersChanged(modifiers) => {
+                        program_state.shift_pressed = modifiers.contains(ModifiersState::SHIFT);
+                        program_state.logo_pressed = modifiers.contains(ModifiersState::LOGO);
+                        // drags snap while control is held
+                        scene
+                            .gizmo_mut()
+                            .set_snap(modifiers.contains(ModifiersState::CTRL));
+                    }
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button: MouseButton::Right,
+                        ..
+                    } => {
+                        let (x, y) = program_state.cursor_position;
+                        match renderer.pick(&scene, x, y) {
+                            Some(pick) => {
+                                log::info!("picked {:?}", pick);
+                                if program_state.shift_pressed {
+                                    scene.toggle_selection(pick.submesh);
+                                } else {
+                                    scene.clear_selection();
+                                    scene.select(pick.submesh);
+                                }
+                            }
+                            None => {
+                                log::info!("picked nothing");
+                                if !program_state.shift_pressed {
+                                    scene.clear_selection();
+                                }
+                            }
+                        }
                     }
                     _ => {}
                 },
                 Event::DeviceEvent { ref event, .. } => match event {
                     DeviceEvent::MouseWheel { delta } => match delta {
                         MouseScrollDelta::LineDelta(_x, y) => {
-                            renderer.zoom(*y);
+                            scene.camera_mut().zoom(*y);
                         }
                         MouseScrollDelta::PixelDelta(_) => {}
                     },
@@ -89,13 +220,33 @@ fn main() {
                     // }
                     DeviceEvent::MouseMotion { delta } => {
                         if program_state.left_mouse_pressed {
-                            renderer.rotate(*delta);
+                            scene
+                                .camera_mut()
+                                .rotate((delta.0 as f32, delta.1 as f32));
                         }
                     }
                     _ => {}
                 },
                 Event::RedrawRequested(_) => {
-                    renderer.draw();
+                    renderer.update(&mut scene);
+                    renderer.draw(&scene);
+
+                    match renderer.loading_progress() {
+                        Some(progress) => {
+                            window.set_title(&format!(
+                                "{} - loading {} ({:.0}%)",
+                                WINDOW_TITLE,
+                                progress.name,
+                                progress.percent()
+                            ));
+                            program_state.loading = true;
+                        }
+                        None if program_state.loading => {
+                            window.set_title(WINDOW_TITLE);
+                            program_state.loading = false;
+                        }
+                        None => {}
+                    }
                 }
                 _ => {}
             }
@@ -0,0 +1,724 @@
+use crate::bounds::{BoundingSphere, Bounds};
+use crate::import_options::ImportOptions;
+use crate::importer::{self, ImportError};
+use crate::lod::Lod;
+use crate::mesh_optimizer::IndexFormat;
+use crate::meshlet::{Cone, Meshlet};
+use crate::mipmaps::MipFilter;
+use crate::model::{MaterialData, MeshData, ModelVertex, NodeData, PrimitiveData};
+use crate::picking::TriangleMesh;
+use crate::shader_bindings::{vector_float4, Material};
+use crate::simd;
+use crate::texture_bake::{self, FnvHasher, TextureCompression};
+use crate::texture_cache::TextureKey;
+use crate::texture_data::ColorSpace;
+use glam::{Mat4, Vec3};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+const CACHE_MAGIC: &[u8; 4] = b"MGBM";
+/// Part of every cache file name, so changing what is stored or how vertices
+/// are built invalidates what was cached before.
+const CACHE_VERSION: u32 = 10;
+
+/// The geometry, materials and node data of a model: everything `ModelData`
+/// holds except the decoded textures.
+pub struct CachedModel {
+    pub meshes: Vec<MeshData>,
+    pub materials: HashMap<Option<usize>, MaterialData>,
+    pub variants: Vec<String>,
+    /// The nodes of the glTF scene, each after its parent.
+    pub nodes: Vec<NodeData>,
+}
+
+/// Named after the glTF file and a hash of its contents and of the buffers it
+/// references, so editing any of them invalidates the cache. Meshes optimized
+/// or simplified differently are cached apart.
+pub fn cache_path(
+    source: &Path,
+    compression: TextureCompression,
+    import_options: &ImportOptions,
+) -> Result<PathBuf, ImportError> {
+    let mut hasher = FnvHasher::default();
+    CACHE_VERSION.hash(&mut hasher);
+    mem::size_of::<ModelVertex>().hash(&mut hasher);
+    mem::size_of::<Material>().hash(&mut hasher);
+    compression.hash(&mut hasher);
+    import_options.optimization.hash(&mut hasher);
+    import_options.lod.hash(&mut hasher);
+    import_options.meshlets.hash(&mut hasher);
+    for file in importer::source_files(source)? {
+        hasher.write(&fs::read(file)?);
+    }
+
+    let stem = source
+        .file_stem()
+        .map_or("model".into(), |stem| stem.to_string_lossy());
+    Ok(texture_bake::bake_directory().join("meshes").join(format!(
+        "{}-{:016x}.bin",
+        stem,
+        hasher.finish()
+    )))
+}
+
+/// Texture paths are stored relative to `model_directory`, the directory of
+/// the glTF file, so the cache does not depend on where the model lives.
+pub fn write(path: &Path, model: &CachedModel, model_directory: &Path) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    let model_directory = canonical_directory(model_directory);
+    let mut writer = Writer {
+        bytes: CACHE_MAGIC.to_vec(),
+        model_directory: &model_directory,
+    };
+    writer.u32(model.meshes.len() as u32);
+    for mesh in model.meshes.iter() {
+        writer.string(&mesh.name);
+        writer.u32(mesh.primitives.len() as u32);
+        for primitive in mesh.primitives.iter() {
+            writer.u32(primitive.vertices.len() as u32);
+            for vertex in primitive.vertices.iter() {
+                writer.vertex(vertex);
+            }
+            writer.u32(primitive.indices.len() as u32);
+            for &index in primitive.indices.iter() {
+                writer.u32(index);
+            }
+            writer.u32(match primitive.index_format {
+                IndexFormat::UInt16 => 0,
+                IndexFormat::UInt32 => 1,
+            });
+            writer.u32(primitive.lods.len() as u32);
+            for lod in primitive.lods.iter() {
+                writer.u32(lod.index_offset);
+                writer.u32(lod.index_count);
+                writer.f32(lod.error);
+            }
+            writer.u32(primitive.meshlets.len() as u32);
+            for meshlet in primitive.meshlets.iter() {
+                writer.u32(meshlet.index_offset);
+                writer.u32(meshlet.index_count);
+                writer.u32(meshlet.vertex_count);
+                writer.bounding_sphere(&meshlet.bounding_sphere);
+                writer.vec3(meshlet.cone.apex);
+                writer.vec3(meshlet.cone.axis);
+                writer.f32(meshlet.cone.cutoff);
+            }
+            writer.optional_index(primitive.material_index);
+            writer.u32(primitive.variant_materials.len() as u32);
+            for (&variant, &material) in primitive.variant_materials.iter() {
+                writer.u32(variant as u32);
+                writer.u32(material as u32);
+            }
+            writer.bounds(&primitive.bounds);
+            writer.bounding_sphere(&primitive.bounding_sphere);
+        }
+        match &mesh.instances {
+            Some(instances) => {
+                writer.u32(1);
+                writer.u32(instances.len() as u32);
+                for instance in instances.iter() {
+                    writer.mat4(instance);
+                }
+            }
+            None => writer.u32(0),
+        }
+    }
+
+    writer.u32(model.materials.len() as u32);
+    for (&index, material) in model.materials.iter() {
+        writer.optional_index(index);
+        for key in material.texture_slots() {
+            writer.texture_key(key.as_ref());
+        }
+        writer.material(&material.material);
+    }
+
+    writer.u32(model.variants.len() as u32);
+    for variant in model.variants.iter() {
+        writer.string(variant);
+    }
+    writer.u32(model.nodes.len() as u32);
+    for node in model.nodes.iter() {
+        writer.string(&node.name);
+        writer.mat4(&node.transform);
+        writer.optional_index(node.parent);
+        writer.optional_index(node.mesh);
+    }
+
+    fs::File::create(path)?.write_all(&writer.bytes)
+}
+
+/// Reads a cache written by `write` for a model in `model_directory`.
+pub fn read(path: &Path, model_directory: &Path) -> io::Result<CachedModel> {
+    let bytes = fs::read(path)?;
+    let model_directory = canonical_directory(model_directory);
+    let mut reader = Reader {
+        bytes: &bytes,
+        offset: 0,
+        model_directory: &model_directory,
+    };
+    if reader.take(CACHE_MAGIC.len())? != CACHE_MAGIC {
+        return Err(invalid());
+    }
+
+    let mut meshes = vec![];
+    for _ in 0..reader.u32()? {
+        let name = reader.string()?;
+        let mut primitives = vec![];
+        for _ in 0..reader.u32()? {
+            let mut vertices = vec![];
+            for _ in 0..reader.count(mem::size_of::<ModelVertex>())? {
+                vertices.push(reader.vertex()?);
+            }
+            let mut indices = vec![];
+            for _ in 0..reader.count(mem::size_of::<u32>())? {
+                indices.push(reader.u32()?);
+            }
+            let index_format = match reader.u32()? {
+                0 => IndexFormat::UInt16,
+                1 => IndexFormat::UInt32,
+                _ => return Err(invalid()),
+            };
+            let mut lods = vec![];
+            for _ in 0..reader.u32()? {
+                let lod = Lod {
+                    index_offset: reader.u32()?,
+                    index_count: reader.u32()?,
+                    error: reader.f32()?,
+                };
+                check_range(lod.index_offset, lod.index_count, indices.len())?;
+                lods.push(lod);
+            }
+            // the full mesh is always there
+            if lods.is_empty() {
+                return Err(invalid());
+            }
+            let mut meshlets = vec![];
+            for _ in 0..reader.u32()? {
+                let meshlet = Meshlet {
+                    index_offset: reader.u32()?,
+                    index_count: reader.u32()?,
+                    vertex_count: reader.u32()?,
+                    bounding_sphere: reader.bounding_sphere()?,
+                    cone: Cone {
+                        apex: reader.vec3()?,
+                        axis: reader.vec3()?,
+                        cutoff: reader.f32()?,
+                    },
+                };
+                check_range(meshlet.index_offset, meshlet.index_count, indices.len())?;
+                meshlets.push(meshlet);
+            }
+            let material_index = reader.optional_index()?;
+            let mut variant_materials = HashMap::new();
+            for _ in 0..reader.u32()? {
+                variant_materials.insert(reader.u32()? as usize, reader.u32()? as usize);
+            }
+            let bounds = reader.bounds()?;
+            let bounding_sphere = reader.bounding_sphere()?;
+            primitives.push(PrimitiveData {
+                vertices,
+                indices,
+                index_format,
+                lods,
+                meshlets,
+                material_index,
+                variant_materials,
+                bounds,
+                bounding_sphere,
+                triangles: TriangleMesh::default(),
+            });
+        }
+        let instances = match reader.u32()? {
+            0 => None,
+            _ => {
+                let mut instances = vec![];
+                for _ in 0..reader.count(mem::size_of::<Mat4>())? {
+                    instances.push(reader.mat4()?);
+                }
+                Some(instances)
+            }
+        };
+        meshes.push(MeshData {
+            name,
+            primitives,
+            instances,
+        });
+    }
+
+    let mut materials = HashMap::new();
+    for _ in 0..reader.u32()? {
+        let index = reader.optional_index()?;
+        let material = MaterialData {
+            base_color_texture: reader.texture_key()?,
+            normal_texture: reader.texture_key()?,
+            metallic_roughness_texture: reader.texture_key()?,
+            occlusion_texture: reader.texture_key()?,
+            emissive_texture: reader.texture_key()?,
+            material: reader.material()?,
+        };
+        materials.insert(index, material);
+    }
+
+    let mut variants = vec![];
+    for _ in 0..reader.u32()? {
+        variants.push(reader.string()?);
+    }
+    let mut nodes = vec![];
+    for index in 0..reader.u32()? as usize {
+        let node = NodeData {
+            name: reader.string()?,
+            transform: reader.mat4()?,
+            parent: reader.optional_index()?,
+            mesh: reader.optional_index()?,
+        };
+        if node.parent.is_some_and(|parent| parent >= index)
+            || node.mesh.is_some_and(|mesh| mesh >= meshes.len())
+        {
+            return Err(invalid());
+        }
+        nodes.push(node);
+    }
+
+    if reader.offset != bytes.len() {
+        return Err(invalid());
+    }
+    Ok(CachedModel {
+        meshes,
+        materials,
+        variants,
+        nodes,
+    })
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "not a mesh cache file")
+}
+
+/// Texture keys hold canonical paths, so the directory they are made
+/// relative to has to be canonical too.
+fn canonical_directory(directory: &Path) -> PathBuf {
+    fs::canonicalize(directory).unwrap_or_else(|_| directory.to_path_buf())
+}
+
+/// Fails unless `count` indices starting at `offset` lie within `len`.
+fn check_range(offset: u32, count: u32, len: usize) -> io::Result<()> {
+    match offset.checked_add(count) {
+        Some(end) if end as usize <= len => Ok(()),
+        _ => Err(invalid()),
+    }
+}
+
+fn vector4(vector: vector_float4) -> [f32; 4] {
+    simd::vec4(vector).to_array()
+}
+
+struct Writer<'a> {
+    bytes: Vec<u8>,
+    model_directory: &'a Path,
+}
+
+impl Writer<'_> {
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn f32s(&mut self, values: &[f32]) {
+        for &value in values {
+            self.f32(value);
+        }
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend(value.as_bytes());
+    }
+
+    fn vertex(&mut self, vertex: &ModelVertex) {
+        self.f32s(&vertex.position);
+        self.f32s(&vertex.normal);
+        self.f32s(&vertex.text_coords);
+        self.f32s(&vertex.tangent);
+        self.f32s(&vertex.bitangent);
+    }
+
+    fn mat4(&mut self, matrix: &Mat4) {
+        self.f32s(&matrix.to_cols_array());
+    }
+
+    fn material(&mut self, material: &Material) {
+        self.f32s(&vector4(material.baseColor));
+        self.f32s(&vector4(material.specularColor));
+        self.f32(material.shininess);
+        self.f32(material.roughness);
+        self.f32(material.metallic);
+    }
+
+    fn optional_index(&mut self, index: Option<usize>) {
+        self.u32(index.map_or(u32::MAX, |index| index as u32));
+    }
+
+    fn bounds(&mut self, bounds: &Bounds) {
+        for value in bounds
+            .min
+            .to_array()
+            .into_iter()
+            .chain(bounds.max.to_array())
+        {
+            self.f32(value);
+        }
+    }
+
+    fn vec3(&mut self, value: Vec3) {
+        for value in value.to_array() {
+            self.f32(value);
+        }
+    }
+
+    fn bounding_sphere(&mut self, sphere: &BoundingSphere) {
+        self.vec3(sphere.center);
+        self.f32(sphere.radius);
+    }
+
+    fn texture_key(&mut self, key: Option<&TextureKey>) {
+        let key = match key {
+            Some(key) => key,
+            None => return self.u32(0),
+        };
+        self.u32(1);
+        let path = key
+            .path
+            .strip_prefix(self.model_directory)
+            .unwrap_or(&key.path);
+        self.string(&path.to_string_lossy());
+        self.optional_index(key.image);
+        self.u32(match key.color_space {
+            ColorSpace::Srgb => 0,
+            ColorSpace::Linear => 1,
+        });
+        self.u32(key.red_only as u32);
+        match key.mip_filter {
+            MipFilter::Color => self.u32(0),
+            MipFilter::Normal => self.u32(1),
+            MipFilter::AlphaCoverage { cutoff } => {
+                self.u32(2);
+                self.f32(cutoff);
+            }
+        }
+        self.u32(match key.compression {
+            TextureCompression::None => 0,
+            TextureCompression::Bc => 1,
+            TextureCompression::Astc => 2,
+        });
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    model_directory: &'a Path,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self.offset.checked_add(len).ok_or_else(invalid)?;
+        let bytes = self.bytes.get(self.offset..end).ok_or_else(invalid)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32s<const N: usize>(&mut self) -> io::Result<[f32; N]> {
+        let mut values = [0.0; N];
+        for value in values.iter_mut() {
+            *value = self.f32()?;
+        }
+        Ok(values)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid())
+    }
+
+    /// The length of an array whose elements take at least `element_size`
+    /// bytes, checked against what is left so a corrupt length cannot make
+    /// the reader allocate more than the file holds.
+    fn count(&mut self, element_size: usize) -> io::Result<usize> {
+        let count = self.u32()? as usize;
+        let remaining = self.bytes.len() - self.offset;
+        if count.checked_mul(element_size).ok_or_else(invalid)? > remaining {
+            return Err(invalid());
+        }
+        Ok(count)
+    }
+
+    fn vertex(&mut self) -> io::Result<ModelVertex> {
+        Ok(ModelVertex {
+            position: self.f32s()?,
+            normal: self.f32s()?,
+            text_coords: self.f32s()?,
+            tangent: self.f32s()?,
+            bitangent: self.f32s()?,
+        })
+    }
+
+    fn mat4(&mut self) -> io::Result<Mat4> {
+        Ok(Mat4::from_cols_array(&self.f32s()?))
+    }
+
+    fn material(&mut self) -> io::Result<Material> {
+        let base_color = self.f32s()?;
+        let specular_color = self.f32s()?;
+        let shininess = self.f32()?;
+        let roughness = self.f32()?;
+        let metallic = self.f32()?;
+        Ok(Material::new(
+            base_color,
+            specular_color,
+            shininess,
+            roughness,
+            metallic,
+        ))
+    }
+
+    fn optional_index(&mut self) -> io::Result<Option<usize>> {
+        Ok(match self.u32()? {
+            u32::MAX => None,
+            index => Some(index as usize),
+        })
+    }
+
+    fn bounds(&mut self) -> io::Result<Bounds> {
+        let values: [f32; 6] = self.f32s()?;
+        Ok(Bounds::new(
+            Vec3::new(values[0], values[1], values[2]),
+            Vec3::new(values[3], values[4], values[5]),
+        ))
+    }
+
+    fn vec3(&mut self) -> io::Result<Vec3> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn bounding_sphere(&mut self) -> io::Result<BoundingSphere> {
+        let center = self.vec3()?;
+        Ok(BoundingSphere::new(center, self.f32()?))
+    }
+
+    fn texture_key(&mut self) -> io::Result<Option<TextureKey>> {
+        if self.u32()? == 0 {
+            return Ok(None);
+        }
+        let path = self.model_directory.join(self.string()?);
+        let image = self.optional_index()?;
+        let color_space = match self.u32()? {
+            0 => ColorSpace::Srgb,
+            1 => ColorSpace::Linear,
+            _ => return Err(invalid()),
+        };
+        let red_only = match self.u32()? {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid()),
+        };
+        let mip_filter = match self.u32()? {
+            0 => MipFilter::Color,
+            1 => MipFilter::Normal,
+            2 => MipFilter::AlphaCoverage {
+                cutoff: self.f32()?,
+            },
+            _ => return Err(invalid()),
+        };
+        let compression = match self.u32()? {
+            0 => TextureCompression::None,
+            1 => TextureCompression::Bc,
+            2 => TextureCompression::Astc,
+            _ => return Err(invalid()),
+        };
+        Ok(Some(TextureKey {
+            path,
+            image,
+            color_space,
+            red_only,
+            mip_filter,
+            compression,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_checked_without_overflow() {
+        assert!(check_range(0, 6, 6).is_ok());
+        assert!(check_range(3, 3, 6).is_ok());
+        assert!(check_range(3, 4, 6).is_err());
+        assert!(check_range(u32::MAX, 2, 6).is_err());
+    }
+
+    #[test]
+    fn counts_cannot_exceed_the_file() {
+        let bytes = [2, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8];
+        let mut reader = Reader {
+            bytes: &bytes,
+            offset: 0,
+            model_directory: Path::new("models"),
+        };
+        assert_eq!(reader.count(4).unwrap(), 2);
+
+        let bytes = u32::MAX.to_le_bytes();
+        let mut reader = Reader {
+            bytes: &bytes,
+            offset: 0,
+            model_directory: Path::new("models"),
+        };
+        assert!(reader.count(mem::size_of::<ModelVertex>()).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let vertex = ModelVertex {
+            position: [1.0, 2.0, 3.0],
+            normal: [0.0, 1.0, 0.0],
+            text_coords: [0.25, 0.75],
+            tangent: [1.0, 0.0, 0.0],
+            bitangent: [0.0, 0.0, 1.0],
+        };
+        let primitive = PrimitiveData {
+            vertices: vec![vertex; 3],
+            indices: vec![0, 1, 2, 2, 1, 0],
+            index_format: IndexFormat::UInt16,
+            lods: vec![Lod {
+                index_offset: 3,
+                index_count: 3,
+                error: 0.5,
+            }],
+            meshlets: vec![],
+            material_index: Some(1),
+            variant_materials: HashMap::from([(0, 1)]),
+            bounds: Bounds::new(Vec3::ZERO, Vec3::ONE),
+            bounding_sphere: BoundingSphere::new(Vec3::splat(0.5), 1.0),
+            triangles: TriangleMesh::default(),
+        };
+        let material = MaterialData {
+            base_color_texture: None,
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            material: Material::new([0.5, 0.25, 1.0, 1.0], [1.0; 4], 16.0, 0.75, 0.125),
+        };
+        let model = CachedModel {
+            meshes: vec![MeshData {
+                name: "mesh".to_string(),
+                primitives: vec![primitive],
+                instances: Some(vec![Mat4::from_translation(Vec3::X)]),
+            }],
+            materials: HashMap::from([(Some(1), material)]),
+            variants: vec!["red".to_string()],
+            nodes: vec![
+                NodeData {
+                    name: "root".to_string(),
+                    transform: Mat4::from_scale(Vec3::splat(2.0)),
+                    parent: None,
+                    mesh: None,
+                },
+                NodeData {
+                    name: "child".to_string(),
+                    transform: Mat4::from_translation(Vec3::Y),
+                    parent: Some(0),
+                    mesh: Some(0),
+                },
+            ],
+        };
+
+        let path = std::env::temp_dir().join("mesh-cache-round-trip.bin");
+        write(&path, &model, Path::new("models")).unwrap();
+        let read_model = read(&path, Path::new("models")).unwrap();
+
+        let primitive = &read_model.meshes[0].primitives[0];
+        assert_eq!(read_model.meshes[0].name, "mesh");
+        assert_eq!(primitive.vertices, vec![vertex; 3]);
+        assert_eq!(primitive.indices, [0, 1, 2, 2, 1, 0]);
+        assert_eq!(primitive.lods[0].index_offset, 3);
+        assert_eq!(primitive.material_index, Some(1));
+        assert_eq!(primitive.variant_materials[&0], 1);
+        assert_eq!(primitive.bounds.max, Vec3::ONE);
+        assert_eq!(
+            read_model.meshes[0].instances,
+            Some(vec![Mat4::from_translation(Vec3::X)])
+        );
+        let child = &read_model.nodes[1];
+        assert_eq!(read_model.nodes[0].transform, model.nodes[0].transform);
+        assert_eq!(child.name, "child");
+        assert_eq!(child.transform, Mat4::from_translation(Vec3::Y));
+        assert_eq!((child.parent, child.mesh), (Some(0), Some(0)));
+        assert_eq!(read_model.variants, ["red"]);
+
+        let material = &read_model.materials[&Some(1)].material;
+        assert_eq!(vector4(material.baseColor), [0.5, 0.25, 1.0, 1.0]);
+        assert_eq!(material.shininess, 16.0);
+        assert_eq!(material.roughness, 0.75);
+        assert_eq!(material.metallic, 0.125);
+
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.pop();
+        fs::write(&path, &bytes).unwrap();
+        assert!(read(&path, Path::new("models")).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn texture_paths_are_relative_to_the_model() {
+        let key = TextureKey::new(
+            Path::new("models/helmet/textures/albedo.png"),
+            None,
+            ColorSpace::Srgb,
+            false,
+            MipFilter::AlphaCoverage { cutoff: 0.5 },
+            TextureCompression::Bc,
+        );
+        let mut writer = Writer {
+            bytes: vec![],
+            model_directory: Path::new("models/helmet"),
+        };
+        writer.texture_key(Some(&key));
+        let stored = String::from_utf8_lossy(&writer.bytes);
+        assert!(stored.contains("textures/albedo.png"));
+        assert!(!stored.contains("helmet"));
+
+        let mut reader = Reader {
+            bytes: &writer.bytes,
+            offset: 0,
+            model_directory: Path::new("elsewhere/helmet"),
+        };
+        let read = reader.texture_key().unwrap().unwrap();
+        assert_eq!(read.path, Path::new("elsewhere/helmet/textures/albedo.png"));
+        assert_eq!(read.color_space, ColorSpace::Srgb);
+        assert!(!read.red_only);
+        assert_eq!(read.mip_filter, MipFilter::AlphaCoverage { cutoff: 0.5 });
+        assert_eq!(read.compression, TextureCompression::Bc);
+        assert_eq!(reader.offset, writer.bytes.len());
+    }
+}
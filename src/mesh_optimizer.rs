@@ -0,0 +1,356 @@
+use meshopt::DecodePosition;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// The FIFO cache size ACMR is measured with. Small enough that the result
+/// does not depend much on the GPU.
+const ANALYSIS_CACHE_SIZE: u32 = 16;
+
+/// Which stages of the import optimization run. Every stage is on by default.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MeshOptimization {
+    /// Merges vertices whose attributes are bit for bit equal.
+    pub weld: bool,
+    /// Reorders triangles for the post-transform vertex cache.
+    pub vertex_cache: bool,
+    /// Reorders triangle clusters to draw front ones first, giving up at most
+    /// this much of the vertex cache gain (1.05 = 5%).
+    pub overdraw_threshold: Option<f32>,
+    /// Reorders vertices in the order the indices first use them.
+    pub vertex_fetch: bool,
+    /// Uses 16-bit indices when every index fits.
+    pub short_indices: bool,
+}
+
+impl Default for MeshOptimization {
+    fn default() -> Self {
+        Self {
+            weld: true,
+            vertex_cache: true,
+            overdraw_threshold: Some(1.05),
+            vertex_fetch: true,
+            short_indices: true,
+        }
+    }
+}
+
+impl Eq for MeshOptimization {}
+
+impl Hash for MeshOptimization {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.weld.hash(state);
+        self.vertex_cache.hash(state);
+        self.overdraw_threshold
+            .map(|threshold| threshold.to_bits())
+            .hash(state);
+        self.vertex_fetch.hash(state);
+        self.short_indices.hash(state);
+    }
+}
+
+/// How the indices of a primitive are uploaded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IndexFormat {
+    UInt16,
+    UInt32,
+}
+
+impl IndexFormat {
+    /// The smallest format that can address `vertex_count` vertices.
+    pub fn for_vertex_count(vertex_count: usize) -> Self {
+        if vertex_count <= u16::MAX as usize + 1 {
+            IndexFormat::UInt16
+        } else {
+            IndexFormat::UInt32
+        }
+    }
+
+    /// The indices as they are uploaded in this format.
+    pub fn encode(self, indices: &[u32]) -> Vec<u8> {
+        match self {
+            IndexFormat::UInt16 => indices
+                .iter()
+                .flat_map(|&index| (index as u16).to_ne_bytes())
+                .collect(),
+            IndexFormat::UInt32 => indices
+                .iter()
+                .flat_map(|index| index.to_ne_bytes())
+                .collect(),
+        }
+    }
+}
+
+/// What the optimization did to one primitive.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OptimizationStats {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    /// Average cache miss ratio: vertices transformed per triangle, between
+    /// 0.5 and 3.0. Lower is better.
+    pub acmr_before: f32,
+    pub acmr_after: f32,
+    pub index_format: IndexFormat,
+}
+
+impl fmt::Display for OptimizationStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "vertices {} -> {}, ACMR {:.3} -> {:.3}, {:?} indices",
+            self.vertices_before,
+            self.vertices_after,
+            self.acmr_before,
+            self.acmr_after,
+            self.index_format
+        )
+    }
+}
+
+/// Vertices transformed per triangle with a simulated FIFO vertex cache.
+pub fn acmr(indices: &[u32], vertex_count: usize) -> f32 {
+    if indices.len() < 3 {
+        return 0.0;
+    }
+    meshopt::analyze_vertex_cache(indices, vertex_count, ANALYSIS_CACHE_SIZE, 0, 0).acmr
+}
+
+/// Runs the stages `options` enables on a triangle list, in place. A
+/// primitive without indices is given them, so it can be welded.
+pub fn optimize<T: Copy + Default + DecodePosition>(
+    vertices: &mut Vec<T>,
+    indices: &mut Vec<u32>,
+    options: &MeshOptimization,
+) -> OptimizationStats {
+    if indices.is_empty() {
+        *indices = (0..vertices.len() as u32).collect();
+    }
+    let vertices_before = vertices.len();
+    let acmr_before = acmr(indices, vertices.len());
+
+    if options.weld {
+        let (vertex_count, remap) = meshopt::generate_vertex_remap(vertices, Some(indices));
+        *indices = meshopt::remap_index_buffer(Some(indices), vertex_count, &remap);
+        *vertices = meshopt::remap_vertex_buffer(vertices, vertex_count, &remap);
+    }
+
+    if options.vertex_cache {
+        *indices = meshopt::optimize_vertex_cache(indices, vertices.len());
+
+        // the overdraw optimizer expects indices ordered for the vertex cache
+        if let Some(threshold) = options.overdraw_threshold {
+            meshopt::optimize_overdraw_in_place_decoder(indices, vertices, threshold);
+        }
+    }
+
+    if options.vertex_fetch {
+        *vertices = meshopt::optimize_vertex_fetch(indices, vertices);
+    }
+
+    let index_format = if options.short_indices {
+        IndexFormat::for_vertex_count(vertices.len())
+    } else {
+        IndexFormat::UInt32
+    };
+
+    OptimizationStats {
+        vertices_before,
+        vertices_after: vertices.len(),
+        acmr_before,
+        acmr_after: acmr(indices, vertices.len()),
+        index_format,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Copy, Clone, Default, PartialEq)]
+    struct Vertex {
+        position: [f32; 3],
+        uv: [f32; 2],
+    }
+
+    impl DecodePosition for Vertex {
+        fn decode_position(&self) -> [f32; 3] {
+            self.position
+        }
+    }
+
+    /// An `n` by `n` grid of quads as an unindexed triangle list in a
+    /// scrambled order, the worst case for the vertex cache.
+    fn scrambled_grid(n: usize) -> Vec<Vertex> {
+        let vertex = |x: usize, y: usize| Vertex {
+            position: [x as f32, y as f32, 0.0],
+            uv: [x as f32 / n as f32, y as f32 / n as f32],
+        };
+        let mut triangles = vec![];
+        for y in 0..n {
+            for x in 0..n {
+                triangles.push([vertex(x, y), vertex(x + 1, y), vertex(x, y + 1)]);
+                triangles.push([vertex(x + 1, y), vertex(x + 1, y + 1), vertex(x, y + 1)]);
+            }
+        }
+        let count = triangles.len();
+        (1..=count)
+            .flat_map(|i| triangles[i * 7919 % count])
+            .collect()
+    }
+
+    /// The triangles as sorted vertex attribute bits, each with its winding
+    /// kept but rotated to start at its smallest corner.
+    fn triangles(vertices: &[Vertex], indices: &[u32]) -> Vec<[[u32; 5]; 3]> {
+        let mut triangles: Vec<[[u32; 5]; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let corners = [0, 1, 2].map(|corner| {
+                    let vertex = vertices[triangle[corner] as usize];
+                    let [x, y, z] = vertex.position.map(f32::to_bits);
+                    let [u, v] = vertex.uv.map(f32::to_bits);
+                    [x, y, z, u, v]
+                });
+                let first = (0..3).min_by_key(|&corner| corners[corner]).unwrap();
+                [0, 1, 2].map(|corner| corners[(first + corner) % 3])
+            })
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    fn identity_indices(vertices: &[Vertex]) -> Vec<u32> {
+        (0..vertices.len() as u32).collect()
+    }
+
+    #[test]
+    fn welding_keeps_every_triangle() {
+        let original = scrambled_grid(40);
+        let mut vertices = original.clone();
+        let mut indices = vec![];
+        let stats = optimize(&mut vertices, &mut indices, &MeshOptimization::default());
+
+        assert_eq!(stats.vertices_before, 40 * 40 * 6);
+        assert_eq!(stats.vertices_after, 41 * 41);
+        assert_eq!(indices.len(), original.len());
+        assert_eq!(
+            triangles(&vertices, &indices),
+            triangles(&original, &identity_indices(&original))
+        );
+    }
+
+    #[test]
+    fn remapped_indices_address_unique_used_vertices() {
+        let mut vertices = scrambled_grid(16);
+        let mut indices = vec![];
+        optimize(&mut vertices, &mut indices, &MeshOptimization::default());
+
+        assert!(indices
+            .iter()
+            .all(|&index| (index as usize) < vertices.len()));
+        let mut used = vec![false; vertices.len()];
+        for &index in indices.iter() {
+            used[index as usize] = true;
+        }
+        assert!(used.iter().all(|&used| used));
+
+        let mut bits: Vec<_> = vertices
+            .iter()
+            .map(|vertex| {
+                (
+                    vertex.position.map(f32::to_bits),
+                    vertex.uv.map(f32::to_bits),
+                )
+            })
+            .collect();
+        bits.sort_unstable();
+        bits.dedup();
+        assert_eq!(bits.len(), vertices.len());
+    }
+
+    #[test]
+    fn vertex_fetch_order_follows_indices() {
+        let mut vertices = scrambled_grid(8);
+        let mut indices = vec![];
+        optimize(&mut vertices, &mut indices, &MeshOptimization::default());
+
+        let mut next = 0;
+        for &index in indices.iter() {
+            assert!(index <= next);
+            if index == next {
+                next += 1;
+            }
+        }
+        assert_eq!(next as usize, vertices.len());
+    }
+
+    #[test]
+    fn vertex_cache_order_lowers_acmr() {
+        let original = scrambled_grid(40);
+        let mut vertices = original.clone();
+        let mut indices = vec![];
+        let options = MeshOptimization {
+            vertex_fetch: false,
+            ..MeshOptimization::default()
+        };
+        let stats = optimize(&mut vertices, &mut indices, &options);
+
+        assert_eq!(stats.acmr_before, 3.0);
+        assert!(stats.acmr_after < 1.0, "{}", stats);
+        assert_eq!(stats.acmr_after, acmr(&indices, vertices.len()));
+        assert_eq!(
+            triangles(&vertices, &indices),
+            triangles(&original, &identity_indices(&original))
+        );
+    }
+
+    #[test]
+    fn reordering_without_welding_keeps_vertices() {
+        let original = scrambled_grid(6);
+        let mut vertices = original.clone();
+        let mut indices = vec![];
+        let options = MeshOptimization {
+            weld: false,
+            vertex_fetch: false,
+            ..MeshOptimization::default()
+        };
+        optimize(&mut vertices, &mut indices, &options);
+
+        assert_eq!(vertices, original);
+        let mut sorted = indices.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, identity_indices(&original));
+    }
+
+    #[test]
+    fn stages_can_be_disabled() {
+        let original = scrambled_grid(4);
+        let mut vertices = original.clone();
+        let mut indices = vec![];
+        let options = MeshOptimization {
+            weld: false,
+            vertex_cache: false,
+            overdraw_threshold: None,
+            vertex_fetch: false,
+            short_indices: false,
+        };
+        let stats = optimize(&mut vertices, &mut indices, &options);
+
+        assert_eq!(vertices, original);
+        assert_eq!(indices, identity_indices(&original));
+        assert_eq!(stats.acmr_before, stats.acmr_after);
+        assert_eq!(stats.index_format, IndexFormat::UInt32);
+    }
+
+    #[test]
+    fn index_formats() {
+        assert_eq!(IndexFormat::for_vertex_count(65536), IndexFormat::UInt16);
+        assert_eq!(IndexFormat::for_vertex_count(65537), IndexFormat::UInt32);
+        assert_eq!(
+            IndexFormat::UInt16.encode(&[1, 65535]),
+            [1u16.to_ne_bytes(), 65535u16.to_ne_bytes()].concat()
+        );
+        assert_eq!(IndexFormat::UInt32.encode(&[70000]), 70000u32.to_ne_bytes());
+        assert_eq!(acmr(&[0, 1], 2), 0.0);
+    }
+}
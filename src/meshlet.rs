@@ -0,0 +1,421 @@
+use crate::bounds::BoundingSphere;
+use crate::frustum::Frustum;
+use glam::{const_vec3, Mat4, Vec3};
+use meshopt::DecodePosition;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// The most vertices a meshlet can have, the limit of mesh shaders.
+pub const MAX_MESHLET_VERTICES: usize = 64;
+/// The most triangles a meshlet can have. Leaves room in a 128 triangle
+/// output for a mesh shader to stay aligned.
+pub const MAX_MESHLET_TRIANGLES: usize = 124;
+
+/// How primitives are split into meshlets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MeshletSettings {
+    pub max_vertices: usize,
+    pub max_triangles: usize,
+}
+
+impl Default for MeshletSettings {
+    fn default() -> Self {
+        Self {
+            max_vertices: MAX_MESHLET_VERTICES,
+            max_triangles: MAX_MESHLET_TRIANGLES,
+        }
+    }
+}
+
+/// Every direction the triangles of a meshlet face lies within the cone
+/// around `axis`. Seen from inside the cone behind `apex`, they all face away.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cone {
+    pub apex: Vec3,
+    pub axis: Vec3,
+    /// The cosine of the half angle, widened by the angle of the triangles.
+    /// 1 or more for a cone that never faces away.
+    pub cutoff: f32,
+}
+
+impl Cone {
+    /// For meshlets that are seen from both sides.
+    pub const NONE: Cone = Cone {
+        apex: const_vec3!([0.0; 3]),
+        axis: const_vec3!([0.0, 0.0, 1.0]),
+        cutoff: 1.0,
+    };
+
+    /// Whether every triangle faces away from `eye` once `transform`, without
+    /// non-uniform scale, is applied.
+    pub fn is_backfacing(&self, eye: Vec3, transform: Mat4) -> bool {
+        if self.cutoff >= 1.0 {
+            return false;
+        }
+        let apex = transform.transform_point3(self.apex);
+        let axis = transform.transform_vector3(self.axis).normalize();
+        (apex - eye).normalize().dot(axis) >= self.cutoff
+    }
+}
+
+/// A cluster of triangles that is culled as a whole.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Meshlet {
+    /// The triangles of the meshlet, as a range of the index buffer of the
+    /// full mesh.
+    pub index_offset: u32,
+    pub index_count: u32,
+    pub vertex_count: u32,
+    pub bounding_sphere: BoundingSphere,
+    pub cone: Cone,
+}
+
+/// Positions of one meshlet, for the bounds of just its vertices.
+struct Position([f32; 3]);
+
+impl DecodePosition for Position {
+    fn decode_position(&self) -> [f32; 3] {
+        self.0
+    }
+}
+
+/// Splits the triangle list in `indices` into meshlets, reordering it so
+/// that each meshlet is a contiguous range. Works best on indices ordered
+/// for the vertex cache. Without `cone_culling`, for double-sided materials,
+/// meshlets never face away.
+pub fn build_meshlets<T: DecodePosition>(
+    vertices: &[T],
+    indices: &mut [u32],
+    settings: &MeshletSettings,
+    cone_culling: bool,
+) -> Vec<Meshlet> {
+    let max_vertices = settings.max_vertices.clamp(3, MAX_MESHLET_VERTICES);
+    let max_triangles = settings.max_triangles.clamp(1, MAX_MESHLET_TRIANGLES);
+    let clusters = meshopt::build_meshlets(indices, vertices.len(), max_vertices, max_triangles);
+
+    let mut reordered = Vec::with_capacity(indices.len());
+    let mut meshlets = Vec::with_capacity(clusters.len());
+    for cluster in clusters.iter() {
+        let local_vertices = &cluster.vertices[..cluster.vertex_count as usize];
+        let local_triangles = &cluster.indices[..cluster.triangle_count as usize];
+
+        let positions: Vec<Position> = local_vertices
+            .iter()
+            .map(|&vertex| Position(vertices[vertex as usize].decode_position()))
+            .collect();
+        let local_indices: Vec<u32> = local_triangles
+            .iter()
+            .flatten()
+            .map(|&index| index as u32)
+            .collect();
+        let bounds = meshopt::compute_cluster_bounds_decoder(&local_indices, &positions);
+
+        let index_offset = reordered.len() as u32;
+        reordered.extend(
+            local_indices
+                .iter()
+                .map(|&index| local_vertices[index as usize]),
+        );
+        meshlets.push(Meshlet {
+            index_offset,
+            index_count: local_indices.len() as u32,
+            vertex_count: cluster.vertex_count as u32,
+            bounding_sphere: BoundingSphere::new(Vec3::from(bounds.center), bounds.radius),
+            cone: if cone_culling {
+                Cone {
+                    apex: Vec3::from(bounds.cone_apex),
+                    axis: Vec3::from(bounds.cone_axis),
+                    cutoff: bounds.cone_cutoff,
+                }
+            } else {
+                Cone::NONE
+            },
+        });
+    }
+
+    indices.copy_from_slice(&reordered);
+    meshlets
+}
+
+/// What cluster culling needs to know about the camera.
+#[derive(Debug, Copy, Clone)]
+pub struct ClusterView {
+    pub frustum: Frustum,
+    pub eye: Vec3,
+}
+
+impl ClusterView {
+    pub fn new(view_projection: Mat4, eye: Vec3) -> Self {
+        Self {
+            frustum: Frustum::from_matrix(view_projection),
+            eye,
+        }
+    }
+
+    /// Whether `meshlet`, placed by `transform`, is in view and faces the eye.
+    pub fn is_visible(&self, meshlet: &Meshlet, transform: Mat4) -> bool {
+        self.frustum
+            .intersects_sphere(&meshlet.bounding_sphere.transform(transform))
+            && !meshlet.cone.is_backfacing(self.eye, transform)
+    }
+
+    /// The index ranges of the visible meshlets, with neighbouring ranges
+    /// merged so they are drawn together.
+    pub fn visible_ranges(&self, meshlets: &[Meshlet], transform: Mat4) -> Vec<Range<u32>> {
+        let mut ranges: Vec<Range<u32>> = vec![];
+        for meshlet in meshlets {
+            if !self.is_visible(meshlet, transform) {
+                continue;
+            }
+            let end = meshlet.index_offset + meshlet.index_count;
+            match ranges.last_mut() {
+                Some(range) if range.end == meshlet.index_offset => range.end = end,
+                _ => ranges.push(meshlet.index_offset..end),
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Default)]
+    struct Vertex([f32; 3]);
+
+    impl DecodePosition for Vertex {
+        fn decode_position(&self) -> [f32; 3] {
+            self.0
+        }
+    }
+
+    /// An `n` by `n` grid over the unit square, counter-clockwise seen from
+    /// +Z, its height given by `height`.
+    fn surface(n: u32, height: impl Fn(f32, f32) -> f32) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = vec![];
+        for y in 0..=n {
+            for x in 0..=n {
+                let (u, v) = (x as f32 / n as f32, y as f32 / n as f32);
+                vertices.push(Vertex([u, v, height(u, v)]));
+            }
+        }
+        let mut indices = vec![];
+        for y in 0..n {
+            for x in 0..n {
+                let i = y * (n + 1) + x;
+                indices.extend([i, i + 1, i + n + 1, i + 1, i + n + 2, i + n + 1]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    fn grid(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+        surface(n, |_, _| 0.0)
+    }
+
+    /// A bumpy surface, so the cones of its meshlets differ.
+    fn dome(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+        surface(n, |u, v| {
+            0.4 * ((u - 0.5).powi(2) + (v - 0.5).powi(2)).sqrt().cos()
+        })
+    }
+
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let first = (0..3).min_by_key(|&corner| triangle[corner]).unwrap();
+                [0, 1, 2].map(|corner| triangle[(first + corner) % 3])
+            })
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    /// The corners of every triangle of `meshlet`.
+    fn meshlet_triangles(
+        vertices: &[Vertex],
+        indices: &[u32],
+        meshlet: &Meshlet,
+    ) -> Vec<[Vec3; 3]> {
+        indices[meshlet.index_offset as usize..][..meshlet.index_count as usize]
+            .chunks_exact(3)
+            .map(|triangle| {
+                [0, 1, 2].map(|corner| Vec3::from(vertices[triangle[corner] as usize].0))
+            })
+            .collect()
+    }
+
+    fn normal([a, b, c]: [Vec3; 3]) -> Vec3 {
+        (b - a).cross(c - a).normalize()
+    }
+
+    #[test]
+    fn meshlets_cover_every_triangle_within_the_limits() {
+        let (vertices, mut indices) = grid(32);
+        let original = indices.clone();
+        let meshlets = build_meshlets(&vertices, &mut indices, &MeshletSettings::default(), true);
+
+        assert!(meshlets.len() > 1);
+        let mut offset = 0;
+        for meshlet in meshlets.iter() {
+            assert_eq!(meshlet.index_offset, offset);
+            assert!(meshlet.index_count as usize / 3 <= MAX_MESHLET_TRIANGLES);
+            assert!(meshlet.vertex_count as usize <= MAX_MESHLET_VERTICES);
+            let mut unique =
+                indices[meshlet.index_offset as usize..][..meshlet.index_count as usize].to_vec();
+            unique.sort_unstable();
+            unique.dedup();
+            assert_eq!(unique.len(), meshlet.vertex_count as usize);
+            offset += meshlet.index_count;
+        }
+        assert_eq!(offset as usize, indices.len());
+        assert_eq!(sorted_triangles(&indices), sorted_triangles(&original));
+    }
+
+    #[test]
+    fn smaller_limits_are_respected() {
+        let (vertices, mut indices) = grid(16);
+        let settings = MeshletSettings {
+            max_vertices: 16,
+            max_triangles: 8,
+        };
+        let meshlets = build_meshlets(&vertices, &mut indices, &settings, true);
+        assert!(meshlets
+            .iter()
+            .all(|meshlet| meshlet.vertex_count <= 16 && meshlet.index_count <= 24));
+        assert!(meshlets.len() >= 16 * 16 * 2 / 8);
+    }
+
+    #[test]
+    fn bounding_spheres_contain_their_vertices() {
+        let (vertices, mut indices) = dome(24);
+        let meshlets = build_meshlets(&vertices, &mut indices, &MeshletSettings::default(), true);
+
+        for meshlet in meshlets.iter() {
+            let sphere = &meshlet.bounding_sphere;
+            for corner in meshlet_triangles(&vertices, &indices, meshlet)
+                .into_iter()
+                .flatten()
+            {
+                assert!(corner.distance(sphere.center) <= sphere.radius + 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn cones_contain_every_triangle_normal() {
+        let (vertices, mut indices) = dome(24);
+        let meshlets = build_meshlets(&vertices, &mut indices, &MeshletSettings::default(), true);
+
+        for meshlet in meshlets.iter() {
+            let cone = &meshlet.cone;
+            assert!(cone.cutoff < 1.0);
+            assert!((cone.axis.length() - 1.0).abs() < 1e-2);
+            // the cutoff is the sine of the half angle of the normal cone
+            let min_dot = (1.0 - cone.cutoff * cone.cutoff).sqrt();
+            for triangle in meshlet_triangles(&vertices, &indices, meshlet) {
+                assert!(normal(triangle).dot(cone.axis) >= min_dot - 1e-2);
+            }
+        }
+    }
+
+    #[test]
+    fn backfacing_cones_only_hide_triangles_facing_away() {
+        let (vertices, mut indices) = dome(16);
+        let meshlets = build_meshlets(&vertices, &mut indices, &MeshletSettings::default(), true);
+
+        let mut culled = 0;
+        for x in -4..=4 {
+            for y in -4..=4 {
+                for z in [-3.0, -0.5, 2.0] {
+                    let eye = Vec3::new(x as f32 * 0.5, y as f32 * 0.5, z);
+                    for meshlet in meshlets.iter() {
+                        if !meshlet.cone.is_backfacing(eye, Mat4::IDENTITY) {
+                            continue;
+                        }
+                        culled += 1;
+                        for triangle in meshlet_triangles(&vertices, &indices, meshlet) {
+                            assert!(normal(triangle).dot(triangle[0] - eye) >= -1e-4);
+                        }
+                    }
+                }
+            }
+        }
+        assert!(culled > 0);
+    }
+
+    #[test]
+    fn cones_cull_from_behind() {
+        let (vertices, mut indices) = grid(8);
+        let meshlets = build_meshlets(&vertices, &mut indices, &MeshletSettings::default(), true);
+        let projection = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 0.01, 100.0);
+        let view_from = |eye: Vec3| {
+            let view = Mat4::look_at_lh(eye, Vec3::new(0.5, 0.5, 0.0), Vec3::Y);
+            ClusterView::new(projection * view, eye)
+        };
+        assert!(meshlets
+            .iter()
+            .all(|meshlet| meshlet.cone.axis.dot(Vec3::Z) > 0.99));
+
+        let front = view_from(Vec3::new(0.5, 0.5, 3.0));
+        assert!(meshlets
+            .iter()
+            .all(|meshlet| front.is_visible(meshlet, Mat4::IDENTITY)));
+        let ranges = front.visible_ranges(&meshlets, Mat4::IDENTITY);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0], 0..indices.len() as u32);
+
+        let back = view_from(Vec3::new(0.5, 0.5, -3.0));
+        assert!(back.visible_ranges(&meshlets, Mat4::IDENTITY).is_empty());
+
+        // turned around by the model matrix, the grid faces the back view
+        let turn = Mat4::from_translation(Vec3::new(0.5, 0.5, 0.0))
+            * Mat4::from_rotation_y(std::f32::consts::PI)
+            * Mat4::from_translation(Vec3::new(-0.5, -0.5, 0.0));
+        assert!(meshlets
+            .iter()
+            .all(|meshlet| back.is_visible(meshlet, turn)));
+
+        let mut double_sided = indices.clone();
+        let meshlets = build_meshlets(
+            &vertices,
+            &mut double_sided,
+            &MeshletSettings::default(),
+            false,
+        );
+        assert!(meshlets.iter().all(|meshlet| meshlet.cone == Cone::NONE));
+        assert!(meshlets
+            .iter()
+            .all(|meshlet| back.is_visible(meshlet, Mat4::IDENTITY)));
+    }
+
+    #[test]
+    fn frustum_culls_clusters_out_of_view() {
+        let (vertices, indices) = grid(32);
+        let mut indices = meshopt::optimize_vertex_cache(&indices, vertices.len());
+        let meshlets = build_meshlets(&vertices, &mut indices, &MeshletSettings::default(), true);
+
+        // a narrow view of the lower left corner
+        let eye = Vec3::new(0.1, 0.1, 0.5);
+        let projection = Mat4::perspective_lh(0.3, 1.0, 0.01, 100.0);
+        let view = Mat4::look_at_lh(eye, Vec3::new(0.1, 0.1, 0.0), Vec3::Y);
+        let cluster_view = ClusterView::new(projection * view, eye);
+
+        let visible: Vec<&Meshlet> = meshlets
+            .iter()
+            .filter(|meshlet| cluster_view.is_visible(meshlet, Mat4::IDENTITY))
+            .collect();
+        assert!(!visible.is_empty() && visible.len() < meshlets.len() / 2);
+
+        let drawn: u32 = cluster_view
+            .visible_ranges(&meshlets, Mat4::IDENTITY)
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum();
+        let expected: u32 = visible.iter().map(|meshlet| meshlet.index_count).sum();
+        assert_eq!(drawn, expected);
+    }
+}
@@ -0,0 +1,392 @@
+use crate::texture_data::{TextureData, TextureFormat, TextureUsage};
+use std::hash::{Hash, Hasher};
+
+/// Steps of the search for the alpha scale that preserves coverage.
+const COVERAGE_SEARCH_STEPS: usize = 16;
+
+/// How the texels of one mip level are combined into the next.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MipFilter {
+    /// Box filter on linear values; sRGB levels are decoded first.
+    Color,
+    /// Averages tangent-space normals and renormalizes them.
+    Normal,
+    /// Like `Color`, then scales alpha so that the fraction of texels at or
+    /// above `cutoff` stays the same as in the top level.
+    AlphaCoverage { cutoff: f32 },
+}
+
+impl MipFilter {
+    /// Alpha coverage only matters for the base color of `MASK` materials.
+    pub fn new(usage: TextureUsage, alpha_cutoff: Option<f32>) -> Self {
+        match (usage, alpha_cutoff) {
+            (TextureUsage::Normal, _) => MipFilter::Normal,
+            (TextureUsage::BaseColor, Some(cutoff)) => MipFilter::AlphaCoverage { cutoff },
+            _ => MipFilter::Color,
+        }
+    }
+}
+
+// Cutoffs come from glTF files and are never NaN.
+impl Eq for MipFilter {}
+
+impl Hash for MipFilter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        if let MipFilter::AlphaCoverage { cutoff } = self {
+            cutoff.to_bits().hash(state);
+        }
+    }
+}
+
+/// Number of levels down to 1x1, including the top one.
+pub fn mip_level_count(width: u32, height: u32) -> usize {
+    (32 - width.max(height).max(1).leading_zeros()) as usize
+}
+
+/// Every mip level of `texture_data`, starting with a copy of it. Levels keep
+/// the format of the top level.
+pub fn generate_mip_chain(texture_data: &TextureData, filter: MipFilter) -> Vec<TextureData> {
+    // one and two channel images cannot hold a normal
+    let filter = match (filter, texture_data.format) {
+        (MipFilter::Normal, TextureFormat::R8Unorm | TextureFormat::RG8Unorm) => MipFilter::Color,
+        _ => filter,
+    };
+
+    let level_count = mip_level_count(texture_data.width, texture_data.height);
+    let mut levels = Vec::with_capacity(level_count);
+
+    let mut width = texture_data.width;
+    let mut height = texture_data.height;
+    let mut pixels = texture_data.to_rgba_f32();
+    let coverage = match filter {
+        MipFilter::AlphaCoverage { cutoff } => alpha_coverage(&pixels, cutoff),
+        _ => 0.0,
+    };
+
+    levels.push(texture_data.clone());
+    for _ in 1..level_count {
+        let next_width = (width / 2).max(1);
+        let next_height = (height / 2).max(1);
+        pixels = downsample(&pixels, width, height, next_width, next_height);
+        width = next_width;
+        height = next_height;
+
+        match filter {
+            MipFilter::Color => {}
+            MipFilter::Normal => {
+                for pixel in pixels.iter_mut() {
+                    renormalize(pixel);
+                }
+            }
+            MipFilter::AlphaCoverage { cutoff } => {
+                let scale = coverage_scale(&pixels, cutoff, coverage);
+                for pixel in pixels.iter_mut() {
+                    pixel[3] = (pixel[3] * scale).clamp(0.0, 1.0);
+                }
+            }
+        }
+
+        levels.push(TextureData::from_rgba_f32(
+            texture_data.format,
+            width,
+            height,
+            &pixels,
+        ));
+    }
+
+    levels
+}
+
+/// Fraction of texels that pass an alpha test against `cutoff`.
+pub fn alpha_coverage(pixels: &[[f32; 4]], cutoff: f32) -> f32 {
+    if pixels.is_empty() {
+        return 0.0;
+    }
+    let covered = pixels.iter().filter(|pixel| pixel[3] >= cutoff).count();
+    covered as f32 / pixels.len() as f32
+}
+
+/// Averages the source texels under each destination texel. Odd sizes make
+/// neighbouring footprints share their middle row or column.
+fn downsample(
+    pixels: &[[f32; 4]],
+    width: u32,
+    height: u32,
+    next_width: u32,
+    next_height: u32,
+) -> Vec<[f32; 4]> {
+    let footprint = |index: u32, size: u32, next_size: u32| {
+        let start = index * size / next_size;
+        let end = ((index + 1) * size).div_ceil(next_size);
+        start..end
+    };
+
+    let mut next = Vec::with_capacity((next_width * next_height) as usize);
+    for y in 0..next_height {
+        let rows = footprint(y, height, next_height);
+        for x in 0..next_width {
+            let columns = footprint(x, width, next_width);
+
+            let mut sum = [0.0; 4];
+            let mut count = 0;
+            for source_y in rows.clone() {
+                for source_x in columns.clone() {
+                    let pixel = pixels[(source_y * width + source_x) as usize];
+                    for (total, value) in sum.iter_mut().zip(pixel) {
+                        *total += value;
+                    }
+                    count += 1;
+                }
+            }
+            next.push(sum.map(|total| total / count as f32));
+        }
+    }
+    next
+}
+
+/// Normals are stored as `n * 0.5 + 0.5`; averaging shortens them.
+fn renormalize(pixel: &mut [f32; 4]) {
+    let x = pixel[0] * 2.0 - 1.0;
+    let y = pixel[1] * 2.0 - 1.0;
+    let z = pixel[2] * 2.0 - 1.0;
+    let length = (x * x + y * y + z * z).sqrt();
+    if length <= f32::EPSILON {
+        pixel[0] = 0.5;
+        pixel[1] = 0.5;
+        pixel[2] = 1.0;
+        return;
+    }
+    pixel[0] = x / length * 0.5 + 0.5;
+    pixel[1] = y / length * 0.5 + 0.5;
+    pixel[2] = z / length * 0.5 + 0.5;
+}
+
+/// Binary search for the alpha scale that brings the coverage of `pixels`
+/// closest to `coverage`.
+fn coverage_scale(pixels: &[[f32; 4]], cutoff: f32, coverage: f32) -> f32 {
+    let scaled_coverage = |scale: f32| {
+        let covered = pixels
+            .iter()
+            .filter(|pixel| (pixel[3] * scale).min(1.0) >= cutoff)
+            .count();
+        covered as f32 / pixels.len() as f32
+    };
+
+    let mut low = 0.0;
+    let mut high = 4.0;
+    let mut best = (1.0, (scaled_coverage(1.0) - coverage).abs());
+    for _ in 0..COVERAGE_SEARCH_STEPS {
+        let scale = (low + high) / 2.0;
+        let scaled = scaled_coverage(scale);
+        let error = (scaled - coverage).abs();
+        if error < best.1 {
+            best = (scale, error);
+        }
+        if scaled < coverage {
+            low = scale;
+        } else {
+            high = scale;
+        }
+    }
+    best.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        texel: impl Fn(u32, u32) -> [u8; 4],
+    ) -> TextureData {
+        let mut data = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                data.extend(texel(x, y));
+            }
+        }
+        TextureData {
+            format,
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn coverage(level: &TextureData, cutoff: f32) -> f32 {
+        let covered = level
+            .data
+            .chunks_exact(4)
+            .filter(|texel| texel[3] as f32 / 255.0 >= cutoff)
+            .count();
+        covered as f32 / (level.width * level.height) as f32
+    }
+
+    /// Decodes the BGRA texel at the start of `data` into a normal.
+    fn normal(data: &[u8]) -> [f32; 3] {
+        let decode = |value: u8| value as f32 / 255.0 * 2.0 - 1.0;
+        [decode(data[2]), decode(data[1]), decode(data[0])]
+    }
+
+    fn length(vector: [f32; 3]) -> f32 {
+        vector.iter().map(|value| value * value).sum::<f32>().sqrt()
+    }
+
+    #[test]
+    fn counts_levels_down_to_one_texel() {
+        assert_eq!(mip_level_count(0, 0), 1);
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(2, 1), 2);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(5, 3), 3);
+        assert_eq!(mip_level_count(1, 1000), 10);
+    }
+
+    #[test]
+    fn halves_each_dimension_down_to_one() {
+        let top = texture(TextureFormat::BGRA8Unorm, 5, 3, |_, _| [1, 2, 3, 4]);
+        let chain = generate_mip_chain(&top, MipFilter::Color);
+
+        let sizes: Vec<(u32, u32, usize)> = chain
+            .iter()
+            .map(|level| (level.width, level.height, level.data.len()))
+            .collect();
+        assert_eq!(sizes, vec![(5, 3, 60), (2, 1, 8), (1, 1, 4)]);
+        assert_eq!(chain[0], top);
+        assert!(chain.iter().all(|level| level.format == top.format
+            && level.data.chunks(4).all(|texel| texel == [1, 2, 3, 4])));
+    }
+
+    #[test]
+    fn odd_sizes_share_the_middle_texels() {
+        // One red texel in the middle column of three feeds both halves.
+        let top = texture(TextureFormat::BGRA8Unorm, 3, 1, |x, _| match x {
+            1 => [0, 0, 255, 255],
+            _ => [0, 0, 0, 255],
+        });
+        let chain = generate_mip_chain(&top, MipFilter::Color);
+        assert_eq!((chain[1].width, chain[1].height), (1, 1));
+        assert_eq!(chain[1].data, vec![0, 0, 85, 255]);
+
+        let top = texture(TextureFormat::R8Unorm, 5, 1, |x, _| [x as u8 * 60, 0, 0, 0]);
+        let top = TextureData {
+            data: top.data.chunks(4).map(|texel| texel[0]).collect(),
+            ..top
+        };
+        let chain = generate_mip_chain(&top, MipFilter::Color);
+        // Footprints 0..3 and 2..5 of 0, 60, 120, 180, 240.
+        assert_eq!(chain[1].data, vec![60, 180]);
+    }
+
+    #[test]
+    fn averages_srgb_levels_in_linear_space() {
+        let checker = |x: u32, y: u32| match (x + y) % 2 {
+            0 => [0, 0, 0, 255],
+            _ => [255, 255, 255, 255],
+        };
+
+        // Half the light is about 188 in sRGB; averaging the encoded
+        // values would give 128.
+        let srgb = texture(TextureFormat::BGRA8UnormSrgb, 2, 2, checker);
+        let chain = generate_mip_chain(&srgb, MipFilter::Color);
+        assert_eq!(chain[1].data, vec![188, 188, 188, 255]);
+
+        let linear = texture(TextureFormat::BGRA8Unorm, 2, 2, checker);
+        let chain = generate_mip_chain(&linear, MipFilter::Color);
+        assert_eq!(chain[1].data, vec![128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn renormalizes_averaged_normals() {
+        // +x next to +z.
+        let top = texture(TextureFormat::BGRA8Unorm, 2, 1, |x, _| match x {
+            0 => [128, 128, 255, 255],
+            _ => [255, 128, 128, 255],
+        });
+
+        let [x, _, z] = normal(&generate_mip_chain(&top, MipFilter::Normal)[1].data);
+        assert!((length([x, 0.0, z]) - 1.0).abs() < 0.02);
+        assert!((x - z).abs() < 0.02);
+
+        let box_filtered = normal(&generate_mip_chain(&top, MipFilter::Color)[1].data);
+        assert!(length(box_filtered) < 0.8);
+    }
+
+    #[test]
+    fn degenerate_normals_point_up() {
+        let mut pixel = [0.5, 0.5, 0.5, 1.0];
+        renormalize(&mut pixel);
+        assert_eq!(pixel, [0.5, 0.5, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn narrow_normal_maps_are_box_filtered() {
+        let top = TextureData {
+            format: TextureFormat::RG8Unorm,
+            width: 2,
+            height: 1,
+            data: vec![0, 0, 255, 255],
+        };
+        let chain = generate_mip_chain(&top, MipFilter::Normal);
+        assert_eq!(chain[1].data, vec![128, 128]);
+    }
+
+    #[test]
+    fn keeps_alpha_coverage() {
+        let noise = |x: u32, y: u32| {
+            let hash = ((x * 73856093) ^ (y * 19349663)).wrapping_mul(2654435761) >> 24;
+            [255, 255, 255, hash as u8]
+        };
+        let top = texture(TextureFormat::BGRA8UnormSrgb, 16, 16, noise);
+        let top_coverage = coverage(&top, 0.7);
+
+        // Box filtering pulls alpha towards the mean and erodes coverage.
+        let box_filtered = generate_mip_chain(&top, MipFilter::Color);
+        assert!((coverage(&box_filtered[2], 0.7) - top_coverage).abs() > 0.1);
+
+        let kept = generate_mip_chain(&top, MipFilter::AlphaCoverage { cutoff: 0.7 });
+        for level in &kept[1..4] {
+            assert!(
+                (coverage(level, 0.7) - top_coverage).abs() <= 0.05,
+                "{}x{}: {}",
+                level.width,
+                level.height,
+                coverage(level, 0.7)
+            );
+        }
+    }
+
+    #[test]
+    fn measures_alpha_coverage() {
+        let pixels = [
+            [0.0, 0.0, 0.0, 0.2],
+            [0.0, 0.0, 0.0, 0.5],
+            [0.0, 0.0, 0.0, 0.9],
+        ];
+        assert_eq!(alpha_coverage(&pixels, 0.5), 2.0 / 3.0);
+        assert_eq!(alpha_coverage(&[], 0.5), 0.0);
+    }
+
+    #[test]
+    fn picks_filters_by_usage() {
+        assert_eq!(
+            MipFilter::new(TextureUsage::BaseColor, Some(0.5)),
+            MipFilter::AlphaCoverage { cutoff: 0.5 }
+        );
+        assert_eq!(
+            MipFilter::new(TextureUsage::BaseColor, None),
+            MipFilter::Color
+        );
+        assert_eq!(
+            MipFilter::new(TextureUsage::Emissive, Some(0.5)),
+            MipFilter::Color
+        );
+        assert_eq!(
+            MipFilter::new(TextureUsage::Normal, None),
+            MipFilter::Normal
+        );
+    }
+}
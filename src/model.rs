@@ -1,3 +1,13 @@
+use crate::bounds::{BoundingSphere, Bounds};
+use crate::import_options::ImportOptions;
+use crate::importer::{self, GltfImport, ImportError};
+use crate::ktx;
+use crate::loader::{LoadProgress, ProgressTracker};
+use crate::lod::{self, Lod, LodView};
+use crate::mesh_cache::{self, CachedModel};
+use crate::mesh_optimizer::{self, IndexFormat};
+use crate::meshlet::{self, ClusterView, Meshlet};
+use crate::picking::TriangleMesh;
 use crate::shader_bindings::{
     Attributes_Bitangent, Attributes_Normal, Attributes_Position, Attributes_Tangent,
     Attributes_UV, BufferIndices_BufferIndexFragmentUniforms as BufferIndexFragmentUniforms,
@@ -7,15 +17,29 @@ use crate::shader_bindings::{
     Textures_BaseColorTexture, Textures_EmissiveTexture, Textures_MetallicRoughnessTexture,
     Textures_NormalTexture, Textures_OcclusionTexture, Uniforms,
 };
-use crate::{node::InnerNode, texturable::Texturable};
-use glam::{f32::Quat, Mat3A, Mat4, Vec2, Vec3, Vec4};
+use crate::simd;
+use crate::texture_bake::TextureCompression;
+use crate::texture_cache::{TextureCache, TextureKey};
+use crate::texture_data::TextureData;
+use crate::{
+    instancing,
+    instancing::Instances,
+    node::{InnerNode, NodeContent, NodeId, SceneGraph},
+    renderer::COLOR_PIXEL_FORMAT,
+    texturable::Texturable,
+    texture_data::TextureUsage,
+    variants,
+};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use image::error::ImageResult;
 use metal::*;
-use std::mem;
-
-const TEXTURE_PATH: &str = "DamagedHelmet";
-// const TEXTURE_PATH: &str = "FlightHelmet";
+use rayon::prelude::*;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{iter, mem};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct ModelVertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
@@ -36,6 +60,12 @@ impl Default for ModelVertex {
     }
 }
 
+impl meshopt::DecodePosition for ModelVertex {
+    fn decode_position(&self) -> [f32; 3] {
+        self.position
+    }
+}
+
 impl Material {
     pub fn new(
         base_color: [f32; 4],
@@ -44,14 +74,12 @@ impl Material {
         roughness: f32,
         metallic: f32,
     ) -> Self {
-        unsafe {
-            Self {
-                baseColor: std::mem::transmute(Vec4::from(base_color)),
-                specularColor: std::mem::transmute(Vec4::from(specular_color)),
-                shininess: std::mem::transmute(shininess),
-                roughness: std::mem::transmute(roughness),
-                metallic: std::mem::transmute(metallic),
-            }
+        Self {
+            baseColor: simd::vector4(Vec4::from(base_color)),
+            specularColor: simd::vector4(Vec4::from(specular_color)),
+            shininess,
+            roughness,
+            metallic,
         }
     }
 }
@@ -62,144 +90,244 @@ impl Default for Material {
     }
 }
 
-pub struct Submesh {
-    pub(crate) vertex_buffer: Buffer,
-    pub(crate) index_buffer: Buffer,
-    pub(crate) num_elements: u64,
-    pub(crate) textures: Textures,
-    pub(crate) pipeline_state: RenderPipelineState,
-    pub(crate) material: [Material; 1],
+/// The textures and factors of one glTF material, resolved on a loader
+/// thread. Textures are referred to by cache key.
+pub struct MaterialData {
+    pub(crate) base_color_texture: Option<TextureKey>,
+    pub(crate) normal_texture: Option<TextureKey>,
+    pub(crate) metallic_roughness_texture: Option<TextureKey>,
+    pub(crate) occlusion_texture: Option<TextureKey>,
+    pub(crate) emissive_texture: Option<TextureKey>,
+    pub(crate) material: Material,
 }
 
-impl Submesh {
+impl MaterialData {
     pub fn from_gltf(
-        device: &Device,
-        library: &Library,
         material: &Option<gltf::Material>,
-        vertex_buffer: Buffer,
-        index_buffer: Buffer,
-        num_elements: u64,
-    ) -> Self {
-        let mut textures = Textures::default();
-        let mut pbr_material = Material::default();
-
-        if let Some(material) = material {
-            let normal_texture_source = material.normal_texture().map(|info| {
-                println!("normal text_coord index: {}", info.tex_coord());
-                match info.texture().source().source() {
-                    gltf::image::Source::Uri { uri, .. } => uri,
-                    x => {
-                        println!("x = {:?}", x);
-                        todo!()
-                    }
-                }
-            });
-
-            let normal_texture = normal_texture_source.map(|source| {
-                Self::load_texture(format!("{}/{}", TEXTURE_PATH, source).as_ref(), &device)
-                    .expect("Unable to load normal texture")
-            });
-
-            let occlusion_texture_source = material.occlusion_texture().map(|info| {
-                println!("occlusion text_coord index: {}", info.tex_coord());
-                match info.texture().source().source() {
-                    gltf::image::Source::Uri { uri, .. } => uri,
-                    x => {
-                        println!("x = {:?}", x);
-                        todo!()
-                    }
-                }
-            });
-
-            let occlusion_texture = occlusion_texture_source.map(|source| {
-                Self::load_texture(format!("{}/{}", TEXTURE_PATH, source).as_ref(), &device)
-                    .expect("Unable to load occlusion texture")
-            });
-
-            let emissive_texture_source = material.emissive_texture().map(|info| {
-                println!("emissive text_coord index: {}", info.tex_coord());
-                match info.texture().source().source() {
-                    gltf::image::Source::Uri { uri, .. } => uri,
-                    x => {
-                        println!("x = {:?}", x);
-                        todo!()
-                    }
-                }
-            });
-
-            let emissive_texture = emissive_texture_source.map(|source| {
-                Self::load_texture(format!("{}/{}", TEXTURE_PATH, source).as_ref(), &device)
-                    .expect("Unable to load emissive texture")
-            });
-
-            let emissive_factor = material.emissive_factor();
-            println!("emissive factor: {:?}", emissive_factor);
-
-            let pbr_metallic_roughness = material.pbr_metallic_roughness();
-            let base_color_factor = pbr_metallic_roughness.base_color_factor();
-            let metallic_factor = pbr_metallic_roughness.metallic_factor();
-            let roughness_factor = pbr_metallic_roughness.roughness_factor();
-
-            let base_color_texture_source =
-                pbr_metallic_roughness.base_color_texture().map(|info| {
-                    println!("base color text_coord index: {}", info.tex_coord());
-                    match info.texture().source().source() {
-                        gltf::image::Source::Uri { uri, .. } => uri,
-                        x => {
-                            println!("x = {:?}", x);
-                            todo!()
-                        }
-                    }
-                });
-
-            let base_color_texture = base_color_texture_source.map(|source| {
-                Self::load_texture(format!("{}/{}", TEXTURE_PATH, source).as_ref(), &device)
-                    .expect("Unable to load base color texture")
+        import: &GltfImport,
+        compression: TextureCompression,
+    ) -> Result<Self, ImportError> {
+        let material = match material {
+            Some(material) => material,
+            None => {
+                return Ok(Self {
+                    base_color_texture: None,
+                    normal_texture: None,
+                    metallic_roughness_texture: None,
+                    occlusion_texture: None,
+                    emissive_texture: None,
+                    material: Material::default(),
+                })
+            }
+        };
+
+        let alpha_cutoff = match material.alpha_mode() {
+            gltf::material::AlphaMode::Mask => Some(material.alpha_cutoff().unwrap_or(0.5)),
+            _ => None,
+        };
+
+        let normal_texture_source = material.normal_texture().map(|info| {
+            log::debug!("normal text_coord index: {}", info.tex_coord());
+            Self::texture_source(&info.texture(), import)
+        });
+
+        let normal_texture = normal_texture_source.transpose()?.map(|(path, image)| {
+            Self::texture_key(
+                &path,
+                image,
+                TextureUsage::Normal,
+                alpha_cutoff,
+                compression,
+            )
+        });
+
+        let occlusion_texture_source = material.occlusion_texture().map(|info| {
+            log::debug!("occlusion text_coord index: {}", info.tex_coord());
+            Self::texture_source(&info.texture(), import)
+        });
+
+        let occlusion_texture = occlusion_texture_source.transpose()?.map(|(path, image)| {
+            Self::texture_key(
+                &path,
+                image,
+                TextureUsage::Occlusion,
+                alpha_cutoff,
+                compression,
+            )
+        });
+
+        let emissive_texture_source = material.emissive_texture().map(|info| {
+            log::debug!("emissive text_coord index: {}", info.tex_coord());
+            Self::texture_source(&info.texture(), import)
+        });
+
+        let emissive_texture = emissive_texture_source.transpose()?.map(|(path, image)| {
+            Self::texture_key(
+                &path,
+                image,
+                TextureUsage::Emissive,
+                alpha_cutoff,
+                compression,
+            )
+        });
+
+        let emissive_factor = material.emissive_factor();
+        log::debug!("emissive factor: {:?}", emissive_factor);
+
+        let pbr_metallic_roughness = material.pbr_metallic_roughness();
+        let base_color_factor = pbr_metallic_roughness.base_color_factor();
+        let metallic_factor = pbr_metallic_roughness.metallic_factor();
+        let roughness_factor = pbr_metallic_roughness.roughness_factor();
+
+        let base_color_texture_source = pbr_metallic_roughness.base_color_texture().map(|info| {
+            log::debug!("base color text_coord index: {}", info.tex_coord());
+            Self::texture_source(&info.texture(), import)
+        });
+
+        let base_color_texture = base_color_texture_source.transpose()?.map(|(path, image)| {
+            Self::texture_key(
+                &path,
+                image,
+                TextureUsage::BaseColor,
+                alpha_cutoff,
+                compression,
+            )
+        });
+
+        let metallic_roughness_texture_source = pbr_metallic_roughness
+            .metallic_roughness_texture()
+            .map(|info| {
+                log::debug!("metallic roughness text_coord index: {}", info.tex_coord());
+                Self::texture_source(&info.texture(), import)
             });
 
-            let metallic_roughness_texture_source = pbr_metallic_roughness
-                .metallic_roughness_texture()
-                .map(|info| {
-                    println!("metallic roughness text_coord index: {}", info.tex_coord());
-                    match info.texture().source().source() {
-                        gltf::image::Source::Uri { uri, .. } => uri,
-                        x => {
-                            println!("x = {:?}", x);
-                            todo!()
-                        }
-                    }
+        let metallic_roughness_texture =
+            metallic_roughness_texture_source
+                .transpose()?
+                .map(|(path, image)| {
+                    Self::texture_key(
+                        &path,
+                        image,
+                        TextureUsage::MetallicRoughness,
+                        alpha_cutoff,
+                        compression,
+                    )
                 });
 
-            let metallic_roughness_texture = metallic_roughness_texture_source.map(|source| {
-                Self::load_texture(format!("{}/{}", TEXTURE_PATH, source).as_ref(), &device)
-                    .expect("Unable to load metallic roughness texture")
-            });
-
-            textures = Textures::new(
-                base_color_texture,
-                normal_texture,
-                metallic_roughness_texture,
-                occlusion_texture,
-                emissive_texture,
-            );
-            pbr_material = Material::new(
+        Ok(Self {
+            base_color_texture,
+            normal_texture,
+            metallic_roughness_texture,
+            occlusion_texture,
+            emissive_texture,
+            material: Material::new(
                 base_color_factor,
                 [0.0, 0.0, 0.0, 0.0],
                 0.0,
                 roughness_factor,
                 metallic_factor,
-            );
+            ),
+        })
+    }
+
+    /// The file the image of `texture` is read from, and the index of the
+    /// image when it is embedded in a buffer view of the glTF file instead.
+    /// Prefers the KTX2 image of `KHR_texture_basisu` when it loads, and falls
+    /// back to the core image otherwise.
+    fn texture_source(
+        texture: &gltf::Texture,
+        import: &GltfImport,
+    ) -> Result<(PathBuf, Option<usize>), ImportError> {
+        // Image URIs are relative to the glTF file, so every model reads its
+        // own images even when they share file names.
+        let base = import.path.parent().unwrap_or_else(|| Path::new("./"));
+        let image_path = |uri: &str| importer::resolve_uri(base, uri);
+
+        if let Some(uri) = ktx::basisu_image_uri(&import.json, texture) {
+            let path = image_path(uri);
+            if ktx::can_load(&path) {
+                return Ok((path, None));
+            }
+            log::warn!("unable to load {}, using the fallback image", uri);
         }
 
-        let pipeline_state = Submesh::build_pipeline_state(library, device, &textures);
+        let image = texture.source();
+        match image.source() {
+            gltf::image::Source::Uri { uri, .. } => Ok((image_path(uri), None)),
+            gltf::image::Source::View { .. } => {
+                // Checked here so a broken view fails the import rather than
+                // the texture upload.
+                import.image_bytes(image.index())?;
+                Ok((import.path.clone(), Some(image.index())))
+            }
+        }
+    }
+
+    /// Every texture slot, in a fixed order.
+    pub(crate) fn texture_slots(&self) -> [&Option<TextureKey>; 5] {
+        [
+            &self.base_color_texture,
+            &self.normal_texture,
+            &self.metallic_roughness_texture,
+            &self.occlusion_texture,
+            &self.emissive_texture,
+        ]
+    }
+
+    fn texture_keys(&self) -> impl Iterator<Item = &TextureKey> {
+        self.texture_slots().into_iter().flatten()
+    }
+}
+
+impl Texturable for MaterialData {}
+
+/// The textures, factors and pipeline state a submesh is drawn with for one
+/// glTF material. Built once per material and shared between submeshes, so
+/// switching variants never recompiles a pipeline.
+#[derive(Clone)]
+pub struct SubmeshMaterial {
+    pub(crate) textures: Textures,
+    pub(crate) pipeline_state: RenderPipelineState,
+    pub(crate) material: [Material; 1],
+    /// Keys of `textures`, slot by slot.
+    texture_slots: [Option<TextureKey>; 5],
+}
+
+impl SubmeshMaterial {
+    /// Uploads the textures of `material_data`, taking already decoded pixels
+    /// from `decoded` when the cache does not have them yet.
+    pub fn from_data(
+        device: &Device,
+        library: &Library,
+        material_data: &MaterialData,
+        decoded: &mut HashMap<TextureKey, Vec<TextureData>>,
+        texture_cache: &mut TextureCache<Texture>,
+    ) -> Self {
+        let mut load_texture = |key: &Option<TextureKey>, name: &str| {
+            key.as_ref().map(|key| {
+                Self::load_texture(key, device, texture_cache, decoded)
+                    .unwrap_or_else(|error| panic!("Unable to load {} texture: {}", name, error))
+            })
+        };
+
+        let textures = Textures::new(
+            load_texture(&material_data.base_color_texture, "base color"),
+            load_texture(&material_data.normal_texture, "normal"),
+            load_texture(
+                &material_data.metallic_roughness_texture,
+                "metallic roughness",
+            ),
+            load_texture(&material_data.occlusion_texture, "occlusion"),
+            load_texture(&material_data.emissive_texture, "emissive"),
+        );
+
+        let pipeline_state = Self::build_pipeline_state(library, device, &textures);
 
         Self {
-            vertex_buffer,
-            index_buffer,
-            num_elements,
             textures,
             pipeline_state,
-            material: [pbr_material],
+            material: [material_data.material],
+            texture_slots: material_data.texture_slots().map(Clone::clone),
         }
     }
 
@@ -222,13 +350,13 @@ impl Submesh {
         let pipeline_state_descriptor = RenderPipelineDescriptor::new();
         pipeline_state_descriptor.set_vertex_function(Some(&vertex_function));
         pipeline_state_descriptor.set_fragment_function(Some(&fragment_function));
-        pipeline_state_descriptor.set_vertex_descriptor(Some(&vertex_descriptor));
+        pipeline_state_descriptor.set_vertex_descriptor(Some(vertex_descriptor));
         pipeline_state_descriptor.set_depth_attachment_pixel_format(MTLPixelFormat::Depth32Float);
         pipeline_state_descriptor
             .color_attachments()
             .object_at(0)
             .unwrap()
-            .set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+            .set_pixel_format(COLOR_PIXEL_FORMAT);
 
         device
             .new_render_pipeline_state(&pipeline_state_descriptor)
@@ -269,134 +397,412 @@ impl Submesh {
     }
 }
 
-impl Texturable for Submesh {}
+impl Texturable for SubmeshMaterial {}
 
-pub struct Mesh {
-    name: String,
-    inner_node: InnerNode,
-    pub(crate) submeshes: Vec<Submesh>,
+pub struct Submesh {
+    pub(crate) vertex_buffer: Buffer,
+    pub(crate) index_buffer: Buffer,
+    pub(crate) num_elements: u64,
+    pub(crate) index_type: MTLIndexType,
+    pub(crate) lods: Vec<Lod>,
+    pub(crate) meshlets: Vec<Meshlet>,
+    pub(crate) textures: Textures,
+    pub(crate) pipeline_state: RenderPipelineState,
+    pub(crate) material: [Material; 1],
+    pub(crate) bounds: Bounds,
+    pub(crate) bounding_sphere: BoundingSphere,
+    /// The positions and triangles of the full mesh, for picking.
+    pub(crate) triangles: TriangleMesh,
+    material_index: Option<usize>,
+    variant_materials: HashMap<usize, usize>,
 }
 
-impl Mesh {
+impl Submesh {
+    pub fn new(
+        submesh_material: &SubmeshMaterial,
+        material_index: Option<usize>,
+        variant_materials: HashMap<usize, usize>,
+        vertex_buffer: Buffer,
+        index_buffer: Buffer,
+        num_elements: u64,
+        bounds: Bounds,
+    ) -> Self {
+        Self {
+            vertex_buffer,
+            index_buffer,
+            num_elements,
+            // replaced by the format the indices were uploaded in
+            index_type: MTLIndexType::UInt32,
+            // replaced by the simplified levels when there are any
+            lods: vec![Lod::full(num_elements as u32)],
+            meshlets: vec![],
+            bounds,
+            // replaced by the tighter sphere around the vertices when known
+            bounding_sphere: BoundingSphere::from_bounds(&bounds),
+            // replaced by a copy of the geometry when it is known
+            triangles: TriangleMesh::default(),
+            textures: submesh_material.textures.clone(),
+            pipeline_state: submesh_material.pipeline_state.clone(),
+            material: submesh_material.material,
+            material_index,
+            variant_materials,
+        }
+    }
+
+    /// The material this submesh uses for `variant`, or its default material
+    /// when there is no variant or no mapping for it.
+    pub fn material_index(&self, variant: Option<usize>) -> Option<usize> {
+        variants::variant_material(&self.variant_materials, self.material_index, variant)
+    }
+
+    /// Bytes per index in the index buffer.
+    pub fn index_size(&self) -> u64 {
+        match self.index_type {
+            MTLIndexType::UInt16 => 2,
+            MTLIndexType::UInt32 => 4,
+        }
+    }
+
+    pub fn set_material(&mut self, submesh_material: &SubmeshMaterial) {
+        self.textures = submesh_material.textures.clone();
+        self.pipeline_state = submesh_material.pipeline_state.clone();
+        self.material = submesh_material.material;
+    }
+}
+
+/// Vertices and indices of one primitive, decoded and given tangents on a
+/// loader thread.
+pub struct PrimitiveData {
+    pub(crate) vertices: Vec<ModelVertex>,
+    pub(crate) indices: Vec<u32>,
+    /// The format `indices` are uploaded in.
+    pub(crate) index_format: IndexFormat,
+    /// The levels of detail, as ranges of `indices`, the full mesh first.
+    pub(crate) lods: Vec<Lod>,
+    /// Clusters of the full mesh, empty when it was not split.
+    pub(crate) meshlets: Vec<Meshlet>,
+    pub(crate) material_index: Option<usize>,
+    pub(crate) variant_materials: HashMap<usize, usize>,
+    pub(crate) bounds: Bounds,
+    pub(crate) bounding_sphere: BoundingSphere,
+    /// The positions and triangles of the full mesh, for picking. Not
+    /// cached; built by `build_triangles` once the vertices are converted.
+    pub(crate) triangles: TriangleMesh,
+}
+
+impl PrimitiveData {
     fn from_gltf(
-        device: &Device,
-        library: &Library,
-        buffers: &Vec<gltf::buffer::Data>,
-        mesh: gltf::Mesh,
-        material: Option<gltf::material::Material>,
-    ) -> Mesh {
-        let mut submeshes = vec![];
-        for primitive in mesh.primitives() {
-            println!("- Primitive #{}", primitive.index());
-            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-
-            let mut vertices = vec![];
-            let mut indices = vec![];
-
-            if let Some(iter) = reader.read_positions() {
-                vertices = iter
-                    .map(|vertex_position| ModelVertex {
-                        position: [vertex_position[0], vertex_position[1], vertex_position[2]],
-                        ..ModelVertex::default()
-                    })
-                    .collect()
-            }
+        import: &GltfImport,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+        import_options: &ImportOptions,
+    ) -> Self {
+        log::debug!("- Primitive #{}", primitive.index());
+        let reader = primitive.reader(|buffer| Some(&import.buffers[buffer.index()]));
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+
+        if let Some(iter) = reader.read_positions() {
+            vertices = iter
+                .map(|vertex_position| ModelVertex {
+                    position: [vertex_position[0], vertex_position[1], vertex_position[2]],
+                    ..ModelVertex::default()
+                })
+                .collect()
+        }
+
+        if let Some(iter) = reader.read_indices() {
+            indices = iter.into_u32().collect()
+        }
 
-            if let Some(iter) = reader.read_indices() {
-                indices = iter.into_u32().map(|index| index).collect()
+        if let Some(iter) = reader.read_normals() {
+            for (i, vertex_normal) in iter.enumerate() {
+                vertices[i].normal = [vertex_normal[0], vertex_normal[1], vertex_normal[2]];
             }
+        }
 
-            if let Some(iter) = reader.read_normals() {
-                for (i, vertex_normal) in iter.enumerate() {
-                    vertices[i].normal = [vertex_normal[0], vertex_normal[1], vertex_normal[2]];
-                }
+        if let Some(iter) = reader.read_tex_coords(0) {
+            for (i, text_coord) in iter.into_f32().enumerate() {
+                vertices[i].text_coords = [text_coord[0], text_coord[1]];
             }
+        }
 
-            if let Some(iter) = reader.read_tex_coords(0) {
-                for (i, text_coord) in iter.into_f32().enumerate() {
-                    vertices[i].text_coords = [text_coord[0], text_coord[1]];
-                }
+        // if let Some(iter) = reader.read_tex_coords(1) {
+        //     for (i, text_coord) in iter.into_f32().enumerate() {
+        //         vertices[i].text_coords[1] = [text_coord[0], text_coord[1]];
+        //     }
+        // }
+
+        if let Some(iter) = reader.read_tangents() {
+            for (i, tangent) in iter.enumerate() {
+                vertices[i].tangent = [tangent[0], tangent[1], tangent[2]];
+                let normal_vector = Vec3::from(vertices[i].normal);
+                let tangent_vector = Vec3::from(vertices[i].tangent);
+                vertices[i].bitangent = (normal_vector.cross(tangent_vector) * tangent[3]).into();
+            }
+        } else {
+            let mut triangles_included = (0..vertices.len()).collect::<Vec<_>>();
+            // Calculate tangents and bitangets. We're going to
+            // use the triangles, so we need to loop through the
+            // indices in chunks of 3
+            for c in indices.chunks(3) {
+                let v0 = vertices[c[0] as usize];
+                let v1 = vertices[c[1] as usize];
+                let v2 = vertices[c[2] as usize];
+
+                let pos0: Vec3 = v0.position.into();
+                let pos1: Vec3 = v1.position.into();
+                let pos2: Vec3 = v2.position.into();
+
+                let uv0: Vec2 = v0.text_coords.into();
+                let uv1: Vec2 = v1.text_coords.into();
+                let uv2: Vec2 = v2.text_coords.into();
+
+                // Calculate the edges of the triangle
+                let delta_pos1 = pos1 - pos0;
+                let delta_pos2 = pos2 - pos0;
+
+                // This will give us a direction to calculate the
+                // tangent and bitangent
+                let delta_uv1 = uv1 - uv0;
+                let delta_uv2 = uv2 - uv0;
+
+                // Solving the following system of equations will
+                // give us the tangent and bitangent.
+                //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
+                //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
+                // Luckily, the place I found this equation provided
+                // the solution!
+                let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
+                let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+                let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r;
+
+                // We'll use the same tangent/bitangent for each vertex in the triangle
+                vertices[c[0] as usize].tangent =
+                    (tangent + Vec3::from(vertices[c[0] as usize].tangent)).into();
+                vertices[c[1] as usize].tangent =
+                    (tangent + Vec3::from(vertices[c[1] as usize].tangent)).into();
+                vertices[c[2] as usize].tangent =
+                    (tangent + Vec3::from(vertices[c[2] as usize].tangent)).into();
+                vertices[c[0] as usize].bitangent =
+                    (bitangent + Vec3::from(vertices[c[0] as usize].bitangent)).into();
+                vertices[c[1] as usize].bitangent =
+                    (bitangent + Vec3::from(vertices[c[1] as usize].bitangent)).into();
+                vertices[c[2] as usize].bitangent =
+                    (bitangent + Vec3::from(vertices[c[2] as usize].bitangent)).into();
+
+                // Used to average the tangents/bitangents
+                triangles_included[c[0] as usize] += 1;
+                triangles_included[c[1] as usize] += 1;
+                triangles_included[c[2] as usize] += 1;
             }
 
-            // if let Some(iter) = reader.read_tex_coords(1) {
-            //     for (i, text_coord) in iter.into_f32().enumerate() {
-            //         vertices[i].text_coords[1] = [text_coord[0], text_coord[1]];
-            //     }
-            // }
-
-            if let Some(iter) = reader.read_tangents() {
-                for (i, tangent) in iter.enumerate() {
-                    vertices[i].tangent = [tangent[0], tangent[1], tangent[2]];
-                    let normal_vector = Vec3::from(vertices[i].normal);
-                    let tangent_vector = Vec3::from(vertices[i].tangent);
-                    vertices[i].bitangent =
-                        (normal_vector.cross(tangent_vector) * tangent[3]).into();
-                }
-            } else {
-                let mut triangles_included = (0..vertices.len()).collect::<Vec<_>>();
-                // Calculate tangents and bitangets. We're going to
-                // use the triangles, so we need to loop through the
-                // indices in chunks of 3
-                for c in indices.chunks(3) {
-                    let v0 = vertices[c[0] as usize];
-                    let v1 = vertices[c[1] as usize];
-                    let v2 = vertices[c[2] as usize];
-
-                    let pos0: Vec3 = v0.position.into();
-                    let pos1: Vec3 = v1.position.into();
-                    let pos2: Vec3 = v2.position.into();
-
-                    let uv0: Vec2 = v0.text_coords.into();
-                    let uv1: Vec2 = v1.text_coords.into();
-                    let uv2: Vec2 = v2.text_coords.into();
-
-                    // Calculate the edges of the triangle
-                    let delta_pos1 = pos1 - pos0;
-                    let delta_pos2 = pos2 - pos0;
-
-                    // This will give us a direction to calculate the
-                    // tangent and bitangent
-                    let delta_uv1 = uv1 - uv0;
-                    let delta_uv2 = uv2 - uv0;
-
-                    // Solving the following system of equations will
-                    // give us the tangent and bitangent.
-                    //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
-                    //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
-                    // Luckily, the place I found this equation provided
-                    // the solution!
-                    let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-                    let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
-                    let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r;
-
-                    // We'll use the same tangent/bitangent for each vertex in the triangle
-                    vertices[c[0] as usize].tangent =
-                        (tangent + Vec3::from(vertices[c[0] as usize].tangent)).into();
-                    vertices[c[1] as usize].tangent =
-                        (tangent + Vec3::from(vertices[c[1] as usize].tangent)).into();
-                    vertices[c[2] as usize].tangent =
-                        (tangent + Vec3::from(vertices[c[2] as usize].tangent)).into();
-                    vertices[c[0] as usize].bitangent =
-                        (bitangent + Vec3::from(vertices[c[0] as usize].bitangent)).into();
-                    vertices[c[1] as usize].bitangent =
-                        (bitangent + Vec3::from(vertices[c[1] as usize].bitangent)).into();
-                    vertices[c[2] as usize].bitangent =
-                        (bitangent + Vec3::from(vertices[c[2] as usize].bitangent)).into();
-
-                    // Used to average the tangents/bitangents
-                    triangles_included[c[0] as usize] += 1;
-                    triangles_included[c[1] as usize] += 1;
-                    triangles_included[c[2] as usize] += 1;
-                }
+            // Average the tangents/bitangents
+            for (i, n) in triangles_included.into_iter().enumerate() {
+                let denom = 1.0 / n as f32;
+                let v = &mut vertices[i];
+                v.tangent = (Vec3::from(v.tangent) * denom).normalize().into();
+                v.bitangent = (Vec3::from(v.bitangent) * denom).normalize().into();
+            }
+        }
 
-                // Average the tangents/bitangents
-                for (i, n) in triangles_included.into_iter().enumerate() {
-                    let denom = 1.0 / n as f32;
-                    let mut v = &mut vertices[i];
-                    v.tangent = (Vec3::from(v.tangent) * denom).normalize().into();
-                    v.bitangent = (Vec3::from(v.bitangent) * denom).normalize().into();
-                }
+        let index_format = match &import_options.optimization {
+            Some(optimization) => {
+                let stats = mesh_optimizer::optimize(&mut vertices, &mut indices, optimization);
+                log::debug!("optimized: {}", stats);
+                stats.index_format
             }
+            None => IndexFormat::UInt32,
+        };
+
+        let variant_materials = variants::read_variant_mappings(&import.json, primitive, mesh);
+
+        // built before the levels of detail, which are appended to the
+        // indices, so that only the full mesh is split
+        let meshlets = match &import_options.meshlets {
+            Some(settings) => {
+                let cone_culling = !Self::is_double_sided(import, primitive, &variant_materials);
+                let meshlets =
+                    meshlet::build_meshlets(&vertices, &mut indices, settings, cone_culling);
+                log::debug!("meshlets: {}", meshlets.len());
+                meshlets
+            }
+            None => vec![],
+        };
+
+        let lods = match &import_options.lod {
+            Some(settings) => {
+                let lods = lod::build_lods(&vertices, &mut indices, settings);
+                let triangles: Vec<u32> = lods.iter().map(|lod| lod.index_count / 3).collect();
+                log::debug!("levels of detail: {:?} triangles", triangles);
+                lods
+            }
+            None => vec![Lod::full(indices.len() as u32)],
+        };
+
+        let positions = vertices.iter().map(|vertex| Vec3::from(vertex.position));
+        let bounds = Bounds::from_points(positions.clone());
+        let bounding_sphere = BoundingSphere::from_points(positions);
+
+        Self {
+            vertices,
+            indices,
+            index_format,
+            lods,
+            meshlets,
+            bounds,
+            bounding_sphere,
+            material_index: primitive.material().index(),
+            variant_materials,
+            triangles: TriangleMesh::default(),
+        }
+    }
+
+    /// Builds the tree over the triangles of the full mesh that picking casts
+    /// rays against, so that it is not built on the thread that owns the
+    /// device.
+    fn build_triangles(&mut self) {
+        let full = &self.lods[0];
+        let full = full.index_offset as usize..(full.index_offset + full.index_count) as usize;
+        self.triangles = TriangleMesh::new(
+            self.vertices
+                .iter()
+                .map(|vertex| Vec3::from(vertex.position))
+                .collect(),
+            self.indices[full].to_vec(),
+        );
+    }
+
+    /// Whether any material the primitive can be drawn with shows its back
+    /// faces.
+    fn is_double_sided(
+        import: &GltfImport,
+        primitive: &gltf::Primitive,
+        variant_materials: &HashMap<usize, usize>,
+    ) -> bool {
+        primitive.material().double_sided()
+            || variant_materials.values().any(|&index| {
+                import
+                    .document
+                    .materials()
+                    .nth(index)
+                    .is_some_and(|material| material.double_sided())
+            })
+    }
+
+    /// Every material this primitive can be drawn with.
+    fn material_indices(&self) -> impl Iterator<Item = Option<usize>> + '_ {
+        let variant_materials = self.variant_materials.values().map(|&index| Some(index));
+        iter::once(self.material_index).chain(variant_materials)
+    }
+}
+
+pub struct MeshData {
+    pub(crate) name: String,
+    pub(crate) primitives: Vec<PrimitiveData>,
+    pub(crate) instances: Option<Vec<Mat4>>,
+}
+
+impl MeshData {
+    /// Bounds of every primitive and instance, in the space of the node.
+    fn bounds(&self) -> Bounds {
+        let bounds = self
+            .primitives
+            .iter()
+            .fold(Bounds::EMPTY, |bounds, primitive| {
+                bounds.union(primitive.bounds)
+            });
+
+        match &self.instances {
+            Some(transforms) if !transforms.is_empty() => transforms
+                .iter()
+                .fold(Bounds::EMPTY, |instances, &transform| {
+                    instances.union(bounds.transform(transform))
+                }),
+            _ => bounds,
+        }
+    }
+}
+
+impl MeshData {
+    fn from_gltf(
+        import: &GltfImport,
+        mesh: &gltf::Mesh,
+        node: &gltf::Node,
+        import_options: &ImportOptions,
+    ) -> Self {
+        let primitives = mesh
+            .primitives()
+            .map(|primitive| PrimitiveData::from_gltf(import, mesh, &primitive, import_options))
+            .collect();
+
+        let instances =
+            instancing::read_gpu_instancing(&import.document, &import.buffers, &import.json, node);
+        if let Some(instances) = &instances {
+            log::debug!("instances: {}", instances.len());
+        }
+
+        Self {
+            name: mesh.name().unwrap_or("untitled").to_string(),
+            primitives,
+            instances,
+        }
+    }
+}
+
+/// A node of the glTF scene, with its transform relative to its parent.
+pub struct NodeData {
+    pub(crate) name: String,
+    pub(crate) transform: Mat4,
+    /// The position of the parent in the node list, which comes first.
+    /// Top-level nodes hang from the model's root node.
+    pub(crate) parent: Option<usize>,
+    /// The index of the mesh the node places.
+    pub(crate) mesh: Option<usize>,
+}
+
+/// The transform of each of `nodes` relative to the model, for nodes listed
+/// after their parents.
+fn model_matrices(nodes: &[NodeData]) -> Vec<Mat4> {
+    let mut matrices: Vec<Mat4> = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let parent = node
+            .parent
+            .map_or(Mat4::IDENTITY, |parent| matrices[parent]);
+        matrices.push(parent * node.transform);
+    }
+    matrices
+}
+
+pub struct Mesh {
+    name: String,
+    node: NodeId,
+    pub(crate) submeshes: Vec<Submesh>,
+    pub(crate) instances: Instances,
+}
+
+impl Mesh {
+    fn from_data(
+        device: &Device,
+        mesh_data: MeshData,
+        materials: &HashMap<Option<usize>, SubmeshMaterial>,
+        node: NodeId,
+    ) -> Mesh {
+        let mut submeshes = vec![];
+        for primitive in mesh_data.primitives {
+            let PrimitiveData {
+                vertices,
+                indices,
+                index_format,
+                lods,
+                meshlets,
+                material_index,
+                variant_materials,
+                bounds,
+                bounding_sphere,
+                triangles,
+            } = primitive;
 
             let vertex_buffer = device.new_buffer_with_data(
                 vertices.as_ptr() as *const _,
@@ -404,99 +810,385 @@ impl Mesh {
                 MTLResourceOptions::CPUCacheModeDefaultCache
                     | MTLResourceOptions::StorageModeManaged,
             );
+            let index_data = index_format.encode(&indices);
             let index_buffer = device.new_buffer_with_data(
-                indices.as_ptr() as *const _,
-                mem::size_of::<u32>() as u64 * indices.len() as u64,
+                index_data.as_ptr() as *const _,
+                index_data.len() as u64,
                 MTLResourceOptions::CPUCacheModeDefaultCache
                     | MTLResourceOptions::StorageModeManaged,
             );
             let num_elements = indices.len() as u64;
 
-            let submesh = Submesh::from_gltf(
-                &device,
-                &library,
-                &material,
+            let mut submesh = Submesh::new(
+                &materials[&material_index],
+                material_index,
+                variant_materials,
                 vertex_buffer,
                 index_buffer,
                 num_elements,
+                bounds,
             );
+            submesh.bounding_sphere = bounding_sphere;
+            submesh.triangles = triangles;
+            submesh.lods = lods;
+            submesh.meshlets = meshlets;
+            submesh.index_type = match index_format {
+                IndexFormat::UInt16 => MTLIndexType::UInt16,
+                IndexFormat::UInt32 => MTLIndexType::UInt32,
+            };
             submeshes.push(submesh);
         }
-        let inner_node = InnerNode::default();
-
-        Self {
-            name: mesh.name().unwrap_or("untitled").to_string(),
+        let mut mesh = Self {
+            name: mesh_data.name,
             submeshes,
-            inner_node,
+            node,
+            instances: Instances::default(),
+        };
+        if let Some(transforms) = mesh_data.instances {
+            mesh.set_instances(device, &transforms);
         }
+        mesh
     }
 
-    pub fn set_position(&mut self, position: Vec3) {
-        self.inner_node.position = position;
+    /// Draws `transforms.len()` more copies of the mesh, placed by
+    /// `transforms` in the space of its node.
+    pub fn add_instances(&mut self, device: &Device, transforms: &[Mat4]) {
+        self.instances.push(device, transforms);
+    }
+
+    /// Draws the mesh once per transform in `transforms`, or once in place if
+    /// it is empty.
+    pub fn set_instances(&mut self, device: &Device, transforms: &[Mat4]) {
+        self.instances.set(device, transforms);
     }
 
     pub fn name(&self) -> &String {
-        &self.inner_node.name
+        &self.name
     }
 
-    pub fn set_rotation(&mut self, rotation: Vec3) {
-        self.inner_node.rotation = rotation;
+    /// The node placing the mesh under its model.
+    pub fn node(&self) -> NodeId {
+        self.node
     }
 
-    pub fn set_scale(&mut self, scale: Vec3) {
-        self.inner_node.scale = scale;
+    /// Bounds of the vertices of every instance, in the space of the mesh's
+    /// node.
+    pub fn bounds(&self) -> Bounds {
+        let bounds = self
+            .submeshes
+            .iter()
+            .fold(Bounds::EMPTY, |bounds, submesh| {
+                bounds.union(submesh.bounds)
+            });
+
+        match self.instances.transforms() {
+            [] => bounds,
+            transforms => transforms
+                .iter()
+                .fold(Bounds::EMPTY, |instances, &transform| {
+                    instances.union(bounds.transform(transform))
+                }),
+        }
     }
 
-    pub fn apply_translation(&mut self, translation: Mat4) {
-        todo!()
+    /// Bounds of the vertices of the submesh at `index` in every instance,
+    /// in the space `model_matrix` places the mesh in.
+    pub fn submesh_bounds(&self, index: usize, model_matrix: Mat4) -> Bounds {
+        let bounds = self.submeshes[index].bounds;
+        match self.instances.transforms() {
+            [] => bounds.transform(model_matrix),
+            transforms => transforms
+                .iter()
+                .fold(Bounds::EMPTY, |instances, &transform| {
+                    instances.union(bounds.transform(model_matrix * transform))
+                }),
+        }
+    }
+
+    /// Like `bounds`, as a sphere.
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        let sphere = self
+            .submeshes
+            .iter()
+            .fold(BoundingSphere::EMPTY, |sphere, submesh| {
+                sphere.union(submesh.bounding_sphere)
+            });
+
+        match self.instances.transforms() {
+            [] => sphere,
+            transforms => transforms
+                .iter()
+                .fold(BoundingSphere::EMPTY, |instances, &transform| {
+                    instances.union(sphere.transform(transform))
+                }),
+        }
     }
+}
+
+/// Everything needed to build a `Model`, decoded without touching the GPU so
+/// that it can be produced on a loader thread.
+pub struct ModelData {
+    name: String,
+    tiling: u32,
+    import_options: ImportOptions,
+    meshes: Vec<MeshData>,
+    materials: HashMap<Option<usize>, MaterialData>,
+    textures: HashMap<TextureKey, Vec<TextureData>>,
+    variants: Vec<String>,
+    nodes: Vec<NodeData>,
+}
+
+impl ModelData {
+    /// Reads the meshes and materials from the mesh cache, or parses the glTF
+    /// file and caches them, then converts the geometry as `import_options`
+    /// asks and decodes every texture. Each file read is reported through
+    /// `progress`. Textures are decoded in parallel.
+    pub fn load(
+        name: &str,
+        tiling: u32,
+        import_options: ImportOptions,
+        compression: TextureCompression,
+        progress: &(dyn Fn(LoadProgress) + Sync),
+    ) -> Result<ModelData, ImportError> {
+        let path = model_path(name);
+
+        let cache_path = mesh_cache::cache_path(&path, compression, &import_options)?;
+        let model_directory = path.parent().unwrap_or_else(|| Path::new("./"));
+        let cached = mesh_cache::read(&cache_path, model_directory);
+        let (mut cached_model, files_read, bytes_read) = match cached {
+            Ok(cached_model) => {
+                log::info!("loaded {} from {}", name, cache_path.display());
+                (cached_model, 1, file_size(&cache_path))
+            }
+            Err(_) => {
+                let import = importer::import(path.as_path())?;
+                let cached_model = Self::import_model(&import, compression, &import_options)?;
+                if let Err(error) = mesh_cache::write(&cache_path, &cached_model, model_directory) {
+                    log::warn!("unable to write {}: {}", cache_path.display(), error);
+                }
 
-    pub fn apply_rotation(&mut self, rotation: Mat4) {
-        todo!()
+                let buffer_bytes: usize = import.buffers.iter().map(|buffer| buffer.len()).sum();
+                let files_read = 1 + import.document.buffers().len();
+                (
+                    cached_model,
+                    files_read,
+                    file_size(&path) + buffer_bytes as u64,
+                )
+            }
+        };
+        if !import_options.is_identity() {
+            Self::convert(&mut cached_model, &import_options);
+        }
+        cached_model
+            .meshes
+            .par_iter_mut()
+            .flat_map(|mesh| mesh.primitives.par_iter_mut())
+            .for_each(PrimitiveData::build_triangles);
+        let CachedModel {
+            meshes,
+            materials,
+            variants,
+            nodes,
+        } = cached_model;
+
+        let texture_keys: HashSet<&TextureKey> = materials
+            .values()
+            .flat_map(MaterialData::texture_keys)
+            .collect();
+        let texture_keys: Vec<&TextureKey> = texture_keys.into_iter().collect();
+        let texture_bytes: u64 = texture_keys.iter().map(|key| file_size(&key.path)).sum();
+
+        let tracker = ProgressTracker::new(
+            name,
+            files_read + texture_keys.len(),
+            bytes_read + texture_bytes,
+        );
+        progress(tracker.advance(files_read, bytes_read));
+
+        let textures = texture_keys
+            .par_iter()
+            .map(|&key| {
+                let levels = key.decode()?;
+                progress(tracker.advance(1, file_size(&key.path)));
+                Ok((key.clone(), levels))
+            })
+            .collect::<ImageResult<HashMap<TextureKey, Vec<TextureData>>>>()?;
+
+        Ok(ModelData {
+            name: name.to_string(),
+            tiling,
+            import_options,
+            meshes,
+            materials,
+            textures,
+            variants,
+            nodes,
+        })
     }
 
-    pub fn apply_scale(&mut self, scale: Mat4) {
-        todo!()
+    /// Converts every vertex with the matrix of `import_options`. Instance and
+    /// node transforms are conjugated by it, so they act on the converted
+    /// vertices as they did on the original ones.
+    fn convert(cached_model: &mut CachedModel, import_options: &ImportOptions) {
+        let bounds = model_matrices(&cached_model.nodes)
+            .into_iter()
+            .zip(cached_model.nodes.iter())
+            .filter_map(|(matrix, node)| Some((matrix, cached_model.meshes.get(node.mesh?)?)))
+            .fold(Bounds::EMPTY, |bounds, (matrix, mesh)| {
+                bounds.union(mesh.bounds().transform(matrix))
+            });
+        let matrix = import_options.matrix(&bounds);
+        let inverse = matrix.inverse();
+
+        for mesh in cached_model.meshes.iter_mut() {
+            for primitive in mesh.primitives.iter_mut() {
+                for vertex in primitive.vertices.iter_mut() {
+                    vertex.position = matrix.transform_point3(Vec3::from(vertex.position)).into();
+                    for direction in [
+                        &mut vertex.normal,
+                        &mut vertex.tangent,
+                        &mut vertex.bitangent,
+                    ] {
+                        *direction = import_options
+                            .transform_direction(Vec3::from(*direction))
+                            .into();
+                    }
+                }
+
+                let positions = primitive
+                    .vertices
+                    .iter()
+                    .map(|vertex| Vec3::from(vertex.position));
+                primitive.bounds = Bounds::from_points(positions.clone());
+                primitive.bounding_sphere = BoundingSphere::from_points(positions);
+
+                for meshlet in primitive.meshlets.iter_mut() {
+                    meshlet.bounding_sphere = meshlet.bounding_sphere.transform(matrix);
+                    meshlet.cone.apex = matrix.transform_point3(meshlet.cone.apex);
+                    meshlet.cone.axis = import_options.transform_direction(meshlet.cone.axis);
+                }
+            }
+
+            for instance in mesh.instances.iter_mut().flatten() {
+                *instance = matrix * *instance * inverse;
+            }
+        }
+        for node in cached_model.nodes.iter_mut() {
+            node.transform = matrix * node.transform * inverse;
+        }
     }
 
-    pub fn apply_transform_matrix(&mut self, transform_matrix: Mat4) {
-        let current_position = self.inner_node.position;
-        let current_rotation = self.inner_node.rotation;
-        let current_scale = self.inner_node.scale;
+    /// Builds the vertex data, optimized and simplified if `import_options`
+    /// asks to, and resolves the materials of a parsed glTF file.
+    fn import_model(
+        import: &GltfImport,
+        compression: TextureCompression,
+        import_options: &ImportOptions,
+    ) -> Result<CachedModel, ImportError> {
+        let gltf = &import.document;
+        log::debug!(
+            "{} nodes, {} meshes, {} materials",
+            gltf.nodes().len(),
+            gltf.meshes().len(),
+            gltf.materials().len()
+        );
 
-        let (scale, rotation, translation) = transform_matrix.to_scale_rotation_translation();
+        let mut meshes = vec![];
+        let mut nodes = vec![];
+        for (gltf_node, parent) in importer::scene_nodes(gltf) {
+            let mesh = gltf_node.mesh().map(|gltf_mesh| {
+                meshes.push(MeshData::from_gltf(
+                    import,
+                    &gltf_mesh,
+                    &gltf_node,
+                    import_options,
+                ));
+                meshes.len() - 1
+            });
+            nodes.push(NodeData {
+                name: gltf_node
+                    .name()
+                    .map_or_else(|| format!("node {}", gltf_node.index()), str::to_string),
+                transform: Mat4::from_cols_array_2d(&gltf_node.transform().matrix()),
+                parent,
+                mesh,
+            });
+        }
 
-        self.inner_node.position =
-            (Mat4::from_translation(translation) * Vec4::from((current_position, 1.0))).truncate();
-        self.inner_node.rotation = rotation.to_scaled_axis() + Vec3::from(current_rotation);
-        self.inner_node.scale =
-            (Mat4::from_scale(scale) * Vec4::from((current_scale, 1.0))).truncate();
+        let mut materials = HashMap::new();
+        for primitive in meshes.iter().flat_map(|mesh| mesh.primitives.iter()) {
+            for index in primitive.material_indices() {
+                if let Entry::Vacant(entry) = materials.entry(index) {
+                    entry.insert(MaterialData::from_gltf(
+                        &index.and_then(|index| gltf.materials().nth(index)),
+                        import,
+                        compression,
+                    )?);
+                }
+            }
+        }
+
+        Ok(CachedModel {
+            meshes,
+            materials,
+            variants: variants::read_variant_names(&import.json),
+            nodes,
+        })
     }
 
-    pub fn model_matrix(&self) -> Mat4 {
-        self.inner_node.model_matrix()
+    pub fn name(&self) -> &str {
+        &self.name
     }
 }
 
+/// Where the glTF file of the model called `name` is.
+pub fn model_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("assets/models/{}", name))
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+/// Where a model is seen from, for picking what of it is drawn.
+pub struct RenderView {
+    pub lod: LodView,
+    pub clusters: ClusterView,
+}
+
 pub struct Model {
-    inner_node: InnerNode,
+    name: String,
+    node: NodeId,
+    /// The nodes of the glTF scene, below `node`.
+    nodes: Vec<NodeId>,
     pub(crate) meshes: Vec<Mesh>,
     pub(crate) tiling: u32,
     pub(crate) sampler_state: SamplerState,
+    import_options: ImportOptions,
+    materials: HashMap<Option<usize>, SubmeshMaterial>,
+    variants: Vec<String>,
+    active_variant: Option<usize>,
 }
 
 impl Model {
     pub fn new(
-        inner_node: InnerNode,
+        name: String,
+        node: NodeId,
         meshes: Vec<Mesh>,
         tiling: u32,
         sampler_state: SamplerState,
     ) -> Model {
         Model {
-            inner_node,
+            name,
+            node,
+            nodes: vec![],
             meshes,
             tiling,
             sampler_state,
+            import_options: ImportOptions::default(),
+            materials: HashMap::new(),
+            variants: vec![],
+            active_variant: None,
         }
     }
 
@@ -505,117 +1197,241 @@ impl Model {
         tiling: u32,
         device: &Device,
         library: &Library,
+        texture_cache: &mut TextureCache<Texture>,
+        scene_graph: &mut SceneGraph,
     ) -> Model {
-        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join(format!("assets/models/{}", name));
-        let (gltf, buffers, _) = gltf::import(path.as_path()).expect("Failed to load gltf file");
-
-        let mut meshes: Vec<Mesh> = vec![];
-
-        println!("nodes len: {}", gltf.nodes().len());
-        println!("cameras len: {}", gltf.cameras().len());
-        println!("materials len: {}", gltf.materials().len());
-        println!("meshes len: {}", gltf.meshes().len());
-
-        for gltf_node in gltf.nodes() {
-            println!("");
-            println!("transform: {:?}", gltf_node.transform());
-            println!("name: {:?}", gltf_node.name());
-            println!("children len: {:?}", gltf_node.children().len());
-            if let Some(gltf_mesh) = gltf_node.mesh() {
-                println!("Mesh #{}", gltf_mesh.index());
-                println!("name: {:?}", gltf_mesh.name());
-                let material = gltf.materials().nth(gltf_mesh.index());
-
-                let mesh = Mesh::from_gltf(device, library, &buffers, gltf_mesh, material);
-                meshes.push(mesh);
-            } else if let Some(gltf_camera) = gltf_node.camera() {
-                println!("camera: {:?}", gltf_camera);
+        let model_data = ModelData::load(
+            name,
+            tiling,
+            ImportOptions::default(),
+            TextureCompression::None,
+            &|_| {},
+        )
+        .expect("Failed to load gltf file");
+        Model::from_data(model_data, device, library, texture_cache, scene_graph)
+    }
+
+    /// Uploads a model decoded by `ModelData::load`. Must run on the thread
+    /// that owns the device. The model is added to `scene_graph` as a root
+    /// node, with the nodes of the glTF scene below it, each placing the
+    /// mesh it refers to.
+    pub fn from_data(
+        model_data: ModelData,
+        device: &Device,
+        library: &Library,
+        texture_cache: &mut TextureCache<Texture>,
+        scene_graph: &mut SceneGraph,
+    ) -> Model {
+        let ModelData {
+            name,
+            tiling,
+            import_options,
+            meshes,
+            materials,
+            mut textures,
+            variants,
+            nodes,
+        } = model_data;
+
+        let materials: HashMap<Option<usize>, SubmeshMaterial> = materials
+            .iter()
+            .map(|(index, material_data)| {
+                let material = SubmeshMaterial::from_data(
+                    device,
+                    library,
+                    material_data,
+                    &mut textures,
+                    texture_cache,
+                );
+                (*index, material)
+            })
+            .collect();
+
+        let node = scene_graph.add(InnerNode::named(&name), NodeContent::Model, None);
+
+        let mut node_ids: Vec<NodeId> = Vec::with_capacity(nodes.len());
+        let mut mesh_nodes = vec![None; meshes.len()];
+        for node_data in nodes {
+            let parent = node_data.parent.map_or(node, |parent| node_ids[parent]);
+            let content = node_data.mesh.map_or(NodeContent::Empty, NodeContent::Mesh);
+            let id = scene_graph.add(
+                InnerNode::from_matrix(&node_data.name, node_data.transform),
+                content,
+                Some(parent),
+            );
+            if let Some(mesh) = node_data.mesh {
+                mesh_nodes[mesh] = Some(id);
             }
+            node_ids.push(id);
         }
 
+        let meshes = meshes
+            .into_iter()
+            .zip(mesh_nodes)
+            .enumerate()
+            .map(|(index, (mesh_data, mesh_node))| {
+                let mesh_node = mesh_node.unwrap_or_else(|| {
+                    scene_graph.add(
+                        InnerNode::named(&mesh_data.name),
+                        NodeContent::Mesh(index),
+                        Some(node),
+                    )
+                });
+                Mesh::from_data(device, mesh_data, &materials, mesh_node)
+            })
+            .collect();
+
         let sampler_state = Model::build_sampler_state(device);
 
-        let mut inner_node = InnerNode::default();
-        inner_node.name = name.to_string();
+        let mut model = Model::new(name, node, meshes, tiling, sampler_state);
+        model.nodes = node_ids;
+        model.import_options = import_options;
+        model.materials = materials;
+        model.variants = variants;
+        log::debug!("material variants: {:?}", model.variants);
+
+        model
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
 
-        let mut model = Model::new(inner_node, meshes, tiling, sampler_state);
+    /// The root node of the model. The nodes of the glTF scene are below it.
+    pub fn node(&self) -> NodeId {
+        self.node
+    }
 
-        let first_node = gltf.nodes().nth(0).unwrap();
-        println!("transform: {:?}", first_node.transform());
+    /// Whether `node` is one of the glTF nodes of the model, rather than a
+    /// node attached to it.
+    pub fn owns_node(&self, node: NodeId) -> bool {
+        self.nodes.contains(&node) || self.meshes.iter().any(|mesh| mesh.node == node)
+    }
 
-        match first_node.transform() {
-            gltf::scene::Transform::Matrix { matrix } => {
-                model.apply_transform_matrix(Mat4::from_cols_array_2d(&matrix));
-            }
-            gltf::scene::Transform::Decomposed {
-                translation,
-                rotation,
-                scale,
-            } => {
-                let transform_matrix = Mat4::from_scale_rotation_translation(
-                    Vec3::from(scale),
-                    Quat::from_array(rotation),
-                    Vec3::from(translation),
-                );
-                model.apply_transform_matrix(transform_matrix);
-            }
-        }
+    /// How the model was converted on import, to load it the same way again.
+    pub fn import_options(&self) -> ImportOptions {
+        self.import_options
+    }
 
-        model
+    /// Bounds of every mesh in world space, placed by its node.
+    pub fn world_bounds(&self, scene_graph: &SceneGraph) -> Bounds {
+        self.meshes.iter().fold(Bounds::EMPTY, |bounds, mesh| {
+            bounds.union(mesh.bounds().transform(scene_graph.world_matrix(mesh.node)))
+        })
     }
 
-    pub fn set_position(&mut self, position: Vec3) {
-        self.inner_node.position = position;
+    pub fn world_bounding_sphere(&self, scene_graph: &SceneGraph) -> BoundingSphere {
+        self.meshes
+            .iter()
+            .fold(BoundingSphere::EMPTY, |sphere, mesh| {
+                sphere.union(
+                    mesh.bounding_sphere()
+                        .transform(scene_graph.world_matrix(mesh.node)),
+                )
+            })
     }
 
-    pub fn name(&self) -> &String {
-        &self.inner_node.name
+    /// The material a submesh is drawn with, in the selected variant.
+    pub fn submesh_material(&self, mesh_index: usize, submesh_index: usize) -> Option<usize> {
+        self.meshes[mesh_index].submeshes[submesh_index].material_index(self.active_variant)
     }
 
-    pub fn set_rotation(&mut self, rotation: Vec3) {
-        self.inner_node.rotation = rotation;
+    /// Adds copies of the mesh at `mesh_index`. Returns how many times the
+    /// mesh is now drawn, or `None` if the model has no such mesh.
+    pub fn add_instances(
+        &mut self,
+        device: &Device,
+        mesh_index: usize,
+        transforms: &[Mat4],
+    ) -> Option<usize> {
+        let mesh = self.meshes.get_mut(mesh_index)?;
+        mesh.add_instances(device, transforms);
+        Some(mesh.instances.count() as usize)
     }
 
-    pub fn set_scale(&mut self, scale: Vec3) {
-        self.inner_node.scale = scale;
+    /// Hands the model's textures back to the cache. The model must not be
+    /// drawn afterwards.
+    pub fn release_textures(&self, texture_cache: &mut TextureCache<Texture>) {
+        for material in self.materials.values() {
+            for key in material.texture_slots.iter().flatten() {
+                texture_cache.release(key);
+            }
+        }
     }
 
-    pub fn apply_translation(&mut self, translation: Mat4) {
-        todo!()
+    /// Image files of every texture the model uses.
+    pub(crate) fn texture_paths(&self) -> impl Iterator<Item = &Path> {
+        self.materials
+            .values()
+            .flat_map(|material| material.texture_slots.iter().flatten())
+            .map(|key| key.path.as_path())
     }
 
-    pub fn apply_rotation(&mut self, rotation: Mat4) {
-        todo!()
+    /// Points every material using `key` to `texture`, which replaced the
+    /// texture of that key in the cache.
+    pub fn replace_texture(&mut self, key: &TextureKey, texture: &Texture) {
+        for material in self.materials.values_mut() {
+            let slots = material.textures.slots_mut();
+            for (slot_key, slot) in material.texture_slots.iter().zip(slots) {
+                if slot_key.as_ref() == Some(key) {
+                    *slot = Some(texture.clone());
+                }
+            }
+        }
+        self.apply_variant(self.active_variant);
     }
 
-    pub fn apply_scale(&mut self, scale: Mat4) {
-        todo!()
+    pub fn variants(&self) -> &[String] {
+        &self.variants
     }
 
-    pub fn apply_transform_matrix(&mut self, transform_matrix: Mat4) {
-        let current_position = self.inner_node.position;
-        let current_rotation = self.inner_node.rotation;
-        let current_scale = self.inner_node.scale;
+    pub fn active_variant(&self) -> Option<&str> {
+        self.active_variant
+            .map(|variant| self.variants[variant].as_str())
+    }
 
-        let (scale, rotation, translation) = transform_matrix.to_scale_rotation_translation();
+    /// Switches to the `KHR_materials_variants` variant called `name`. Returns
+    /// `false`, leaving the materials untouched, if the model has no such variant.
+    pub fn select_variant(&mut self, name: &str) -> bool {
+        match self.variants.iter().position(|variant| variant == name) {
+            Some(variant) => {
+                self.apply_variant(Some(variant));
+                true
+            }
+            None => false,
+        }
+    }
 
-        self.inner_node.position =
-            (Mat4::from_translation(translation) * Vec4::from((current_position, 1.0))).truncate();
-        self.inner_node.rotation = rotation.to_scaled_axis() + Vec3::from(current_rotation);
-        self.inner_node.scale =
-            (Mat4::from_scale(scale) * Vec4::from((current_scale, 1.0))).truncate();
+    /// Goes back to the default material of every primitive.
+    pub fn clear_variant(&mut self) {
+        self.apply_variant(None);
     }
 
-    pub fn model_matrix(&self) -> Mat4 {
-        self.inner_node.model_matrix()
+    fn apply_variant(&mut self, variant: Option<usize>) {
+        for mesh in self.meshes.iter_mut() {
+            for submesh in mesh.submeshes.iter_mut() {
+                let material_index = submesh.material_index(variant);
+                match variants::loaded_material(&self.materials, material_index) {
+                    Some(material) => submesh.set_material(material),
+                    None => log::warn!("material {:?} is not loaded", material_index),
+                }
+            }
+        }
+        self.active_variant = variant;
     }
 
+    /// Draws every submesh `is_visible` accepts, given the index of its mesh
+    /// and its own, placed by the node of its mesh, at the level of detail
+    /// the `lod` view of `view` picks for it.
+    /// Submeshes split into meshlets draw only those its `clusters` view sees.
     pub fn render(
         &self,
         render_encoder: &RenderCommandEncoderRef,
         uniforms: &mut [Uniforms],
         fragment_uniforms: &mut [FragmentUniforms],
+        scene_graph: &SceneGraph,
+        view: &RenderView,
+        is_visible: &dyn Fn(usize, usize) -> bool,
     ) {
         fragment_uniforms[0].tiling = self.tiling;
         render_encoder.set_fragment_bytes(
@@ -626,17 +1442,31 @@ impl Model {
 
         render_encoder.set_fragment_sampler_state(0, Some(&self.sampler_state));
 
-        for mesh in self.meshes.iter() {
-            uniforms[0].modelMatrix = unsafe { std::mem::transmute(self.model_matrix()) };
-            uniforms[0].normalMatrix =
-                unsafe { std::mem::transmute(Mat3A::from_mat4(self.model_matrix())) };
+        for (mesh_index, mesh) in self.meshes.iter().enumerate() {
+            if !(0..mesh.submeshes.len()).any(|index| is_visible(mesh_index, index)) {
+                continue;
+            }
+            let model_matrix = scene_graph.world_matrix(mesh.node);
+            (uniforms[0].modelMatrix, uniforms[0].normalMatrix) =
+                simd::model_and_normal_matrix(model_matrix);
             render_encoder.set_vertex_bytes(
                 BufferIndexUniforms as u64,
                 std::mem::size_of::<Uniforms>() as u64,
                 uniforms.as_ptr() as *const _,
             );
 
-            for submesh in mesh.submeshes.iter() {
+            let placements: Vec<Mat4> = match mesh.instances.transforms() {
+                [] => vec![model_matrix],
+                transforms => transforms
+                    .iter()
+                    .map(|&transform| model_matrix * transform)
+                    .collect(),
+            };
+
+            for (submesh_index, submesh) in mesh.submeshes.iter().enumerate() {
+                if !is_visible(mesh_index, submesh_index) {
+                    continue;
+                }
                 render_encoder.set_render_pipeline_state(&submesh.pipeline_state);
 
                 render_encoder.set_vertex_buffer(
@@ -644,17 +1474,18 @@ impl Model {
                     Some(&submesh.vertex_buffer),
                     0,
                 );
+                mesh.instances.bind(render_encoder);
 
                 if let Some(diffuse_texture) = &submesh.textures.diffuse_texture {
                     render_encoder.set_fragment_texture(
                         Textures_BaseColorTexture as u64,
-                        Some(&diffuse_texture),
+                        Some(diffuse_texture),
                     );
                 }
 
                 if let Some(normal_texture) = &submesh.textures.normal_texture {
                     render_encoder
-                        .set_fragment_texture(Textures_NormalTexture as u64, Some(&normal_texture));
+                        .set_fragment_texture(Textures_NormalTexture as u64, Some(normal_texture));
                 }
 
                 if let Some(metallic_roughness_texture) =
@@ -662,21 +1493,21 @@ impl Model {
                 {
                     render_encoder.set_fragment_texture(
                         Textures_MetallicRoughnessTexture as u64,
-                        Some(&metallic_roughness_texture),
+                        Some(metallic_roughness_texture),
                     );
                 }
 
                 if let Some(occlusion_texture) = &submesh.textures.ambient_occlusion_texture {
                     render_encoder.set_fragment_texture(
                         Textures_OcclusionTexture as u64,
-                        Some(&occlusion_texture),
+                        Some(occlusion_texture),
                     );
                 }
 
                 if let Some(emissive_texture) = &submesh.textures.emissive_texture {
                     render_encoder.set_fragment_texture(
                         Textures_EmissiveTexture as u64,
-                        Some(&emissive_texture),
+                        Some(emissive_texture),
                     );
                 }
 
@@ -686,13 +1517,75 @@ impl Model {
                     submesh.material.as_ptr() as *const _,
                 );
 
-                // render_encoder.set_triangle_fill_mode(MTLTriangleFillMode::Lines);
-                render_encoder.draw_indexed_primitives(
+                let level = view
+                    .lod
+                    .select(&submesh.lods, &submesh.bounding_sphere, &placements);
+                let lod = submesh.lods[level];
+                let lod_range = lod.index_offset..lod.index_offset + lod.index_count;
+                // meshlets split the full mesh; instanced meshes are drawn whole
+                let ranges = match placements[..] {
+                    [transform] if level == 0 && !submesh.meshlets.is_empty() => {
+                        view.clusters.visible_ranges(&submesh.meshlets, transform)
+                    }
+                    _ => vec![lod_range],
+                };
+
+                for range in ranges {
+                    // render_encoder.set_triangle_fill_mode(MTLTriangleFillMode::Lines);
+                    render_encoder.draw_indexed_primitives_instanced(
+                        MTLPrimitiveType::Triangle,
+                        range.len() as u64,
+                        submesh.index_type,
+                        &submesh.index_buffer,
+                        range.start as u64 * submesh.index_size(),
+                        mesh.instances.count(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Draws the full mesh of every submesh `include` accepts, given the
+    /// index of its mesh and its own, with whatever pipeline is set. Only
+    /// the uniforms, vertices and instances are bound.
+    pub fn render_geometry(
+        &self,
+        render_encoder: &RenderCommandEncoderRef,
+        uniforms: &mut [Uniforms],
+        scene_graph: &SceneGraph,
+        include: &dyn Fn(usize, usize) -> bool,
+    ) {
+        for (mesh_index, mesh) in self.meshes.iter().enumerate() {
+            if !(0..mesh.submeshes.len()).any(|index| include(mesh_index, index)) {
+                continue;
+            }
+            let model_matrix = scene_graph.world_matrix(mesh.node);
+            (uniforms[0].modelMatrix, uniforms[0].normalMatrix) =
+                simd::model_and_normal_matrix(model_matrix);
+            render_encoder.set_vertex_bytes(
+                BufferIndexUniforms as u64,
+                std::mem::size_of::<Uniforms>() as u64,
+                uniforms.as_ptr() as *const _,
+            );
+            mesh.instances.bind(render_encoder);
+
+            for (submesh_index, submesh) in mesh.submeshes.iter().enumerate() {
+                if !include(mesh_index, submesh_index) {
+                    continue;
+                }
+                render_encoder.set_vertex_buffer(
+                    BufferIndexVertices as u64,
+                    Some(&submesh.vertex_buffer),
+                    0,
+                );
+                let full = submesh.lods[0];
+                render_encoder.draw_indexed_primitives_instanced(
                     MTLPrimitiveType::Triangle,
-                    submesh.num_elements,
-                    MTLIndexType::UInt32,
+                    full.index_count as u64,
+                    submesh.index_type,
                     &submesh.index_buffer,
-                    0,
+                    full.index_offset as u64 * submesh.index_size(),
+                    mesh.instances.count(),
                 );
             }
         }
@@ -710,7 +1603,7 @@ impl Model {
     }
 }
 
-fn default_vertex_descriptor() -> &'static VertexDescriptorRef {
+pub(crate) fn default_vertex_descriptor() -> &'static VertexDescriptorRef {
     let vertex_descriptor = VertexDescriptor::new();
     let mut offset = 0;
 
@@ -778,6 +1671,7 @@ fn default_vertex_descriptor() -> &'static VertexDescriptorRef {
     vertex_descriptor
 }
 
+#[derive(Clone, Default)]
 pub struct Textures {
     // filename: String,
     pub(crate) diffuse_texture: Option<Texture>,
@@ -805,14 +1699,15 @@ impl Textures {
     }
 }
 
-impl Default for Textures {
-    fn default() -> Self {
-        Textures {
-            diffuse_texture: None,
-            normal_texture: None,
-            metallic_roughness_texture: None,
-            ambient_occlusion_texture: None,
-            emissive_texture: None,
-        }
+impl Textures {
+    /// In the order of `MaterialData::texture_slots`.
+    fn slots_mut(&mut self) -> [&mut Option<Texture>; 5] {
+        [
+            &mut self.diffuse_texture,
+            &mut self.normal_texture,
+            &mut self.metallic_roughness_texture,
+            &mut self.ambient_occlusion_texture,
+            &mut self.emissive_texture,
+        ]
     }
 }
@@ -1,34 +1,14 @@
-use crate::{camera::Camera, model::Model};
-use glam::{Mat4, Vec3};
-use std::rc::Rc;
-
-// pub enum NodeType {
-//     Camera(Camera),
-//     Model,
-// }
-
-pub trait SceneNode {
-    fn get_postion(&self) -> &Vec3;
-    fn set_postion(&self);
-    fn get_rotation(&self) -> &Vec3;
-    fn set_rotation(&self);
-    fn get_scale(&self) -> &Vec3;
-    fn set_scale(&self);
-}
-
-struct TestNode {
-    inner_node: InnerNode,
-}
-
-pub struct Node {
-    parent: Rc<Node>,
-    children: Vec<Node>,
-}
+use glam::{EulerRot, Mat4, Quat, Vec3};
+use std::cell::Cell;
+use std::fmt;
 
+/// The name of a node and its transform relative to its parent: scale, then
+/// rotation, then translation, as glTF stores it.
+#[derive(Debug, Clone, PartialEq)]
 pub struct InnerNode {
     pub(crate) name: String,
     pub(crate) position: Vec3,
-    pub(crate) rotation: Vec3,
+    pub(crate) rotation: Quat,
     pub(crate) scale: Vec3,
 }
 
@@ -37,14 +17,14 @@ impl Default for InnerNode {
         Self::new(
             "untitled".to_string(),
             Vec3::new(0.0, 0.0, 0.0),
-            Vec3::new(0.0, 0.0, 0.0),
+            Quat::IDENTITY,
             Vec3::new(1.0, 1.0, 1.0),
         )
     }
 }
 
 impl InnerNode {
-    pub fn new(name: String, position: Vec3, rotation: Vec3, scale: Vec3) -> Self {
+    pub fn new(name: String, position: Vec3, rotation: Quat, scale: Vec3) -> Self {
         Self {
             name,
             position,
@@ -53,13 +33,715 @@ impl InnerNode {
         }
     }
 
+    pub fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// A node whose model matrix is `matrix`, less any shear.
+    pub fn from_matrix(name: &str, matrix: Mat4) -> Self {
+        let mut node = Self::named(name);
+        node.set_matrix(matrix);
+        node
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+    }
+
+    pub fn rotation(&self) -> Quat {
+        self.rotation
+    }
+
+    pub fn set_rotation(&mut self, rotation: Quat) {
+        self.rotation = rotation.normalize();
+    }
+
+    /// The rotation as angles around X, then Y, then Z, in radians. For
+    /// showing and editing; the rotation itself is kept as a quaternion.
+    pub fn euler_angles(&self) -> Vec3 {
+        let (x, y, z) = self.rotation.to_euler(EulerRot::XYZ);
+        Vec3::new(x, y, z)
+    }
+
+    pub fn set_euler_angles(&mut self, angles: Vec3) {
+        self.rotation = Quat::from_euler(EulerRot::XYZ, angles.x, angles.y, angles.z);
+    }
+
+    pub fn scale(&self) -> Vec3 {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: Vec3) {
+        self.scale = scale;
+    }
+
     pub fn model_matrix(&self) -> Mat4 {
-        let translate_matrix = Mat4::from_translation(self.position);
-        let rotate_matrix = Mat4::from_rotation_x(self.rotation.x)
-            * Mat4::from_rotation_y(self.rotation.y)
-            * Mat4::from_rotation_z(self.rotation.z);
-        let scale_matrix = Mat4::from_scale(self.scale);
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
+    }
+
+    /// Replaces the transform with one that has `matrix` as its model
+    /// matrix. Shears are lost.
+    pub fn set_matrix(&mut self, matrix: Mat4) {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        self.position = translation;
+        self.rotation = rotation.normalize();
+        self.scale = scale;
+    }
+
+    /// The transform of `child`, placed under this node, relative to the
+    /// parent of this node. Exact unless this node has a non-uniform scale
+    /// and `child` a rotation that is not a multiple of 90 degrees, which
+    /// would need a shear.
+    pub fn compose(&self, child: &InnerNode) -> InnerNode {
+        InnerNode {
+            name: child.name.clone(),
+            position: self.position + self.rotation * (self.scale * child.position),
+            rotation: (self.rotation * child.rotation).normalize(),
+            scale: self.scale * child.scale,
+        }
+    }
+
+    /// Moves the node by `translation`, in the space of its parent.
+    pub fn apply_translation(&mut self, translation: Vec3) {
+        self.position += translation;
+    }
+
+    /// Rotates the node around the origin of its parent.
+    pub fn apply_rotation(&mut self, rotation: Quat) {
+        self.position = rotation * self.position;
+        self.rotation = (rotation * self.rotation).normalize();
+    }
+
+    /// Scales the node from the origin of its parent, along the axes of
+    /// the parent. A non-uniform scale is only exact while the rotation is
+    /// a multiple of 90 degrees.
+    pub fn apply_scale(&mut self, scale: Vec3) {
+        self.position *= scale;
+        self.scale *= scale;
+    }
+
+    /// Applies `transform_matrix` after the node's own transform, in the
+    /// space of its parent.
+    pub fn apply_transform_matrix(&mut self, transform_matrix: Mat4) {
+        *self = InnerNode::from_matrix(&self.name, transform_matrix).compose(self);
+    }
+}
+
+/// A handle to a node of a `SceneGraph`. Handles of removed nodes stay
+/// invalid when their slot is reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+/// What a node places in the world. The graph only holds transforms; models,
+/// lights and cameras refer to their node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeContent {
+    /// Groups its children.
+    Empty,
+    Model,
+    /// The mesh at this index of the model the node is below.
+    Mesh(usize),
+    Camera,
+    /// The light at this index of the lighting.
+    Light(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SceneGraphError {
+    /// The node has been removed.
+    InvalidNode(NodeId),
+    /// The new parent is the node itself or one of its descendants.
+    Cycle,
+}
+
+impl fmt::Display for SceneGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneGraphError::InvalidNode(id) => write!(f, "node {:?} has been removed", id),
+            SceneGraphError::Cycle => write!(f, "a node cannot be its own ancestor"),
+        }
+    }
+}
+
+impl std::error::Error for SceneGraphError {}
+
+pub struct Node {
+    inner_node: InnerNode,
+    content: NodeContent,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    /// Valid while `dirty` is unset. Every descendant of a dirty node is
+    /// dirty too.
+    world_matrix: Cell<Mat4>,
+    dirty: Cell<bool>,
+}
+
+impl Node {
+    pub fn name(&self) -> &str {
+        &self.inner_node.name
+    }
+
+    /// The transform relative to the parent.
+    pub fn local(&self) -> &InnerNode {
+        &self.inner_node
+    }
+
+    pub fn content(&self) -> NodeContent {
+        self.content
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+struct Slot {
+    generation: u32,
+    node: Option<Node>,
+}
+
+/// Nodes in a flat arena, linked to their parent and children by handle.
+/// World transforms are computed when asked for and cached until the node
+/// or one of its ancestors moves.
+#[derive(Default)]
+pub struct SceneGraph {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    roots: Vec<NodeId>,
+    /// Bumped by every change that may move a node in the world.
+    revision: u64,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node as the last child of `parent`, or as a root.
+    ///
+    /// Panics if `parent` has been removed.
+    pub fn add(
+        &mut self,
+        inner_node: InnerNode,
+        content: NodeContent,
+        parent: Option<NodeId>,
+    ) -> NodeId {
+        if let Some(parent) = parent {
+            assert!(self.contains(parent), "parent node has been removed");
+        }
+
+        let node = Node {
+            inner_node,
+            content,
+            parent,
+            children: vec![],
+            world_matrix: Cell::new(Mat4::IDENTITY),
+            dirty: Cell::new(true),
+        };
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+                NodeId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    node: Some(node),
+                });
+                NodeId {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        };
+
+        match parent {
+            Some(parent) => self.node_mut(parent).unwrap().children.push(id),
+            None => self.roots.push(id),
+        }
+        self.revision += 1;
+        id
+    }
+
+    /// Removes `id` and everything below it. Returns what the removed nodes
+    /// held, parents before children.
+    pub fn remove(&mut self, id: NodeId) -> Vec<(NodeId, NodeContent)> {
+        if !self.contains(id) {
+            return vec![];
+        }
+        self.unlink(id);
+        self.revision += 1;
+
+        let mut removed = vec![];
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            let slot = &mut self.slots[id.index as usize];
+            let node = slot.node.take().unwrap();
+            slot.generation += 1;
+            self.free.push(id.index);
+
+            removed.push((id, node.content));
+            pending.extend(node.children.into_iter().rev());
+        }
+        removed
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&Node> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    /// The transform of `id` relative to its parent, to change it. Moves
+    /// every descendant along.
+    pub fn local_mut(&mut self, id: NodeId) -> Option<&mut InnerNode> {
+        self.mark_dirty(id);
+        self.revision += 1;
+        self.node_mut(id).map(|node| &mut node.inner_node)
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// Changes whenever a node is added, removed, reparented or handed out
+    /// by `local_mut`, so what is derived from world transforms can be kept
+    /// until it does.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every node, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.node.as_ref().map(|node| {
+                let id = NodeId {
+                    index: index as u32,
+                    generation: slot.generation,
+                };
+                (id, node)
+            })
+        })
+    }
+
+    /// The first node called `name`, parents before children.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        let mut pending: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = pending.pop() {
+            let node = self.get(id)?;
+            if node.name() == name {
+                return Some(id);
+            }
+            pending.extend(node.children.iter().rev());
+        }
+        None
+    }
+
+    /// `id` and every node below it, parents before children.
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut descendants = vec![];
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if let Some(node) = self.get(id) {
+                descendants.push(id);
+                pending.extend(node.children.iter().rev());
+            }
+        }
+        descendants
+    }
+
+    /// The model matrix of `id` in world space: its own transform, then the
+    /// transform of each ancestor. Identity for removed nodes.
+    pub fn world_matrix(&self, id: NodeId) -> Mat4 {
+        let node = match self.get(id) {
+            Some(node) => node,
+            None => return Mat4::IDENTITY,
+        };
+        if node.dirty.get() {
+            let parent_matrix = node
+                .parent
+                .map_or(Mat4::IDENTITY, |parent| self.world_matrix(parent));
+            node.world_matrix
+                .set(parent_matrix * node.inner_node.model_matrix());
+            node.dirty.set(false);
+        }
+        node.world_matrix.get()
+    }
+
+    /// Moves `id` under `parent`, or makes it a root, keeping where it is in
+    /// the world.
+    pub fn set_parent(
+        &mut self,
+        id: NodeId,
+        parent: Option<NodeId>,
+    ) -> Result<(), SceneGraphError> {
+        if !self.contains(id) {
+            return Err(SceneGraphError::InvalidNode(id));
+        }
+        if let Some(parent) = parent {
+            if !self.contains(parent) {
+                return Err(SceneGraphError::InvalidNode(parent));
+            }
+            if self.is_ancestor(id, parent) {
+                return Err(SceneGraphError::Cycle);
+            }
+        }
+
+        let world_matrix = self.world_matrix(id);
+        let parent_matrix = parent.map_or(Mat4::IDENTITY, |parent| self.world_matrix(parent));
+
+        self.unlink(id);
+        match parent {
+            Some(parent) => self.node_mut(parent).unwrap().children.push(id),
+            None => self.roots.push(id),
+        }
+        let node = self.node_mut(id).unwrap();
+        node.parent = parent;
+        node.inner_node
+            .set_matrix(parent_matrix.inverse() * world_matrix);
+        self.mark_dirty(id);
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// Whether `ancestor` is `id` or above it.
+    pub fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            if id == ancestor {
+                return true;
+            }
+            current = self.get(id).and_then(|node| node.parent);
+        }
+        false
+    }
+
+    /// Detaches `id` from its parent or from the roots.
+    fn unlink(&mut self, id: NodeId) {
+        let parent = self.get(id).and_then(|node| node.parent);
+        let siblings = match parent {
+            Some(parent) => &mut self.node_mut(parent).unwrap().children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != id);
+    }
+
+    /// Stops at nodes that are dirty already, since everything below them is.
+    fn mark_dirty(&self, id: NodeId) {
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if let Some(node) = self.get(id) {
+                if !node.dirty.replace(true) {
+                    pending.extend(node.children.iter());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Mat4, b: Mat4) -> bool {
+        a.abs_diff_eq(b, 1e-4)
+    }
+
+    fn at(name: &str, position: Vec3) -> InnerNode {
+        let mut node = InnerNode::named(name);
+        node.set_position(position);
+        node
+    }
+
+    /// The glTF sample DamagedHelmet root node, and a made-up node with every
+    /// component set, as glTF stores them.
+    const DECOMPOSED: [([f32; 3], [f32; 4], [f32; 3]); 2] = [
+        (
+            [0.0, 0.0, 0.0],
+            [0.70710677, 0.0, 0.0, 0.70710677],
+            [1.0, 1.0, 1.0],
+        ),
+        (
+            [1.5, -2.0, 0.25],
+            [0.18257418, 0.36514837, 0.5477226, 0.73029673],
+            [2.0, 0.5, 3.0],
+        ),
+    ];
+
+    fn gltf_node((translation, rotation, scale): ([f32; 3], [f32; 4], [f32; 3])) -> InnerNode {
+        InnerNode::new(
+            "gltf".to_string(),
+            Vec3::from(translation),
+            Quat::from_array(rotation),
+            Vec3::from(scale),
+        )
+    }
+
+    #[test]
+    fn model_matrix_matches_gltf_decomposition() {
+        for decomposed in DECOMPOSED {
+            let node = gltf_node(decomposed);
+            let (translation, rotation, scale) = decomposed;
+            // glTF: T * R * S
+            let expected = Mat4::from_translation(Vec3::from(translation))
+                * Mat4::from_quat(Quat::from_array(rotation))
+                * Mat4::from_scale(Vec3::from(scale));
+            assert!(close(node.model_matrix(), expected));
+
+            let round_trip = InnerNode::from_matrix("matrix", expected);
+            assert!(round_trip
+                .position()
+                .abs_diff_eq(Vec3::from(translation), 1e-5));
+            assert!(round_trip.scale().abs_diff_eq(Vec3::from(scale), 1e-5));
+            let quat = Quat::from_array(rotation);
+            assert!(round_trip.rotation().dot(quat).abs() > 1.0 - 1e-5);
+        }
+    }
+
+    #[test]
+    fn euler_angles_are_applied_x_then_y_then_z() {
+        let mut node = InnerNode::named("n");
+        node.set_euler_angles(Vec3::new(0.3, -0.7, 1.1));
+        let expected =
+            Mat4::from_rotation_x(0.3) * Mat4::from_rotation_y(-0.7) * Mat4::from_rotation_z(1.1);
+        assert!(close(node.model_matrix(), expected));
+        assert!(node
+            .euler_angles()
+            .abs_diff_eq(Vec3::new(0.3, -0.7, 1.1), 1e-5));
+    }
+
+    #[test]
+    fn applied_transforms_act_in_parent_space() {
+        let node = gltf_node(DECOMPOSED[1]);
+        let matrix = node.model_matrix();
+
+        let mut moved = node.clone();
+        moved.apply_translation(Vec3::new(1.0, 2.0, 3.0));
+        assert!(close(
+            moved.model_matrix(),
+            Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0)) * matrix
+        ));
+
+        let rotation = Quat::from_axis_angle(Vec3::new(1.0, 1.0, 0.0).normalize(), 0.9);
+        let mut rotated = node.clone();
+        rotated.apply_rotation(rotation);
+        assert!(close(
+            rotated.model_matrix(),
+            Mat4::from_quat(rotation) * matrix
+        ));
+
+        let mut scaled = node.clone();
+        scaled.apply_scale(Vec3::splat(2.5));
+        assert!(close(
+            scaled.model_matrix(),
+            Mat4::from_scale(Vec3::splat(2.5)) * matrix
+        ));
+
+        // the DamagedHelmet root, under a parent that is axis aligned
+        let transform = gltf_node(DECOMPOSED[0]).model_matrix();
+        let mut transformed = InnerNode::from_matrix(
+            "t",
+            Mat4::from_scale_rotation_translation(
+                Vec3::new(1.0, 2.0, 3.0),
+                Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+                Vec3::new(4.0, 5.0, 6.0),
+            ),
+        );
+        let before = transformed.model_matrix();
+        transformed.apply_transform_matrix(transform);
+        assert!(close(transformed.model_matrix(), transform * before));
+        assert_eq!(transformed.name(), "t");
+    }
+
+    #[test]
+    fn compose_matches_matrix_product() {
+        let mut parent = gltf_node(DECOMPOSED[0]);
+        parent.set_position(Vec3::new(-1.0, 0.5, 2.0));
+        parent.set_scale(Vec3::splat(1.5));
+        let child = gltf_node(DECOMPOSED[1]);
+
+        let composed = parent.compose(&child);
+        assert!(close(
+            composed.model_matrix(),
+            parent.model_matrix() * child.model_matrix()
+        ));
+        assert_eq!(composed.name(), "gltf");
+    }
+
+    #[test]
+    fn world_transform_composes_ancestors() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(at("root", Vec3::X), NodeContent::Empty, None);
+        let child = graph.add(at("child", Vec3::Y), NodeContent::Empty, Some(root));
+        let grandchild = graph.add(at("grandchild", Vec3::Z), NodeContent::Empty, Some(child));
+
+        graph.local_mut(root).unwrap().set_scale(Vec3::splat(2.0));
+        let expected = Mat4::from_translation(Vec3::X)
+            * Mat4::from_scale(Vec3::splat(2.0))
+            * Mat4::from_translation(Vec3::Y)
+            * Mat4::from_translation(Vec3::Z);
+        assert!(close(graph.world_matrix(grandchild), expected));
+        let position = graph.world_matrix(grandchild).transform_point3(Vec3::ONE);
+        assert!(position.abs_diff_eq(Vec3::new(3.0, 4.0, 4.0), 1e-5));
+        assert_eq!(graph.find("grandchild"), Some(grandchild));
+        assert_eq!(graph.descendants(root), vec![root, child, grandchild]);
+    }
+
+    #[test]
+    fn moving_a_parent_moves_cached_children() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(at("root", Vec3::ZERO), NodeContent::Model, None);
+        let child = graph.add(at("child", Vec3::X), NodeContent::Mesh(0), Some(root));
+
+        // cache both, then move the root
+        assert!(close(
+            graph.world_matrix(child),
+            Mat4::from_translation(Vec3::X)
+        ));
+        graph.local_mut(root).unwrap().set_position(Vec3::Y);
+        assert!(close(
+            graph.world_matrix(child),
+            Mat4::from_translation(Vec3::X + Vec3::Y)
+        ));
+
+        // only the child is cached while the root is dirty; moving the root again
+        graph.world_matrix(child);
+        graph.local_mut(root).unwrap().set_position(Vec3::Z);
+        graph.local_mut(root).unwrap().set_position(-Vec3::Z);
+        assert!(close(
+            graph.world_matrix(child),
+            Mat4::from_translation(Vec3::X - Vec3::Z)
+        ));
+    }
+
+    #[test]
+    fn reparenting_keeps_the_world_transform() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(at("a", Vec3::new(1.0, 0.0, 0.0)), NodeContent::Empty, None);
+        graph
+            .local_mut(a)
+            .unwrap()
+            .set_rotation(Quat::from_rotation_y(1.0));
+        graph.local_mut(a).unwrap().set_scale(Vec3::splat(2.0));
+        let b = graph.add(at("b", Vec3::new(0.0, 5.0, 0.0)), NodeContent::Empty, None);
+        let c = graph.add(
+            at("c", Vec3::new(0.0, 0.0, 1.0)),
+            NodeContent::Empty,
+            Some(b),
+        );
+
+        let before = graph.world_matrix(c);
+        graph.set_parent(c, Some(a)).unwrap();
+        assert!(close(graph.world_matrix(c), before));
+        assert_eq!(graph.get(c).unwrap().parent(), Some(a));
+        assert!(graph.get(b).unwrap().children().is_empty());
+        assert_eq!(graph.get(a).unwrap().children(), &[c]);
+
+        graph.set_parent(c, None).unwrap();
+        assert!(close(graph.world_matrix(c), before));
+        assert_eq!(graph.roots(), &[a, b, c]);
+    }
+
+    #[test]
+    fn revision_changes_with_every_move() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(InnerNode::named("a"), NodeContent::Empty, None);
+        let b = graph.add(InnerNode::named("b"), NodeContent::Empty, None);
+
+        let mut revision = graph.revision();
+        graph.world_matrix(a);
+        graph.get(b);
+        assert_eq!(graph.revision(), revision);
+
+        graph.local_mut(a).unwrap().set_position(Vec3::X);
+        assert_ne!(graph.revision(), revision);
+        revision = graph.revision();
+        graph.set_parent(b, Some(a)).unwrap();
+        assert_ne!(graph.revision(), revision);
+        revision = graph.revision();
+        graph.remove(b);
+        assert_ne!(graph.revision(), revision);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(InnerNode::named("a"), NodeContent::Empty, None);
+        let b = graph.add(InnerNode::named("b"), NodeContent::Empty, Some(a));
+        let c = graph.add(InnerNode::named("c"), NodeContent::Empty, Some(b));
+
+        assert_eq!(graph.set_parent(a, Some(c)), Err(SceneGraphError::Cycle));
+        assert_eq!(graph.set_parent(a, Some(a)), Err(SceneGraphError::Cycle));
+        assert_eq!(graph.get(a).unwrap().parent(), None);
+        assert_eq!(graph.roots(), &[a]);
+    }
+
+    #[test]
+    fn removed_handles_stay_invalid() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(InnerNode::named("a"), NodeContent::Model, None);
+        let b = graph.add(InnerNode::named("b"), NodeContent::Mesh(0), Some(a));
+        let c = graph.add(InnerNode::named("c"), NodeContent::Light(1), Some(b));
+
+        let removed = graph.remove(b);
+        assert_eq!(
+            removed,
+            vec![(b, NodeContent::Mesh(0)), (c, NodeContent::Light(1))]
+        );
+        assert!(graph.get(a).unwrap().children().is_empty());
+        assert_eq!(graph.len(), 1);
 
-        translate_matrix * rotate_matrix * scale_matrix
+        let d = graph.add(InnerNode::named("d"), NodeContent::Empty, None);
+        assert!(!graph.contains(b) && !graph.contains(c));
+        assert!(graph.contains(d));
+        assert_eq!(
+            graph.set_parent(b, None),
+            Err(SceneGraphError::InvalidNode(b))
+        );
+        assert!(graph.local_mut(c).is_none());
+        assert_eq!(graph.world_matrix(c), Mat4::IDENTITY);
+        assert!(graph.remove(c).is_empty());
     }
 }
@@ -0,0 +1,209 @@
+use crate::culling::SubmeshId;
+use crate::model;
+use crate::renderer::COLOR_PIXEL_FORMAT;
+use crate::scene::Scene;
+use crate::shader_bindings::{
+    BufferIndices_BufferIndexOutline as BufferIndexOutline, OutlineUniforms, Uniforms,
+};
+use crate::simd;
+use glam::{Vec2, Vec4};
+use metal::*;
+use std::mem;
+
+/// Stencil value of the pixels the selection covers.
+const SELECTED: u32 = 1;
+
+/// Draws an outline around the selected submeshes, over what has already
+/// been drawn. The selection first marks the pixels it covers in a stencil
+/// buffer, then is drawn again pushed out along its normals, only where it
+/// is not marked and not behind the depth of the main pass.
+pub struct Outline {
+    mask_pipeline_state: RenderPipelineState,
+    outline_pipeline_state: RenderPipelineState,
+    mask_depth_stencil_state: DepthStencilState,
+    outline_depth_stencil_state: DepthStencilState,
+    /// The size of the drawable. Recreated by `resize`.
+    stencil_texture: Texture,
+}
+
+impl Outline {
+    pub fn new(library: &Library, device: &Device, width: u64, height: u64) -> Self {
+        Self {
+            mask_pipeline_state: Self::build_pipeline_state(library, device, false),
+            outline_pipeline_state: Self::build_pipeline_state(library, device, true),
+            // the whole selection is masked, even where it is hidden
+            mask_depth_stencil_state: Self::build_depth_stencil_state(
+                device,
+                MTLCompareFunction::Always,
+                MTLCompareFunction::Always,
+                MTLStencilOperation::Replace,
+            ),
+            outline_depth_stencil_state: Self::build_depth_stencil_state(
+                device,
+                MTLCompareFunction::LessEqual,
+                MTLCompareFunction::NotEqual,
+                MTLStencilOperation::Keep,
+            ),
+            stencil_texture: Self::build_stencil_texture(device, width, height),
+        }
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u64, height: u64) {
+        self.stencil_texture = Self::build_stencil_texture(device, width, height);
+    }
+
+    /// Encodes the outline of the selection of `scene` into `target` as a
+    /// pass of its own, hidden behind what `depth` holds. `uniforms` and
+    /// `depth` hold the view, projection and depth of the main pass. Nothing
+    /// is encoded when nothing is selected.
+    pub fn render(
+        &self,
+        command_buffer: &CommandBufferRef,
+        target: &TextureRef,
+        depth: &TextureRef,
+        scene: &Scene,
+        uniforms: &mut [Uniforms],
+    ) {
+        let outline = scene.settings().outline;
+        if scene.selection().next().is_none() || outline.width <= 0.0 {
+            return;
+        }
+
+        let render_pass_descriptor = RenderPassDescriptor::new();
+        let color_attachment = render_pass_descriptor
+            .color_attachments()
+            .object_at(0)
+            .unwrap();
+        color_attachment.set_texture(Some(target));
+        color_attachment.set_load_action(MTLLoadAction::Load);
+        color_attachment.set_store_action(MTLStoreAction::Store);
+
+        let depth_attachment = render_pass_descriptor.depth_attachment().unwrap();
+        depth_attachment.set_texture(Some(depth));
+        depth_attachment.set_load_action(MTLLoadAction::Load);
+        depth_attachment.set_store_action(MTLStoreAction::DontCare);
+
+        let stencil_attachment = render_pass_descriptor.stencil_attachment().unwrap();
+        stencil_attachment.set_texture(Some(&self.stencil_texture));
+        stencil_attachment.set_load_action(MTLLoadAction::Clear);
+        stencil_attachment.set_store_action(MTLStoreAction::DontCare);
+        stencil_attachment.set_clear_stencil(0);
+
+        let render_encoder = command_buffer.new_render_command_encoder(render_pass_descriptor);
+        render_encoder.push_debug_group("outline");
+        render_encoder.set_stencil_reference_value(SELECTED);
+
+        let passes = [
+            (
+                &self.mask_pipeline_state,
+                &self.mask_depth_stencil_state,
+                0.0,
+            ),
+            (
+                &self.outline_pipeline_state,
+                &self.outline_depth_stencil_state,
+                outline.width,
+            ),
+        ];
+        for (pipeline_state, depth_stencil_state, width) in passes {
+            render_encoder.set_render_pipeline_state(pipeline_state);
+            render_encoder.set_depth_stencil_state(depth_stencil_state);
+            let viewport_size = Vec2::new(target.width() as f32, target.height() as f32);
+            // SAFETY: the uniforms are plain floats, for which zero is valid.
+            let mut outline_uniforms: [OutlineUniforms; 1] = unsafe { mem::zeroed() };
+            outline_uniforms[0].color = simd::vector4(Vec4::from(outline.color));
+            outline_uniforms[0].viewportSize = simd::vector2(viewport_size);
+            outline_uniforms[0].width = width;
+            render_encoder.set_vertex_bytes(
+                BufferIndexOutline as u64,
+                std::mem::size_of::<OutlineUniforms>() as u64,
+                outline_uniforms.as_ptr() as *const _,
+            );
+            render_encoder.set_fragment_bytes(
+                BufferIndexOutline as u64,
+                std::mem::size_of::<OutlineUniforms>() as u64,
+                outline_uniforms.as_ptr() as *const _,
+            );
+
+            for (model_index, model) in scene.models().iter().enumerate() {
+                model.render_geometry(
+                    render_encoder,
+                    uniforms,
+                    scene.scene_graph(),
+                    &|mesh, submesh| {
+                        scene.is_selected(SubmeshId {
+                            model: model_index,
+                            mesh,
+                            submesh,
+                        })
+                    },
+                );
+            }
+        }
+
+        render_encoder.pop_debug_group();
+        render_encoder.end_encoding();
+    }
+
+    /// The mask only writes the stencil; the outline writes color.
+    fn build_pipeline_state(
+        library: &Library,
+        device: &Device,
+        writes_color: bool,
+    ) -> RenderPipelineState {
+        let vertex_function = library.get_function("vertex_outline", None).unwrap();
+        let fragment_function = library.get_function("fragment_outline", None).unwrap();
+
+        let pipeline_state_descriptor = RenderPipelineDescriptor::new();
+        pipeline_state_descriptor.set_vertex_function(Some(&vertex_function));
+        pipeline_state_descriptor.set_fragment_function(Some(&fragment_function));
+        pipeline_state_descriptor.set_vertex_descriptor(Some(model::default_vertex_descriptor()));
+        pipeline_state_descriptor.set_depth_attachment_pixel_format(MTLPixelFormat::Depth32Float);
+        pipeline_state_descriptor.set_stencil_attachment_pixel_format(MTLPixelFormat::Stencil8);
+        let color_attachment = pipeline_state_descriptor
+            .color_attachments()
+            .object_at(0)
+            .unwrap();
+        color_attachment.set_pixel_format(COLOR_PIXEL_FORMAT);
+        if writes_color {
+            color_attachment.set_blending_enabled(true);
+            color_attachment.set_source_rgb_blend_factor(MTLBlendFactor::SourceAlpha);
+            color_attachment.set_destination_rgb_blend_factor(MTLBlendFactor::OneMinusSourceAlpha);
+        } else {
+            color_attachment.set_write_mask(MTLColorWriteMask::empty());
+        }
+
+        device
+            .new_render_pipeline_state(&pipeline_state_descriptor)
+            .unwrap()
+    }
+
+    fn build_stencil_texture(device: &Device, width: u64, height: u64) -> Texture {
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_width(width.max(1));
+        descriptor.set_height(height.max(1));
+        descriptor.set_pixel_format(MTLPixelFormat::Stencil8);
+        descriptor.set_storage_mode(MTLStorageMode::Private);
+        descriptor.set_usage(MTLTextureUsage::RenderTarget);
+        device.new_texture(&descriptor)
+    }
+
+    /// Tests depth without writing it; the main pass has written it already.
+    fn build_depth_stencil_state(
+        device: &Device,
+        depth_compare_function: MTLCompareFunction,
+        stencil_compare_function: MTLCompareFunction,
+        pass_operation: MTLStencilOperation,
+    ) -> DepthStencilState {
+        let stencil = StencilDescriptor::new();
+        stencil.set_stencil_compare_function(stencil_compare_function);
+        stencil.set_depth_stencil_pass_operation(pass_operation);
+
+        let descriptor = DepthStencilDescriptor::new();
+        descriptor.set_depth_compare_function(depth_compare_function);
+        descriptor.set_depth_write_enabled(false);
+        descriptor.set_front_face_stencil(Some(&stencil));
+        descriptor.set_back_face_stencil(Some(&stencil));
+        device.new_depth_stencil_state(&descriptor)
+    }
+}
@@ -0,0 +1,373 @@
+use crate::bounds::Bounds;
+use crate::bvh::{Bvh, Overlap};
+use crate::culling::SubmeshId;
+use crate::node::NodeId;
+use glam::{Mat4, Vec3, Vec4};
+use std::cell::Cell;
+
+/// The points `origin + t * direction` with `t >= 0`. The direction is not
+/// normalized, so that `t` stays the same when the ray is transformed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    /// The ray through the pixel at `(x, y)` of a `width` by `height`
+    /// viewport, from the near plane of the camera towards its far plane.
+    /// Pixels are counted from the top left, and clip space is Metal's.
+    pub fn from_screen(x: f32, y: f32, width: f32, height: f32, view_projection: Mat4) -> Self {
+        let ndc_x = 2.0 * x / width - 1.0;
+        let ndc_y = 1.0 - 2.0 * y / height;
+        let inverse = view_projection.inverse();
+        let unproject = |depth: f32| {
+            let point = inverse * Vec4::new(ndc_x, ndc_y, depth, 1.0);
+            point.truncate() / point.w
+        };
+        let near = unproject(0.0);
+        let far = unproject(1.0);
+        // a unit direction, so distances along the ray are world units
+        Self::new(near, (far - near).normalize())
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// The same ray in the space `matrix` maps into.
+    pub fn transform(&self, matrix: Mat4) -> Self {
+        Self::new(
+            matrix.transform_point3(self.origin),
+            matrix.transform_vector3(self.direction),
+        )
+    }
+
+    /// Where the ray enters `bounds`, or 0 if it starts inside.
+    pub fn intersect_bounds(&self, bounds: &Bounds) -> Option<f32> {
+        if bounds.is_empty() {
+            return None;
+        }
+        let mut enter = 0.0_f32;
+        let mut exit = f32::INFINITY;
+        for axis in 0..3 {
+            let (origin, direction) = (self.origin[axis], self.direction[axis]);
+            let (min, max) = (bounds.min[axis], bounds.max[axis]);
+            if direction == 0.0 {
+                // parallel to the slab: inside it or never
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+            let to_min = (min - origin) / direction;
+            let to_max = (max - origin) / direction;
+            enter = enter.max(to_min.min(to_max));
+            exit = exit.min(to_min.max(to_max));
+        }
+        if enter <= exit {
+            Some(enter)
+        } else {
+            None
+        }
+    }
+
+    /// Where the ray hits the triangle `a`, `b`, `c` from either side, as
+    /// `t` and the weights of `b` and `c`.
+    pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<(f32, f32, f32)> {
+        let edge_1 = b - a;
+        let edge_2 = c - a;
+        let p = self.direction.cross(edge_2);
+        let determinant = edge_1.dot(p);
+        if determinant.abs() <= f32::EPSILON * edge_1.length() * edge_2.length() {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(edge_1);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge_2.dot(q) * inverse;
+        if t < 0.0 {
+            return None;
+        }
+        Some((t, u, v))
+    }
+}
+
+/// The closest triangle a ray hits.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TriangleHit {
+    /// The index of the triangle, its first index being `3 * triangle`.
+    pub triangle: usize,
+    pub t: f32,
+    /// The weights of the three corners of the triangle at the hit.
+    pub barycentrics: Vec3,
+}
+
+/// A CPU copy of the positions and triangles of a primitive, with a tree
+/// over the triangles to cast rays against.
+#[derive(Debug, Clone, Default)]
+pub struct TriangleMesh {
+    positions: Vec<Vec3>,
+    indices: Vec<u32>,
+    bvh: Bvh<usize>,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Vec3>, indices: Vec<u32>) -> Self {
+        let bvh = Bvh::new(
+            indices
+                .chunks_exact(3)
+                .enumerate()
+                .map(|(triangle, corners)| {
+                    let bounds =
+                        Bounds::from_points(corners.iter().map(|&index| positions[index as usize]));
+                    (bounds, triangle)
+                }),
+        );
+        Self {
+            positions,
+            indices,
+            bvh,
+        }
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// The corners of a triangle.
+    pub fn triangle(&self, triangle: usize) -> [Vec3; 3] {
+        let corners = &self.indices[triangle * 3..triangle * 3 + 3];
+        [
+            self.positions[corners[0] as usize],
+            self.positions[corners[1] as usize],
+            self.positions[corners[2] as usize],
+        ]
+    }
+
+    /// The closest triangle `ray` hits no further than `max_t`.
+    pub fn intersect(&self, ray: &Ray, max_t: f32) -> Option<TriangleHit> {
+        let closest = Cell::new(max_t);
+        let mut hit = None;
+        self.bvh.query(
+            |bounds| match ray.intersect_bounds(bounds) {
+                Some(t) if t <= closest.get() => Overlap::Partial,
+                _ => Overlap::Outside,
+            },
+            |_, &triangle| {
+                let [a, b, c] = self.triangle(triangle);
+                if let Some((t, u, v)) = ray.intersect_triangle(a, b, c) {
+                    if t <= closest.get() {
+                        closest.set(t);
+                        hit = Some(TriangleHit {
+                            triangle,
+                            t,
+                            barycentrics: Vec3::new(1.0 - u - v, u, v),
+                        });
+                    }
+                }
+            },
+        );
+        hit
+    }
+}
+
+/// What a ray hit in a scene.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Pick {
+    pub submesh: SubmeshId,
+    /// The node of the mesh the submesh belongs to.
+    pub node: NodeId,
+    /// The instance of the mesh that was hit, if it is instanced.
+    pub instance: Option<usize>,
+    /// The index of the triangle in the submesh.
+    pub triangle: usize,
+    /// The material the submesh is drawn with, `None` for the default one.
+    pub material: Option<usize>,
+    /// The weights of the three corners of the triangle at the hit.
+    pub barycentrics: Vec3,
+    /// The hit in world space.
+    pub position: Vec3,
+    /// How far along the ray the hit is.
+    pub distance: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    fn view_projection() -> Mat4 {
+        let projection = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.5, 0.1, 100.0);
+        let view = Mat4::look_at_lh(Vec3::new(0.0, 0.0, -5.0), Vec3::ZERO, Vec3::Y);
+        projection * view
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn screen_rays() {
+        let center = Ray::from_screen(600.0, 400.0, 1200.0, 800.0, view_projection());
+        assert!(close(center.direction, Vec3::Z));
+        assert!(close(center.origin, Vec3::new(0.0, 0.0, -4.9)));
+
+        // the top right corner, 45 degrees up with a 90 degree vertical fov
+        let corner = Ray::from_screen(1200.0, 0.0, 1200.0, 800.0, view_projection());
+        let expected = Vec3::new(1.5, 1.0, 1.0).normalize();
+        // look_at_lh looking down +Z keeps +X to the right
+        assert!(close(corner.direction, expected), "{:?}", corner.direction);
+    }
+
+    #[test]
+    fn bounds_hits() {
+        let bounds = Bounds::new(Vec3::splat(-1.0), Vec3::splat(1.0));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::Z);
+        assert_eq!(ray.intersect_bounds(&bounds), Some(4.0));
+        // starting inside
+        assert_eq!(
+            Ray::new(Vec3::ZERO, Vec3::Z).intersect_bounds(&bounds),
+            Some(0.0)
+        );
+        // pointing away, missing to the side, and on a face
+        assert_eq!(
+            Ray::new(Vec3::new(0.0, 0.0, -5.0), -Vec3::Z).intersect_bounds(&bounds),
+            None
+        );
+        assert_eq!(
+            Ray::new(Vec3::new(2.0, 0.0, -5.0), Vec3::Z).intersect_bounds(&bounds),
+            None
+        );
+        assert_eq!(
+            Ray::new(Vec3::new(1.0, 0.0, -5.0), Vec3::Z).intersect_bounds(&bounds),
+            Some(4.0)
+        );
+        // a flat box
+        let flat = Bounds::new(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(
+            Ray::new(Vec3::new(0.0, 3.0, 0.0), -Vec3::Y).intersect_bounds(&flat),
+            Some(3.0)
+        );
+        assert_eq!(ray.intersect_bounds(&Bounds::EMPTY), None);
+    }
+
+    #[test]
+    fn triangle_hits() {
+        let (a, b, c) = (Vec3::ZERO, Vec3::X, Vec3::Y);
+        let ray = Ray::new(Vec3::new(0.25, 0.5, -2.0), Vec3::Z);
+        let (t, u, v) = ray.intersect_triangle(a, b, c).unwrap();
+        assert!((t - 2.0).abs() < 1e-6 && (u - 0.25).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+        // from the back side too
+        let back = Ray::new(Vec3::new(0.25, 0.5, 2.0), -Vec3::Z);
+        assert!(back.intersect_triangle(a, b, c).is_some());
+        // outside the triangle, behind the ray, and parallel to it
+        assert!(Ray::new(Vec3::new(0.75, 0.5, -2.0), Vec3::Z)
+            .intersect_triangle(a, b, c)
+            .is_none());
+        assert!(Ray::new(Vec3::new(0.25, 0.5, 2.0), Vec3::Z)
+            .intersect_triangle(a, b, c)
+            .is_none());
+        assert!(Ray::new(Vec3::new(0.25, 0.5, 0.0), Vec3::X)
+            .intersect_triangle(a, b, c)
+            .is_none());
+    }
+
+    /// A bumpy grid of `n` by `n` quads on the XZ plane.
+    fn terrain(n: u32) -> TriangleMesh {
+        let mut positions = vec![];
+        for z in 0..=n {
+            for x in 0..=n {
+                let height = ((x * 7 + z * 13) % 5) as f32 * 0.1;
+                positions.push(Vec3::new(x as f32, height, z as f32));
+            }
+        }
+        let mut indices = vec![];
+        for z in 0..n {
+            for x in 0..n {
+                let corner = z * (n + 1) + x;
+                indices.extend([corner, corner + n + 1, corner + 1]);
+                indices.extend([corner + 1, corner + n + 1, corner + n + 2]);
+            }
+        }
+        TriangleMesh::new(positions, indices)
+    }
+
+    fn brute_force(mesh: &TriangleMesh, ray: &Ray) -> Option<(usize, f32)> {
+        (0..mesh.triangle_count())
+            .filter_map(|triangle| {
+                let [a, b, c] = mesh.triangle(triangle);
+                ray.intersect_triangle(a, b, c)
+                    .map(|(t, _, _)| (triangle, t))
+            })
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+    }
+
+    #[test]
+    fn mesh_hits_match_brute_force() {
+        let mesh = terrain(40);
+        assert_eq!(mesh.triangle_count(), 3200);
+        let mut hits = 0;
+        for i in 0..200 {
+            let x = (i * 37 % 450) as f32 * 0.1 - 2.0;
+            let z = (i * 53 % 430) as f32 * 0.1 - 1.0;
+            let ray = Ray::new(Vec3::new(x, 10.0, z), Vec3::new(0.3, -1.0, 0.2));
+            let hit = mesh.intersect(&ray, f32::INFINITY);
+            let expected = brute_force(&mesh, &ray);
+            match (hit, expected) {
+                (Some(hit), Some((_, t))) => {
+                    hits += 1;
+                    assert!((hit.t - t).abs() < 1e-5);
+                    let [a, b, c] = mesh.triangle(hit.triangle);
+                    let point =
+                        a * hit.barycentrics.x + b * hit.barycentrics.y + c * hit.barycentrics.z;
+                    assert!(close(point, ray.at(hit.t)));
+                }
+                (None, None) => {}
+                other => panic!("{:?}", other),
+            }
+        }
+        assert!(hits > 100);
+        // nothing closer than the limit
+        let ray = Ray::new(Vec3::new(5.5, 10.0, 5.5), -Vec3::Y);
+        assert!(mesh.intersect(&ray, 9.0).is_none());
+        assert!(mesh.intersect(&ray, 10.0).is_some());
+    }
+
+    #[test]
+    fn transformed_rays_keep_their_parameter() {
+        let matrix = Mat4::from_scale_rotation_translation(
+            Vec3::splat(2.5),
+            Quat::from_rotation_y(0.8),
+            Vec3::new(1.0, 2.0, 3.0),
+        );
+        let ray = Ray::new(
+            Vec3::new(-1.0, 4.0, 0.5),
+            Vec3::new(0.2, -0.9, 0.1).normalize(),
+        );
+        let local = ray.transform(matrix.inverse());
+        for t in [0.0, 1.0, 7.5] {
+            assert!(close(matrix.transform_point3(local.at(t)), ray.at(t)));
+        }
+    }
+}
@@ -1,15 +1,43 @@
-use crate::camera::{ArcballCamera, CameraFunction};
+use crate::culling::{CullingStats, SubmeshId, Visibility};
+use crate::frustum::Frustum;
+use crate::gizmo::GizmoView;
+use crate::gizmo_overlay::GizmoOverlay;
+use crate::hot_reload::{HotReload, Reload};
+use crate::import_options::ImportOptions;
+use crate::importer;
+use crate::loader::{AssetLoader, LoadEvent, LoadProgress};
+use crate::lod::LodView;
+use crate::meshlet::ClusterView;
+use crate::node::SceneGraph;
+use crate::outline::Outline;
+use crate::picking::{Pick, Ray};
+use crate::scene::Scene;
+use crate::scene_file::{SceneDescription, SceneFileError};
 use crate::shader_bindings::{
     BufferIndices_BufferIndexLights as BufferIndexLights, FragmentUniforms, Light, Uniforms,
 };
-use crate::{lighting::Lighting, model::Model, skybox::Skybox};
+use crate::simd::{matrix3, matrix4, vector3};
+use crate::texture_cache::{self, CacheStats, TextureCache};
+use crate::{
+    model::{self, Model, RenderView},
+    skybox::Skybox,
+    texturable,
+};
 use cocoa::{appkit::NSView, base::id as cocoa_id};
 use core_graphics_types::geometry::CGSize;
 use glam::{Mat3A, Mat4, Vec3, Vec3A};
 use metal::*;
 use objc::runtime::YES;
+use std::path::{Path, PathBuf};
 use winit::{platform::macos::WindowExtMacOS, window::Window};
 
+/// Format of the drawable and every color attachment. Writes are sRGB encoded
+/// by the GPU, so shaders output linear color.
+pub(crate) const COLOR_PIXEL_FORMAT: MTLPixelFormat = MTLPixelFormat::BGRA8Unorm_sRGB;
+
+/// The scene `default_scene` opens.
+const DEFAULT_SCENE: &str = "assets/scenes/default.json";
+
 pub struct Renderer {
     draw_size_width: u64,
     draw_size_height: u64,
@@ -20,22 +48,27 @@ pub struct Renderer {
     uniforms: [Uniforms; 1],
     skybox_uniforms: [Uniforms; 1],
     fragment_uniforms: [FragmentUniforms; 1],
-    camera: ArcballCamera,
-    models: Vec<Model>,
-    skybox: Option<Skybox>,
+    placeholder: Model,
+    /// Holds just the node of the placeholder, which no scene owns.
+    placeholder_graph: SceneGraph,
+    loader: AssetLoader,
+    loading_progress: Option<LoadProgress>,
+    hot_reload: HotReload,
+    texture_cache: TextureCache<Texture>,
+    brdf_lut: Option<Texture>,
     depth_stencil_state: DepthStencilState,
-    lighting: Lighting,
+    /// The depth of the main pass, kept for the outline pass. Recreated when
+    /// the drawable is resized.
+    depth_texture: Texture,
+    culling_stats: CullingStats,
+    outline: Outline,
+    gizmo_overlay: GizmoOverlay,
 }
 
 fn get_high_performance_device() -> Option<Device> {
-    let devices_list = Device::all();
-    for device in devices_list {
-        if !device.is_low_power() && !device.is_removable() && !device.is_headless() {
-            return Some(device);
-        }
-    }
-
-    None
+    Device::all()
+        .into_iter()
+        .find(|device| !device.is_low_power() && !device.is_removable() && !device.is_headless())
 }
 
 impl Renderer {
@@ -56,13 +89,13 @@ impl Renderer {
 
         let layer = MetalLayer::new();
         layer.set_device(&device);
-        layer.set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+        layer.set_pixel_format(COLOR_PIXEL_FORMAT);
         layer.set_presents_with_transaction(false);
 
         unsafe {
             let view = window.ns_view() as cocoa_id;
             view.setWantsLayer(YES);
-            view.setLayer(std::mem::transmute(layer.as_ref()));
+            view.setLayer(layer.as_ref() as *const MetalLayerRef as cocoa_id);
         }
 
         let draw_size = window.inner_size();
@@ -76,70 +109,56 @@ impl Renderer {
 
         let brdf_lut = Self::build_brdf(&device, &library, &command_queue);
 
-        let skybox = Skybox::new(&library, &device, brdf_lut);
-
-        let (camera, model) = Self::read_gltf_asset(&device, &library);
+        let mut texture_cache = TextureCache::new();
 
-        let models = vec![model];
+        let compression = texturable::texture_compression(&device);
+        log::info!("texture compression: {:?}", compression);
+        let loader = AssetLoader::new(compression);
 
-        // generate mipmaps
-        for model in models.iter() {
-            for mesh in model.meshes.iter() {
-                for submesh in mesh.submeshes.iter() {
-                    let command_buffer = command_queue.new_command_buffer();
-                    let blit_command_encoder = command_buffer.new_blit_command_encoder();
-
-                    if let Some(texture) = &submesh.textures.diffuse_texture {
-                        blit_command_encoder.generate_mipmaps(&texture);
-                    }
-                    if let Some(texture) = &submesh.textures.normal_texture {
-                        blit_command_encoder.generate_mipmaps(&texture);
-                    }
-                    if let Some(texture) = &submesh.textures.metallic_roughness_texture {
-                        blit_command_encoder.generate_mipmaps(&texture);
-                    }
-                    if let Some(texture) = &submesh.textures.emissive_texture {
-                        blit_command_encoder.generate_mipmaps(&texture);
-                    }
-                    if let Some(texture) = &submesh.textures.ambient_occlusion_texture {
-                        blit_command_encoder.generate_mipmaps(&texture);
-                    }
+        let hot_reload = HotReload::new();
 
-                    blit_command_encoder.end_encoding();
-                    command_buffer.commit();
-                }
-            }
+        // shown while the models are still loading
+        let mut placeholder_graph = SceneGraph::new();
+        let placeholder = Model::from_gltf_filename(
+            "cube.gltf",
+            1,
+            &device,
+            &library,
+            &mut texture_cache,
+            &mut placeholder_graph,
+        );
+        if let Some(local) = placeholder_graph.local_mut(placeholder.node()) {
+            local.set_scale(Vec3::new(0.2, 0.2, 0.2));
         }
 
         let uniforms = Uniforms {
-            modelMatrix: unsafe { std::mem::transmute(Mat4::ZERO) },
-            viewMatrix: unsafe { std::mem::transmute(Mat4::ZERO) },
-            projectionMatrix: unsafe { std::mem::transmute(Mat4::ZERO) },
-            normalMatrix: unsafe { std::mem::transmute(Mat3A::ZERO) },
+            modelMatrix: matrix4(Mat4::ZERO),
+            viewMatrix: matrix4(Mat4::ZERO),
+            projectionMatrix: matrix4(Mat4::ZERO),
+            normalMatrix: matrix3(Mat3A::ZERO),
         };
 
         let skybox_uniforms = Uniforms {
-            modelMatrix: unsafe { std::mem::transmute(Mat4::IDENTITY) },
-            viewMatrix: unsafe { std::mem::transmute(Mat4::ZERO) },
-            projectionMatrix: unsafe { std::mem::transmute(Mat4::ZERO) },
-            normalMatrix: unsafe { std::mem::transmute(Mat3A::ZERO) },
+            modelMatrix: matrix4(Mat4::IDENTITY),
+            viewMatrix: matrix4(Mat4::ZERO),
+            projectionMatrix: matrix4(Mat4::ZERO),
+            normalMatrix: matrix3(Mat3A::ZERO),
         };
 
         let depth_stencil_state = Self::build_depth_stencil_state(&device);
-
-        let lighting = Lighting::new();
-
-        let camera_position = camera.position();
+        let depth_texture =
+            Self::build_depth_texture(&device, draw_size.width as u64, draw_size.height as u64);
+        let outline = Outline::new(
+            &library,
+            &device,
+            draw_size.width as u64,
+            draw_size.height as u64,
+        );
+        let gizmo_overlay = GizmoOverlay::new(&library, &device);
 
         let fragment_uniforms = FragmentUniforms {
-            lightCount: lighting.count,
-            cameraPosition: unsafe {
-                std::mem::transmute(Vec3A::new(
-                    camera_position.x,
-                    camera_position.y,
-                    camera_position.z,
-                ))
-            },
+            lightCount: 0,
+            cameraPosition: vector3(Vec3A::ZERO),
             tiling: 1,
             __bindgen_padding_0: unsafe { std::mem::zeroed() },
         };
@@ -154,31 +173,268 @@ impl Renderer {
             uniforms: [uniforms],
             skybox_uniforms: [skybox_uniforms],
             fragment_uniforms: [fragment_uniforms],
-            camera,
-            models,
-            skybox: Some(skybox),
+            placeholder,
+            placeholder_graph,
+            loader,
+            loading_progress: None,
+            hot_reload,
+            texture_cache,
+            brdf_lut,
             depth_stencil_state,
-            lighting,
+            depth_texture,
+            culling_stats: CullingStats::default(),
+            outline,
+            gizmo_overlay,
         }
     }
 
+    /// Resizes the drawable. The aspect ratio of the cameras of each scene
+    /// is set through `ArcballCamera::set_aspect_ratio`.
     pub fn resize(&mut self, width: u32, height: u32) {
         self.layer
             .set_drawable_size(CGSize::new(width as f64, height as f64));
+        self.draw_size_width = width as u64;
+        self.draw_size_height = height as u64;
+        self.depth_texture =
+            Self::build_depth_texture(&self.device, self.draw_size_width, self.draw_size_height);
+        self.outline
+            .resize(&self.device, self.draw_size_width, self.draw_size_height);
+    }
+
+    /// Builds the scene `description` lists and starts loading its models,
+    /// which are added by `update` once they have loaded.
+    pub fn load_scene(&mut self, description: &SceneDescription) -> Scene {
+        let skybox = match &description.environment {
+            Some(environment) => {
+                for directory in environment.directories() {
+                    self.hot_reload.planner_mut().watch_environment(&directory);
+                }
+                self.hot_reload.update_watches();
+                Some(Skybox::new(
+                    &self.library,
+                    &self.device,
+                    self.brdf_lut.clone(),
+                    &mut self.texture_cache,
+                    environment.clone(),
+                ))
+            }
+            None => None,
+        };
+        let aspect_ratio = self.draw_size_width as f32 / self.draw_size_height as f32;
+        let scene = Scene::new(description, skybox, aspect_ratio);
+        for model in description.models.iter() {
+            self.loader
+                .load_model(&model.name, model.tiling, model.import_options);
+        }
+        scene
+    }
 
-        let aspect_ratio = width as f32 / height as f32;
-        self.camera.set_aspect_ratio(aspect_ratio);
+    /// Loads the scene file at `path`. Save a scene with
+    /// `scene.description().save(path)`.
+    pub fn open_scene(&mut self, path: &Path) -> Result<Scene, SceneFileError> {
+        Ok(self.load_scene(&SceneDescription::open(path)?))
     }
 
-    pub fn zoom(&mut self, delta: f32) {
-        self.camera.zoom(delta);
+    /// An empty scene with the default lights and environment, seen from an
+    /// orbiting camera.
+    pub fn new_scene(&mut self) -> Scene {
+        self.load_scene(&SceneDescription::default())
     }
 
-    pub fn rotate(&mut self, delta: (f64, f64)) {
-        self.camera.rotate((delta.0 as f32, delta.1 as f32));
+    /// A new scene showing the sample helmet.
+    pub fn default_scene(&mut self) -> Scene {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(DEFAULT_SCENE);
+        self.open_scene(&path)
+            .unwrap_or_else(|error| panic!("Unable to open {}: {}", path.display(), error))
     }
 
-    pub fn draw(&mut self) {
+    /// Draws `transforms.len()` extra copies of one mesh of a model of `scene`
+    /// in a single instanced draw call. Returns how many copies of the mesh are
+    /// drawn, the original included, or `None` if there is no such mesh.
+    pub fn add_instances(
+        &self,
+        scene: &mut Scene,
+        model_index: usize,
+        mesh_index: usize,
+        transforms: &[Mat4],
+    ) -> Option<usize> {
+        let count =
+            scene
+                .models
+                .get_mut(model_index)?
+                .add_instances(&self.device, mesh_index, transforms);
+        scene.invalidate_submesh_bvh();
+        count
+    }
+
+    /// Drops a model of `scene` and its nodes, with everything attached to
+    /// them, releasing the textures only it was using.
+    pub fn remove_model(&mut self, scene: &mut Scene, model_index: usize) {
+        let model = scene.remove_model(model_index);
+        model.release_textures(&mut self.texture_cache);
+    }
+
+    /// What of `scene` is under the pixel at `(x, y)` of the drawable,
+    /// counted from its top left corner.
+    pub fn pick(&self, scene: &Scene, x: f32, y: f32) -> Option<Pick> {
+        let camera = scene.camera();
+        let ray = Ray::from_screen(
+            x,
+            y,
+            self.draw_size_width as f32,
+            self.draw_size_height as f32,
+            camera.projection_matrix() * *camera.view_matrix(),
+        );
+        scene.pick(&ray)
+    }
+
+    /// How `scene` is seen on the drawable, to hit test and drag its gizmo
+    /// with the cursor.
+    pub fn gizmo_view(&self, scene: &Scene) -> GizmoView {
+        let camera = scene.camera();
+        GizmoView {
+            view_projection: camera.projection_matrix() * *camera.view_matrix(),
+            width: self.draw_size_width as f32,
+            height: self.draw_size_height as f32,
+        }
+    }
+
+    /// How many submeshes the last `draw` sent to the GPU and left out as
+    /// outside the view.
+    pub fn culling_stats(&self) -> CullingStats {
+        self.culling_stats
+    }
+
+    pub fn texture_cache_stats(&self) -> CacheStats {
+        self.texture_cache.stats()
+    }
+
+    /// Starts loading a model in the background, converting it to Y-up and
+    /// meters as `import_options` says. It is added to the scene passed to
+    /// the first `update` after it has finished loading.
+    pub fn load_model(&mut self, name: &str, tiling: u32, import_options: ImportOptions) {
+        self.loader.load_model(name, tiling, import_options);
+    }
+
+    /// Progress of the most recently reported load, `None` once every
+    /// requested model has been loaded.
+    pub fn loading_progress(&self) -> Option<&LoadProgress> {
+        self.loading_progress.as_ref()
+    }
+
+    /// Uploads the models the loader has finished decoding into `scene`.
+    fn finish_loading(&mut self, scene: &mut Scene) {
+        for event in self.loader.poll() {
+            match event {
+                LoadEvent::Progress(progress) => {
+                    self.loading_progress = Some(progress);
+                }
+                LoadEvent::Loaded { model_data, reload } => {
+                    let model = Model::from_data(
+                        *model_data,
+                        &self.device,
+                        &self.library,
+                        &mut self.texture_cache,
+                        scene.scene_graph_mut(),
+                    );
+                    self.watch_model(&model);
+
+                    let existing = scene
+                        .models
+                        .iter()
+                        .position(|existing| existing.name() == model.name());
+                    match existing {
+                        Some(index) if reload => {
+                            // The new model holds its own references, so
+                            // textures both of them use stay cached.
+                            let previous = scene.replace_model(index, model);
+                            previous.release_textures(&mut self.texture_cache);
+                        }
+                        _ => scene.add_model(model),
+                    }
+                    log::debug!("texture cache: {:?}", self.texture_cache.stats());
+                }
+                LoadEvent::Failed {
+                    name,
+                    error,
+                    reload,
+                } => {
+                    log::error!("failed to load {}: {}", name, error);
+                    if !reload {
+                        scene.drop_pending_model(&name);
+                    }
+                }
+                LoadEvent::TextureReloaded { key, levels } => {
+                    let texture = texture_cache::TextureFactory::new_texture(&self.device, &levels);
+                    if self.texture_cache.replace(&key, texture.clone()) {
+                        for model in scene.models.iter_mut() {
+                            model.replace_texture(&key, &texture);
+                        }
+                        log::info!("reloaded {}", key.path.display());
+                    }
+                }
+                LoadEvent::TextureFailed { key, error } => {
+                    log::warn!("unable to reload {}: {}", key.path.display(), error);
+                }
+            }
+        }
+
+        if !self.loader.is_loading() {
+            self.loading_progress = None;
+        }
+    }
+
+    /// Watches the files of `model` for changes.
+    fn watch_model(&mut self, model: &Model) {
+        let planner = self.hot_reload.planner_mut();
+        match importer::source_files(&model::model_path(model.name())) {
+            Ok(files) => planner.watch_model(model.name(), files),
+            Err(error) => log::warn!("unable to watch {}: {}", model.name(), error),
+        }
+        for path in model.texture_paths() {
+            planner.watch_texture(path);
+        }
+        self.hot_reload.update_watches();
+    }
+
+    /// Starts reloading the assets whose files changed. Textures and models
+    /// are decoded by the loader and swapped in by `finish_loading`.
+    fn reload_changed_assets(&mut self, scene: &mut Scene) {
+        for reload in self.hot_reload.poll() {
+            match reload {
+                Reload::Model(name) => {
+                    if let Some(model) = scene.models.iter().find(|model| *model.name() == name) {
+                        log::info!("reloading {}", name);
+                        self.loader
+                            .reload_model(&name, model.tiling, model.import_options());
+                    }
+                }
+                Reload::Texture(path) => {
+                    for key in self.texture_cache.keys_for_path(&path) {
+                        self.loader.reload_texture(key);
+                    }
+                }
+                Reload::Environment => {
+                    if let Some(skybox) = &mut scene.skybox {
+                        log::info!("reloading environment");
+                        skybox.reload_environment(&self.device);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Brings `scene` up to date: swaps in assets whose files changed, adds
+    /// the models that finished loading and moves the nodes of the cameras
+    /// to where the cameras are. Call it before each `draw`.
+    pub fn update(&mut self, scene: &mut Scene) {
+        self.reload_changed_assets(scene);
+        self.finish_loading(scene);
+        scene.sync_camera_nodes();
+    }
+
+    /// Draws `scene` from its current camera.
+    pub fn draw(&mut self, scene: &Scene) {
         let drawable = match self.layer.next_drawable() {
             Some(drawable) => drawable,
             None => return,
@@ -190,76 +446,133 @@ impl Renderer {
             .color_attachments()
             .object_at(0)
             .unwrap();
-        color_attachment.set_texture(Some(&drawable.texture()));
+        color_attachment.set_texture(Some(drawable.texture()));
         color_attachment.set_load_action(MTLLoadAction::Clear);
         color_attachment.set_clear_color(MTLClearColor::new(0.2, 0.2, 0.25, 1.0));
         // color_attachment.set_clear_color(MTLClearColor::new(0.93, 0.97, 1.0, 1.0));
         color_attachment.set_store_action(MTLStoreAction::Store);
 
-        let depth_buffer_descriptor = TextureDescriptor::new();
-        depth_buffer_descriptor.set_width(self.draw_size_width);
-        depth_buffer_descriptor.set_height(self.draw_size_height);
-        depth_buffer_descriptor.set_pixel_format(MTLPixelFormat::Depth32Float);
-        depth_buffer_descriptor.set_storage_mode(MTLStorageMode::Private);
-        depth_buffer_descriptor
-            .set_usage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
         let depth_attachment = render_pass_descriptor.depth_attachment().unwrap();
-        depth_attachment.set_texture(Some(&self.device.new_texture(&depth_buffer_descriptor)));
+        depth_attachment.set_texture(Some(&self.depth_texture));
         depth_attachment.set_load_action(MTLLoadAction::Clear);
-        depth_attachment.set_store_action(MTLStoreAction::DontCare);
+        // the outline pass tests against it
+        depth_attachment.set_store_action(MTLStoreAction::Store);
         depth_attachment.set_clear_depth(1.0);
 
-        self.uniforms[0].projectionMatrix =
-            unsafe { std::mem::transmute(*self.camera.projection_matrix()) };
-        self.uniforms[0].viewMatrix = unsafe { std::mem::transmute(*self.camera.view_matrix()) };
+        let camera = scene.camera();
+        self.uniforms[0].projectionMatrix = matrix4(camera.projection_matrix());
+        self.uniforms[0].viewMatrix = matrix4(*camera.view_matrix());
+
+        let lights = scene.lighting.placed_lights(scene.scene_graph());
+        self.fragment_uniforms[0].lightCount = lights.len() as u32;
 
         self.skybox_uniforms[0].viewMatrix = self.uniforms[0].viewMatrix;
         self.skybox_uniforms[0].projectionMatrix = self.uniforms[0].projectionMatrix;
 
-        self.fragment_uniforms[0].cameraPosition = unsafe {
-            std::mem::transmute(Vec3A::new(
-                self.camera.position().x,
-                self.camera.position().y,
-                self.camera.position().z,
-            ))
-        };
+        self.fragment_uniforms[0].cameraPosition = vector3(Vec3A::from(*camera.position()));
 
         let command_buffer = self.command_queue.new_command_buffer();
-        let render_encoder = command_buffer.new_render_command_encoder(&render_pass_descriptor);
+        let render_encoder = command_buffer.new_render_command_encoder(render_pass_descriptor);
         // render_encoder.set_front_facing_winding(MTLWinding::CounterClockwise);
         // render_encoder.set_cull_mode(MTLCullMode::Back);
         render_encoder.set_depth_stencil_state(&self.depth_stencil_state);
 
         render_encoder.set_fragment_bytes(
             BufferIndexLights as u64,
-            std::mem::size_of::<Light>() as u64 * self.lighting.count as u64,
-            self.lighting.lights.as_ptr() as *const _,
+            std::mem::size_of::<Light>() as u64 * lights.len() as u64,
+            lights.as_ptr() as *const _,
         );
 
-        if let Some(skybox) = &self.skybox {
-            skybox.update(&render_encoder);
+        if let Some(skybox) = &scene.skybox {
+            skybox.update(render_encoder);
         }
 
-        for model in self.models.iter() {
-            render_encoder.push_debug_group(&model.name());
+        let lod = LodView::new(
+            *camera.position(),
+            camera.fov_radians(),
+            self.draw_size_height as f32,
+            scene.settings.lod_threshold,
+        );
+        let view_projection = camera.projection_matrix() * *camera.view_matrix();
+        let view = RenderView {
+            lod,
+            clusters: ClusterView::new(view_projection, *camera.position()),
+        };
+
+        let visibility =
+            Visibility::cull(&scene.submesh_bvh(), &Frustum::from_matrix(view_projection));
+        self.culling_stats = visibility.stats();
+
+        for (model_index, model) in scene.models.iter().enumerate() {
+            render_encoder.push_debug_group(model.name());
             model.render(
-                &render_encoder,
+                render_encoder,
+                &mut self.uniforms,
+                &mut self.fragment_uniforms,
+                scene.scene_graph(),
+                &view,
+                &|mesh, submesh| {
+                    visibility.is_visible(SubmeshId {
+                        model: model_index,
+                        mesh,
+                        submesh,
+                    })
+                },
+            );
+            render_encoder.pop_debug_group();
+        }
+
+        if self.loader.is_loading() {
+            render_encoder.push_debug_group("placeholder");
+            self.placeholder.render(
+                render_encoder,
                 &mut self.uniforms,
                 &mut self.fragment_uniforms,
+                &self.placeholder_graph,
+                &view,
+                &|_, _| true,
             );
             render_encoder.pop_debug_group();
         }
 
-        if let Some(skybox) = &self.skybox {
-            skybox.render(&render_encoder, &mut self.skybox_uniforms);
+        if let Some(skybox) = &scene.skybox {
+            skybox.render(render_encoder, &mut self.skybox_uniforms);
         }
 
         render_encoder.end_encoding();
 
-        command_buffer.present_drawable(&drawable);
+        self.outline.render(
+            command_buffer,
+            drawable.texture(),
+            &self.depth_texture,
+            scene,
+            &mut self.uniforms,
+        );
+
+        if let Some(frame) = scene.gizmo_frame(&self.gizmo_view(scene)) {
+            self.gizmo_overlay.render(
+                &self.device,
+                command_buffer,
+                drawable.texture(),
+                &scene.gizmo().geometry(&frame),
+                &self.uniforms,
+            );
+        }
+
+        command_buffer.present_drawable(drawable);
         command_buffer.commit();
     }
 
+    fn build_depth_texture(device: &Device, width: u64, height: u64) -> Texture {
+        let descriptor = TextureDescriptor::new();
+        descriptor.set_width(width.max(1));
+        descriptor.set_height(height.max(1));
+        descriptor.set_pixel_format(MTLPixelFormat::Depth32Float);
+        descriptor.set_storage_mode(MTLStorageMode::Private);
+        descriptor.set_usage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+        device.new_texture(&descriptor)
+    }
+
     fn build_depth_stencil_state(device: &Device) -> DepthStencilState {
         let descriptor = DepthStencilDescriptor::new();
         descriptor.set_depth_compare_function(MTLCompareFunction::Less);
@@ -267,24 +580,6 @@ impl Renderer {
         device.new_depth_stencil_state(&descriptor)
     }
 
-    fn read_gltf_asset(device: &Device, library: &Library) -> (ArcballCamera, Model) {
-        let mut model =
-            Model::from_gltf_filename("DamagedHelmet/DamagedHelmet.gltf", 1, &device, &library);
-        model.set_rotation(Vec3::new(
-            270.0_f32.to_radians(),
-            180.0_f32.to_radians(),
-            220.0_f32.to_radians(),
-        ));
-        // let model =
-        //     Model::from_gltf_filename("FlightHelmet/FlightHelmet.gltf", 1, &device, &library);
-
-        let mut camera = ArcballCamera::new(0.5, 10.0, Vec3::new(0.0, 0.3, 0.0), 3.5);
-        camera.set_position(Vec3::new(0.0, 0.0, 2.5));
-        camera.set_rotation(Vec3::new(0.0, 160.0_f32.to_radians(), 0.0));
-
-        (camera, model)
-    }
-
     fn build_brdf(
         device: &Device,
         library: &Library,
@@ -1,21 +1,591 @@
-use crate::{camera::Camera, node::Node};
+use crate::{
+    bounds::Bounds,
+    bvh::{Bvh, Overlap},
+    camera::ArcballCamera,
+    culling::SubmeshId,
+    gizmo::{Gizmo, GizmoFrame, GizmoHandle, GizmoView},
+    lighting::Lighting,
+    model::Model,
+    node::{InnerNode, NodeContent, NodeId, SceneGraph},
+    picking::{Pick, Ray},
+    scene_file::{ModelDescription, RenderSettings, SceneDescription, TransformDescription},
+    skybox::Skybox,
+};
+use glam::Mat4;
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::BTreeSet;
 
-struct Scene {
-    cameras: Vec<Camera>,
+/// Everything that is drawn: the models and lights placed by a node graph,
+/// the cameras looking at them, the environment around them and how they are
+/// rendered. Built by `Renderer::load_scene`, filled by `Renderer::update` as
+/// models finish loading and drawn by `Renderer::draw`. `description` turns
+/// it back into a scene file.
+pub struct Scene {
+    scene_graph: SceneGraph,
+    pub(crate) models: Vec<Model>,
+    /// Models of the scene file that are still loading.
+    pending_models: Vec<ModelDescription>,
+    cameras: Vec<ArcballCamera>,
+    camera_nodes: Vec<NodeId>,
     current_camera_index: usize,
-    nodes: Vec<Node>,
+    pub(crate) lighting: Lighting,
+    pub(crate) skybox: Option<Skybox>,
+    pub(crate) settings: RenderSettings,
+    /// The submeshes drawn with an outline.
+    selection: BTreeSet<SubmeshId>,
+    /// Moves, rotates and scales the selected node.
+    gizmo: Gizmo,
+    /// Built on first use after the models change, and refit when the scene
+    /// graph revision it was fit at is out of date.
+    submesh_bvh: RefCell<Option<(Bvh<SubmeshId>, u64)>>,
 }
 
 impl Scene {
-    fn new() -> Self {
-        Scene {
-            cameras: vec![Camera::default()],
+    /// A scene with the lights, cameras and settings of `description`,
+    /// around `skybox`. Its models are expected to be loading.
+    pub(crate) fn new(
+        description: &SceneDescription,
+        skybox: Option<Skybox>,
+        aspect_ratio: f32,
+    ) -> Self {
+        let mut scene_graph = SceneGraph::new();
+        let lighting = Lighting::new(&description.lights, &mut scene_graph);
+        let mut scene = Scene {
+            scene_graph,
+            models: vec![],
+            pending_models: description.models.clone(),
+            cameras: vec![],
+            camera_nodes: vec![],
             current_camera_index: 0,
-            nodes: vec![],
+            lighting,
+            skybox,
+            settings: description.settings,
+            selection: BTreeSet::new(),
+            gizmo: Gizmo::new(),
+            submesh_bvh: RefCell::new(None),
+        };
+        for camera in description.cameras.iter() {
+            let mut camera = ArcballCamera::from_description(camera);
+            camera.set_aspect_ratio(aspect_ratio);
+            scene.add_camera(camera);
         }
+        if scene.cameras.is_empty() {
+            let mut camera = ArcballCamera::from_description(&Default::default());
+            camera.set_aspect_ratio(aspect_ratio);
+            scene.add_camera(camera);
+        }
+        scene.set_current_camera(description.current_camera);
+        scene
+    }
+
+    /// The scene as a scene file. Models that are still loading are written
+    /// as they were given.
+    pub fn description(&self) -> SceneDescription {
+        let mut models: Vec<ModelDescription> = self
+            .models
+            .iter()
+            .map(|model| ModelDescription {
+                name: model.name().to_string(),
+                tiling: model.tiling,
+                transform: self
+                    .scene_graph
+                    .get(model.node())
+                    .map(|node| TransformDescription::from(node.local())),
+                import_options: model.import_options(),
+            })
+            .collect();
+        models.extend(self.pending_models.iter().cloned());
+
+        SceneDescription {
+            models,
+            lights: self.lighting.descriptions(&self.scene_graph),
+            cameras: self
+                .cameras
+                .iter()
+                .map(ArcballCamera::description)
+                .collect(),
+            current_camera: self.current_camera_index,
+            environment: self
+                .skybox
+                .as_ref()
+                .map(|skybox| skybox.environment().clone()),
+            settings: self.settings,
+            ..SceneDescription::default()
+        }
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    /// How far, in pixels, a simplified level of detail may stray from the
+    /// full mesh on screen before a finer level is drawn. Zero always draws
+    /// the full meshes.
+    pub fn set_lod_threshold(&mut self, pixels: f32) {
+        self.settings.lod_threshold = pixels.max(0.0);
+    }
+
+    /// The nodes placing every model, mesh, light and camera.
+    pub fn scene_graph(&self) -> &SceneGraph {
+        &self.scene_graph
+    }
+
+    /// To move, reparent or group nodes. Models, meshes, lights and cameras
+    /// keep the handle of their node, so removing those nodes detaches them
+    /// from the scene for good.
+    pub fn scene_graph_mut(&mut self) -> &mut SceneGraph {
+        &mut self.scene_graph
+    }
+
+    pub fn models(&self) -> &[Model] {
+        &self.models
+    }
+
+    /// How the outline around the selection is drawn. A width of zero hides
+    /// it.
+    pub fn set_outline(&mut self, color: [f32; 4], width: f32) {
+        self.settings.outline.color = color;
+        self.settings.outline.width = width.max(0.0);
+    }
+
+    /// The selected submeshes, in order.
+    pub fn selection(&self) -> impl Iterator<Item = SubmeshId> + '_ {
+        self.selection.iter().copied()
+    }
+
+    pub fn is_selected(&self, id: SubmeshId) -> bool {
+        self.selection.contains(&id)
+    }
+
+    /// Adds a submesh to the selection. Returns `false` if there is no such
+    /// submesh.
+    pub fn select(&mut self, id: SubmeshId) -> bool {
+        if !self.contains_submesh(id) {
+            return false;
+        }
+        self.selection.insert(id);
+        true
+    }
+
+    /// Adds every submesh of a model to the selection. Returns `false` if
+    /// there is no such model.
+    pub fn select_model(&mut self, model_index: usize) -> bool {
+        let model = match self.models.get(model_index) {
+            Some(model) => model,
+            None => return false,
+        };
+        for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+            for submesh_index in 0..mesh.submeshes.len() {
+                self.selection.insert(SubmeshId {
+                    model: model_index,
+                    mesh: mesh_index,
+                    submesh: submesh_index,
+                });
+            }
+        }
+        true
+    }
+
+    pub fn deselect(&mut self, id: SubmeshId) {
+        self.selection.remove(&id);
+    }
+
+    /// Selects the submesh if it is not selected, and deselects it if it is.
+    pub fn toggle_selection(&mut self, id: SubmeshId) {
+        if !self.selection.remove(&id) {
+            self.select(id);
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    /// The node the gizmo moves: the node of the mesh of the first selected
+    /// submesh.
+    pub fn selected_node(&self) -> Option<NodeId> {
+        let id = self.selection.iter().next()?;
+        Some(self.models[id.model].meshes[id.mesh].node())
+    }
+
+    pub fn gizmo(&self) -> &Gizmo {
+        &self.gizmo
+    }
+
+    /// To change the mode, space and snapping of the gizmo.
+    pub fn gizmo_mut(&mut self) -> &mut Gizmo {
+        &mut self.gizmo
+    }
+
+    /// Where the gizmo of the selected node is, if a node is selected.
+    pub fn gizmo_frame(&self, view: &GizmoView) -> Option<GizmoFrame> {
+        let node = self.selected_node()?;
+        Some(self.gizmo.frame(self.scene_graph.world_matrix(node), view))
+    }
+
+    /// Highlights the handle of the gizmo under the pixel at `(x, y)`.
+    pub fn hover_gizmo(&mut self, view: &GizmoView, x: f32, y: f32) -> Option<GizmoHandle> {
+        match self.gizmo_frame(view) {
+            Some(frame) => self.gizmo.hover(&frame, &view.ray(x, y)),
+            None => None,
+        }
+    }
+
+    /// Starts dragging the handle of the gizmo under the pixel at `(x, y)`.
+    /// Returns `false` if there is none.
+    pub fn begin_gizmo_drag(&mut self, view: &GizmoView, x: f32, y: f32) -> bool {
+        let (node, frame) = match (self.selected_node(), self.gizmo_frame(view)) {
+            (Some(node), Some(frame)) => (node, frame),
+            _ => return false,
+        };
+        let (local, parent) = match self.scene_graph.get(node) {
+            Some(node) => (node.local().clone(), node.parent()),
+            None => return false,
+        };
+        let parent_matrix = parent.map_or(Mat4::IDENTITY, |parent| {
+            self.scene_graph.world_matrix(parent)
+        });
+        self.gizmo
+            .begin_drag(&frame, &view.ray(x, y), node, &local, parent_matrix)
+    }
+
+    /// Moves the dragged node as the cursor moved to the pixel at `(x, y)`.
+    pub fn drag_gizmo(&mut self, view: &GizmoView, x: f32, y: f32) {
+        let node = match self.gizmo.dragged_node() {
+            Some(node) => node,
+            None => return,
+        };
+        if let Some(transform) = self.gizmo.drag(&view.ray(x, y)) {
+            if let Some(local) = self.scene_graph.local_mut(node) {
+                *local = transform;
+            }
+        }
+    }
+
+    pub fn end_gizmo_drag(&mut self) {
+        self.gizmo.end_drag();
+    }
+
+    fn contains_submesh(&self, id: SubmeshId) -> bool {
+        self.models
+            .get(id.model)
+            .and_then(|model| model.meshes.get(id.mesh))
+            .is_some_and(|mesh| id.submesh < mesh.submeshes.len())
+    }
+
+    /// A tree over the bounds of every submesh in world space. Kept between
+    /// calls, and refit only if a node changed since the last one.
+    pub fn submesh_bvh(&self) -> Ref<'_, Bvh<SubmeshId>> {
+        let revision = self.scene_graph.revision();
+        {
+            let mut cached = self.submesh_bvh.borrow_mut();
+            match &mut *cached {
+                Some((bvh, fitted)) => {
+                    if *fitted != revision {
+                        bvh.refit(|&id| self.world_submesh_bounds(id));
+                        *fitted = revision;
+                    }
+                }
+                None => *cached = Some((self.build_submesh_bvh(), revision)),
+            }
+        }
+        Ref::map(self.submesh_bvh.borrow(), |cached| {
+            &cached.as_ref().unwrap().0
+        })
     }
 
-    pub fn current_camera(&self) -> &Camera {
+    /// Drops the tree of `submesh_bvh`, to build it again over the submeshes
+    /// the models have now.
+    pub(crate) fn invalidate_submesh_bvh(&mut self) {
+        *self.submesh_bvh.get_mut() = None;
+    }
+
+    fn build_submesh_bvh(&self) -> Bvh<SubmeshId> {
+        let mut items = vec![];
+        for (model_index, model) in self.models.iter().enumerate() {
+            for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+                for submesh_index in 0..mesh.submeshes.len() {
+                    let id = SubmeshId {
+                        model: model_index,
+                        mesh: mesh_index,
+                        submesh: submesh_index,
+                    };
+                    items.push((self.world_submesh_bounds(id), id));
+                }
+            }
+        }
+        Bvh::new(items)
+    }
+
+    fn world_submesh_bounds(&self, id: SubmeshId) -> Bounds {
+        let mesh = &self.models[id.model].meshes[id.mesh];
+        mesh.submesh_bounds(id.submesh, self.scene_graph.world_matrix(mesh.node()))
+    }
+
+    /// The closest triangle of any model `ray` hits, in world space.
+    pub fn pick(&self, ray: &Ray) -> Option<Pick> {
+        let closest = Cell::new(f32::INFINITY);
+        let mut pick = None;
+        self.submesh_bvh().query(
+            |bounds| match ray.intersect_bounds(bounds) {
+                Some(t) if t <= closest.get() => Overlap::Partial,
+                _ => Overlap::Outside,
+            },
+            |_, &id| {
+                let model = &self.models[id.model];
+                let mesh = &model.meshes[id.mesh];
+                let submesh = &mesh.submeshes[id.submesh];
+                let world_matrix = self.scene_graph.world_matrix(mesh.node());
+                let placements: Vec<(Option<usize>, Mat4)> = match mesh.instances.transforms() {
+                    [] => vec![(None, world_matrix)],
+                    transforms => transforms
+                        .iter()
+                        .enumerate()
+                        .map(|(instance, &transform)| (Some(instance), world_matrix * transform))
+                        .collect(),
+                };
+                for (instance, matrix) in placements {
+                    // the ray keeps its parameter in the space of the mesh
+                    let local_ray = ray.transform(matrix.inverse());
+                    if let Some(hit) = submesh.triangles.intersect(&local_ray, closest.get()) {
+                        closest.set(hit.t);
+                        pick = Some(Pick {
+                            submesh: id,
+                            node: mesh.node(),
+                            instance,
+                            triangle: hit.triangle,
+                            material: model.submesh_material(id.mesh, id.submesh),
+                            barycentrics: hit.barycentrics,
+                            position: ray.at(hit.t),
+                            distance: hit.t * ray.direction.length(),
+                        });
+                    }
+                }
+            },
+        );
+        pick
+    }
+
+    /// The root node of a model.
+    pub fn model_node(&self, model_index: usize) -> Option<NodeId> {
+        self.models.get(model_index).map(Model::node)
+    }
+
+    /// Adds a model that finished loading. If the scene file placed it, its
+    /// root node takes that transform and the cameras stay where the file
+    /// put them; otherwise the first model is framed.
+    pub(crate) fn add_model(&mut self, model: Model) {
+        let pending = self
+            .pending_models
+            .iter()
+            .position(|pending| pending.name == *model.name());
+        if let Some(index) = pending {
+            let pending = self.pending_models.remove(index);
+            if let Some(transform) = pending.transform {
+                if let Some(local) = self.scene_graph.local_mut(model.node()) {
+                    *local = transform.inner_node(model.name());
+                }
+            }
+            self.models.push(model);
+            self.invalidate_submesh_bvh();
+            return;
+        }
+        self.models.push(model);
+        self.invalidate_submesh_bvh();
+        if self.models.len() == 1 {
+            self.frame_all();
+        }
+    }
+
+    /// Forgets a model of the scene file that could not be loaded.
+    pub(crate) fn drop_pending_model(&mut self, name: &str) {
+        if let Some(index) = self
+            .pending_models
+            .iter()
+            .position(|pending| pending.name == name)
+        {
+            self.pending_models.remove(index);
+        }
+    }
+
+    /// Takes a model and its nodes, with everything attached to them, out of
+    /// the scene. Its textures are still held; `Renderer::remove_model`
+    /// releases them.
+    pub(crate) fn remove_model(&mut self, model_index: usize) -> Model {
+        let model = self.models.remove(model_index);
+        self.scene_graph.remove(model.node());
+        self.invalidate_submesh_bvh();
+        // the models after it move down by one
+        self.selection = self
+            .selection
+            .iter()
+            .filter(|id| id.model != model_index)
+            .map(|&id| SubmeshId {
+                model: if id.model > model_index {
+                    id.model - 1
+                } else {
+                    id.model
+                },
+                ..id
+            })
+            .collect();
+        model
+    }
+
+    /// Puts `model` in place of the model at `model_index`, which it is a
+    /// reload of, and returns the previous one. The new model takes the place
+    /// of the previous one in the graph, along with what was attached to it.
+    pub(crate) fn replace_model(&mut self, model_index: usize, model: Model) -> Model {
+        let node = model.node();
+        let previous = std::mem::replace(&mut self.models[model_index], model);
+        let previous_node = match self.scene_graph.get(previous.node()) {
+            Some(previous_node) => previous_node,
+            None => return previous,
+        };
+        let parent = previous_node.parent();
+        let local = previous_node.local().clone();
+        let attached: Vec<NodeId> = previous_node
+            .children()
+            .iter()
+            .copied()
+            .filter(|&child| !previous.owns_node(child))
+            .collect();
+
+        if let Some(parent) = parent {
+            self.scene_graph
+                .set_parent(node, Some(parent))
+                .expect("the parent of a model cannot be in the new model");
+        }
+        if let Some(node) = self.scene_graph.local_mut(node) {
+            *node = local;
+        }
+        for child in attached {
+            self.scene_graph
+                .set_parent(child, Some(node))
+                .expect("a node attached to a model cannot contain the new model");
+        }
+        self.scene_graph.remove(previous.node());
+        self.invalidate_submesh_bvh();
+        // the reloaded model may have fewer meshes or submeshes
+        let selection = std::mem::take(&mut self.selection);
+        self.selection = selection
+            .into_iter()
+            .filter(|&id| self.contains_submesh(id))
+            .collect();
+        previous
+    }
+
+    /// The camera the scene is drawn from.
+    pub fn camera(&self) -> &ArcballCamera {
         &self.cameras[self.current_camera_index]
     }
+
+    /// The node of the current camera is moved along on the next
+    /// `Renderer::update`.
+    pub fn camera_mut(&mut self) -> &mut ArcballCamera {
+        &mut self.cameras[self.current_camera_index]
+    }
+
+    /// Adds a camera with a node of its own. Returns its index.
+    pub fn add_camera(&mut self, camera: ArcballCamera) -> usize {
+        let index = self.cameras.len();
+        let node = self.scene_graph.add(
+            InnerNode::named(&format!("camera {}", index)),
+            NodeContent::Camera,
+            None,
+        );
+        self.cameras.push(camera);
+        self.camera_nodes.push(node);
+        self.sync_camera_nodes();
+        index
+    }
+
+    pub fn cameras(&self) -> &[ArcballCamera] {
+        &self.cameras
+    }
+
+    pub fn current_camera_index(&self) -> usize {
+        self.current_camera_index
+    }
+
+    /// Draws the scene from the camera at `index`. Returns `false` if there
+    /// is no such camera.
+    pub fn set_current_camera(&mut self, index: usize) -> bool {
+        if index >= self.cameras.len() {
+            return false;
+        }
+        self.current_camera_index = index;
+        true
+    }
+
+    /// The node of the camera at `index`. It follows the camera, so moving it
+    /// has no effect, but nodes can be attached to it.
+    pub fn camera_node(&self, index: usize) -> Option<NodeId> {
+        self.camera_nodes.get(index).copied()
+    }
+
+    /// The node placing the light at `index`.
+    pub fn light_node(&self, index: usize) -> Option<NodeId> {
+        self.lighting.nodes.get(index).copied()
+    }
+
+    /// Points the current camera at the center of every model, far enough
+    /// back to see all of them.
+    pub fn frame_all(&mut self) {
+        let camera = &mut self.cameras[self.current_camera_index];
+        camera.frame_all(&self.models, &self.scene_graph);
+    }
+
+    /// Like `frame_all`, for the models at the indices in `selection`.
+    pub fn frame_selection(&mut self, selection: &[usize]) {
+        let camera = &mut self.cameras[self.current_camera_index];
+        camera.frame_selection(&self.models, &self.scene_graph, selection);
+    }
+
+    /// Names of the material variants of every model.
+    pub fn material_variants(&self) -> Vec<String> {
+        let mut variants: Vec<String> = vec![];
+        for model in self.models.iter() {
+            for variant in model.variants() {
+                if !variants.contains(variant) {
+                    variants.push(variant.clone());
+                }
+            }
+        }
+        variants
+    }
+
+    /// Selects the material variant called `name` on every model that has it.
+    /// Returns `false` if no model has such a variant.
+    pub fn select_material_variant(&mut self, name: &str) -> bool {
+        let mut selected = false;
+        for model in self.models.iter_mut() {
+            selected |= model.select_variant(name);
+        }
+        selected
+    }
+
+    pub fn clear_material_variant(&mut self) {
+        for model in self.models.iter_mut() {
+            model.clear_variant();
+        }
+    }
+
+    /// Moves the node of each camera to where the camera is. Nodes of
+    /// cameras that did not move are left alone, so the scene graph revision
+    /// only changes when something moved.
+    pub(crate) fn sync_camera_nodes(&mut self) {
+        for (camera, &node) in self.cameras.iter().zip(self.camera_nodes.iter()) {
+            let matrix = camera.view_matrix().inverse();
+            let moved = self
+                .scene_graph
+                .get(node)
+                .is_some_and(|node| !node.local().model_matrix().abs_diff_eq(matrix, 1e-4));
+            if moved {
+                if let Some(local) = self.scene_graph.local_mut(node) {
+                    local.set_matrix(matrix);
+                }
+            }
+        }
+    }
 }
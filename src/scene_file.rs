@@ -0,0 +1,459 @@
+use crate::import_options::ImportOptions;
+use crate::node::InnerNode;
+use glam::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The version written to new scene files. Files from a newer version are
+/// rejected rather than loaded without what they added.
+pub const SCENE_FILE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneFileError::Io(error) => write!(f, "{}", error),
+            SceneFileError::Json(error) => write!(f, "{}", error),
+            SceneFileError::UnsupportedVersion(version) => write!(
+                f,
+                "scene file version {} is newer than version {}",
+                version, SCENE_FILE_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+impl From<std::io::Error> for SceneFileError {
+    fn from(error: std::io::Error) -> Self {
+        SceneFileError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SceneFileError {
+    fn from(error: serde_json::Error) -> Self {
+        SceneFileError::Json(error)
+    }
+}
+
+/// Everything a scene is built from, as stored in a scene file: the models
+/// with their placement, the lights, the cameras, the environment and the
+/// render settings. Fields left out of a file take their default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SceneDescription {
+    pub version: u32,
+    #[serde(default)]
+    pub models: Vec<ModelDescription>,
+    #[serde(default = "default_lights")]
+    pub lights: Vec<LightDescription>,
+    #[serde(default = "default_cameras")]
+    pub cameras: Vec<CameraDescription>,
+    #[serde(default)]
+    pub current_camera: usize,
+    /// `null` draws no skybox.
+    #[serde(default = "default_environment")]
+    pub environment: Option<EnvironmentDescription>,
+    #[serde(default)]
+    pub settings: RenderSettings,
+}
+
+impl Default for SceneDescription {
+    /// No models, with the default lights, camera and environment.
+    fn default() -> Self {
+        Self {
+            version: SCENE_FILE_VERSION,
+            models: vec![],
+            lights: default_lights(),
+            cameras: default_cameras(),
+            current_camera: 0,
+            environment: default_environment(),
+            settings: RenderSettings::default(),
+        }
+    }
+}
+
+impl SceneDescription {
+    pub fn open(path: &Path) -> Result<Self, SceneFileError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SceneFileError> {
+        let mut json = self.to_json()?;
+        json.push('\n');
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SceneFileError> {
+        let description: SceneDescription = serde_json::from_str(json)?;
+        if description.version > SCENE_FILE_VERSION {
+            return Err(SceneFileError::UnsupportedVersion(description.version));
+        }
+        Ok(description)
+    }
+
+    /// Pretty printed, so changes to a scene read well in a diff.
+    pub fn to_json(&self) -> Result<String, SceneFileError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// A model file under `assets/models`, loaded as `Renderer::load_model`
+/// does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelDescription {
+    pub name: String,
+    #[serde(default = "default_tiling")]
+    pub tiling: u32,
+    /// Replaces the transform of the root node of the model, which otherwise
+    /// keeps the one from the model file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<TransformDescription>,
+    #[serde(default)]
+    pub import_options: ImportOptions,
+}
+
+impl ModelDescription {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            tiling: default_tiling(),
+            transform: None,
+            import_options: ImportOptions::default(),
+        }
+    }
+}
+
+/// A translation, a rotation quaternion as `[x, y, z, w]` and a scale, as in
+/// glTF nodes.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformDescription {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for TransformDescription {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0; 3],
+        }
+    }
+}
+
+impl TransformDescription {
+    pub fn inner_node(&self, name: &str) -> InnerNode {
+        InnerNode::new(
+            name.to_string(),
+            Vec3::from(self.translation),
+            Quat::from_array(self.rotation),
+            Vec3::from(self.scale),
+        )
+    }
+}
+
+impl From<&InnerNode> for TransformDescription {
+    fn from(inner_node: &InnerNode) -> Self {
+        Self {
+            translation: inner_node.position().to_array(),
+            rotation: inner_node.rotation().to_array(),
+            scale: inner_node.scale().to_array(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LightKind {
+    Sun,
+    Spot,
+    Point,
+    Ambient,
+}
+
+/// A light as the shaders see it, placed at `position` in world space.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LightDescription {
+    pub kind: LightKind,
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    pub specular_color: [f32; 3],
+    pub intensity: f32,
+    /// Constant, linear and quadratic falloff of point and spot lights.
+    pub attenuation: [f32; 3],
+    pub cone_angle: f32,
+    pub cone_direction: [f32; 3],
+    pub cone_attenuation: f32,
+}
+
+impl Default for LightDescription {
+    /// A white sun above the origin.
+    fn default() -> Self {
+        Self {
+            kind: LightKind::Sun,
+            position: [0.0; 3],
+            color: [1.0; 3],
+            specular_color: [1.0; 3],
+            intensity: 0.6,
+            attenuation: [1.0, 0.0, 0.0],
+            cone_angle: 0.0,
+            cone_direction: [0.0; 3],
+            cone_attenuation: 0.0,
+        }
+    }
+}
+
+/// An orbiting camera. Angles are in degrees.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CameraDescription {
+    pub target: [f32; 3],
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub fov: f32,
+}
+
+impl Default for CameraDescription {
+    fn default() -> Self {
+        Self {
+            target: [0.0; 3],
+            distance: 3.5,
+            min_distance: 0.5,
+            max_distance: 10.0,
+            pitch: 0.0,
+            yaw: 0.0,
+            fov: 70.0,
+        }
+    }
+}
+
+/// The maps lighting and surrounding the scene, under `assets/environments`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EnvironmentDescription {
+    /// A KTX2 cube map with its prefiltered mips. When the file does not
+    /// exist, the faces are read from `{face}.jpg` and the mips from
+    /// `specular/{level}/specular-{level}-{face}.png` next to it.
+    pub cube_map: String,
+    /// The six faces of the diffuse irradiance stacked vertically.
+    pub irradiance_map: String,
+}
+
+impl Default for EnvironmentDescription {
+    fn default() -> Self {
+        Self {
+            cube_map: "sea/environment.ktx2".to_string(),
+            irradiance_map: "venice_sunset/irradiance-6.png".to_string(),
+        }
+    }
+}
+
+impl EnvironmentDescription {
+    pub fn cube_map_path(&self) -> PathBuf {
+        environment_path(&self.cube_map)
+    }
+
+    pub fn irradiance_map_path(&self) -> PathBuf {
+        environment_path(&self.irradiance_map)
+    }
+
+    /// Everything the maps are loaded from, to watch for changes.
+    pub fn directories(&self) -> Vec<PathBuf> {
+        let mut directories = vec![];
+        for path in [self.cube_map_path(), self.irradiance_map_path()] {
+            if let Some(directory) = path.parent() {
+                if !directories.iter().any(|existing| existing == directory) {
+                    directories.push(directory.to_path_buf());
+                }
+            }
+        }
+        directories
+    }
+}
+
+/// Settings of the renderer that change how a scene looks.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RenderSettings {
+    /// How far, in pixels, a simplified level of detail may stray from the
+    /// full mesh on screen before a finer level is drawn. Zero always draws
+    /// the full meshes.
+    pub lod_threshold: f32,
+    pub outline: OutlineSettings,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            lod_threshold: 1.0,
+            outline: OutlineSettings::default(),
+        }
+    }
+}
+
+/// How the outline around the selected submeshes looks.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutlineSettings {
+    /// Linear RGBA.
+    pub color: [f32; 4],
+    /// In pixels.
+    pub width: f32,
+}
+
+impl Default for OutlineSettings {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 0.5, 0.0, 1.0],
+            width: 2.0,
+        }
+    }
+}
+
+fn environment_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("assets/environments")
+        .join(name)
+}
+
+fn default_tiling() -> u32 {
+    1
+}
+
+/// A sun in front of and above the origin, and a faint ambient light.
+fn default_lights() -> Vec<LightDescription> {
+    vec![
+        LightDescription {
+            position: [0.4, 1.0, -2.0],
+            ..LightDescription::default()
+        },
+        LightDescription {
+            kind: LightKind::Ambient,
+            intensity: 0.1,
+            ..LightDescription::default()
+        },
+    ]
+}
+
+fn default_cameras() -> Vec<CameraDescription> {
+    vec![CameraDescription::default()]
+}
+
+fn default_environment() -> Option<EnvironmentDescription> {
+    Some(EnvironmentDescription::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import_options::{Unit, UpAxis};
+    use crate::lod::LodSettings;
+    use crate::mesh_optimizer::MeshOptimization;
+    use crate::meshlet::MeshletSettings;
+
+    fn helmet_scene() -> SceneDescription {
+        let mut description = SceneDescription::default();
+        let mut helmet = ModelDescription::new("DamagedHelmet/DamagedHelmet.gltf");
+        helmet.import_options = ImportOptions::default()
+            .with_optimization(MeshOptimization::default())
+            .with_meshlets(MeshletSettings::default())
+            .with_lods(LodSettings::default());
+        description.models.push(helmet);
+        description.cameras[0].yaw = 160.0;
+        description
+    }
+
+    #[test]
+    fn round_trip_is_exact() {
+        let mut description = helmet_scene();
+        let node = InnerNode::new(
+            "x".to_string(),
+            Vec3::new(0.1, -2.3, 1e-7),
+            Quat::from_rotation_y(0.7) * Quat::from_rotation_x(1.3),
+            Vec3::new(0.3, 0.3, 0.3),
+        );
+        let mut other = ModelDescription::new("Sponza/Sponza.gltf");
+        other.tiling = 4;
+        other.transform = Some(TransformDescription::from(&node));
+        other.import_options = ImportOptions::new(UpAxis::Z, Unit::Custom(0.123), true);
+        description.models.push(other);
+        description.lights.push(LightDescription {
+            kind: LightKind::Spot,
+            cone_angle: 0.3,
+            cone_direction: [0.0, -1.0, 0.0],
+            ..LightDescription::default()
+        });
+        description.cameras.push(CameraDescription {
+            pitch: -12.5,
+            ..CameraDescription::default()
+        });
+        description.current_camera = 1;
+        description.environment = None;
+        description.settings.lod_threshold = 0.25;
+
+        let json = description.to_json().unwrap();
+        let read = SceneDescription::from_json(&json).unwrap();
+        assert_eq!(read, description);
+        assert_eq!(read.to_json().unwrap(), json);
+
+        let transform = read.models[1].transform.unwrap().inner_node("x");
+        assert_eq!(transform.model_matrix(), node.model_matrix());
+    }
+
+    #[test]
+    fn missing_fields_take_defaults() {
+        let read =
+            SceneDescription::from_json(r#"{ "version": 1, "models": [{ "name": "Box.gltf" }] }"#)
+                .unwrap();
+        let mut expected = SceneDescription::default();
+        expected.models.push(ModelDescription::new("Box.gltf"));
+        assert_eq!(read, expected);
+
+        let read = SceneDescription::from_json(
+            r#"{ "version": 1, "environment": null, "lights": [{ "kind": "point", "intensity": 2.0 }],
+                 "models": [{ "name": "Box.gltf", "importOptions": { "unit": "centimeters", "lod": {} } }] }"#,
+        )
+        .unwrap();
+        assert_eq!(read.environment, None);
+        assert_eq!(read.lights[0].kind, LightKind::Point);
+        assert_eq!(read.lights[0].color, [1.0; 3]);
+        assert_eq!(read.models[0].import_options.unit, Unit::Centimeters);
+        assert_eq!(
+            read.models[0].import_options.lod,
+            Some(LodSettings::default())
+        );
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let error = SceneDescription::from_json(r#"{ "version": 2 }"#).unwrap_err();
+        assert!(matches!(error, SceneFileError::UnsupportedVersion(2)));
+        assert!(SceneDescription::from_json(r#"{ "models": [] }"#).is_err());
+    }
+
+    #[test]
+    fn save_and_open() {
+        let path = std::env::temp_dir().join("scene_file_round_trip.json");
+        let description = helmet_scene();
+        description.save(&path).unwrap();
+        assert_eq!(SceneDescription::open(&path).unwrap(), description);
+        std::fs::remove_file(path).unwrap();
+    }
+}
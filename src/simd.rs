@@ -0,0 +1,65 @@
+//! Conversions between glam types and the simd types of the shader bindings.
+//!
+//! glam matrices are column-major with 16-byte aligned columns, the layout of
+//! `simd_float4x4` and `simd_float3x3`. `Vec4` and `Vec2` are packed floats
+//! like `simd_float4` and `simd_float2`, and `Vec3A` is three floats padded to
+//! 16 bytes like `simd_float3`. `transmute` checks that the sizes match.
+
+use crate::shader_bindings::{
+    matrix_float3x3, matrix_float4x4, vector_float2, vector_float3, vector_float4,
+};
+use glam::{Mat3A, Mat4, Vec2, Vec3A, Vec4};
+use std::mem;
+
+/// Defines `pub fn name(value: From) -> To` for each pair of types that share
+/// a layout.
+macro_rules! conversions {
+    ($($name:ident: $from:ty => $to:ty;)*) => {
+        $(
+            pub fn $name(value: $from) -> $to {
+                // SAFETY: the two types have the same layout, as listed above.
+                unsafe { mem::transmute::<$from, $to>(value) }
+            }
+        )*
+    };
+}
+
+conversions! {
+    matrix4: Mat4 => matrix_float4x4;
+    matrix3: Mat3A => matrix_float3x3;
+    vector4: Vec4 => vector_float4;
+    vector3: Vec3A => vector_float3;
+    vector2: Vec2 => vector_float2;
+    mat4: matrix_float4x4 => Mat4;
+    vec4: vector_float4 => Vec4;
+    vec3a: vector_float3 => Vec3A;
+}
+
+/// The model matrix and the normal matrix for `transform`.
+pub fn model_and_normal_matrix(transform: Mat4) -> (matrix_float4x4, matrix_float3x3) {
+    (matrix4(transform), matrix3(Mat3A::from_mat4(transform)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    #[test]
+    fn conversions_round_trip() {
+        let transform = Mat4::from_scale_rotation_translation(
+            Vec3::new(1.0, 2.0, 3.0),
+            glam::Quat::from_rotation_y(0.5),
+            Vec3::new(4.0, 5.0, 6.0),
+        );
+        assert_eq!(mat4(matrix4(transform)), transform);
+        assert_eq!(mat4(model_and_normal_matrix(transform).0), transform);
+
+        let vector = Vec4::new(1.0, 2.0, 3.0, 4.0);
+        assert_eq!(vec4(vector4(vector)), vector);
+        assert_eq!(
+            vec3a(vector3(vector.truncate().into())),
+            Vec3A::new(1.0, 2.0, 3.0)
+        );
+    }
+}
@@ -1,15 +1,24 @@
 use crate::model::{Model, Submesh};
+use crate::node::SceneGraph;
+use crate::scene_file::EnvironmentDescription;
 use crate::shader_bindings::{
     Attributes_Bitangent, Attributes_Normal, Attributes_Position, Attributes_Tangent,
     Attributes_UV, BufferIndices_BufferIndexSkybox as BufferIndexSkybox, Textures_BRDFLut,
     Textures_CubeMap, Textures_CubeMapDiffuse, Uniforms,
 };
+use crate::{
+    ktx::KtxTexture,
+    renderer::COLOR_PIXEL_FORMAT,
+    simd, texturable,
+    texture_bake::TextureCompression,
+    texture_cache::TextureCache,
+    texture_data::{ColorSpace, TextureData},
+};
 use glam::{Mat4, Vec4};
-use image;
-use image::{error::ImageResult, GenericImageView};
+use image::error::{
+    ImageError, ImageFormatHint, ImageResult, UnsupportedError, UnsupportedErrorKind,
+};
 use metal::*;
-use std::fs::File;
-use std::io::BufReader;
 use std::mem;
 use std::path::Path;
 
@@ -20,6 +29,7 @@ pub struct Skybox {
     cube_map: Option<Texture>,
     irradiance_map: Option<Texture>,
     brdf_lut: Option<Texture>,
+    environment: EnvironmentDescription,
     pipeline_state: RenderPipelineState,
     depth_stencil_state: DepthStencilState,
 }
@@ -30,12 +40,25 @@ impl Skybox {
         // environment_library: &Library,
         device: &Device,
         brdf_lut: Option<Texture>,
+        texture_cache: &mut TextureCache<Texture>,
+        environment: EnvironmentDescription,
     ) -> Self {
-        let model = Model::from_gltf_filename("cube.gltf", 1, device, library);
+        // Only the geometry of the cube is kept, so its nodes are not part of
+        // the scene.
+        let model = Model::from_gltf_filename(
+            "cube.gltf",
+            1,
+            device,
+            library,
+            texture_cache,
+            &mut SceneGraph::new(),
+        );
+        model.release_textures(texture_cache);
         let pipeline_state = Self::build_pipeline_state(library, device);
         let depth_stencil_state = Self::build_depth_stencil_state(device);
-        let cube_map = Self::load_cube_map(device).ok();
-        let irradiance_map = Self::load_irradiance_map(device).ok();
+        let cube_map = Self::load_cube_map(device, &environment.cube_map_path()).ok();
+        let irradiance_map =
+            Self::load_irradiance_map(device, &environment.irradiance_map_path()).ok();
 
         let Submesh {
             vertex_buffer,
@@ -47,15 +70,34 @@ impl Skybox {
         Self {
             vertex_buffer: vertex_buffer.clone(),
             index_buffer: index_buffer.clone(),
-            num_elements: num_elements.clone(),
+            num_elements: *num_elements,
             cube_map,
             irradiance_map,
             brdf_lut,
+            environment,
             pipeline_state,
             depth_stencil_state,
         }
     }
 
+    /// The maps the skybox was loaded from.
+    pub fn environment(&self) -> &EnvironmentDescription {
+        &self.environment
+    }
+
+    /// Loads the cube map and the irradiance map again. A map that fails to
+    /// load keeps its previous texture.
+    pub fn reload_environment(&mut self, device: &Device) {
+        match Self::load_cube_map(device, &self.environment.cube_map_path()) {
+            Ok(cube_map) => self.cube_map = Some(cube_map),
+            Err(error) => log::warn!("unable to reload cube map: {}", error),
+        }
+        match Self::load_irradiance_map(device, &self.environment.irradiance_map_path()) {
+            Ok(irradiance_map) => self.irradiance_map = Some(irradiance_map),
+            Err(error) => log::warn!("unable to reload irradiance map: {}", error),
+        }
+    }
+
     pub fn render(
         &self,
         render_encoder: &RenderCommandEncoderRef,
@@ -67,11 +109,11 @@ impl Skybox {
         render_encoder.set_depth_stencil_state(&self.depth_stencil_state);
         render_encoder.set_vertex_buffer(BufferIndexSkybox as u64, Some(&self.vertex_buffer), 0);
 
-        let mut view_matrix: Mat4 = unsafe { mem::transmute(uniforms[0].viewMatrix) };
+        let mut view_matrix = simd::mat4(uniforms[0].viewMatrix);
+        let mut projection_matrix = simd::mat4(uniforms[0].projectionMatrix);
         let col3 = view_matrix.col_mut(3);
         *col3 = Vec4::new(0.0, 0.0, 0.0, 1.0);
-        let mut projection_matrix: Mat4 = unsafe { mem::transmute(uniforms[0].projectionMatrix) };
-        projection_matrix = projection_matrix * view_matrix;
+        projection_matrix *= view_matrix;
 
         render_encoder.set_vertex_bytes(
             1,
@@ -87,7 +129,7 @@ impl Skybox {
 
         render_encoder.set_fragment_texture(
             Textures_CubeMap as u64,
-            Some(&self.cube_map.as_ref().unwrap()),
+            Some(self.cube_map.as_ref().unwrap()),
         );
 
         render_encoder.draw_indexed_primitives(
@@ -101,16 +143,16 @@ impl Skybox {
 
     pub fn update(&self, render_encoder: &RenderCommandEncoderRef) {
         if let Some(cube_map) = &self.cube_map {
-            render_encoder.set_fragment_texture(Textures_CubeMap as u64, Some(&cube_map));
+            render_encoder.set_fragment_texture(Textures_CubeMap as u64, Some(cube_map));
         }
 
         if let Some(irradiance_map) = &self.irradiance_map {
             render_encoder
-                .set_fragment_texture(Textures_CubeMapDiffuse as u64, Some(&irradiance_map));
+                .set_fragment_texture(Textures_CubeMapDiffuse as u64, Some(irradiance_map));
         }
 
         if let Some(brdf_lut) = &self.brdf_lut {
-            render_encoder.set_fragment_texture(Textures_BRDFLut as u64, Some(&brdf_lut));
+            render_encoder.set_fragment_texture(Textures_BRDFLut as u64, Some(brdf_lut));
         }
     }
 
@@ -185,13 +227,13 @@ impl Skybox {
         let pipeline_state_descriptor = RenderPipelineDescriptor::new();
         pipeline_state_descriptor.set_vertex_function(Some(&vertex_function));
         pipeline_state_descriptor.set_fragment_function(Some(&fragment_function));
-        pipeline_state_descriptor.set_vertex_descriptor(Some(&vertex_descriptor));
+        pipeline_state_descriptor.set_vertex_descriptor(Some(vertex_descriptor));
         pipeline_state_descriptor.set_depth_attachment_pixel_format(MTLPixelFormat::Depth32Float);
         pipeline_state_descriptor
             .color_attachments()
             .object_at(0)
             .unwrap()
-            .set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+            .set_pixel_format(COLOR_PIXEL_FORMAT);
 
         device
             .new_render_pipeline_state(&pipeline_state_descriptor)
@@ -205,221 +247,160 @@ impl Skybox {
         device.new_depth_stencil_state(&descriptor)
     }
 
-    fn load_cube_map(device: &Device) -> ImageResult<Texture> {
+    /// Loads the KTX2 file at `path`, or the separate images next to it when
+    /// it does not exist.
+    fn load_cube_map(device: &Device, path: &Path) -> ImageResult<Texture> {
         println!("Load cube map");
+
+        if path.exists() {
+            return Self::load_ktx2_cube_map(
+                device,
+                &KtxTexture::open(path, TextureCompression::None)?,
+            );
+        }
+        let directory = path.parent().unwrap_or_else(|| Path::new("./"));
+
+        // Load HDR equirectangular texture
+        // let hdr_data =
+        //     TextureData::open("assets/environments/venice_sunset/venice_sunset_4k.hdr").unwrap();
+
+        let cubemaps = ["right", "left", "top", "bottom", "front", "back"];
+        let mut faces = vec![];
+        for map in cubemaps.iter() {
+            let path = directory.join(format!("{}.jpg", map));
+
+            let face = TextureData::open(path)?.into_color_space(ColorSpace::Srgb);
+            println!(
+                "dimensions: {}x{} {:?}",
+                face.width, face.height, face.format
+            );
+            faces.push(face);
+        }
+
+        // Every face and mip level has to share the format of the first face.
+        let format = faces[0].format;
+
         let texture_descriptor = TextureDescriptor::new();
         texture_descriptor.set_storage_mode(MTLStorageMode::Shared);
-        texture_descriptor.set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+        texture_descriptor.set_pixel_format(texturable::pixel_format(format));
         texture_descriptor.set_texture_type(MTLTextureType::Cube);
-        // texture_descriptor.set_width(width as u64);
-        // texture_descriptor.set_height(height as u64);
-        texture_descriptor.set_width(2048);
-        texture_descriptor.set_height(2048);
+        texture_descriptor.set_width(faces[0].width as u64);
+        texture_descriptor.set_height(faces[0].height as u64);
         texture_descriptor.set_mipmap_level_count(9);
-        // texture_descriptor.set_mipmap_level_count_for_size(MTLSize {
-        //     width: width as u64,
-        //     height: height as u64,
-        //     depth: 1,
-        // });
         let texture = device.new_texture(&texture_descriptor);
 
-        // Load HDR equirectangular texture
-        // let hdr_file =
-        //     File::open("assets/environments/venice_sunset/venice_sunset_4k.hdr").unwrap();
-        // let hdr_file = BufReader::new(hdr_file);
-        // let hdr_decoder = image::hdr::HdrDecoder::new(hdr_file).unwrap();
-        // let hdr_metadata = hdr_decoder.metadata();
-
-        // let hdr_pixels = hdr_decoder.read_image_hdr().unwrap();
-
-        // let mut hdr_pixels_raw = Vec::new();
-        // for pixel in hdr_pixels {
-        //     hdr_pixels_raw.push(pixel[0]);
-        //     hdr_pixels_raw.push(pixel[1]);
-        //     hdr_pixels_raw.push(pixel[2]);
-        //     hdr_pixels_raw.push(1.0);
-        // }
-
-        let cubemaps = ["right", "left", "top", "bottom", "front", "back"];
-        for (i, map) in cubemaps.iter().enumerate() {
-            let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-                .join(format!("assets/environments/sea/{}.jpg", map));
-
-            let img = image::open(path).unwrap();
-            let (width, height) = img.dimensions();
-            println!("dimensions: {}x{}", width, height);
-
-            let region = MTLRegion::new_2d(0, 0, width as u64, height as u64);
-
-            let image_scale = 1;
-            let cube_size = width * image_scale;
-            let bytes_per_pixel = 4;
-            let bytes_per_row = bytes_per_pixel * cube_size;
-            let bytes_per_image = bytes_per_row * cube_size;
-
-            let mut new_buf: Vec<u8> = vec![];
-
-            match img {
-                image::DynamicImage::ImageRgb8(img) => {
-                    println!("Image RGB 8");
-                    for pixel in img.pixels() {
-                        new_buf.push(pixel[2]);
-                        new_buf.push(pixel[1]);
-                        new_buf.push(pixel[0]);
-                        // new_buf.push(pixel[0]);
-                        // new_buf.push(pixel[1]);
-                        // new_buf.push(pixel[2]);
-                        new_buf.push(255);
-                    }
-                }
-                image::DynamicImage::ImageRgba8(img) => {
-                    println!("Image RGBA 8");
-                    for pixel in img.pixels() {
-                        new_buf.push(pixel[2]);
-                        new_buf.push(pixel[1]);
-                        new_buf.push(pixel[0]);
-                        // new_buf.push(pixel[0]);
-                        // new_buf.push(pixel[1]);
-                        // new_buf.push(pixel[2]);
-                        new_buf.push(pixel[3]);
-                    }
-                }
-                _ => {
-                    todo!()
-                }
-            }
+        for (i, (map, face)) in cubemaps.iter().zip(faces.iter()).enumerate() {
+            let face = face.convert(format);
+            let region = MTLRegion::new_2d(0, 0, face.width as u64, face.height as u64);
 
             texture.replace_region_in_slice(
                 region,
                 0,
                 i as u64,
-                new_buf.as_ptr() as _,
-                bytes_per_row as u64,
-                bytes_per_image as u64,
+                face.data.as_ptr() as _,
+                face.bytes_per_row(),
+                face.bytes_per_image(),
             );
 
             for mipmap_level in 1..=8 {
-                let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!(
-                    "assets/environments/sea/specular/{}/specular-{}-{}.png",
+                let path = directory.join(format!(
+                    "specular/{}/specular-{}-{}.png",
                     mipmap_level, mipmap_level, map
                 ));
 
-                let img = image::open(path)?;
-                let (width, height) = img.dimensions();
-                println!("dimensions: {}x{}", width, height);
-
-                let region = MTLRegion::new_2d(0, 0, width as u64, height as u64);
-
-                let image_scale = 1;
-                let cube_size = width * image_scale;
-                let bytes_per_pixel = 4;
-                let bytes_per_row = bytes_per_pixel * cube_size;
-                let bytes_per_image = bytes_per_row * cube_size;
-
-                let mut new_buf: Vec<u8> = vec![];
-
-                match img {
-                    image::DynamicImage::ImageRgb8(img) => {
-                        for pixel in img.pixels() {
-                            new_buf.push(pixel[2]);
-                            new_buf.push(pixel[1]);
-                            new_buf.push(pixel[0]);
-                            new_buf.push(255);
-                        }
-                    }
-                    image::DynamicImage::ImageRgba8(img) => {
-                        for pixel in img.pixels() {
-                            new_buf.push(pixel[2]);
-                            new_buf.push(pixel[1]);
-                            new_buf.push(pixel[0]);
-                            new_buf.push(pixel[3]);
-                        }
-                    }
-                    _ => {
-                        todo!()
-                    }
-                }
+                let mip = TextureData::open(path)?
+                    .into_color_space(ColorSpace::Srgb)
+                    .convert(format);
+                println!("dimensions: {}x{}", mip.width, mip.height);
+
+                let region = MTLRegion::new_2d(0, 0, mip.width as u64, mip.height as u64);
 
                 texture.replace_region_in_slice(
                     region,
                     mipmap_level as u64,
                     i as u64,
-                    new_buf.as_ptr() as _,
-                    bytes_per_row as u64,
-                    bytes_per_image as u64,
+                    mip.data.as_ptr() as _,
+                    mip.bytes_per_row(),
+                    mip.bytes_per_image(),
                 );
             }
         }
         Ok(texture)
     }
 
-    fn load_irradiance_map(device: &Device) -> ImageResult<Texture> {
-        let each_size = 128;
+    /// Uploads every face and level of a cube map KTX2 file.
+    fn load_ktx2_cube_map(device: &Device, ktx_texture: &KtxTexture) -> ImageResult<Texture> {
+        if ktx_texture.face_count != 6 {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormatHint::Name("KTX2".to_string()),
+                    UnsupportedErrorKind::GenericFeature(format!(
+                        "{} faces in a cube map",
+                        ktx_texture.face_count
+                    )),
+                ),
+            ));
+        }
+
+        let top = &ktx_texture.levels[0][0];
+        println!("dimensions: {}x{} {:?}", top.width, top.height, top.format);
+
         let texture_descriptor = TextureDescriptor::new();
         texture_descriptor.set_storage_mode(MTLStorageMode::Shared);
-        texture_descriptor.set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+        texture_descriptor.set_pixel_format(texturable::pixel_format(top.format));
         texture_descriptor.set_texture_type(MTLTextureType::Cube);
-        texture_descriptor.set_width(each_size);
-        texture_descriptor.set_height(each_size);
-
+        texture_descriptor.set_width(top.width as u64);
+        texture_descriptor.set_height(top.height as u64);
+        texture_descriptor.set_mipmap_level_count(ktx_texture.levels.len() as u64);
         let texture = device.new_texture(&texture_descriptor);
 
-        let map = "irradiance-6.png";
-        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join(format!("assets/environments/venice_sunset/{}", map));
+        for (mipmap_level, faces) in ktx_texture.levels.iter().enumerate() {
+            for (i, face) in faces.iter().enumerate() {
+                let region = MTLRegion::new_2d(0, 0, face.width as u64, face.height as u64);
 
-        let img = image::open(path)?;
-        let (width, height) = img.dimensions();
-        println!("dimensions: {}x{}", width, height);
+                texture.replace_region_in_slice(
+                    region,
+                    mipmap_level as u64,
+                    i as u64,
+                    face.data.as_ptr() as _,
+                    face.bytes_per_row(),
+                    face.bytes_per_image(),
+                );
+            }
+        }
+        Ok(texture)
+    }
+
+    fn load_irradiance_map(device: &Device, path: &Path) -> ImageResult<Texture> {
+        let each_size = 128;
+
+        let strip = TextureData::open(path)?.into_color_space(ColorSpace::Srgb);
+        println!(
+            "dimensions: {}x{} {:?}",
+            strip.width, strip.height, strip.format
+        );
+
+        let texture_descriptor = TextureDescriptor::new();
+        texture_descriptor.set_storage_mode(MTLStorageMode::Shared);
+        texture_descriptor.set_pixel_format(texturable::pixel_format(strip.format));
+        texture_descriptor.set_texture_type(MTLTextureType::Cube);
+        texture_descriptor.set_width(each_size as u64);
+        texture_descriptor.set_height(each_size as u64);
 
+        let texture = device.new_texture(&texture_descriptor);
+
+        // The six faces are stacked vertically in one image.
         for i in 0..6 {
-            let start = i * each_size;
-            let end = (i * each_size) + (each_size - 1);
-            let region = MTLRegion::new_2d(0, 0, each_size, each_size);
-
-            let image_scale = 1;
-            let cube_size = each_size * image_scale;
-            let bytes_per_pixel = 4;
-            let bytes_per_row = bytes_per_pixel * cube_size;
-            let bytes_per_image = bytes_per_row * cube_size;
-
-            let mut new_buf: Vec<u8> = vec![];
-
-            match img {
-                image::DynamicImage::ImageRgb8(ref img) => {
-                    for i in start..=end {
-                        let row = img.rows().nth(i as usize).unwrap();
-                        for pixel in row {
-                            new_buf.push(pixel[2]);
-                            new_buf.push(pixel[1]);
-                            new_buf.push(pixel[0]);
-                            new_buf.push(255);
-                        }
-                    }
-                }
-                image::DynamicImage::ImageRgba8(ref img) => {
-                    for i in start..=end {
-                        let row = img.rows().nth(i as usize).unwrap();
-                        for pixel in row {
-                            new_buf.push(pixel[2]);
-                            new_buf.push(pixel[1]);
-                            new_buf.push(pixel[0]);
-                            new_buf.push(pixel[3]);
-                        }
-                    }
-                }
-                _ => {
-                    todo!()
-                }
-            }
+            let face = strip.rows(i * each_size, each_size);
+            let region = MTLRegion::new_2d(0, 0, each_size as u64, each_size as u64);
 
             texture.replace_region_in_slice(
                 region,
                 0,
                 i as u64,
-                new_buf.as_ptr() as _,
-                bytes_per_row as u64,
-                bytes_per_image as u64,
+                face.data.as_ptr() as _,
+                face.bytes_per_row(),
+                face.bytes_per_image(),
             );
         }
         Ok(texture)
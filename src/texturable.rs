@@ -1,63 +1,114 @@
-use image;
-use image::{error::ImageResult, GenericImageView};
+use crate::mipmaps::MipFilter;
+use crate::texture_bake::TextureCompression;
+use crate::texture_cache::{TextureCache, TextureFactory, TextureKey};
+use crate::texture_data::{TextureData, TextureFormat, TextureUsage};
+use image::error::ImageResult;
 use metal::*;
+use std::collections::HashMap;
+use std::path::Path;
 
-pub trait Texturable {
-    fn load_texture(image_name: &str, device: &Device) -> ImageResult<Texture> {
-        println!("image_name: {}", image_name);
+pub fn pixel_format(format: TextureFormat) -> MTLPixelFormat {
+    match format {
+        TextureFormat::R8Unorm => MTLPixelFormat::R8Unorm,
+        TextureFormat::RG8Unorm => MTLPixelFormat::RG8Unorm,
+        TextureFormat::BGRA8Unorm => MTLPixelFormat::BGRA8Unorm,
+        TextureFormat::BGRA8UnormSrgb => MTLPixelFormat::BGRA8Unorm_sRGB,
+        TextureFormat::RGBA16Unorm => MTLPixelFormat::RGBA16Unorm,
+        TextureFormat::RGBA16Float => MTLPixelFormat::RGBA16Float,
+        TextureFormat::RGBA32Float => MTLPixelFormat::RGBA32Float,
+        TextureFormat::BC5RGUnorm => MTLPixelFormat::BC5_RGUnorm,
+        TextureFormat::BC7RGBAUnorm => MTLPixelFormat::BC7_RGBAUnorm,
+        TextureFormat::BC7RGBAUnormSrgb => MTLPixelFormat::BC7_RGBAUnorm_sRGB,
+        TextureFormat::ASTC4x4Ldr => MTLPixelFormat::ASTC_4x4_LDR,
+        TextureFormat::ASTC4x4Srgb => MTLPixelFormat::ASTC_4x4_sRGB,
+    }
+}
+
+/// Apple GPUs sample ASTC; every other Mac GPU supports the BC formats.
+pub fn texture_compression(device: &DeviceRef) -> TextureCompression {
+    if device.supports_family(MTLGPUFamily::Apple2) {
+        TextureCompression::Astc
+    } else {
+        TextureCompression::Bc
+    }
+}
+
+impl TextureFactory for Device {
+    type Texture = Texture;
 
-        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join(format!("assets/models/{}", image_name));
+    fn new_texture(&self, levels: &[TextureData]) -> Texture {
+        let texture_data = &levels[0];
+        log::debug!(
+            "dimensions: {}x{} {:?}",
+            texture_data.width,
+            texture_data.height,
+            texture_data.format
+        );
 
-        let img = image::open(path)?;
-        let (width, height) = img.dimensions();
-        println!("dimensions: {}x{}", width, height);
+        let width = texture_data.width as u64;
+        let height = texture_data.height as u64;
 
         let texture_descriptor = TextureDescriptor::new();
         texture_descriptor.set_storage_mode(MTLStorageMode::Shared);
-        texture_descriptor.set_pixel_format(MTLPixelFormat::BGRA8Unorm);
-        texture_descriptor.set_width(width as u64);
-        texture_descriptor.set_height(height as u64);
+        texture_descriptor.set_pixel_format(pixel_format(texture_data.format));
+        texture_descriptor.set_width(width);
+        texture_descriptor.set_height(height);
         // texture_descriptor.set_depth(1);
-        texture_descriptor.set_mipmap_level_count_for_size(MTLSize {
-            width: width as u64,
-            height: height as u64,
-            depth: 1,
-        });
-        let texture = device.new_texture(&texture_descriptor);
-        let mut new_buf: Vec<u8> = vec![];
-
-        match img {
-            image::DynamicImage::ImageRgb8(img) => {
-                for pixel in img.pixels() {
-                    new_buf.push(pixel[2]);
-                    new_buf.push(pixel[1]);
-                    new_buf.push(pixel[0]);
-                    new_buf.push(255);
-                }
-            }
-            image::DynamicImage::ImageRgba8(img) => {
-                for pixel in img.pixels() {
-                    new_buf.push(pixel[2]);
-                    new_buf.push(pixel[1]);
-                    new_buf.push(pixel[0]);
-                    new_buf.push(pixel[3]);
-                }
-            }
-            _ => {
-                todo!()
-            }
-        };
-
-        let region = MTLRegion {
-            origin: MTLOrigin { x: 0, y: 0, z: 0 },
-            size: MTLSize {
-                width: width as u64,
-                height: height as u64,
-                depth: 1,
-            },
-        };
-        texture.replace_region(region, 0, new_buf.as_ptr() as _, width as u64 * 4);
-        Ok(texture)
+        texture_descriptor.set_mipmap_level_count(levels.len() as u64);
+        let texture = DeviceRef::new_texture(self, &texture_descriptor);
+
+        for (level, level_data) in levels.iter().enumerate() {
+            let region = MTLRegion {
+                origin: MTLOrigin { x: 0, y: 0, z: 0 },
+                size: MTLSize {
+                    width: level_data.width as u64,
+                    height: level_data.height as u64,
+                    depth: 1,
+                },
+            };
+            texture.replace_region(
+                region,
+                level as u64,
+                level_data.data.as_ptr() as _,
+                level_data.bytes_per_row(),
+            );
+        }
+        texture
+    }
+}
+
+pub trait Texturable {
+    /// `alpha_cutoff` is the cutoff of a `MASK` material, whose base color mips
+    /// then keep the coverage of the top level.
+    fn texture_key(
+        path: &Path,
+        image: Option<usize>,
+        usage: TextureUsage,
+        alpha_cutoff: Option<f32>,
+        compression: TextureCompression,
+    ) -> TextureKey {
+        TextureKey::new(
+            path,
+            image,
+            usage.color_space(),
+            usage.reads_red_only(),
+            MipFilter::new(usage, alpha_cutoff),
+            compression,
+        )
+    }
+
+    /// Gets the texture for `key` from the cache, uploading the pixels in
+    /// `decoded` on a miss or decoding the file if they are not there.
+    fn load_texture(
+        key: &TextureKey,
+        device: &Device,
+        texture_cache: &mut TextureCache<Texture>,
+        decoded: &mut HashMap<TextureKey, Vec<TextureData>>,
+    ) -> ImageResult<Texture> {
+        log::debug!("image_name: {}", key.path.display());
+        texture_cache.acquire_with(key, device, |key| match decoded.remove(key) {
+            Some(levels) => Ok(levels),
+            None => key.decode(),
+        })
     }
 }
@@ -0,0 +1,340 @@
+use crate::mipmaps::MipFilter;
+use crate::texture_cache::TextureKey;
+use crate::texture_data::{TextureData, TextureFormat};
+use image::error::ImageResult;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const BAKED_MAGIC: &[u8; 4] = b"MGBT";
+/// Part of every baked file name, so changing the encoders or the layout of the
+/// files invalidates what was baked before.
+const BAKE_VERSION: u32 = 2;
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher`, its output is the same in every
+/// build, so baked and cached file names stay valid across toolchain updates.
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// The family of block compressed formats textures are baked to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TextureCompression {
+    None,
+    /// BC7 for colors, BC5 for normal maps.
+    Bc,
+    /// ASTC 4x4 for everything, on Apple GPUs.
+    Astc,
+}
+
+impl TextureCompression {
+    /// The format an image stored as `format` is baked to. Only 8-bit images
+    /// are compressed; float and 16-bit images keep their precision.
+    pub fn format(self, format: TextureFormat, mip_filter: MipFilter) -> TextureFormat {
+        let eight_bit = matches!(
+            format,
+            TextureFormat::R8Unorm
+                | TextureFormat::RG8Unorm
+                | TextureFormat::BGRA8Unorm
+                | TextureFormat::BGRA8UnormSrgb
+        );
+        if !eight_bit {
+            return format;
+        }
+
+        match self {
+            TextureCompression::None => format,
+            TextureCompression::Bc if mip_filter == MipFilter::Normal => TextureFormat::BC5RGUnorm,
+            TextureCompression::Bc if format.is_srgb() => TextureFormat::BC7RGBAUnormSrgb,
+            TextureCompression::Bc => TextureFormat::BC7RGBAUnorm,
+            TextureCompression::Astc if format.is_srgb() => TextureFormat::ASTC4x4Srgb,
+            TextureCompression::Astc => TextureFormat::ASTC4x4Ldr,
+        }
+    }
+}
+
+pub fn bake_directory() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/baked")
+}
+
+/// The compressed mip chain of `key`. Reuses the file baked earlier unless the
+/// source image has changed since, otherwise encodes the image and writes it
+/// to the bake directory.
+pub fn bake(key: &TextureKey) -> ImageResult<Vec<TextureData>> {
+    let baked_path = baked_path(key)?;
+    if let Ok(levels) = read_baked(&baked_path) {
+        return Ok(levels);
+    }
+
+    let levels = key.decode_mip_chain()?;
+    let format = key.compression.format(levels[0].format, key.mip_filter);
+    let levels: Vec<TextureData> = levels.iter().map(|level| level.convert(format)).collect();
+
+    if let Err(error) = write_baked(&baked_path, &levels) {
+        log::warn!("unable to write {}: {}", baked_path.display(), error);
+    }
+    Ok(levels)
+}
+
+/// Named after the source image and a hash of everything that affects the
+/// baked data, including the size and modification time of the source.
+fn baked_path(key: &TextureKey) -> io::Result<PathBuf> {
+    let metadata = fs::metadata(&key.path)?;
+
+    let mut hasher = FnvHasher::default();
+    BAKE_VERSION.hash(&mut hasher);
+    key.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    metadata.modified()?.hash(&mut hasher);
+
+    let stem = key
+        .path
+        .file_stem()
+        .map_or("texture".into(), |stem| stem.to_string_lossy());
+    Ok(bake_directory().join(format!("{}-{:016x}.bin", stem, hasher.finish())))
+}
+
+fn format_index(format: TextureFormat) -> u32 {
+    TextureFormat::ALL
+        .iter()
+        .position(|&other| other == format)
+        .unwrap() as u32
+}
+
+pub fn write_baked(path: &Path, levels: &[TextureData]) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    let mut bytes = BAKED_MAGIC.to_vec();
+    bytes.extend(format_index(levels[0].format).to_le_bytes());
+    bytes.extend((levels.len() as u32).to_le_bytes());
+    for level in levels {
+        bytes.extend(level.width.to_le_bytes());
+        bytes.extend(level.height.to_le_bytes());
+        bytes.extend((level.data.len() as u64).to_le_bytes());
+        bytes.extend(&level.data);
+    }
+
+    fs::File::create(path)?.write_all(&bytes)
+}
+
+pub fn read_baked(path: &Path) -> io::Result<Vec<TextureData>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a baked texture");
+
+    let bytes = fs::read(path)?;
+    let mut remaining = bytes.as_slice();
+    let mut take = |len: usize| -> io::Result<&[u8]> {
+        if len > remaining.len() {
+            return Err(invalid());
+        }
+        let (taken, rest) = remaining.split_at(len);
+        remaining = rest;
+        Ok(taken)
+    };
+    let u32_at = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+
+    if take(4)? != BAKED_MAGIC {
+        return Err(invalid());
+    }
+    let format = *TextureFormat::ALL
+        .get(u32_at(take(4)?) as usize)
+        .ok_or_else(invalid)?;
+    let level_count = u32_at(take(4)?);
+
+    let mut levels = vec![];
+    for _ in 0..level_count {
+        let width = u32_at(take(4)?);
+        let height = u32_at(take(4)?);
+        let len = u64::from_le_bytes(take(8)?.try_into().unwrap());
+
+        // Checked before anything is allocated, and without overflow, so a
+        // corrupt header cannot ask for more than the file holds.
+        let block_rows = height.div_ceil(format.block_size()) as u64;
+        let mut level = TextureData {
+            format,
+            width,
+            height,
+            data: vec![],
+        };
+        if level.bytes_per_row().checked_mul(block_rows) != Some(len) {
+            return Err(invalid());
+        }
+        level.data = take(usize::try_from(len).map_err(|_| invalid())?)?.to_vec();
+        levels.push(level);
+    }
+
+    if levels.is_empty() {
+        return Err(invalid());
+    }
+    Ok(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fnv(bytes: &[u8]) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    fn levels() -> Vec<TextureData> {
+        [(4, 4), (2, 2), (1, 1)]
+            .iter()
+            .map(|&(width, height)| TextureData {
+                format: TextureFormat::BC7RGBAUnorm,
+                width,
+                height,
+                data: (0..16).map(|i| (i * width) as u8).collect(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fnv_matches_reference_values() {
+        assert_eq!(fnv(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn baked_files_round_trip() {
+        let path = std::env::temp_dir().join("texture-bake-round-trip.bin");
+        write_baked(&path, &levels()).unwrap();
+        assert_eq!(read_baked(&path).unwrap(), levels());
+
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.pop();
+        fs::write(&path, &bytes).unwrap();
+        assert!(read_baked(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn level_sizes_are_checked_before_reading() {
+        let path = std::env::temp_dir().join("texture-bake-level-size.bin");
+        write_baked(&path, &levels()[..1]).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        // magic, format, level count, width, height, then the data length
+        let len_offset = 20;
+
+        for len in [u64::MAX, 1 << 40, 15, 17] {
+            let mut corrupt = bytes.clone();
+            corrupt[len_offset..len_offset + 8].copy_from_slice(&len.to_le_bytes());
+            fs::write(&path, &corrupt).unwrap();
+            assert!(read_baked(&path).is_err(), "length {}", len);
+        }
+
+        // Dimensions whose size overflows.
+        let mut corrupt = bytes;
+        corrupt[12..20].copy_from_slice(&[0xff; 8]);
+        fs::write(&path, &corrupt).unwrap();
+        assert!(read_baked(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compression_picks_formats() {
+        use TextureFormat::*;
+
+        let cases = [
+            (
+                TextureCompression::None,
+                BGRA8UnormSrgb,
+                MipFilter::Color,
+                BGRA8UnormSrgb,
+            ),
+            (
+                TextureCompression::Bc,
+                BGRA8UnormSrgb,
+                MipFilter::Color,
+                BC7RGBAUnormSrgb,
+            ),
+            (
+                TextureCompression::Bc,
+                BGRA8Unorm,
+                MipFilter::Color,
+                BC7RGBAUnorm,
+            ),
+            (
+                TextureCompression::Bc,
+                R8Unorm,
+                MipFilter::Color,
+                BC7RGBAUnorm,
+            ),
+            (
+                TextureCompression::Bc,
+                BGRA8Unorm,
+                MipFilter::Normal,
+                BC5RGUnorm,
+            ),
+            (
+                TextureCompression::Astc,
+                BGRA8UnormSrgb,
+                MipFilter::Color,
+                ASTC4x4Srgb,
+            ),
+            (
+                TextureCompression::Astc,
+                RG8Unorm,
+                MipFilter::Color,
+                ASTC4x4Ldr,
+            ),
+            (
+                TextureCompression::Astc,
+                BGRA8Unorm,
+                MipFilter::Normal,
+                ASTC4x4Ldr,
+            ),
+            // 16-bit and float images keep their precision.
+            (
+                TextureCompression::Bc,
+                RGBA16Unorm,
+                MipFilter::Color,
+                RGBA16Unorm,
+            ),
+            (
+                TextureCompression::Astc,
+                RGBA16Float,
+                MipFilter::Color,
+                RGBA16Float,
+            ),
+            (
+                TextureCompression::Bc,
+                RGBA32Float,
+                MipFilter::Normal,
+                RGBA32Float,
+            ),
+        ];
+        for (compression, format, mip_filter, expected) in cases {
+            assert_eq!(
+                compression.format(format, mip_filter),
+                expected,
+                "{:?} {:?} {:?}",
+                compression,
+                format,
+                mip_filter
+            );
+        }
+    }
+}
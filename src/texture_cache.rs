@@ -0,0 +1,381 @@
+use crate::importer;
+use crate::ktx::{self, KtxTexture};
+use crate::mipmaps::{self, MipFilter};
+use crate::texture_bake::{self, TextureCompression};
+use crate::texture_data::{ColorSpace, TextureData};
+use image::error::ImageResult;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Creates GPU textures from decoded pixels. Implemented for `metal::Device` in
+/// `texturable`, so the cache itself does not depend on Metal.
+pub trait TextureFactory {
+    type Texture: Clone;
+
+    /// `levels` holds the full mip chain, largest level first.
+    fn new_texture(&self, levels: &[TextureData]) -> Self::Texture;
+}
+
+/// The same image file loaded as sRGB and as linear data, with differently
+/// filtered mips or compressed differently, are different textures.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextureKey {
+    pub path: PathBuf,
+    /// The glTF image the texture is read from when it is embedded in a buffer
+    /// view of the glTF file at `path` rather than stored in a file of its own.
+    pub image: Option<usize>,
+    pub color_space: ColorSpace,
+    /// The texture is only read from its red channel, so one and two channel
+    /// images are not spread over the color channels.
+    pub red_only: bool,
+    pub mip_filter: MipFilter,
+    pub compression: TextureCompression,
+}
+
+impl TextureKey {
+    pub fn new(
+        path: &Path,
+        image: Option<usize>,
+        color_space: ColorSpace,
+        red_only: bool,
+        mip_filter: MipFilter,
+        compression: TextureCompression,
+    ) -> Self {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        Self {
+            path,
+            image,
+            color_space,
+            red_only,
+            mip_filter,
+            compression,
+        }
+    }
+
+    /// The mip chain of the image this key refers to, baked if the key asks
+    /// for compression.
+    pub fn decode(&self) -> ImageResult<Vec<TextureData>> {
+        match self.compression {
+            TextureCompression::None => self.decode_mip_chain(),
+            _ => texture_bake::bake(self),
+        }
+    }
+
+    /// Reads and converts the image and builds its mips, unless it is a KTX2
+    /// file that comes with them.
+    pub fn decode_mip_chain(&self) -> ImageResult<Vec<TextureData>> {
+        if let Some(image) = self.image {
+            let bytes = importer::read_image(&self.path, image)?;
+            let texture_data = self.sampled_layout(TextureData::from_memory(&bytes)?);
+            return Ok(mipmaps::generate_mip_chain(&texture_data, self.mip_filter));
+        }
+
+        if ktx::is_ktx2(&self.path) {
+            let levels: Vec<TextureData> = KtxTexture::open(&self.path, self.compression)?
+                .mip_chain(0)
+                .into_iter()
+                .map(|level| self.sampled_layout(level))
+                .collect();
+            if levels.len() > 1 || levels[0].format.is_compressed() {
+                return Ok(levels);
+            }
+            return Ok(mipmaps::generate_mip_chain(&levels[0], self.mip_filter));
+        }
+
+        let texture_data = self.sampled_layout(TextureData::open(&self.path)?);
+        Ok(mipmaps::generate_mip_chain(&texture_data, self.mip_filter))
+    }
+
+    /// Decoded pixels in the color space and channel layout the texture is
+    /// sampled in.
+    fn sampled_layout(&self, texture_data: TextureData) -> TextureData {
+        let texture_data = texture_data.into_color_space(self.color_space);
+        if self.red_only {
+            texture_data
+        } else {
+            texture_data.spread_gray()
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub textures: usize,
+}
+
+struct Entry<T> {
+    texture: T,
+    ref_count: usize,
+}
+
+/// Shares decoded and uploaded textures between submeshes and models. Every
+/// `acquire` must be balanced by a `release`; a texture is dropped once its
+/// last user releases it.
+pub struct TextureCache<T> {
+    entries: HashMap<TextureKey, Entry<T>>,
+    hits: usize,
+    misses: usize,
+}
+
+impl<T> Default for TextureCache<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+impl<T: Clone> TextureCache<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached texture for `key`, decoding the file on a miss.
+    pub fn acquire<F>(&mut self, key: &TextureKey, factory: &F) -> ImageResult<T>
+    where
+        F: TextureFactory<Texture = T>,
+    {
+        self.acquire_with(key, factory, TextureKey::decode)
+    }
+
+    /// Like `acquire`, with the decoding step supplied by the caller.
+    pub fn acquire_with<F, L>(&mut self, key: &TextureKey, factory: &F, load: L) -> ImageResult<T>
+    where
+        F: TextureFactory<Texture = T>,
+        L: FnOnce(&TextureKey) -> ImageResult<Vec<TextureData>>,
+    {
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.hits += 1;
+            entry.ref_count += 1;
+            return Ok(entry.texture.clone());
+        }
+
+        self.misses += 1;
+        let texture = factory.new_texture(&load(key)?);
+        self.entries.insert(
+            key.clone(),
+            Entry {
+                texture: texture.clone(),
+                ref_count: 1,
+            },
+        );
+        Ok(texture)
+    }
+
+    /// Gives up one reference to `key`. Returns `true` if that was the last one
+    /// and the texture was evicted.
+    pub fn release(&mut self, key: &TextureKey) -> bool {
+        let entry = match self.entries.get_mut(key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        entry.ref_count -= 1;
+        if entry.ref_count == 0 {
+            self.entries.remove(key);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Swaps in a new texture for `key`, keeping its users. Returns `false` if
+    /// the key is not cached.
+    pub fn replace(&mut self, key: &TextureKey, texture: T) -> bool {
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.texture = texture;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Keys of every cached texture loaded from `path`.
+    pub fn keys_for_path(&self, path: &Path) -> Vec<TextureKey> {
+        self.entries
+            .keys()
+            .filter(|key| key.path == path)
+            .cloned()
+            .collect()
+    }
+
+    pub fn ref_count(&self, key: &TextureKey) -> usize {
+        self.entries.get(key).map_or(0, |entry| entry.ref_count)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            textures: self.entries.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture_data::{TextureFormat, TextureUsage};
+    use std::cell::Cell;
+
+    /// Hands out a new number per upload.
+    struct Uploads(Cell<usize>);
+
+    impl TextureFactory for Uploads {
+        type Texture = usize;
+
+        fn new_texture(&self, _levels: &[TextureData]) -> usize {
+            self.0.set(self.0.get() + 1);
+            self.0.get()
+        }
+    }
+
+    fn key(path: &str, color_space: ColorSpace) -> TextureKey {
+        TextureKey::new(
+            Path::new(path),
+            None,
+            color_space,
+            false,
+            MipFilter::Color,
+            TextureCompression::None,
+        )
+    }
+
+    fn pixel(_key: &TextureKey) -> ImageResult<Vec<TextureData>> {
+        Ok(vec![TextureData {
+            format: TextureFormat::R8Unorm,
+            width: 1,
+            height: 1,
+            data: vec![0],
+        }])
+    }
+
+    #[test]
+    fn shares_textures_between_users() {
+        let uploads = Uploads(Cell::new(0));
+        let mut cache = TextureCache::new();
+        let key = key("/models/a/albedo.png", ColorSpace::Srgb);
+
+        let first = cache.acquire_with(&key, &uploads, pixel).unwrap();
+        let second = cache.acquire_with(&key, &uploads, pixel).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(uploads.0.get(), 1);
+        assert_eq!(cache.ref_count(&key), 2);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                textures: 1
+            }
+        );
+    }
+
+    #[test]
+    fn evicts_on_last_release() {
+        let uploads = Uploads(Cell::new(0));
+        let mut cache = TextureCache::new();
+        let key = key("/models/a/albedo.png", ColorSpace::Srgb);
+        cache.acquire_with(&key, &uploads, pixel).unwrap();
+        cache.acquire_with(&key, &uploads, pixel).unwrap();
+
+        assert!(!cache.release(&key));
+        assert_eq!(cache.ref_count(&key), 1);
+        assert!(cache.release(&key));
+        assert_eq!(cache.ref_count(&key), 0);
+        assert_eq!(cache.stats().textures, 0);
+        assert!(!cache.release(&key));
+
+        // Acquiring again after eviction uploads again.
+        cache.acquire_with(&key, &uploads, pixel).unwrap();
+        assert_eq!(uploads.0.get(), 2);
+    }
+
+    #[test]
+    fn keys_differ_by_color_space_and_directory() {
+        let uploads = Uploads(Cell::new(0));
+        let mut cache = TextureCache::new();
+        let srgb = key("/models/a/albedo.png", ColorSpace::Srgb);
+        let linear = key("/models/a/albedo.png", ColorSpace::Linear);
+        let other_model = key("/models/b/albedo.png", ColorSpace::Srgb);
+
+        let textures: Vec<usize> = [&srgb, &linear, &other_model]
+            .iter()
+            .map(|key| cache.acquire_with(key, &uploads, pixel).unwrap())
+            .collect();
+        assert_eq!(textures, vec![1, 2, 3]);
+        assert_eq!(cache.stats().textures, 3);
+
+        assert!(cache.release(&other_model));
+        assert_eq!(cache.ref_count(&srgb), 1);
+        assert_eq!(
+            cache.keys_for_path(Path::new("/models/a/albedo.png")).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn replace_keeps_users() {
+        let uploads = Uploads(Cell::new(0));
+        let mut cache = TextureCache::new();
+        let key = key("/models/a/albedo.png", ColorSpace::Srgb);
+        cache.acquire_with(&key, &uploads, pixel).unwrap();
+        cache.acquire_with(&key, &uploads, pixel).unwrap();
+
+        assert!(cache.replace(&key, 7));
+        assert_eq!(cache.ref_count(&key), 2);
+        assert_eq!(
+            cache
+                .acquire_with(&key, &uploads, |_| unreachable!())
+                .unwrap(),
+            7
+        );
+        assert!(!cache.replace(&self::key("/missing.png", ColorSpace::Srgb), 8));
+    }
+
+    #[test]
+    fn failed_loads_are_not_cached() {
+        let uploads = Uploads(Cell::new(0));
+        let mut cache = TextureCache::new();
+        let key = key("/nonexistent/albedo.png", ColorSpace::Srgb);
+
+        assert!(cache.acquire(&key, &uploads).is_err());
+        assert_eq!(cache.ref_count(&key), 0);
+        assert_eq!(uploads.0.get(), 0);
+    }
+
+    #[test]
+    fn gray_images_are_sampled_as_their_usage_reads_them() {
+        let path = std::env::temp_dir().join("texture-cache-gray.png");
+        image::GrayImage::from_raw(1, 1, vec![51])
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let decode = |usage: TextureUsage| {
+            let key = TextureKey::new(
+                &path,
+                None,
+                usage.color_space(),
+                usage.reads_red_only(),
+                MipFilter::new(usage, None),
+                TextureCompression::None,
+            );
+            key.decode().unwrap().swap_remove(0)
+        };
+
+        // pbr.metal reads metalness from red and roughness from green.
+        let metallic_roughness = decode(TextureUsage::MetallicRoughness);
+        assert_eq!(metallic_roughness.format, TextureFormat::BGRA8Unorm);
+        assert_eq!(metallic_roughness.to_rgba_f32(), [[0.2, 0.2, 0.2, 1.0]]);
+
+        let occlusion = decode(TextureUsage::Occlusion);
+        assert_eq!(occlusion.format, TextureFormat::R8Unorm);
+        assert_eq!(occlusion.to_rgba_f32()[0][0], 0.2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
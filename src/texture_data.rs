@@ -0,0 +1,835 @@
+use crate::block_compression;
+use crate::ktx::{self, KtxTexture};
+use crate::texture_bake::TextureCompression;
+use image::{error::ImageResult, hdr::HdrDecoder, DynamicImage, GenericImageView};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Largest finite value a half float can hold.
+const F16_MAX: f32 = 65504.0;
+
+/// The pixel layouts images are converted to before being uploaded. Mapped to
+/// `MTLPixelFormat` in `texturable`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+    R8Unorm,
+    RG8Unorm,
+    BGRA8Unorm,
+    BGRA8UnormSrgb,
+    RGBA16Unorm,
+    RGBA16Float,
+    RGBA32Float,
+    BC5RGUnorm,
+    BC7RGBAUnorm,
+    BC7RGBAUnormSrgb,
+    ASTC4x4Ldr,
+    ASTC4x4Srgb,
+}
+
+impl TextureFormat {
+    pub const ALL: [TextureFormat; 12] = [
+        TextureFormat::R8Unorm,
+        TextureFormat::RG8Unorm,
+        TextureFormat::BGRA8Unorm,
+        TextureFormat::BGRA8UnormSrgb,
+        TextureFormat::RGBA16Unorm,
+        TextureFormat::RGBA16Float,
+        TextureFormat::RGBA32Float,
+        TextureFormat::BC5RGUnorm,
+        TextureFormat::BC7RGBAUnorm,
+        TextureFormat::BC7RGBAUnormSrgb,
+        TextureFormat::ASTC4x4Ldr,
+        TextureFormat::ASTC4x4Srgb,
+    ];
+
+    /// Bytes of one pixel, or of one block for compressed formats.
+    pub fn bytes_per_block(self) -> usize {
+        match self {
+            TextureFormat::R8Unorm => 1,
+            TextureFormat::RG8Unorm => 2,
+            TextureFormat::BGRA8Unorm | TextureFormat::BGRA8UnormSrgb => 4,
+            TextureFormat::RGBA16Unorm | TextureFormat::RGBA16Float => 8,
+            TextureFormat::RGBA32Float => 16,
+            TextureFormat::BC5RGUnorm
+            | TextureFormat::BC7RGBAUnorm
+            | TextureFormat::BC7RGBAUnormSrgb
+            | TextureFormat::ASTC4x4Ldr
+            | TextureFormat::ASTC4x4Srgb => block_compression::BYTES_PER_BLOCK,
+        }
+    }
+
+    /// Width and height of a block; 1 for uncompressed formats.
+    pub fn block_size(self) -> u32 {
+        if self.is_compressed() {
+            4
+        } else {
+            1
+        }
+    }
+
+    pub fn is_compressed(self) -> bool {
+        matches!(
+            self,
+            TextureFormat::BC5RGUnorm
+                | TextureFormat::BC7RGBAUnorm
+                | TextureFormat::BC7RGBAUnormSrgb
+                | TextureFormat::ASTC4x4Ldr
+                | TextureFormat::ASTC4x4Srgb
+        )
+    }
+
+    pub fn is_srgb(self) -> bool {
+        matches!(
+            self,
+            TextureFormat::BGRA8UnormSrgb
+                | TextureFormat::BC7RGBAUnormSrgb
+                | TextureFormat::ASTC4x4Srgb
+        )
+    }
+}
+
+/// How the stored values of a texture are to be interpreted when sampled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+/// The material slot a texture is loaded for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureUsage {
+    BaseColor,
+    Emissive,
+    Normal,
+    MetallicRoughness,
+    Occlusion,
+}
+
+impl TextureUsage {
+    /// glTF stores colors sRGB encoded and everything else as linear data.
+    pub fn color_space(self) -> ColorSpace {
+        match self {
+            TextureUsage::BaseColor | TextureUsage::Emissive => ColorSpace::Srgb,
+            TextureUsage::Normal | TextureUsage::MetallicRoughness | TextureUsage::Occlusion => {
+                ColorSpace::Linear
+            }
+        }
+    }
+
+    /// Occlusion is read from the red channel alone; every other usage reads
+    /// gray images from whichever color channel it needs.
+    pub fn reads_red_only(self) -> bool {
+        self == TextureUsage::Occlusion
+    }
+}
+
+/// Tightly packed pixels ready to be copied into a texture with
+/// `replace_region`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureData {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl TextureData {
+    /// Opens an image file, reading Radiance `.hdr` files as float data. Only
+    /// the top level of the first face of a `.ktx2` file is returned.
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let path = path.as_ref();
+        if ktx::is_ktx2(path) {
+            let mut texture = KtxTexture::open(path, TextureCompression::None)?;
+            return Ok(texture.levels.swap_remove(0).swap_remove(0));
+        }
+
+        let is_hdr = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+
+        if is_hdr {
+            let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+            let metadata = decoder.metadata();
+            let pixels: Vec<[f32; 3]> = decoder
+                .read_image_hdr()?
+                .into_iter()
+                .map(|pixel| pixel.0)
+                .collect();
+            Ok(Self::from_rgb_f32(metadata.width, metadata.height, &pixels))
+        } else {
+            Ok(Self::from_image(&image::open(path)?))
+        }
+    }
+
+    /// Decodes an image file already read into memory, such as an image
+    /// embedded in a `.glb` file.
+    pub fn from_memory(bytes: &[u8]) -> ImageResult<Self> {
+        Ok(Self::from_image(&image::load_from_memory(bytes)?))
+    }
+
+    /// Converts a decoded image to the smallest format that holds it without
+    /// losing precision: one and two channel 8-bit images stay `R8`/`RG8` until
+    /// `spread_gray`, other 8-bit images become `BGRA8` and every 16-bit image
+    /// `RGBA16Unorm`.
+    pub fn from_image(image: &DynamicImage) -> Self {
+        let (width, height) = image.dimensions();
+
+        let (format, data) = match image {
+            DynamicImage::ImageLuma8(image) => (TextureFormat::R8Unorm, image.as_raw().clone()),
+            DynamicImage::ImageLumaA8(image) => (TextureFormat::RG8Unorm, image.as_raw().clone()),
+            DynamicImage::ImageRgb8(image) => (
+                TextureFormat::BGRA8Unorm,
+                image
+                    .as_raw()
+                    .chunks_exact(3)
+                    .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], 255])
+                    .collect(),
+            ),
+            DynamicImage::ImageRgba8(image) => (
+                TextureFormat::BGRA8Unorm,
+                image
+                    .as_raw()
+                    .chunks_exact(4)
+                    .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+                    .collect(),
+            ),
+            DynamicImage::ImageBgr8(image) => (
+                TextureFormat::BGRA8Unorm,
+                image
+                    .as_raw()
+                    .chunks_exact(3)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+                    .collect(),
+            ),
+            DynamicImage::ImageBgra8(image) => (TextureFormat::BGRA8Unorm, image.as_raw().clone()),
+            DynamicImage::ImageLuma16(image) => (
+                TextureFormat::RGBA16Unorm,
+                u16_bytes(image.as_raw().iter().flat_map(|&l| [l, l, l, u16::MAX])),
+            ),
+            DynamicImage::ImageLumaA16(image) => (
+                TextureFormat::RGBA16Unorm,
+                u16_bytes(
+                    image
+                        .as_raw()
+                        .chunks_exact(2)
+                        .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]]),
+                ),
+            ),
+            DynamicImage::ImageRgb16(image) => (
+                TextureFormat::RGBA16Unorm,
+                u16_bytes(
+                    image
+                        .as_raw()
+                        .chunks_exact(3)
+                        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u16::MAX]),
+                ),
+            ),
+            DynamicImage::ImageRgba16(image) => (
+                TextureFormat::RGBA16Unorm,
+                u16_bytes(image.as_raw().iter().copied()),
+            ),
+        };
+
+        Self {
+            format,
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Float RGB pixels, as read from HDR files. Stored as half floats unless a
+    /// value is out of half range.
+    pub fn from_rgb_f32(width: u32, height: u32, pixels: &[[f32; 3]]) -> Self {
+        let rgba: Vec<[f32; 4]> = pixels
+            .iter()
+            .map(|pixel| [pixel[0], pixel[1], pixel[2], 1.0])
+            .collect();
+
+        let fits_in_half = pixels.iter().flatten().all(|value| value.abs() <= F16_MAX);
+        let format = if fits_in_half {
+            TextureFormat::RGBA16Float
+        } else {
+            TextureFormat::RGBA32Float
+        };
+
+        Self::from_rgba_f32(format, width, height, &rgba)
+    }
+
+    /// Tags the pixels with the color space they are stored in, so the GPU
+    /// returns linear values when sampling. There are no single or two channel
+    /// sRGB formats on macOS, so those are expanded to `BGRA8`, and 16-bit
+    /// images are linearized on the CPU instead. Float images are always linear.
+    pub fn into_color_space(self, color_space: ColorSpace) -> Self {
+        let format = match (color_space, self.format) {
+            (ColorSpace::Linear, TextureFormat::BGRA8UnormSrgb) => TextureFormat::BGRA8Unorm,
+            (ColorSpace::Linear, TextureFormat::BC7RGBAUnormSrgb) => TextureFormat::BC7RGBAUnorm,
+            (ColorSpace::Linear, TextureFormat::ASTC4x4Srgb) => TextureFormat::ASTC4x4Ldr,
+            (ColorSpace::Linear, _) => return self,
+            (ColorSpace::Srgb, TextureFormat::BGRA8Unorm) => TextureFormat::BGRA8UnormSrgb,
+            (ColorSpace::Srgb, TextureFormat::BC7RGBAUnorm) => TextureFormat::BC7RGBAUnormSrgb,
+            (ColorSpace::Srgb, TextureFormat::ASTC4x4Ldr) => TextureFormat::ASTC4x4Srgb,
+            (ColorSpace::Srgb, TextureFormat::R8Unorm | TextureFormat::RG8Unorm) => {
+                return self.spread_gray().into_color_space(color_space);
+            }
+            (ColorSpace::Srgb, TextureFormat::RGBA16Unorm) => {
+                let pixels: Vec<[f32; 4]> = self
+                    .to_rgba_f32()
+                    .iter()
+                    .map(|pixel| {
+                        [
+                            srgb_to_linear(pixel[0]),
+                            srgb_to_linear(pixel[1]),
+                            srgb_to_linear(pixel[2]),
+                            pixel[3],
+                        ]
+                    })
+                    .collect();
+                return Self::from_rgba_f32(
+                    TextureFormat::RGBA16Unorm,
+                    self.width,
+                    self.height,
+                    &pixels,
+                );
+            }
+            (ColorSpace::Srgb, _) => return self,
+        };
+
+        Self { format, ..self }
+    }
+
+    /// Spreads one and two channel images over the color channels, as `rrr1`
+    /// and `rrra` in `BGRA8`. Metal samples `R8` as `(r, 0, 0, 1)` and `RG8` as
+    /// `(r, g, 0, 1)`, which only suits textures read from their red channel.
+    pub fn spread_gray(self) -> Self {
+        let data = match self.format {
+            TextureFormat::R8Unorm => self.data.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+            TextureFormat::RG8Unorm => self
+                .data
+                .chunks_exact(2)
+                .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+                .collect(),
+            _ => return self,
+        };
+        Self {
+            format: TextureFormat::BGRA8Unorm,
+            data,
+            ..self
+        }
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        if self.format.is_srgb() {
+            ColorSpace::Srgb
+        } else {
+            ColorSpace::Linear
+        }
+    }
+
+    /// Bytes of one row of pixels, or of one row of blocks for compressed
+    /// formats.
+    pub fn bytes_per_row(&self) -> u64 {
+        let blocks = self.width.div_ceil(self.format.block_size());
+        blocks as u64 * self.format.bytes_per_block() as u64
+    }
+
+    pub fn bytes_per_image(&self) -> u64 {
+        let block_rows = self.height.div_ceil(self.format.block_size());
+        self.bytes_per_row() * block_rows as u64
+    }
+
+    /// `count` rows starting at `start`, e.g. one face of a vertical cube strip.
+    /// Compressed images can only be split at block boundaries.
+    pub fn rows(&self, start: u32, count: u32) -> Self {
+        let block_size = self.format.block_size();
+        let bytes_per_row = self.bytes_per_row() as usize;
+        let begin = (start / block_size) as usize * bytes_per_row;
+        let end = begin + count.div_ceil(block_size) as usize * bytes_per_row;
+
+        Self {
+            format: self.format,
+            width: self.width,
+            height: count,
+            data: self.data[begin..end].to_vec(),
+        }
+    }
+
+    /// Re-encodes the pixels in another format, so that images of different
+    /// layouts can share one texture (e.g. the faces and mips of a cube map).
+    pub fn convert(&self, format: TextureFormat) -> Self {
+        if format == self.format {
+            return self.clone();
+        }
+        Self::from_rgba_f32(format, self.width, self.height, &self.to_rgba_f32())
+    }
+
+    /// Linear RGBA values; sRGB formats are decoded.
+    pub(crate) fn to_rgba_f32(&self) -> Vec<[f32; 4]> {
+        let bytes_per_pixel = self.format.bytes_per_block();
+        let unorm8 = |value: u8| value as f32 / 255.0;
+
+        if self.format.is_compressed() {
+            return self
+                .decompress()
+                .iter()
+                .map(|pixel| {
+                    let [r, g, b, a] = pixel.map(unorm8);
+                    if self.format.is_srgb() {
+                        [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a]
+                    } else {
+                        [r, g, b, a]
+                    }
+                })
+                .collect();
+        }
+
+        self.data
+            .chunks_exact(bytes_per_pixel)
+            .map(|pixel| match self.format {
+                TextureFormat::R8Unorm => [unorm8(pixel[0]), 0.0, 0.0, 1.0],
+                TextureFormat::RG8Unorm => [unorm8(pixel[0]), unorm8(pixel[1]), 0.0, 1.0],
+                TextureFormat::BGRA8Unorm => [
+                    unorm8(pixel[2]),
+                    unorm8(pixel[1]),
+                    unorm8(pixel[0]),
+                    unorm8(pixel[3]),
+                ],
+                TextureFormat::BGRA8UnormSrgb => [
+                    srgb_to_linear(unorm8(pixel[2])),
+                    srgb_to_linear(unorm8(pixel[1])),
+                    srgb_to_linear(unorm8(pixel[0])),
+                    unorm8(pixel[3]),
+                ],
+                TextureFormat::RGBA16Unorm => {
+                    let mut rgba = [0.0; 4];
+                    for (value, bytes) in rgba.iter_mut().zip(pixel.chunks_exact(2)) {
+                        *value = u16::from_ne_bytes([bytes[0], bytes[1]]) as f32 / 65535.0;
+                    }
+                    rgba
+                }
+                TextureFormat::RGBA16Float => {
+                    let mut rgba = [0.0; 4];
+                    for (value, bytes) in rgba.iter_mut().zip(pixel.chunks_exact(2)) {
+                        *value = f16_to_f32(u16::from_ne_bytes([bytes[0], bytes[1]]));
+                    }
+                    rgba
+                }
+                TextureFormat::RGBA32Float => {
+                    let mut rgba = [0.0; 4];
+                    for (value, bytes) in rgba.iter_mut().zip(pixel.chunks_exact(4)) {
+                        *value = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    }
+                    rgba
+                }
+                _ => unreachable!("compressed formats are decoded above"),
+            })
+            .collect()
+    }
+
+    pub(crate) fn from_rgba_f32(
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        pixels: &[[f32; 4]],
+    ) -> Self {
+        let unorm8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let unorm16 = |value: f32| (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+
+        if format.is_compressed() {
+            let pixels: Vec<[u8; 4]> = pixels
+                .iter()
+                .map(|pixel| {
+                    let [r, g, b, a] = *pixel;
+                    if format.is_srgb() {
+                        [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a].map(unorm8)
+                    } else {
+                        [r, g, b, a].map(unorm8)
+                    }
+                })
+                .collect();
+            return Self::compress(format, width, height, &pixels);
+        }
+
+        let mut data = Vec::with_capacity(pixels.len() * format.bytes_per_block());
+        for pixel in pixels {
+            match format {
+                TextureFormat::R8Unorm => data.push(unorm8(pixel[0])),
+                TextureFormat::RG8Unorm => data.extend([unorm8(pixel[0]), unorm8(pixel[1])]),
+                TextureFormat::BGRA8Unorm => data.extend([
+                    unorm8(pixel[2]),
+                    unorm8(pixel[1]),
+                    unorm8(pixel[0]),
+                    unorm8(pixel[3]),
+                ]),
+                TextureFormat::BGRA8UnormSrgb => data.extend([
+                    unorm8(linear_to_srgb(pixel[2])),
+                    unorm8(linear_to_srgb(pixel[1])),
+                    unorm8(linear_to_srgb(pixel[0])),
+                    unorm8(pixel[3]),
+                ]),
+                TextureFormat::RGBA16Unorm => {
+                    for value in pixel {
+                        data.extend(unorm16(*value).to_ne_bytes());
+                    }
+                }
+                TextureFormat::RGBA16Float => {
+                    for value in pixel {
+                        data.extend(f32_to_f16(*value).to_ne_bytes());
+                    }
+                }
+                TextureFormat::RGBA32Float => {
+                    for value in pixel {
+                        data.extend(value.to_ne_bytes());
+                    }
+                }
+                _ => unreachable!("compressed formats are encoded above"),
+            }
+        }
+
+        Self {
+            format,
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Block compresses 8-bit RGBA pixels, already sRGB encoded for the sRGB
+    /// formats.
+    fn compress(format: TextureFormat, width: u32, height: u32, pixels: &[[u8; 4]]) -> Self {
+        let data = match format {
+            TextureFormat::BC5RGUnorm => block_compression::encode_bc5(width, height, pixels),
+            TextureFormat::BC7RGBAUnorm | TextureFormat::BC7RGBAUnormSrgb => {
+                block_compression::encode_bc7(width, height, pixels)
+            }
+            TextureFormat::ASTC4x4Ldr | TextureFormat::ASTC4x4Srgb => {
+                block_compression::encode_astc(width, height, pixels)
+            }
+            _ => unreachable!("{:?} is not a compressed format", format),
+        };
+
+        Self {
+            format,
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn decompress(&self) -> Vec<[u8; 4]> {
+        let (width, height, data) = (self.width, self.height, &self.data);
+        match self.format {
+            TextureFormat::BC5RGUnorm => block_compression::decode_bc5(width, height, data),
+            TextureFormat::BC7RGBAUnorm | TextureFormat::BC7RGBAUnormSrgb => {
+                block_compression::decode_bc7(width, height, data)
+            }
+            TextureFormat::ASTC4x4Ldr => block_compression::decode_astc(width, height, data, false),
+            TextureFormat::ASTC4x4Srgb => block_compression::decode_astc(width, height, data, true),
+            _ => unreachable!("{:?} is not a compressed format", self.format),
+        }
+    }
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn u16_bytes(values: impl Iterator<Item = u16>) -> Vec<u8> {
+    values.flat_map(u16::to_ne_bytes).collect()
+}
+
+/// Rounds to the nearest half float, flushing values below the smallest
+/// subnormal to zero and overflowing to infinity.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+        return sign | ((mantissa >> shift) + round) as u16;
+    }
+
+    // A carry out of the mantissa correctly bumps the exponent.
+    let round = ((mantissa >> 12) & 1) as u16;
+    (sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16) + round
+}
+
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal: value is mantissa * 2^-24.
+            let value = mantissa as f32 / (1 << 24) as f32;
+            return if sign != 0 { -value } else { value };
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma, LumaA, Rgb, Rgba};
+
+    fn luma8(width: u32, height: u32, pixels: Vec<u8>) -> TextureData {
+        TextureData::from_image(&DynamicImage::ImageLuma8(
+            ImageBuffer::from_raw(width, height, pixels).unwrap(),
+        ))
+    }
+
+    fn f32_values(data: &[u8]) -> Vec<f32> {
+        data.chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect()
+    }
+
+    #[test]
+    fn spreads_one_and_two_channel_images_over_the_colors() {
+        // Decoded narrow, which is how textures read from red alone keep them.
+        let gray = luma8(2, 1, vec![10, 20]);
+        assert_eq!(gray.format, TextureFormat::R8Unorm);
+        assert_eq!(gray.data, vec![10, 20]);
+        assert_eq!(gray.bytes_per_row(), 2);
+
+        let spread = gray.spread_gray();
+        assert_eq!(spread.format, TextureFormat::BGRA8Unorm);
+        assert_eq!(spread.data, vec![10, 10, 10, 255, 20, 20, 20, 255]);
+        assert_eq!(spread.bytes_per_row(), 8);
+
+        let gray_alpha = TextureData::from_image(&DynamicImage::ImageLumaA8(
+            ImageBuffer::<LumaA<u8>, _>::from_raw(1, 1, vec![1, 2]).unwrap(),
+        ));
+        assert_eq!(gray_alpha.format, TextureFormat::RG8Unorm);
+        assert_eq!(gray_alpha.data, vec![1, 2]);
+        assert_eq!(gray_alpha.spread_gray().data, vec![1, 1, 1, 2]);
+
+        let rgb = TextureData::from_image(&DynamicImage::ImageRgb8(
+            ImageBuffer::<Rgb<u8>, _>::from_raw(1, 1, vec![1, 2, 3]).unwrap(),
+        ));
+        assert_eq!(rgb.clone().spread_gray(), rgb);
+    }
+
+    #[test]
+    fn swizzles_color_images_to_bgra() {
+        let rgb = TextureData::from_image(&DynamicImage::ImageRgb8(
+            ImageBuffer::<Rgb<u8>, _>::from_raw(1, 1, vec![1, 2, 3]).unwrap(),
+        ));
+        assert_eq!(rgb.format, TextureFormat::BGRA8Unorm);
+        assert_eq!(rgb.data, vec![3, 2, 1, 255]);
+
+        let rgba = TextureData::from_image(&DynamicImage::ImageRgba8(
+            ImageBuffer::<Rgba<u8>, _>::from_raw(1, 1, vec![1, 2, 3, 4]).unwrap(),
+        ));
+        assert_eq!(rgba.format, TextureFormat::BGRA8Unorm);
+        assert_eq!(rgba.data, vec![3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn widens_sixteen_bit_images() {
+        let gray = TextureData::from_image(&DynamicImage::ImageLuma16(
+            ImageBuffer::<Luma<u16>, _>::from_raw(1, 1, vec![1000]).unwrap(),
+        ));
+        assert_eq!(gray.format, TextureFormat::RGBA16Unorm);
+        assert_eq!(gray.bytes_per_image(), 8);
+        assert_eq!(gray.data.len(), 8);
+
+        // 1000 / 65535 of 255 rounds to 4.
+        assert_eq!(
+            gray.convert(TextureFormat::BGRA8Unorm).data,
+            vec![4, 4, 4, 255]
+        );
+    }
+
+    #[test]
+    fn stores_hdr_pixels_as_half_floats_when_they_fit() {
+        let hdr = TextureData::from_rgb_f32(1, 1, &[[0.5, 2.0, 100.0]]);
+        assert_eq!(hdr.format, TextureFormat::RGBA16Float);
+        assert_eq!(
+            f32_values(&hdr.convert(TextureFormat::RGBA32Float).data),
+            vec![0.5, 2.0, 100.0, 1.0]
+        );
+
+        let bright = TextureData::from_rgb_f32(1, 1, &[[1e6, 0.0, 0.0]]);
+        assert_eq!(bright.format, TextureFormat::RGBA32Float);
+    }
+
+    #[test]
+    fn converts_between_uncompressed_formats() {
+        // An R8 texel samples as (r, 0, 0, 1).
+        let bgra = luma8(1, 1, vec![51]).convert(TextureFormat::BGRA8Unorm);
+        assert_eq!(bgra.data, vec![0, 0, 51, 255]);
+
+        let float = bgra.convert(TextureFormat::RGBA32Float);
+        assert_eq!(float.bytes_per_row(), 16);
+        assert_eq!(f32_values(&float.data), vec![0.2, 0.0, 0.0, 1.0]);
+
+        let back = float.convert(TextureFormat::RG8Unorm);
+        assert_eq!(back.format, TextureFormat::RG8Unorm);
+        assert_eq!(back.data, vec![51, 0]);
+    }
+
+    #[test]
+    fn takes_rows() {
+        let strip = luma8(1, 4, vec![1, 2, 3, 4]);
+        let rows = strip.rows(2, 2);
+        assert_eq!((rows.width, rows.height), (1, 2));
+        assert_eq!(rows.data, vec![3, 4]);
+    }
+
+    #[test]
+    fn decodes_images_in_memory() {
+        let image = DynamicImage::ImageRgb8(
+            ImageBuffer::<Rgb<u8>, _>::from_raw(1, 1, vec![1, 2, 3]).unwrap(),
+        );
+        let mut png = vec![];
+        image
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let texture_data = TextureData::from_memory(&png).unwrap();
+        assert_eq!(texture_data.format, TextureFormat::BGRA8Unorm);
+        assert_eq!(texture_data.data, vec![3, 2, 1, 255]);
+        assert!(TextureData::from_memory(&png[..8]).is_err());
+    }
+
+    #[test]
+    fn half_floats_round_trip() {
+        for value in [
+            0.0,
+            -0.0,
+            1.0,
+            -2.5,
+            65504.0,
+            6.1035156e-5,
+            5.96e-8,
+            0.333,
+            1e-3,
+        ] {
+            let half = f32_to_f16(value);
+            let back = f16_to_f32(half);
+            assert!(
+                (back - value).abs() <= value.abs() * 1e-3 + 6e-8,
+                "{} -> {:x} -> {}",
+                value,
+                half,
+                back
+            );
+        }
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert!(f16_to_f32(0x7e00).is_nan());
+
+        for half in 0..0x7c00 {
+            assert_eq!(f32_to_f16(f16_to_f32(half)), half);
+        }
+    }
+
+    #[test]
+    fn usages_pick_color_spaces() {
+        assert_eq!(TextureUsage::BaseColor.color_space(), ColorSpace::Srgb);
+        assert_eq!(TextureUsage::Emissive.color_space(), ColorSpace::Srgb);
+        assert_eq!(TextureUsage::Normal.color_space(), ColorSpace::Linear);
+        assert_eq!(
+            TextureUsage::MetallicRoughness.color_space(),
+            ColorSpace::Linear
+        );
+        assert_eq!(TextureUsage::Occlusion.color_space(), ColorSpace::Linear);
+        assert!(TextureUsage::Occlusion.reads_red_only());
+        assert!(!TextureUsage::MetallicRoughness.reads_red_only());
+    }
+
+    #[test]
+    fn transfer_functions_round_trip() {
+        for i in 0..=255 {
+            let value = i as f32 / 255.0;
+            assert!((srgb_to_linear(linear_to_srgb(value)) - value).abs() < 1e-5);
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+        }
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        // Mid gray in sRGB is about a fifth of the light.
+        assert!((srgb_to_linear(0.5) - 0.2140).abs() < 1e-3);
+    }
+
+    #[test]
+    fn eight_bit_srgb_keeps_the_encoded_bytes() {
+        let gray = luma8(1, 1, vec![77]);
+        let srgb = gray.clone().into_color_space(ColorSpace::Srgb);
+        assert_eq!(srgb.format, TextureFormat::BGRA8UnormSrgb);
+        assert_eq!(srgb.data, vec![77, 77, 77, 255]);
+        assert_eq!(srgb.color_space(), ColorSpace::Srgb);
+        assert_eq!(gray.clone().into_color_space(ColorSpace::Linear), gray);
+
+        let linear = srgb.clone().into_color_space(ColorSpace::Linear);
+        assert_eq!(linear.format, TextureFormat::BGRA8Unorm);
+        assert_eq!(linear.data, srgb.data);
+    }
+
+    #[test]
+    fn srgb_round_trips_through_float() {
+        let rgb = TextureData::from_image(&DynamicImage::ImageRgb8(
+            ImageBuffer::<Rgb<u8>, _>::from_raw(1, 1, vec![1, 2, 3]).unwrap(),
+        ))
+        .into_color_space(ColorSpace::Srgb);
+        assert_eq!(rgb.data, vec![3, 2, 1, 255]);
+
+        let float = rgb.convert(TextureFormat::RGBA32Float);
+        assert!((f32_values(&float.data)[0] - srgb_to_linear(1.0 / 255.0)).abs() < 1e-6);
+        assert_eq!(
+            float.convert(TextureFormat::BGRA8UnormSrgb).data,
+            vec![3, 2, 1, 255]
+        );
+    }
+
+    #[test]
+    fn sixteen_bit_images_are_linearized() {
+        // No 16-bit sRGB format exists, so the values are decoded up front.
+        let gray = TextureData::from_image(&DynamicImage::ImageLuma16(
+            ImageBuffer::<Luma<u16>, _>::from_raw(1, 1, vec![32768]).unwrap(),
+        ));
+        let srgb = gray.into_color_space(ColorSpace::Srgb);
+        assert_eq!(srgb.format, TextureFormat::RGBA16Unorm);
+
+        let red = u16::from_ne_bytes([srgb.data[0], srgb.data[1]]) as f32 / 65535.0;
+        assert!((red - 0.2140).abs() < 1e-3, "{}", red);
+        assert_eq!(u16::from_ne_bytes([srgb.data[6], srgb.data[7]]), 65535);
+    }
+}
@@ -0,0 +1,163 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+const KHR_MATERIALS_VARIANTS: &str = "KHR_materials_variants";
+
+/// Names of the material variants declared at the root of the document, in
+/// index order.
+pub fn read_variant_names(json: &Value) -> Vec<String> {
+    json["extensions"][KHR_MATERIALS_VARIANTS]["variants"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, variant)| {
+            variant["name"]
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("variant {}", index))
+        })
+        .collect()
+}
+
+/// Maps variant indices to the material index a primitive uses for them.
+/// Variants without a mapping keep the primitive's default material.
+pub fn read_variant_mappings(
+    json: &Value,
+    primitive: &gltf::Primitive,
+    mesh: &gltf::Mesh,
+) -> HashMap<usize, usize> {
+    let mappings = &json["meshes"][mesh.index()]["primitives"][primitive.index()]["extensions"]
+        [KHR_MATERIALS_VARIANTS]["mappings"];
+
+    let mut variant_materials = HashMap::new();
+    for mapping in mappings.as_array().into_iter().flatten() {
+        let material = match mapping["material"].as_u64() {
+            Some(material) => material as usize,
+            None => continue,
+        };
+        let variants = mapping["variants"].as_array().into_iter().flatten();
+        for variant in variants.filter_map(Value::as_u64) {
+            variant_materials.insert(variant as usize, material);
+        }
+    }
+
+    variant_materials
+}
+
+/// The material a primitive uses in `variant`: the one `variant_materials`
+/// maps it to, or its `default` material when there is no variant or no
+/// mapping for it.
+pub fn variant_material(
+    variant_materials: &HashMap<usize, usize>,
+    default: Option<usize>,
+    variant: Option<usize>,
+) -> Option<usize> {
+    variant
+        .and_then(|variant| variant_materials.get(&variant).copied())
+        .or(default)
+}
+
+/// The loaded material at `index`, or the default material when that one
+/// was not loaded.
+pub fn loaded_material<M>(
+    materials: &HashMap<Option<usize>, M>,
+    index: Option<usize>,
+) -> Option<&M> {
+    materials.get(&index).or_else(|| materials.get(&None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variants_json() -> Value {
+        serde_json::from_str(
+            r#"{
+                "asset": { "version": "2.0" },
+                "extensionsUsed": ["KHR_materials_variants"],
+                "extensions": {
+                    "KHR_materials_variants": {
+                        "variants": [{ "name": "red" }, {}, { "name": "blue" }]
+                    }
+                },
+                "materials": [{}, {}, {}],
+                "meshes": [{
+                    "primitives": [
+                        { "attributes": {}, "material": 0 },
+                        {
+                            "attributes": {},
+                            "material": 0,
+                            "extensions": {
+                                "KHR_materials_variants": {
+                                    "mappings": [
+                                        { "material": 1, "variants": [0, 2] },
+                                        { "variants": [1] },
+                                        { "material": 2, "variants": [2] }
+                                    ]
+                                }
+                            }
+                        }
+                    ]
+                }]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn mappings(primitive: usize) -> HashMap<usize, usize> {
+        let json = variants_json();
+        // Unvalidated, as the primitives have no positions.
+        let root = serde_json::from_value(json.clone()).unwrap();
+        let document = gltf::Document::from_json_without_validation(root);
+        let mesh = document.meshes().next().unwrap();
+        let primitive = mesh.primitives().nth(primitive).unwrap();
+        read_variant_mappings(&json, &primitive, &mesh)
+    }
+
+    #[test]
+    fn names_unnamed_variants_by_index() {
+        assert_eq!(
+            read_variant_names(&variants_json()),
+            ["red", "variant 1", "blue"]
+        );
+        assert!(read_variant_names(&Value::Null).is_empty());
+    }
+
+    #[test]
+    fn reads_mappings_of_a_primitive() {
+        // Mappings without a material are skipped, and later mappings win.
+        assert_eq!(mappings(1), HashMap::from([(0, 1), (2, 2)]));
+        assert!(mappings(0).is_empty());
+    }
+
+    #[test]
+    fn selects_the_mapped_or_default_material() {
+        let variant_materials = mappings(1);
+        assert_eq!(variant_material(&variant_materials, Some(0), None), Some(0));
+        assert_eq!(
+            variant_material(&variant_materials, Some(0), Some(0)),
+            Some(1)
+        );
+        assert_eq!(
+            variant_material(&variant_materials, Some(0), Some(1)),
+            Some(0)
+        );
+        assert_eq!(
+            variant_material(&variant_materials, Some(0), Some(2)),
+            Some(2)
+        );
+        assert_eq!(variant_material(&variant_materials, None, Some(1)), None);
+    }
+
+    #[test]
+    fn unloaded_materials_fall_back_to_the_default() {
+        let materials = HashMap::from([(None, "default"), (Some(0), "first")]);
+        assert_eq!(loaded_material(&materials, Some(0)), Some(&"first"));
+        assert_eq!(loaded_material(&materials, Some(7)), Some(&"default"));
+        assert_eq!(loaded_material(&materials, None), Some(&"default"));
+
+        let materials = HashMap::from([(Some(0), "first")]);
+        assert_eq!(loaded_material(&materials, Some(7)), None);
+    }
+}